@@ -1437,10 +1437,18 @@ impl pallet_vesting::Config for Runtime {
 
 impl pallet_mmr::Config for Runtime {
 	const INDEXING_PREFIX: &'static [u8] = b"mmr";
+	type RuntimeEvent = RuntimeEvent;
 	type Hashing = <Runtime as frame_system::Config>::Hashing;
 	type Hash = <Runtime as frame_system::Config>::Hash;
 	type LeafData = pallet_mmr::ParentNumberAndHash<Self>;
+	type LeafTransform = ();
+	type LeafValidator = ();
+	type CrossChainCommitment = ();
 	type OnNewRoot = ();
+	type RootWrapper = ();
+	type OnAppend = ();
+	type IndexingEnabled = ConstBool<true>;
+	type FinalizedRootOrigin = EnsureRoot<AccountId>;
 	type WeightInfo = ();
 }
 
@@ -227,15 +227,34 @@ where
 }
 
 /// Converts an mmr-specific error into a [`CallError`].
+///
+/// Every error has an explicit code, so that adding an error variant requires picking one.
 fn mmr_error_into_rpc_error(err: MmrError) -> CallError {
 	let error_code = MMR_ERROR +
 		match err {
-			MmrError::LeafNotFound => 1,
-			MmrError::GenerateProof => 2,
+			MmrError::LeafNotFound | MmrError::LeafIndexOutOfRange { .. } => 1,
+			MmrError::GenerateProof |
+			MmrError::ReadLimitExceeded |
+			MmrError::ProofGenerationTimeout { .. } => 2,
 			MmrError::Verify => 3,
 			MmrError::InvalidNumericOp => 4,
 			MmrError::InvalidBestKnownBlock => 5,
-			_ => 0,
+			MmrError::Push => 6,
+			MmrError::GetRoot => 7,
+			MmrError::Commit => 8,
+			MmrError::PalletNotIncluded => 9,
+			MmrError::InvalidLeafIndex => 10,
+			MmrError::MissingRootDigest => 11,
+			MmrError::UnknownAncestorHash { .. } => 12,
+			MmrError::LeafNotIndexed { .. } => 13,
+			MmrError::OffchainChecksumMismatch => 14,
+			MmrError::UnsupportedProofVersion { .. } => 15,
+			MmrError::LeafTypeMismatch => 16,
+			MmrError::UnknownParentHash => 17,
+			MmrError::LeafTooLarge { .. } => 18,
+			MmrError::TooManyLeavesInBlock => 19,
+			MmrError::LeafSchemaMismatch { .. } => 20,
+			MmrError::UntrustedLeafCount { .. } => 21,
 		};
 
 	CallError::Custom(ErrorObject::owned(error_code, err.to_string(), Some(format!("{:?}", err))))
@@ -353,4 +372,17 @@ mod tests {
 		// then
 		assert_eq!(actual, expected);
 	}
+
+	#[test]
+	fn should_map_errors_to_their_codes() {
+		let code = |err| match mmr_error_into_rpc_error(err) {
+			CallError::Custom(err) => err.code(),
+			err => panic!("Unexpected error: {:?}", err),
+		};
+
+		assert_eq!(code(MmrError::Verify), MMR_ERROR + 3);
+		assert_eq!(code(MmrError::UnknownAncestorHash { block_num: 6 }), MMR_ERROR + 12);
+		assert_eq!(code(MmrError::LeafNotIndexed { leaf_index: 4 }), MMR_ERROR + 13);
+		assert_eq!(code(MmrError::TooManyLeavesInBlock), MMR_ERROR + 19);
+	}
 }
@@ -28,7 +28,7 @@ use sc_offchain::OffchainDb;
 use sp_blockchain::{CachedHeaderMetadata, ForkBackend};
 use sp_consensus_beefy::MmrRootHash;
 use sp_core::offchain::{DbExternalities, StorageKind};
-use sp_mmr_primitives::{utils, utils::NodesUtils, MmrApi, NodeIndex};
+use sp_mmr_primitives::{utils, utils::NodesUtils, MmrApi, TypedLeafIndex, TypedNodeIndex};
 use sp_runtime::{
 	traits::{Block, Header, NumberFor, One},
 	Saturating,
@@ -74,11 +74,11 @@ where
 		})
 	}
 
-	fn node_temp_offchain_key(&self, pos: NodeIndex, parent_hash: B::Hash) -> Vec<u8> {
+	fn node_temp_offchain_key(&self, pos: TypedNodeIndex, parent_hash: B::Hash) -> Vec<u8> {
 		NodesUtils::node_temp_offchain_key::<B::Header>(&self.indexing_prefix, pos, parent_hash)
 	}
 
-	fn node_canon_offchain_key(&self, pos: NodeIndex) -> Vec<u8> {
+	fn node_canon_offchain_key(&self, pos: TypedNodeIndex) -> Vec<u8> {
 		NodesUtils::node_canon_offchain_key(&self.indexing_prefix, pos)
 	}
 
@@ -111,10 +111,10 @@ where
 		&self,
 		block_num: NumberFor<B>,
 		action: &str,
-	) -> Option<Vec<NodeIndex>> {
+	) -> Option<Vec<TypedNodeIndex>> {
 		match utils::block_num_to_leaf_index::<B::Header>(block_num, self.first_mmr_block) {
 			Ok(leaf_idx) => {
-				let branch = NodesUtils::right_branch_ending_in_leaf(leaf_idx);
+				let branch = NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(leaf_idx));
 				debug!(
 					target: LOG_TARGET,
 					"Nodes to {} for block {}: {:?}", action, block_num, branch
@@ -32,7 +32,7 @@ use sp_core::{
 	H256,
 };
 use sp_mmr_primitives as mmr;
-use sp_mmr_primitives::{utils::NodesUtils, LeafIndex, NodeIndex};
+use sp_mmr_primitives::{utils::NodesUtils, LeafIndex, TypedLeafIndex, TypedNodeIndex};
 use sp_runtime::{
 	generic::BlockId,
 	traits::{Block as BlockT, Header as HeaderT},
@@ -82,7 +82,7 @@ impl MmrBlock {
 		*self.block.header.parent_hash()
 	}
 
-	pub fn get_offchain_key(&self, node: NodeIndex, key_type: OffchainKeyType) -> Vec<u8> {
+	pub fn get_offchain_key(&self, node: TypedNodeIndex, key_type: OffchainKeyType) -> Vec<u8> {
 		match key_type {
 			OffchainKeyType::Temp => NodesUtils::node_temp_offchain_key::<Header>(
 				MockRuntimeApi::INDEXING_PREFIX,
@@ -137,7 +137,7 @@ impl MockClient {
 		// Simulate writing MMR nodes in offchain storage
 		if let Some(leaf_idx) = maybe_leaf_idx {
 			let mut offchain_db = self.offchain_db();
-			for node in NodesUtils::right_branch_ending_in_leaf(leaf_idx) {
+			for node in NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(leaf_idx)) {
 				let temp_key = NodesUtils::node_temp_offchain_key::<Header>(
 					MockRuntimeApi::INDEXING_PREFIX,
 					node,
@@ -165,7 +165,9 @@ impl MockClient {
 
 	pub fn undo_block_canonicalization(&self, mmr_block: &MmrBlock) {
 		let mut offchain_db = self.offchain_db();
-		for node in NodesUtils::right_branch_ending_in_leaf(mmr_block.leaf_idx.unwrap()) {
+		for node in
+			NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(mmr_block.leaf_idx.unwrap()))
+		{
 			let canon_key = mmr_block.get_offchain_key(node, OffchainKeyType::Canon);
 			let val = offchain_db.local_storage_get(StorageKind::PERSISTENT, &canon_key).unwrap();
 			offchain_db.local_storage_clear(StorageKind::PERSISTENT, &canon_key);
@@ -185,7 +187,9 @@ impl MockClient {
 	{
 		let mut offchain_db = self.offchain_db();
 		for mmr_block in blocks {
-			for node in NodesUtils::right_branch_ending_in_leaf(mmr_block.leaf_idx.unwrap()) {
+			for node in
+				NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(mmr_block.leaf_idx.unwrap()))
+			{
 				let temp_key = mmr_block.get_offchain_key(node, key_type);
 				let val = offchain_db.local_storage_get(StorageKind::PERSISTENT, &temp_key);
 				f(val, mmr_block);
@@ -21,7 +21,7 @@ use codec::Encode;
 use frame_support::{
 	construct_runtime, parameter_types,
 	sp_io::TestExternalities,
-	traits::{ConstU16, ConstU32, ConstU64, GenesisBuild},
+	traits::{ConstBool, ConstU16, ConstU32, ConstU64, GenesisBuild},
 	BasicExternalities,
 };
 use sp_consensus_beefy::mmr::MmrLeafVersion;
@@ -56,7 +56,7 @@ construct_runtime!(
 	{
 		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
 		Session: pallet_session::{Pallet, Call, Storage, Event, Config<T>},
-		Mmr: pallet_mmr::{Pallet, Storage},
+		Mmr: pallet_mmr::{Pallet, Storage, Event<T>},
 		Beefy: pallet_beefy::{Pallet, Config<T>, Storage},
 		BeefyMmr: pallet_beefy_mmr::{Pallet, Storage},
 	}
@@ -111,13 +111,23 @@ pub type MmrLeaf = sp_consensus_beefy::mmr::MmrLeaf<
 impl pallet_mmr::Config for Test {
 	const INDEXING_PREFIX: &'static [u8] = b"mmr";
 
+	type RuntimeEvent = RuntimeEvent;
+
 	type Hashing = Keccak256;
 
 	type Hash = <Keccak256 as Hasher>::Out;
 
 	type LeafData = BeefyMmr;
+	type LeafTransform = ();
+	type LeafValidator = ();
+	type CrossChainCommitment = ();
 
 	type OnNewRoot = pallet_beefy_mmr::DepositBeefyDigest<Test>;
+	type RootWrapper = ();
+	type OnAppend = ();
+
+	type IndexingEnabled = ConstBool<true>;
+	type FinalizedRootOrigin = frame_system::EnsureRoot<Self::AccountId>;
 
 	type WeightInfo = ();
 }
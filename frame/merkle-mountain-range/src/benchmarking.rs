@@ -22,12 +22,52 @@
 use crate::*;
 use frame_benchmarking::v1::benchmarks_instance_pallet;
 use frame_support::traits::OnInitialize;
+use sp_runtime::traits::{Hash, One};
+
+/// Initialize a block with a known parent hash, so that the appended nodes can be indexed
+/// off-chain.
+fn set_parent_hash<T: frame_system::Config>() {
+	let parent_hash = <T as frame_system::Config>::Hashing::hash(b"parent");
+	frame_system::Pallet::<T>::initialize(&One::one(), &parent_hash, &Default::default());
+}
+
+/// Maximum number of on-chain nodes removed by the `clear_mmr` benchmark with
+/// `STORE_ALL_NODES`.
+const MAX_CLEARED_NODES: u32 = 4_096;
+
+/// Number of single-leaf proofs generated by the proof generation benchmarks.
+const PROOFS: LeafIndex = 16;
+
+/// Append `2^depth` leaves, one per block, and index all their nodes in the Off-chain DB.
+///
+/// Returns the block numbers of [`PROOFS`] leaves spread evenly over the MMR.
+fn index_offchain_mmr<T: Config<I>, I: 'static>(depth: u32) -> Vec<T::BlockNumber> {
+	use mmr::storage::{OffchainStorage, Storage};
+	use primitives::{LeafDataProvider, LeafTransform};
+
+	let leaves_count: LeafIndex = 1 << depth;
+	let mut leaves = Vec::new();
+	for number in 1..=leaves_count {
+		let number: T::BlockNumber = (number as u32).into();
+		let parent_hash = <T as frame_system::Config>::Hashing::hash_of(&number);
+		frame_system::Pallet::<T>::initialize(&number, &parent_hash, &Default::default());
+		let leaf = T::LeafTransform::transform(T::LeafData::leaf_data());
+		leaves.push((primitives::TypedLeafIndex(leaves.len() as LeafIndex), leaf));
+		Pallet::<T, I>::on_initialize(number);
+	}
+	let leaves = leaves.into_iter().map(|(index, leaf)| (index, NodeLeafOf::<T, I>::new(leaf)));
+	Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::reindex_offchain(leaves)
+		.expect("All the leaves were appended; qed");
+
+	(0..PROOFS).map(|i| ((i * leaves_count / PROOFS + 1) as u32).into()).collect()
+}
 
 benchmarks_instance_pallet! {
 	on_initialize {
 		let x in 1 .. 1_000;
 
 		let leaves = x as NodeIndex;
+		set_parent_hash::<T>();
 	}: {
 		for b in 0..leaves {
 			Pallet::<T, I>::on_initialize((b as u32).into());
@@ -36,5 +76,103 @@ benchmarks_instance_pallet! {
 		assert_eq!(crate::NumberOfLeaves::<T, I>::get(), leaves);
 	}
 
-	impl_benchmark_test_suite!(Pallet, crate::tests::new_test_ext(), crate::mock::Test);
+	generate_proof {
+		let n in 1 .. 32;
+
+		// The proof of the first leaf of a perfect MMR consists of a node at each height.
+		let leaves: LeafIndex = 1 << n;
+		let nodes = NodesUtils::new(leaves).proof_node_indices(primitives::TypedLeafIndex(0));
+		for node in &nodes {
+			Nodes::<T, I>::insert(node.0, <T as Config<I>>::Hash::default());
+		}
+		NumberOfLeaves::<T, I>::put(leaves);
+		let mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> = mmr::Mmr::new(leaves);
+	}: {
+		mmr.generate_onchain_proof(vec![0]).expect("All the proof nodes are stored; qed");
+	} verify {
+		assert_eq!(nodes.len(), n as usize);
+	}
+
+	// Not an extrinsic, but the proofs are generated within the budget of an offchain worker.
+	// Every node read from the Off-chain DB requires the hash of the block that added it, these
+	// are read once per proof when generating them one by one.
+	#[extra]
+	generate_proofs_uncached {
+		let d in 4 .. 12;
+
+		let block_numbers = index_offchain_mmr::<T, I>(d);
+	}: {
+		for block_number in &block_numbers {
+			Pallet::<T, I>::generate_proof(vec![*block_number], None)
+				.expect("All the nodes are indexed; qed");
+		}
+	}
+
+	// Same proofs as `generate_proofs_uncached`, generated in a batch: the nodes shared by the
+	// proofs, and the hashes of the blocks that added them, are prefetched once.
+	#[extra]
+	generate_proofs_prefetched {
+		let d in 4 .. 12;
+
+		let batches =
+			index_offchain_mmr::<T, I>(d).into_iter().map(|number| vec![number]).collect();
+	}: {
+		Pallet::<T, I>::generate_proofs(batches, None).expect("All the nodes are indexed; qed");
+	}
+
+	// The worst case of `on_initialize` for a given number of peaks. The per-peak slope is meant
+	// to replace the provisional `WeightInfo::on_initialize` estimate.
+	#[extra]
+	on_initialize_merging_peaks {
+		let p in 0 .. 62;
+
+		// The MMR with `2^p - 1` leaves has `p` peaks, all merged by the next leaf, which is the
+		// worst case for an MMR with `p` peaks (as opposed to `p = 0`, merging none).
+		let leaves: LeafIndex = (1 << p) - 1;
+		for peak in Pallet::<T, I>::peaks(leaves) {
+			Nodes::<T, I>::insert(peak, <T as Config<I>>::Hash::default());
+		}
+		NumberOfLeaves::<T, I>::put(leaves);
+		set_parent_hash::<T>();
+	}: {
+		Pallet::<T, I>::on_initialize(One::one());
+	} verify {
+		assert_eq!(crate::NumberOfLeaves::<T, I>::get(), leaves + 1);
+		assert_eq!(Pallet::<T, I>::peaks(leaves + 1).len(), 1);
+	}
+
+	clear_mmr {
+		// The removed nodes are the peaks, or every node with `STORE_ALL_NODES`.
+		let n in 1 .. if T::STORE_ALL_NODES { MAX_CLEARED_NODES } else { 63 };
+		let r in 0 .. MaxNotIndexedRanges::<T, I>::get();
+
+		// The largest MMR of at most `n` nodes with `STORE_ALL_NODES`, otherwise the MMR with
+		// `2^n - 1` leaves, which has `n` peaks.
+		let leaves: LeafIndex = if T::STORE_ALL_NODES {
+			let mut leaves = 1;
+			while NodesUtils::new(leaves + 1).size() <= n as NodeIndex {
+				leaves += 1;
+			}
+			leaves
+		} else {
+			(1 << n) - 1
+		};
+		NumberOfLeaves::<T, I>::put(leaves);
+		let nodes = Pallet::<T, I>::nodes_to_remove(0);
+		for pos in &nodes {
+			Nodes::<T, I>::insert(pos, <T as Config<I>>::Hash::default());
+		}
+		// Every other leaf wasn't indexed, making `r` single leaf ranges.
+		let ranges = (0..r as LeafIndex).map(|range| (2 * range, 2 * range)).collect::<Vec<_>>();
+		NotIndexedLeaves::<T, I>::put(BoundedVec::try_from(ranges).unwrap());
+	}: {
+		Pallet::<T, I>::clear_onchain_mmr();
+	} verify {
+		assert!(nodes.len() <= n as usize);
+		assert_eq!(crate::NumberOfLeaves::<T, I>::get(), 0);
+		assert_eq!(Nodes::<T, I>::iter_keys().count(), 0);
+		assert!(NotIndexedLeaves::<T, I>::get().is_empty());
+	}
+
+	impl_benchmark_test_suite!(Pallet, crate::tests::new_offchain_test_ext(), crate::mock::Test);
 }
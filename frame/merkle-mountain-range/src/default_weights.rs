@@ -24,6 +24,7 @@ use frame_support::weights::{
 };
 
 impl crate::WeightInfo for () {
+	// Provisional estimate, not derived from the `on_initialize_merging_peaks` benchmark yet.
 	fn on_initialize(peaks: u64) -> Weight {
 		// Reading the parent hash.
 		let leaf_weight = DbWeight::get().reads(1);
@@ -37,4 +38,72 @@ impl crate::WeightInfo for () {
 			.saturating_add(hook_weight)
 			.saturating_add(DbWeight::get().reads_writes(2 + peaks, 2 + peaks))
 	}
+
+	fn force_set_leaf_count(nodes: u64, ranges: u32) -> Weight {
+		// Reading the number of leaves, the not indexed leaves, the peak history and checking
+		// the new peaks (possibly in the peak history too).
+		let read_weight = DbWeight::get().reads(3 + 2 * 64 + ranges as u64);
+		// Recomputing the root from the new peaks.
+		let hash_weight = Weight::from_parts(64u64 * 2u64 * WEIGHT_REF_TIME_PER_NANOS, 0);
+
+		// Removing the nodes, restoring the new peaks and truncating the not indexed leaves,
+		// the peak history and the rest of the MMR state.
+		read_weight
+			.saturating_add(hash_weight)
+			.saturating_add(DbWeight::get().writes(5 + 2 * 64 + nodes + ranges as u64))
+	}
+
+	fn request_reindex() -> Weight {
+		// Reading the number of leaves and depositing the event.
+		DbWeight::get().reads_writes(1, 1)
+	}
+
+	fn repair_peak() -> Weight {
+		// Reading the number of leaves, the root and the peaks.
+		let read_weight = DbWeight::get().reads(2 + 64);
+		// Recomputing the root from the peaks.
+		let hash_weight = Weight::from_parts(64u64 * 2u64 * WEIGHT_REF_TIME_PER_NANOS, 0);
+
+		read_weight
+			.saturating_add(hash_weight)
+			.saturating_add(DbWeight::get().writes(1))
+	}
+
+	// Provisional estimate, not derived from the `generate_proof` benchmark yet.
+	fn generate_proof(nodes: u32) -> Weight {
+		// Reading the number of leaves and the proof nodes.
+		let read_weight = DbWeight::get().reads(1 + nodes as u64);
+		// Collecting the proof nodes.
+		let node_weight = Weight::from_parts(nodes as u64 * WEIGHT_REF_TIME_PER_NANOS, 0);
+
+		read_weight.saturating_add(node_weight)
+	}
+
+	fn rebuild_onchain_peaks() -> Weight {
+		// Reading the number of leaves and the root.
+		let read_weight = DbWeight::get().reads(2);
+		// Recomputing the root from the peaks.
+		let hash_weight = Weight::from_parts(64u64 * 2u64 * WEIGHT_REF_TIME_PER_NANOS, 0);
+
+		read_weight
+			.saturating_add(hash_weight)
+			.saturating_add(DbWeight::get().writes(1 + 64))
+	}
+
+	fn note_finalized_root() -> Weight {
+		// Reading the recent root and the finalized block numbers, replacing the oldest entry.
+		DbWeight::get().reads_writes(2, 3)
+	}
+
+	// Provisional estimate, not derived from the `clear_mmr` benchmark yet.
+	fn clear_mmr(nodes: u64, ranges: u32) -> Weight {
+		// Reading the number of leaves, the not indexed leaves and the peak history positions,
+		// removing the nodes and the rest of the MMR state.
+		DbWeight::get().reads_writes(2 + ranges as u64, 6 + nodes + ranges as u64)
+	}
+
+	fn report_state() -> Weight {
+		// Reading the number of leaves and the root, depositing the event.
+		DbWeight::get().reads_writes(2, 1)
+	}
 }
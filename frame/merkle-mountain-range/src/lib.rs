@@ -38,6 +38,19 @@
 //! See [primitives::Compact] documentation for how you can optimize proof size for leafs that are
 //! composed from multiple elements.
 //!
+//! ## Multiple hashing algorithms
+//!
+//! To serve proofs to verifiers using different hashing algorithms (e.g. Keccak256 for Ethereum
+//! and BLAKE2 for Substrate-based chains) from the same leaf data, add one instance of the pallet
+//! per algorithm, each with its own [`Config::Hashing`] and [`Config::INDEXING_PREFIX`], and the
+//! same `LeafData` provider. The hashing is then selected by the instance the proof is generated
+//! from, e.g. by exposing one runtime API per instance.
+//!
+//! Each instance maintains its own MMR, so the storage cost is multiplied by the number of
+//! instances: the peaks on-chain (about `log2(leaves)` hashes each), as well as the full leaves
+//! and all the inner nodes in the Off-chain DB. The cost of an extra leaf hashing and the
+//! on-chain appends is paid on every block too.
+//!
 //! ## What for?
 //!
 //! Primary use case for this pallet is to generate MMR root hashes, that can latter on be used by
@@ -56,10 +69,10 @@
 //! NOTE This pallet is experimental and not proven to work in production.
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use frame_support::{log, weights::Weight};
+use frame_support::{log, traits::Get, weights::Weight, BoundedVec};
 use sp_mmr_primitives::utils;
 use sp_runtime::{
-	traits::{self, One, Saturating},
+	traits::{self, One, Saturating, Zero},
 	SaturatedConversion,
 };
 use sp_std::prelude::*;
@@ -67,6 +80,7 @@ use sp_std::prelude::*;
 pub use pallet::*;
 pub use sp_mmr_primitives::{
 	self as primitives, utils::NodesUtils, Error, LeafDataProvider, LeafIndex, NodeIndex,
+	TypedLeafIndex, TypedNodeIndex,
 };
 
 #[cfg(feature = "runtime-benchmarks")]
@@ -75,6 +89,8 @@ mod default_weights;
 mod mmr;
 #[cfg(test)]
 mod mock;
+#[cfg(feature = "std")]
+pub mod test_utils;
 #[cfg(test)]
 mod tests;
 
@@ -101,15 +117,129 @@ impl<T: frame_system::Config> LeafDataProvider for ParentNumberAndHash<T> {
 	}
 }
 
+/// A [LeafTransform](primitives::LeafTransform) embedding the number of the block adding the
+/// leaf.
+///
+/// The leaves are stored (and committed to) as `(block_number, leaf_data)`, which makes them
+/// self-dating: a verifier of a leaf proof learns the block that added the leaf from the leaf
+/// itself. Note the leaves seeded at genesis (see [`GenesisConfig`]) are given in their stored
+/// form, they should embed the genesis block number.
+pub struct EmbedBlockNumber<T: frame_system::Config> {
+	_phantom: sp_std::marker::PhantomData<T>,
+}
+
+impl<T: frame_system::Config, L> primitives::LeafTransform<L> for EmbedBlockNumber<T>
+where
+	(<T as frame_system::Config>::BlockNumber, L): primitives::FullLeaf + codec::Decode,
+{
+	type Leaf = (<T as frame_system::Config>::BlockNumber, L);
+
+	fn transform(leaf: L) -> Self::Leaf {
+		(frame_system::Pallet::<T>::block_number(), leaf)
+	}
+}
+
 pub trait WeightInfo {
 	fn on_initialize(peaks: NodeIndex) -> Weight;
+	fn force_set_leaf_count(nodes: NodeIndex, ranges: u32) -> Weight;
+	fn request_reindex() -> Weight;
+	fn repair_peak() -> Weight;
+	fn generate_proof(nodes: u32) -> Weight;
+	fn rebuild_onchain_peaks() -> Weight;
+	fn note_finalized_root() -> Weight;
+	fn clear_mmr(nodes: NodeIndex, ranges: u32) -> Weight;
+	fn report_state() -> Weight;
+}
+
+/// The maximum number of peaks of the MMR with at most [`Config::MAX_LEAVES`] leaves.
+///
+/// The number of peaks is the number of ones in the binary representation of the number of
+/// leaves, so it's bounded by the bit length of the maximum number of leaves.
+pub struct MaxPeaks<T, I = ()>(sp_std::marker::PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> Get<u32> for MaxPeaks<T, I> {
+	fn get() -> u32 {
+		LeafIndex::BITS - T::MAX_LEAVES.leading_zeros()
+	}
+}
+
+/// The maximum number of roots in [`FinalizedRoots`], see [`Config::MAX_FINALIZED_ROOTS`].
+pub struct MaxFinalizedRoots<T, I = ()>(sp_std::marker::PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> Get<u32> for MaxFinalizedRoots<T, I> {
+	fn get() -> u32 {
+		T::MAX_FINALIZED_ROOTS
+	}
+}
+
+/// The maximum number of peaks in [`HistoricalPeaks`], see [`Config::MAX_PEAK_HISTORY`].
+pub struct MaxPeakHistory<T, I = ()>(sp_std::marker::PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> Get<u32> for MaxPeakHistory<T, I> {
+	fn get() -> u32 {
+		T::MAX_PEAK_HISTORY
+	}
+}
+
+/// The maximum number of ranges in [`NotIndexedLeaves`], see
+/// [`Config::MAX_NOT_INDEXED_RANGES`].
+pub struct MaxNotIndexedRanges<T, I = ()>(sp_std::marker::PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> Get<u32> for MaxNotIndexedRanges<T, I> {
+	fn get() -> u32 {
+		T::MAX_NOT_INDEXED_RANGES
+	}
 }
 
 /// An MMR specific to the pallet.
-type ModuleMmr<StorageType, T, I> = mmr::Mmr<StorageType, T, I, LeafOf<T, I>>;
+type ModuleMmr<StorageType, T, I> = mmr::Mmr<StorageType, T, I, NodeLeafOf<T, I>>;
+
+/// Maximum number of leaves whose nodes are canonicalized by a single offchain worker run
+/// (see [`Config::OFFCHAIN_RETENTION_BLOCKS`]), every processed block counts as a leaf too.
+pub const MAX_PRUNED_LEAVES_PER_RUN: LeafIndex = 256;
+
+/// Maximum number of entries in [`LeafCountCheckpoints`], i.e. of the kept blocks which didn't
+/// append exactly one leaf.
+pub const MAX_LEAF_COUNT_CHECKPOINTS: u32 = 256;
+
+/// Leaf data, as returned by [`Config::LeafData`].
+type RawLeafOf<T, I> = <<T as Config<I>>::LeafData as primitives::LeafDataProvider>::LeafData;
 
-/// Leaf data.
-type LeafOf<T, I> = <<T as Config<I>>::LeafData as primitives::LeafDataProvider>::LeafData;
+/// Leaf data, as stored in the MMR (see [`Config::LeafTransform`]).
+type LeafOf<T, I> =
+	<<T as Config<I>>::LeafTransform as primitives::LeafTransform<RawLeafOf<T, I>>>::Leaf;
+
+/// Leaf data, as stored in the MMR nodes, i.e. committed to with [`ConfiguredCommitment`].
+type NodeLeafOf<T, I> = primitives::CommittedLeaf<LeafOf<T, I>, ConfiguredCommitment<T, I>>;
+
+/// Parent hashes and leaf ranges of the blocks of the same number seen by the offchain worker
+/// (see [`Pallet::fork_leaves_offchain_key`]).
+type ForkLeavesOf<T> = Vec<(<T as frame_system::Config>::Hash, LeafIndex, LeafIndex)>;
+
+/// Unwrap the leaves (as stored in the MMR nodes) returned along with their `proof`.
+fn unwrap_leaves<T: Config<I>, I: 'static, P>(
+	(leaves, proof): (Vec<NodeLeafOf<T, I>>, P),
+) -> (Vec<LeafOf<T, I>>, P) {
+	(leaves.into_iter().map(primitives::CommittedLeaf::into_inner).collect(), proof)
+}
+
+/// The commitment to the leaves of the MMR, as selected by [`Config::CROSS_CHAIN_COMMITMENT`].
+///
+/// Leaves wrapped in a [`primitives::CommittedLeaf`] with this commitment hash the same way
+/// as in the MMR of the runtime, e.g. for the stateless verification of its proofs.
+pub struct ConfiguredCommitment<T, I = ()>(sp_std::marker::PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> primitives::LeafCommitment<LeafOf<T, I>>
+	for ConfiguredCommitment<T, I>
+{
+	fn commit<H: traits::Hash>(leaf: &LeafOf<T, I>) -> H::Output {
+		if T::CROSS_CHAIN_COMMITMENT {
+			<T::CrossChainCommitment as primitives::LeafCommitment<_>>::commit::<H>(leaf)
+		} else {
+			<LeafOf<T, I> as primitives::FullLeaf>::compact_commitment::<H>(leaf)
+		}
+	}
+}
 
 /// Hashing used for the pallet.
 pub(crate) type HashingOf<T, I> = <T as Config<I>>::Hashing;
@@ -137,6 +267,210 @@ pub mod pallet {
 		/// [`Self::INDEXING_PREFIX`] and its in-tree index (MMR position).
 		const INDEXING_PREFIX: &'static [u8];
 
+		/// Whether all the MMR nodes should be persisted on-chain.
+		///
+		/// By default only the MMR peaks are kept in the [`Nodes`] storage, while the leaves and
+		/// inner nodes are pruned and only available in the Off-chain DB. Setting this to `true`
+		/// makes the pallet keep every node (as a hash) on-chain instead, which allows generating
+		/// proofs directly from the runtime storage, without relying on Off-chain indexing.
+		///
+		/// NOTE the on-chain storage grows linearly (`O(n)`) with the number of leaves in this
+		/// mode (roughly two hashes per leaf), so it's only suitable for small MMRs and
+		/// test/development networks.
+		const STORE_ALL_NODES: bool = false;
+
+		/// Whether the root origin is allowed to force-set the number of leaves in the MMR.
+		///
+		/// This enables the [`Pallet::force_set_leaf_count`] extrinsic, which is a disaster
+		/// recovery tool meant to truncate the MMR to a known-good number of leaves after the
+		/// on-chain state got corrupted.
+		///
+		/// NOTE this is DANGEROUS, truncating the MMR invalidates all the proofs and roots
+		/// that were produced for the removed leaves. Keep it disabled unless really needed.
+		const ALLOW_FORCE_RESET: bool = false;
+
+		/// Whether the root origin is allowed to clear the MMR entirely.
+		///
+		/// This enables the [`Pallet::clear_mmr`] extrinsic, meant for development and test
+		/// networks which need to start the MMR over.
+		///
+		/// NOTE this is DANGEROUS, clearing the MMR invalidates all the proofs and roots that
+		/// were ever produced. Keep it disabled on production networks.
+		const ALLOW_CLEAR: bool = false;
+
+		/// Number of recent blocks whose parent hashes are retained by the pallet itself.
+		///
+		/// Generating a proof requires the hashes of the blocks which added the MMR nodes to
+		/// the Off-chain DB (until the nodes are canonicalized by the MMR client gadget). These
+		/// are looked up in the [`ParentHashes`] ring buffer first, and then in `frame_system`,
+		/// which only keeps `BlockHashCount` of them. A larger retention increases the reach of
+		/// proof generation at the cost of on-chain storage (one hash per retained block).
+		///
+		/// Set to `0` (the default) to rely on `frame_system` only.
+		const PARENT_HASH_RETENTION: u32 = 0;
+
+		/// Whether the MMR peaks should be mirrored in the Off-chain DB.
+		///
+		/// If set, every peak stored on-chain is also pushed to the Off-chain DB via Indexing API
+		/// (under [`Pallet::peak_offchain_key`]), and removed from there once it's pruned. This
+		/// lets off-chain tools find the peaks without a state proof, at the cost of `O(log n)`
+		/// extra writes per block. Note the mirror reflects the latest imported block, so it's
+		/// not reliable in the presence of forks.
+		const MIRROR_PEAKS_OFFCHAIN: bool = false;
+
+		/// Whether to keep track of the total size of the nodes pushed to the Off-chain DB.
+		///
+		/// If set, the size of the encoding of every node indexed off-chain is added to
+		/// [`TotalOffchainBytes`], which allows estimating the growth rate of the Off-chain DB.
+		/// This costs an additional storage read and write per block.
+		const TRACK_OFFCHAIN_SIZE: bool = false;
+
+		/// Whether the nodes pushed to the Off-chain DB should be prefixed with a checksum.
+		///
+		/// If set, every node indexed off-chain is prepended with a short checksum of its
+		/// encoding, which is verified when the node is read back. A corrupted node is then
+		/// reported as [`primitives::Error::OffchainChecksumMismatch`], rather than failing to
+		/// decode or producing an invalid proof. This costs a few extra bytes per node.
+		///
+		/// NOTE changing this setting makes the nodes which are already in the Off-chain DB
+		/// unreadable, they have to be reindexed (see [`Pallet::request_reindex`]).
+		const OFFCHAIN_CHECKSUM: bool = false;
+
+		/// The maximum number of leaves the MMR is expected to hold.
+		///
+		/// Used to bound the number of peaks of the MMR (see [`MaxPeaks`]), e.g. when they are
+		/// included in a benchmarked extrinsic. Defaults to no limit, i.e. at most 64 peaks.
+		const MAX_LEAVES: LeafIndex = LeafIndex::MAX;
+
+		/// Number of finalized blocks after which the Off-chain DB nodes are canonicalized by
+		/// the offchain worker.
+		///
+		/// If set, the offchain worker moves the nodes added by the blocks at least this many
+		/// blocks older than the latest block noted as finalized (see
+		/// [`Pallet::note_finalized_root`]) from their fork-aware keys to the canonical ones,
+		/// the same way the MMR client gadget does on finality. The nodes indexed by the other
+		/// forks of these blocks are removed, as far as the offchain worker has seen them. The
+		/// lag leaves the gadget a chance to process the blocks first. Nothing is pruned unless
+		/// [`Config::MAX_FINALIZED_ROOTS`] is set and the finalized roots are noted.
+		///
+		/// Defaults to `None`, i.e. no pruning by the offchain worker.
+		const OFFCHAIN_RETENTION_BLOCKS: Option<u32> = None;
+
+		/// Whether the leaf of a block should be appended in `on_finalize` rather than in
+		/// `on_initialize`.
+		///
+		/// If set, the leaf data is collected at the end of the block, so it reflects the state
+		/// after all the extrinsics were applied (the weight of the append is still accounted
+		/// for in `on_initialize`). Blocks which didn't apply any extrinsic (inherents included)
+		/// don't append a leaf, so the leaves no longer map one-to-one to blocks and the block
+		/// number based lookups (e.g. generating a proof at a given block number) don't apply.
+		const COMMIT_IN_FINALIZE: bool = false;
+
+		/// The maximum number of MMR roots of finalized blocks kept in [`FinalizedRoots`].
+		///
+		/// If set, the root at the end of each block is also kept for this many blocks (see
+		/// [`RecentRoots`]), so that it can be noted as finalized with
+		/// [`Pallet::note_finalized_root`] in the meantime. Once the limit is reached, the oldest
+		/// finalized root is removed. Defaults to `0`, i.e. no finalized roots are kept.
+		const MAX_FINALIZED_ROOTS: u32 = 0;
+
+		/// The maximum number of pruned peaks kept in [`HistoricalPeaks`].
+		///
+		/// If set, the peaks removed from [`Nodes`] once they stop being peaks are kept along
+		/// with the block they were pruned at, so that the roots of past MMRs can be rebuilt from
+		/// on-chain data alone (see [`Pallet::historical_root`]). Once the limit is reached, the
+		/// oldest pruned peak is removed. Nothing is pruned if [`Config::STORE_ALL_NODES`] is
+		/// set. Defaults to `0`, i.e. no peak history is kept.
+		const MAX_PEAK_HISTORY: u32 = 0;
+
+		/// The maximum number of ranges of leaves kept in [`NotIndexedLeaves`].
+		///
+		/// Once the limit is reached, the latest range is extended to the leaves which were not
+		/// indexed, so the leaves in between are reported as not indexed as well. If set to `0`,
+		/// the not indexed leaves are not tracked. Defaults to `64`.
+		const MAX_NOT_INDEXED_RANGES: u32 = 64;
+
+		/// A stable identifier of the leaf type (i.e. of its encoding schema).
+		///
+		/// If non-zero, the identifier is stored along with the leaves in the Off-chain DB and
+		/// checked when they are read back, so that leaves of a different schema (e.g. after a
+		/// runtime upgrade changing [`Config::LeafData`]) are rejected instead of being decoded
+		/// with the wrong decoder. It should be changed along with the schema of the leaves.
+		/// Defaults to `0`, i.e. the leaves are stored untagged.
+		const LEAF_TYPE_ID: u16 = 0;
+
+		/// Commit to the leaves with [`Config::CrossChainCommitment`], rather than with their
+		/// own commitment (i.e. the hash of their compact encoding, see
+		/// [`primitives::FullLeaf::compact_commitment`]).
+		///
+		/// Meant for MMRs verified outside of Substrate (e.g. by an EVM contract), which can
+		/// then reconstruct the leaf hashes from a verifier-friendly preimage. The commitment
+		/// applies to the whole MMR (its root, the proofs and the digest), so it has to be set
+		/// from the first leaf on. Stateless verifiers have to be given the leaves wrapped in
+		/// a [`primitives::CommittedLeaf`] (or their commitments). Defaults to `false`.
+		const CROSS_CHAIN_COMMITMENT: bool = false;
+
+		/// The encoding of the leaves written to the Off-chain DB.
+		///
+		/// With [`primitives::LeafEncoding::FixedWidth`] every leaf value (including its type id,
+		/// see [`Config::LEAF_TYPE_ID`], and the node encoding, but not the checksum, see
+		/// [`Config::OFFCHAIN_CHECKSUM`]) is zero-padded to the same width, so that external DBs
+		/// can store the leaves as fixed-size records. This trades the wasted padding for a
+		/// predictable layout. Leaves which don't fit the width are not appended at all. Defaults
+		/// to [`primitives::LeafEncoding::Scale`], i.e. no padding.
+		const LEAF_ENCODING: primitives::LeafEncoding = primitives::LeafEncoding::Scale;
+
+		/// The maximum number of leaves appended to the MMR in a single block.
+		///
+		/// This bounds the work of committing the leaves of a block, possibly over several
+		/// commits. The leaves appended in the current block are counted from
+		/// [`BlockFirstLeaf`], and a commit exceeding the limit fails with
+		/// [`primitives::Error::TooManyLeavesInBlock`], with none of its leaves appended. The
+		/// leaves added at genesis (see [`GenesisConfig::leaves`]) are not limited. Defaults to
+		/// `None`, i.e. no limit.
+		const MAX_LEAVES_PER_BLOCK: Option<u32> = None;
+
+		/// The declared schema of the leaves: its version and the fingerprint of the leaf type
+		/// at that version (see [`primitives::leaf_schema_fingerprint`]).
+		///
+		/// Changing the leaf format (e.g. reordering the composed leaf data providers) in a
+		/// runtime upgrade changes the hashes of all the future leaves, breaking the continuity
+		/// of the verification. Declaring the schema makes [`Pallet::check_leaf_schema`] fail
+		/// on such changes, until the schema version is bumped explicitly. Runtimes are expected
+		/// to run the check in their tests. Defaults to `None`, i.e. no schema is declared.
+		const LEAF_SCHEMA: Option<(u32, primitives::LeafSchemaFingerprint)> = None;
+
+		/// Whether the storage of the MMR should return errors instead of panicking on misuse.
+		///
+		/// Appending to the MMR in the off-chain context is a programming error, which panics by
+		/// default. If set, it's reported as an error by the storage instead, for runtimes which
+		/// must never panic. The same goes for the [`GenesisConfig::leaves`] which can't be
+		/// appended (they're logged and skipped instead) and, in debug builds, for the leaves
+		/// whose precomputed hash doesn't match (see
+		/// [`primitives::LeafDataProvider::leaf_data_with_hash`]), which are not appended.
+		const STRICT_NO_PANIC: bool = false;
+
+		/// Whether the nodes written to the Off-chain DB are keyed by the parent hash of the
+		/// block adding them, on top of their position.
+		///
+		/// Blocks of competing forks add different nodes at the same positions, so by default
+		/// the nodes are indexed under fork-aware keys, and moved to position-only (canonical)
+		/// keys once finalized by the MMR client gadget. If unset, all the nodes are written and
+		/// read under their canonical key right away instead, which saves the lookups of the
+		/// block hashes and keeps the keys predictable for external tools. Proofs can't be
+		/// generated for other forks then (see [`primitives::ForkView`]).
+		///
+		/// This is only safe if the runtime never executes blocks which don't end up finalized
+		/// (e.g. chains with instant finality, importing blocks only once they are final), as
+		/// the nodes of different forks would overwrite each other otherwise. Changing it on a
+		/// live chain is not supported: no migration of the already written nodes is done.
+		/// Defaults to `true`.
+		const FORK_SAFE_KEYS: bool = true;
+
+		/// The overarching event type.
+		type RuntimeEvent: From<Event<Self, I>>
+			+ IsType<<Self as frame_system::Config>::RuntimeEvent>;
+
 		/// A hasher type for MMR.
 		///
 		/// To construct trie nodes that result in merging (bagging) two peaks, depending on the
@@ -184,14 +518,76 @@ pub mod pallet {
 		/// writes are identical).
 		type LeafData: primitives::LeafDataProvider;
 
+		/// A transformation of the [`Config::LeafData`] before it's appended to the MMR.
+		///
+		/// The transformed leaf is what gets stored, hashed and returned in proofs. Use `()` to
+		/// store the leaf data as is.
+		type LeafTransform: primitives::LeafTransform<
+			<Self::LeafData as primitives::LeafDataProvider>::LeafData,
+		>;
+
+		/// A policy deciding whether the new leaves (as returned by [`Config::LeafTransform`])
+		/// get appended to the MMR.
+		///
+		/// A rejected leaf is not appended at all, so the block doesn't add a leaf to the MMR
+		/// (which is accounted for by [`Pallet::leaves_added_in_block`]). Validator complexity
+		/// should be `O(1)`. Use `()` to accept all the leaves.
+		type LeafValidator: primitives::LeafValidator<
+			<Self::LeafTransform as primitives::LeafTransform<
+				<Self::LeafData as primitives::LeafDataProvider>::LeafData,
+			>>::Leaf,
+		>;
+
+		/// The commitment to the leaves used if [`Config::CROSS_CHAIN_COMMITMENT`] is set.
+		///
+		/// Use [`primitives::OwnCommitment`] if the leaf type defines its commitment (see
+		/// [`primitives::CompactCommitment`]), or `()` if the leaves are committed to with their
+		/// encoding anyway.
+		type CrossChainCommitment: primitives::LeafCommitment<
+			<Self::LeafTransform as primitives::LeafTransform<
+				<Self::LeafData as primitives::LeafDataProvider>::LeafData,
+			>>::Leaf,
+		>;
+
 		/// A hook to act on the new MMR root.
 		///
 		/// For some applications it might be beneficial to make the MMR root available externally
 		/// apart from having it in the storage. For instance you might output it in the header
 		/// digest (see [`frame_system::Pallet::deposit_log`]) to make it available for Light
 		/// Clients. Hook complexity should be `O(1)`.
+		///
+		/// Note the hook is given the root commitment produced by [`Config::RootWrapper`].
 		type OnNewRoot: primitives::OnNewRoot<<Self as Config<I>>::Hash>;
 
+		/// A post-processing step producing the commitment to the MMR root.
+		///
+		/// The commitment (e.g. a hash of the root along with the next BEEFY authority set) is
+		/// what gets passed to [`Config::OnNewRoot`], and is available via
+		/// [`Pallet::mmr_root_commitment`]. The raw MMR root, which the proofs are verified
+		/// against, is still kept in [`RootHash`]. Use `()` to commit to the raw root.
+		type RootWrapper: primitives::RootWrapper<<Self as Config<I>>::Hash>;
+
+		/// A hook to act on the growth of the MMR.
+		///
+		/// Called every time nodes get appended to the MMR, so that e.g. other pallets can keep a
+		/// secondary index of it up to date without relying on events. Hook complexity should be
+		/// `O(1)`.
+		type OnAppend: primitives::OnAppend;
+
+		/// Whether the MMR nodes should be pushed to the Off-chain DB via Indexing API.
+		///
+		/// Proofs can't be generated for the leaves added while the indexing was disabled.
+		/// The pallet keeps track of these leaves (see [`NotIndexedLeaves`]), so that proof
+		/// generation can report them with [`primitives::Error::LeafNotIndexed`].
+		type IndexingEnabled: Get<bool>;
+
+		/// The origin allowed to note the finalized MMR roots (see
+		/// [`Pallet::note_finalized_root`]).
+		///
+		/// The runtime can't observe finality, so the origin is trusted to only note finalized
+		/// blocks, e.g. `EnsureRoot` or an origin of the finality gadget's authorities.
+		type FinalizedRootOrigin: EnsureOrigin<<Self as frame_system::Config>::RuntimeOrigin>;
+
 		/// Weights for this pallet.
 		type WeightInfo: WeightInfo;
 	}
@@ -202,6 +598,22 @@ pub mod pallet {
 	pub type RootHash<T: Config<I>, I: 'static = ()> =
 		StorageValue<_, <T as Config<I>>::Hash, ValueQuery>;
 
+	/// Hashes of the current peaks (ordered by position) of the MMR with the given number of
+	/// leaves, from which [`RootHash`] is bagged.
+	///
+	/// They are updated incrementally on append, replacing the peaks merged by the new nodes
+	/// with the new ones, so the other peaks don't have to be read from [`Nodes`]. They are
+	/// dropped whenever the peaks in [`Nodes`] are written directly (e.g. by
+	/// [`Pallet::force_set_leaf_count`]) and rebuilt from [`Nodes`] when they're missing or
+	/// not for the current number of leaves. Note the peaks are bagged right to left, so the
+	/// merges to the root are all redone, only the reads of the unchanged peaks are saved.
+	#[pallet::storage]
+	pub type PeakHashes<T: Config<I>, I: 'static = ()> = StorageValue<
+		_,
+		(LeafIndex, BoundedVec<<T as Config<I>>::Hash, MaxPeaks<T, I>>),
+		OptionQuery,
+	>;
+
 	/// Current size of the MMR (number of leaves).
 	#[pallet::storage]
 	#[pallet::getter(fn mmr_leaves)]
@@ -210,43 +622,546 @@ pub mod pallet {
 	/// Hashes of the nodes in the MMR.
 	///
 	/// Note this collection only contains MMR peaks, the inner nodes (and leaves)
-	/// are pruned and only stored in the Offchain DB.
+	/// are pruned and only stored in the Offchain DB (unless [`Config::STORE_ALL_NODES`] is set).
 	#[pallet::storage]
 	#[pallet::getter(fn mmr_peak)]
 	pub type Nodes<T: Config<I>, I: 'static = ()> =
 		StorageMap<_, Identity, NodeIndex, <T as Config<I>>::Hash, OptionQuery>;
 
-	#[pallet::hooks]
-	impl<T: Config<I>, I: 'static> Hooks<BlockNumberFor<T>> for Pallet<T, I> {
-		fn on_initialize(_n: T::BlockNumber) -> Weight {
-			use primitives::LeafDataProvider;
-			let leaves = Self::mmr_leaves();
-			let peaks_before = sp_mmr_primitives::utils::NodesUtils::new(leaves).number_of_peaks();
-			let data = T::LeafData::leaf_data();
-
-			// append new leaf to MMR
-			let mut mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> = mmr::Mmr::new(leaves);
-			// MMR push never fails, but better safe than sorry.
-			if mmr.push(data).is_none() {
-				log::error!(target: "runtime::mmr", "MMR push failed");
-				return T::WeightInfo::on_initialize(peaks_before)
+	/// Ring buffer of recent parent block numbers and hashes.
+	///
+	/// The entry for block `n` is kept under the `n % PARENT_HASH_RETENTION` slot,
+	/// see [`Config::PARENT_HASH_RETENTION`].
+	#[pallet::storage]
+	pub type ParentHashes<T: Config<I>, I: 'static = ()> = StorageMap<
+		_,
+		Twox64Concat,
+		u32,
+		(T::BlockNumber, <T as frame_system::Config>::Hash),
+		OptionQuery,
+	>;
+
+	/// Total size (in bytes) of the encoded nodes pushed to the Off-chain DB.
+	///
+	/// Only tracked if [`Config::TRACK_OFFCHAIN_SIZE`] is set. Note the size of the keys is not
+	/// included, and neither is the size of the nodes written to the Off-chain DB directly
+	/// (e.g. by [`Pallet::reindex_offchain`]).
+	#[pallet::storage]
+	pub type TotalOffchainBytes<T, I = ()> = StorageValue<_, u64, ValueQuery>;
+
+	/// Ranges of leaves added while the Off-chain indexing was disabled.
+	///
+	/// The first and the last (inclusive) leaf index of each range, sorted by the first one.
+	/// At most [`Config::MAX_NOT_INDEXED_RANGES`] ranges are kept.
+	#[pallet::storage]
+	pub type NotIndexedLeaves<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, BoundedVec<(LeafIndex, LeafIndex), MaxNotIndexedRanges<T, I>>, ValueQuery>;
+
+	/// Ring buffer of the MMR roots at the end of the recent blocks.
+	///
+	/// The entry for block `n` is kept under the `n % MAX_FINALIZED_ROOTS` slot,
+	/// see [`Config::MAX_FINALIZED_ROOTS`].
+	#[pallet::storage]
+	pub type RecentRoots<T: Config<I>, I: 'static = ()> =
+		StorageMap<_, Twox64Concat, u32, (T::BlockNumber, <T as Config<I>>::Hash), OptionQuery>;
+
+	/// MMR roots as of the finalized blocks, keyed by the block number.
+	///
+	/// Populated by [`Pallet::note_finalized_root`], only the latest
+	/// [`Config::MAX_FINALIZED_ROOTS`] entries are kept.
+	#[pallet::storage]
+	#[pallet::getter(fn finalized_root)]
+	pub type FinalizedRoots<T: Config<I>, I: 'static = ()> =
+		StorageMap<_, Twox64Concat, T::BlockNumber, <T as Config<I>>::Hash, OptionQuery>;
+
+	/// Block numbers of the [`FinalizedRoots`] entries, in ascending order.
+	#[pallet::storage]
+	pub type FinalizedRootNumbers<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, BoundedVec<T::BlockNumber, MaxFinalizedRoots<T, I>>, ValueQuery>;
+
+	/// Peaks pruned from [`Nodes`], keyed by their position, along with the number of the
+	/// block which pruned them.
+	///
+	/// Only the latest [`Config::MAX_PEAK_HISTORY`] pruned peaks are kept.
+	#[pallet::storage]
+	pub type HistoricalPeaks<T: Config<I>, I: 'static = ()> =
+		StorageMap<_, Identity, NodeIndex, (T::BlockNumber, <T as Config<I>>::Hash), OptionQuery>;
+
+	/// Positions of the [`HistoricalPeaks`] entries, in the order they were pruned.
+	#[pallet::storage]
+	pub type HistoricalPeakPositions<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, BoundedVec<NodeIndex, MaxPeakHistory<T, I>>, ValueQuery>;
+
+	/// Number of leaves appended to the MMR at genesis (see [`GenesisConfig`]).
+	///
+	/// These leaves were not added by any block, so they are excluded from the leaf index to
+	/// block number mapping.
+	#[pallet::storage]
+	#[pallet::getter(fn genesis_leaves)]
+	pub type GenesisLeaves<T, I = ()> = StorageValue<_, LeafIndex, ValueQuery>;
+
+	/// Index of the first leaf appended by the given block, i.e. the number of leaves at its
+	/// start.
+	///
+	/// Kept for the last block which appended leaves, it's replaced by the first append of every
+	/// block (noting the leaves of the previous one in [`LeafCountCheckpoints`]).
+	#[pallet::storage]
+	pub type BlockFirstLeaf<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, (T::BlockNumber, LeafIndex), OptionQuery>;
+
+	/// Number of leaves of the MMR at the start of the given blocks, in ascending order.
+	///
+	/// Blocks usually append a single leaf each, so only the blocks where this doesn't hold
+	/// (e.g. the block after one which appended no leaf or several leaves) are noted. The
+	/// leaves of any other block are then derived from the closest entry. At most
+	/// [`MAX_LEAF_COUNT_CHECKPOINTS`] entries are kept, evicting the oldest ones (see
+	/// [`FirstMappedBlock`]).
+	#[pallet::storage]
+	pub type LeafCountCheckpoints<T: Config<I>, I: 'static = ()> = StorageValue<
+		_,
+		BoundedVec<(T::BlockNumber, LeafIndex), ConstU32<MAX_LEAF_COUNT_CHECKPOINTS>>,
+		ValueQuery,
+	>;
+
+	/// The first block whose leaves are known, set once [`LeafCountCheckpoints`] were evicted.
+	///
+	/// The leaves of the older blocks (except the genesis one) can't be told anymore.
+	#[pallet::storage]
+	pub type FirstMappedBlock<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, T::BlockNumber, OptionQuery>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config<I>, I: 'static = ()> {
+		/// Leaves to seed the MMR with, e.g. inherited from a predecessor chain.
+		///
+		/// Only the peaks (or all the nodes, see [`Config::STORE_ALL_NODES`]) are stored
+		/// on-chain. The Off-chain DB isn't available at genesis, so the full nodes of these
+		/// leaves are not indexed: until they are rebuilt with [`Pallet::reindex_offchain`]
+		/// (from the same leaves, e.g. taken from the chain spec), the proofs requiring them
+		/// can't be generated, except for [`Pallet::generate_onchain_proof`] if all the nodes
+		/// are stored on-chain.
+		#[cfg_attr(
+			feature = "std",
+			serde(
+				default,
+				bound(
+					serialize = "LeafOf<T, I>: frame_support::Serialize",
+					deserialize = "LeafOf<T, I>: frame_support::Deserialize<'de>",
+				)
+			)
+		)]
+		pub leaves: Vec<LeafOf<T, I>>,
+		pub phantom: PhantomData<I>,
+	}
+
+	#[cfg(feature = "std")]
+	impl<T: Config<I>, I: 'static> Default for GenesisConfig<T, I> {
+		fn default() -> Self {
+			Self { leaves: Default::default(), phantom: Default::default() }
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config<I>, I: 'static> GenesisBuild<T, I> for GenesisConfig<T, I>
+	where
+		LeafOf<T, I>: MaybeSerializeDeserialize,
+	{
+		fn build(&self) {
+			if self.leaves.is_empty() {
+				return
+			}
+
+			// The leaves are pushed one by one, so a push only fails for leaves not fitting the
+			// `LEAF_ENCODING`.
+			let mut mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> = mmr::Mmr::new(0);
+			for (index, leaf) in self.leaves.iter().enumerate() {
+				if mmr.push(NodeLeafOf::<T, I>::new(leaf.clone())).is_none() {
+					if !T::STRICT_NO_PANIC {
+						panic!("Genesis MMR leaf {} can't be pushed", index);
+					}
+					log::error!(target: "runtime::mmr", "Genesis MMR leaf {} skipped", index);
+				}
 			}
-			// Update the size, `mmr.finalize()` should also never fail.
 			let (leaves, root) = match mmr.finalize() {
 				Ok((leaves, root)) => (leaves, root),
-				Err(e) => {
-					log::error!(target: "runtime::mmr", "MMR finalize failed: {:?}", e);
-					return T::WeightInfo::on_initialize(peaks_before)
+				Err(e) if T::STRICT_NO_PANIC => {
+					log::error!(
+						target: "runtime::mmr",
+						"Genesis MMR finalize failed, leaves skipped: {:?}", e
+					);
+					return
 				},
+				Err(e) => panic!("Genesis MMR finalize failed: {:?}", e),
 			};
-			<T::OnNewRoot as primitives::OnNewRoot<_>>::on_new_root(&root);
 
 			<NumberOfLeaves<T, I>>::put(leaves);
 			<RootHash<T, I>>::put(root);
+			<GenesisLeaves<T, I>>::put(leaves);
+		}
+	}
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config<I>, I: 'static = ()> {
+		/// Rebuilding the Off-chain DB entries of the leaves `from..=to` was requested.
+		ReindexRequested { from: LeafIndex, to: LeafIndex },
+		/// The state of the MMR was reported: its `root`, number of `leaves` and `size`.
+		MmrState { root: <T as Config<I>>::Hash, leaves: LeafIndex, size: NodeIndex },
+	}
+
+	#[pallet::error]
+	pub enum Error<T, I = ()> {
+		/// Force-setting the number of leaves is disabled (see [`Config::ALLOW_FORCE_RESET`]).
+		ForceResetNotAllowed,
+		/// The requested number of leaves exceeds the current number of leaves.
+		LeafCountTooLarge,
+		/// Some of the peaks of the truncated MMR are not stored on-chain.
+		MissingPeak,
+		/// The requested range of leaves is empty or exceeds the current number of leaves.
+		InvalidLeafRange,
+		/// The MMR has more peaks than allowed by [`Config::MAX_LEAVES`].
+		TooManyPeaks,
+		/// The node is not a peak of the current MMR.
+		NotAPeak,
+		/// The peak is already stored on-chain.
+		PeakNotMissing,
+		/// The repaired peaks don't reproduce the expected MMR root.
+		RootMismatch,
+		/// The number of the given peaks doesn't match the current MMR.
+		PeakCountMismatch,
+		/// Keeping finalized roots is disabled (see [`Config::MAX_FINALIZED_ROOTS`]).
+		FinalizedRootsDisabled,
+		/// A root of the same or a later block was already noted as finalized.
+		StaleFinalizedBlock,
+		/// The root at the end of the block is not known (anymore).
+		UnknownBlockRoot,
+		/// Clearing the MMR is disabled (see [`Config::ALLOW_CLEAR`]).
+		ClearNotAllowed,
+		/// The witness data is lower than the actual number of the entries to remove.
+		BadWitness,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config<I>, I: 'static> Hooks<BlockNumberFor<T>> for Pallet<T, I> {
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let weight = if T::COMMIT_IN_FINALIZE {
+				// The leaf is appended in `on_finalize`, account for the worst case upfront.
+				let peaks = NodesUtils::new(Self::mmr_leaves()).number_of_peaks();
+				Self::append_weight(peaks.saturating_add(1))
+			} else {
+				Self::append_leaf(n)
+			};
+			if T::MAX_FINALIZED_ROOTS > 0 {
+				// The root of the block is kept in `on_finalize`.
+				return weight.saturating_add(T::DbWeight::get().reads_writes(1, 1))
+			}
+			weight
+		}
+
+		fn on_finalize(n: T::BlockNumber) {
+			if T::COMMIT_IN_FINALIZE && <frame_system::Pallet<T>>::extrinsic_count() > 0 {
+				Self::append_leaf(n);
+			}
+			if T::MAX_FINALIZED_ROOTS > 0 {
+				<RecentRoots<T, I>>::insert(Self::recent_root_slot(n), (n, Self::mmr_root_hash()));
+			}
+		}
+
+		fn offchain_worker(n: T::BlockNumber) {
+			if let Some(retention) = T::OFFCHAIN_RETENTION_BLOCKS {
+				Self::note_fork_leaves_offchain(n);
+				Self::prune_offchain(retention);
+			}
+		}
+	}
+
+	#[pallet::call]
+	impl<T: Config<I>, I: 'static> Pallet<T, I> {
+		/// Force-set the number of leaves in the MMR to `new_count`, truncating the MMR.
+		///
+		/// This is a DANGEROUS disaster recovery tool, only available if
+		/// [`Config::ALLOW_FORCE_RESET`] is set. The origin must be root.
+		///
+		/// The `new_count` can't exceed the current number of leaves and all the peaks of the
+		/// truncated MMR have to be present in [`Nodes`] (which is always the case when
+		/// [`Config::STORE_ALL_NODES`] is set) or in [`HistoricalPeaks`], from which they are
+		/// restored. The on-chain nodes which are not part of the truncated MMR (or are not its
+		/// peaks, in the default pruning mode) are removed and the root is recomputed from the
+		/// remaining peaks. The ranges of [`NotIndexedLeaves`] and the mapping of the blocks to the
+		/// leaves (see [`LeafCountCheckpoints`]) are truncated as well.
+		///
+		/// The `removed_nodes` and `not_indexed_ranges` witnesses bound the number of the
+		/// on-chain nodes to remove and of the [`NotIndexedLeaves`] entries, they're checked
+		/// against the storage and the unused weight is refunded.
+		///
+		/// Note the Off-chain DB is not touched, the nodes of the removed leaves will be
+		/// overwritten by the subsequent blocks.
+		#[pallet::call_index(0)]
+		#[pallet::weight(Pallet::<T, I>::force_set_leaf_count_weight(
+			*removed_nodes,
+			*not_indexed_ranges
+		))]
+		pub fn force_set_leaf_count(
+			origin: OriginFor<T>,
+			new_count: LeafIndex,
+			removed_nodes: NodeIndex,
+			not_indexed_ranges: u32,
+		) -> DispatchResultWithPostInfo {
+			ensure_root(origin)?;
+			ensure!(T::ALLOW_FORCE_RESET, Error::<T, I>::ForceResetNotAllowed);
+			ensure!(new_count <= Self::mmr_leaves(), Error::<T, I>::LeafCountTooLarge);
+			let to_remove = Self::nodes_to_remove(new_count);
+			let ranges_count = <NotIndexedLeaves<T, I>>::decode_len().unwrap_or(0) as u32;
+			let nodes_count = to_remove.len() as NodeIndex;
+			ensure!(
+				nodes_count <= removed_nodes && ranges_count <= not_indexed_ranges,
+				Error::<T, I>::BadWitness
+			);
+			let peaks = Self::peaks(new_count)
+				.into_iter()
+				.map(|pos| match <Nodes<T, I>>::get(pos) {
+					Some(hash) => Some((pos, hash, false)),
+					None => <HistoricalPeaks<T, I>>::get(pos).map(|(_, hash)| (pos, hash, true)),
+				})
+				.collect::<Option<Vec<_>>>()
+				.ok_or(Error::<T, I>::MissingPeak)?;
+
+			for pos in &to_remove {
+				Nodes::<T, I>::remove(pos);
+			}
+			// The pruned peaks of the truncated MMR are its peaks again.
+			let restored = peaks
+				.into_iter()
+				.filter(|(_, _, from_history)| *from_history)
+				.map(|(pos, hash, _)| {
+					Nodes::<T, I>::insert(pos, hash);
+					pos
+				})
+				.collect::<Vec<_>>();
+			// The cached peaks are rebuilt from the truncated MMR.
+			<PeakHashes<T, I>>::kill();
+			// The nodes beyond the truncated MMR are going to be replaced.
+			if T::MAX_PEAK_HISTORY > 0 {
+				let size = NodesUtils::new(new_count).size();
+				<HistoricalPeakPositions<T, I>>::mutate(|positions| {
+					positions.retain(|pos| {
+						let keep = *pos < size && !restored.contains(pos);
+						if !keep {
+							<HistoricalPeaks<T, I>>::remove(pos);
+						}
+						keep
+					})
+				});
+			}
+			// The leaf indices beyond the truncated MMR are going to be reused.
+			<NotIndexedLeaves<T, I>>::mutate(|ranges| {
+				ranges.retain(|(start, _)| *start < new_count);
+				if let Some((_, end)) = ranges.last_mut() {
+					*end = (*end).min(new_count.saturating_sub(1));
+				}
+			});
+			// The remaining leaves stay with their blocks, while the subsequent blocks add the
+			// leaves from `new_count` on.
+			Self::truncate_block_leaves(new_count);
+
+			let root = if new_count == 0 {
+				Default::default()
+			} else {
+				let mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> = mmr::Mmr::new(new_count);
+				let (_, root) = mmr.finalize().map_err(|_| Error::<T, I>::MissingPeak)?;
+				root
+			};
+			Self::notify_new_root(&root);
+
+			log::warn!(
+				target: "runtime::mmr",
+				"Number of leaves force-set from {} to {}", Self::mmr_leaves(), new_count
+			);
+			<NumberOfLeaves<T, I>>::put(new_count);
+			<GenesisLeaves<T, I>>::mutate(|genesis_leaves| {
+				*genesis_leaves = (*genesis_leaves).min(new_count)
+			});
+			<RootHash<T, I>>::put(root);
+
+			Ok(Some(Self::force_set_leaf_count_weight(nodes_count, ranges_count)).into())
+		}
 
-			let peaks_after = sp_mmr_primitives::utils::NodesUtils::new(leaves).number_of_peaks();
+		/// Request rebuilding the Off-chain DB entries of the leaves `from..=to`.
+		///
+		/// This only emits [`Event::ReindexRequested`], the actual work is up to the Offchain
+		/// Workers (or external tools) observing the event, see [`Pallet::reindex_offchain`].
+		/// It provides an auditable way to trigger the recovery of a corrupted Off-chain DB.
+		/// The origin must be root.
+		#[pallet::call_index(1)]
+		#[pallet::weight(T::WeightInfo::request_reindex())]
+		pub fn request_reindex(
+			origin: OriginFor<T>,
+			from: LeafIndex,
+			to: LeafIndex,
+		) -> DispatchResult {
+			ensure_root(origin)?;
+			ensure!(from <= to && to < Self::mmr_leaves(), Error::<T, I>::InvalidLeafRange);
+
+			Self::deposit_event(Event::ReindexRequested { from, to });
+			Ok(())
+		}
+
+		/// Restore the peak `pos` of the MMR, which went missing from [`Nodes`], with `hash`.
+		///
+		/// This is a disaster recovery tool, only available if [`Config::ALLOW_FORCE_RESET`] is
+		/// set. The origin must be root.
+		///
+		/// The Off-chain DB can't be read on-chain, so the `hash` has to be recovered off-chain
+		/// first, e.g. with [`Pallet::offchain_node_hash`]. It's only accepted if bagging it with
+		/// the other peaks reproduces the current [`RootHash`], hence the repair can't alter
+		/// the MMR.
+		#[pallet::call_index(2)]
+		#[pallet::weight(T::WeightInfo::repair_peak())]
+		pub fn repair_peak(
+			origin: OriginFor<T>,
+			pos: NodeIndex,
+			hash: <T as Config<I>>::Hash,
+		) -> DispatchResult {
+			ensure_root(origin)?;
+			ensure!(T::ALLOW_FORCE_RESET, Error::<T, I>::ForceResetNotAllowed);
+			let leaves = Self::mmr_leaves();
+			let size = NodesUtils::new(leaves).size();
+			ensure!(NodesUtils::is_peak(pos, size), Error::<T, I>::NotAPeak);
+			ensure!(!Nodes::<T, I>::contains_key(pos), Error::<T, I>::PeakNotMissing);
+
+			// The call is transactional, the peak is removed again if the root doesn't match.
+			Nodes::<T, I>::insert(pos, hash);
+			// The cached peaks would still give the current root, bag the peaks in `Nodes`.
+			<PeakHashes<T, I>>::kill();
+			let mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> = mmr::Mmr::new(leaves);
+			let (_, root) = mmr.finalize().map_err(|_| Error::<T, I>::MissingPeak)?;
+			ensure!(root == Self::mmr_root_hash(), Error::<T, I>::RootMismatch);
+
+			log::warn!(target: "runtime::mmr", "Peak {} repaired", pos);
+			Ok(())
+		}
+
+		/// Overwrite all the peaks of the MMR in [`Nodes`] with `peaks`.
+		///
+		/// This is the last-resort recovery tool for multiple corrupted peaks, only available if
+		/// [`Config::ALLOW_FORCE_RESET`] is set. The origin must be root.
+		///
+		/// The `peaks` (in ascending position order) have to be recovered off-chain first, e.g.
+		/// with [`Pallet::offchain_peaks`]. They're only accepted if bagging them reproduces the
+		/// `expected_root`, which has to be the current [`RootHash`], otherwise nothing is
+		/// changed. Note the non-peak nodes stored with [`Config::STORE_ALL_NODES`] are not
+		/// touched.
+		#[pallet::call_index(3)]
+		#[pallet::weight(T::WeightInfo::rebuild_onchain_peaks())]
+		pub fn rebuild_onchain_peaks(
+			origin: OriginFor<T>,
+			peaks: BoundedVec<<T as Config<I>>::Hash, MaxPeaks<T, I>>,
+			expected_root: <T as Config<I>>::Hash,
+		) -> DispatchResult {
+			ensure_root(origin)?;
+			ensure!(T::ALLOW_FORCE_RESET, Error::<T, I>::ForceResetNotAllowed);
+			ensure!(expected_root == Self::mmr_root_hash(), Error::<T, I>::RootMismatch);
+			let leaves = Self::mmr_leaves();
+			let positions = Self::peaks(leaves);
+			ensure!(positions.len() == peaks.len(), Error::<T, I>::PeakCountMismatch);
+
+			let root = Self::bag_peaks(peaks.to_vec())
+				.ok()
+				.flatten()
+				.ok_or(Error::<T, I>::MissingPeak)?;
+			ensure!(root == expected_root, Error::<T, I>::RootMismatch);
+
+			for (pos, hash) in positions.into_iter().zip(peaks) {
+				Nodes::<T, I>::insert(pos, hash);
+			}
+			// The cached peaks are rebuilt from the new ones.
+			<PeakHashes<T, I>>::kill();
+
+			log::warn!(target: "runtime::mmr", "Peaks of {} leaves rebuilt", leaves);
+			Ok(())
+		}
+
+		/// Note the MMR root at the end of the finalized block `block_number`.
+		///
+		/// The root is kept in [`FinalizedRoots`], giving relayers an authoritative history of
+		/// finalized roots. Only available if [`Config::MAX_FINALIZED_ROOTS`] is set.
+		///
+		/// The runtime can't observe finality, so the origin must be the
+		/// [`Config::FinalizedRootOrigin`], trusted to follow the finalized blocks. The root
+		/// itself is taken from [`RecentRoots`], so the block has to be noted within
+		/// [`Config::MAX_FINALIZED_ROOTS`] blocks. The blocks have to be noted in ascending
+		/// order.
+		#[pallet::call_index(4)]
+		#[pallet::weight(T::WeightInfo::note_finalized_root())]
+		pub fn note_finalized_root(
+			origin: OriginFor<T>,
+			block_number: T::BlockNumber,
+		) -> DispatchResult {
+			T::FinalizedRootOrigin::ensure_origin(origin)?;
+			let root = Self::finalized_root_to_note(block_number)?;
+
+			<FinalizedRootNumbers<T, I>>::mutate(|numbers| {
+				if numbers.is_full() {
+					<FinalizedRoots<T, I>>::remove(numbers.remove(0));
+				}
+				numbers.try_push(block_number).expect("An entry was removed if full; qed");
+			});
+			<FinalizedRoots<T, I>>::insert(block_number, root);
+			Ok(())
+		}
+
+		/// Remove all the leaves from the MMR, so that it starts over as an empty MMR.
+		///
+		/// This is a maintenance tool for development and test networks, only available if
+		/// [`Config::ALLOW_CLEAR`] is set. The origin must be root.
+		///
+		/// All the on-chain nodes are removed and the root is reset to the one of the empty
+		/// MMR, which is passed to [`Config::OnNewRoot`]. The roots and parent hashes kept for
+		/// the past blocks (see [`RecentRoots`], [`FinalizedRoots`] and [`ParentHashes`]), the
+		/// [`LeafCountCheckpoints`] and the [`TotalOffchainBytes`] are cleared as well. Note the
+		/// Off-chain DB is not touched, the nodes of the removed leaves will be overwritten by
+		/// the subsequent blocks (see also [`Config::OFFCHAIN_RETENTION_BLOCKS`]).
+		///
+		/// The `removed_nodes` and `not_indexed_ranges` witnesses bound the number of the
+		/// on-chain nodes and of the [`NotIndexedLeaves`] entries, they're checked against the
+		/// storage and the unused weight is refunded.
+		#[pallet::call_index(5)]
+		#[pallet::weight(Pallet::<T, I>::clear_mmr_weight(*removed_nodes, *not_indexed_ranges))]
+		pub fn clear_mmr(
+			origin: OriginFor<T>,
+			removed_nodes: NodeIndex,
+			not_indexed_ranges: u32,
+		) -> DispatchResultWithPostInfo {
+			ensure_root(origin)?;
+			ensure!(T::ALLOW_CLEAR, Error::<T, I>::ClearNotAllowed);
+			let nodes_count = Self::nodes_to_remove(0).len() as NodeIndex;
+			let ranges_count = <NotIndexedLeaves<T, I>>::decode_len().unwrap_or(0) as u32;
+			ensure!(
+				nodes_count <= removed_nodes && ranges_count <= not_indexed_ranges,
+				Error::<T, I>::BadWitness
+			);
 
-			T::WeightInfo::on_initialize(peaks_before.max(peaks_after))
+			log::warn!(target: "runtime::mmr", "Clearing {} leaves", Self::mmr_leaves());
+			Self::clear_onchain_mmr();
+			Ok(Some(Self::clear_mmr_weight(nodes_count, ranges_count)).into())
+		}
+
+		/// Report the current state of the MMR with [`Event::MmrState`].
+		///
+		/// This is a read-only diagnostic, surfacing the MMR state on-chain for chains without
+		/// RPC access. Any signed origin can call it.
+		#[pallet::call_index(6)]
+		#[pallet::weight(T::WeightInfo::report_state())]
+		pub fn report_state(origin: OriginFor<T>) -> DispatchResult {
+			ensure_signed(origin)?;
+
+			let leaves = Self::mmr_leaves();
+			Self::deposit_event(Event::MmrState {
+				root: Self::mmr_root_hash(),
+				leaves,
+				size: NodesUtils::new(leaves).size(),
+			});
+			Ok(())
 		}
 	}
 }
@@ -275,88 +1190,1450 @@ where
 	}
 }
 
-impl<T: Config<I>, I: 'static> Pallet<T, I> {
-	/// Build offchain key from `parent_hash` of block that originally added node `pos` to MMR.
-	///
-	/// This combination makes the offchain (key,value) entry resilient to chain forks.
-	fn node_temp_offchain_key(
-		pos: NodeIndex,
-		parent_hash: <T as frame_system::Config>::Hash,
-	) -> sp_std::prelude::Vec<u8> {
-		NodesUtils::node_temp_offchain_key::<<T as frame_system::Config>::Header>(
-			&T::INDEXING_PREFIX,
-			pos,
-			parent_hash,
-		)
+/// Stateless MMR proof verification for batch of leaves, hashing with a `hasher` supplied at
+/// runtime.
+///
+/// Unlike [verify_leaves_proof], this isn't tied to the hashing of a specific chain, so a single
+/// verifier can handle MMRs using different hashings (e.g. BLAKE2 or Keccak) by passing the
+/// matching `hasher`. The leaves are given by their hashes (see [mmr::Node::hash]), e.g. the
+/// `hasher` applied to the SCALE-encoded leaf for plain leaves.
+pub fn verify_leaves_proof_with_hasher<Hash>(
+	hasher: &dyn Fn(&[u8]) -> Hash,
+	root: Hash,
+	leaf_hashes: Vec<Hash>,
+	proof: primitives::Proof<Hash>,
+) -> Result<(), primitives::Error>
+where
+	Hash: AsRef<[u8]> + Clone + PartialEq + sp_std::fmt::Debug,
+{
+	let is_valid = mmr::verify_leaves_proof_with_hasher(hasher, root.clone(), leaf_hashes, proof)?;
+	if is_valid {
+		Ok(())
+	} else {
+		Err(primitives::Error::Verify.log_debug(("The proof is incorrect.", root)))
 	}
+}
 
-	/// Build canonical offchain key for node `pos` in MMR.
-	///
-	/// Used for nodes added by now finalized blocks.
-	/// Never read keys using `node_canon_offchain_key` unless you sure that
-	/// there's no `node_offchain_key` key in the storage.
-	fn node_canon_offchain_key(pos: NodeIndex) -> sp_std::prelude::Vec<u8> {
+/// Stateless MMR proof verification for batch of leaves, given an `encoded_proof` of any
+/// supported format version (see [primitives::VersionedProof]).
+///
+/// The proof is verified by the routine of its format version, the same way
+/// [verify_leaves_proof] does. Returns [primitives::Error::UnsupportedProofVersion] if the
+/// version of the proof is not known to this verifier.
+pub fn verify_versioned_leaves_proof<H, L>(
+	root: H::Output,
+	leaves: Vec<mmr::Node<H, L>>,
+	encoded_proof: &[u8],
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	let version = *encoded_proof
+		.first()
+		.ok_or_else(|| primitives::Error::Verify.log_debug("Empty proof."))?;
+	if version > primitives::VersionedProof::<H::Output>::LATEST_VERSION {
+		return Err(primitives::Error::UnsupportedProofVersion { version }
+			.log_debug("Unknown proof version."))
+	}
+
+	let proof: primitives::VersionedProof<H::Output> = codec::Decode::decode(&mut &*encoded_proof)
+		.map_err(|e| primitives::Error::Verify.log_debug(("Undecodable proof.", e)))?;
+	match proof {
+		primitives::VersionedProof::V0(proof) => verify_leaves_proof::<H, L>(root, leaves, proof),
+	}
+}
+
+/// Stateless MMR proof verification for batch of leaves, against an MMR of declared `size`.
+///
+/// Same as [verify_leaves_proof], but the verifier provides the size of the MMR it expects the
+/// proof for, either as the number of leaves or the number of nodes. The proof is rejected if
+/// the `size` is not a valid MMR size, or doesn't match the size the proof was generated for.
+pub fn verify_leaves_proof_with_size<H, L>(
+	root: H::Output,
+	leaves: Vec<mmr::Node<H, L>>,
+	proof: primitives::Proof<H::Output>,
+	size: primitives::MmrSize,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	let leaf_count = size
+		.leaf_count()
+		.ok_or_else(|| primitives::Error::Verify.log_debug(("Invalid MMR size.", size)))?;
+	if leaf_count != proof.leaf_count {
+		return Err(primitives::Error::Verify.log_debug((
+			"The proof doesn't match the MMR size.",
+			proof.leaf_count,
+			size,
+		)))
+	}
+
+	verify_leaves_proof::<H, L>(root, leaves, proof)
+}
+
+/// Stateless MMR proof verification for batch of leaves, bounded by a trusted leaf count.
+///
+/// Same as [verify_leaves_proof], but if the verifier (e.g. a light client) only trusts the
+/// MMR up to `trusted_leaf_count` leaves, proofs generated for more leaves are rejected with
+/// [primitives::Error::UntrustedLeafCount] before being verified, since they attest to a state
+/// the verifier hasn't validated. With `None`, the leaf count of the proof is not checked.
+pub fn verify_leaves_proof_with_trusted_leaf_count<H, L>(
+	root: H::Output,
+	leaves: Vec<mmr::Node<H, L>>,
+	proof: primitives::Proof<H::Output>,
+	trusted_leaf_count: Option<LeafIndex>,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	if let Some(trusted) = trusted_leaf_count {
+		if proof.leaf_count > trusted {
+			return Err(primitives::Error::UntrustedLeafCount {
+				leaf_count: proof.leaf_count,
+				trusted,
+			})
+		}
+	}
+
+	verify_leaves_proof::<H, L>(root, leaves, proof)
+}
+
+/// Stateless MMR proof verification for a single leaf, given its positioned proof `items`.
+///
+/// The `items` are pairs of MMR node positions and hashes, as returned by
+/// [Pallet::generate_proof_with_positions] for the leaf `leaf_index` of the MMR with
+/// `leaf_count` leaves. They are converted into a regular [primitives::Proof] and verified the
+/// same way [verify_leaves_proof] does.
+pub fn verify_leaf_proof_with_positions<H, L>(
+	root: H::Output,
+	leaf: mmr::Node<H, L>,
+	leaf_index: LeafIndex,
+	leaf_count: LeafIndex,
+	items: Vec<(NodeIndex, H::Output)>,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	let proof = mmr::proof_from_positioned_items::<H>(leaf_index, leaf_count, items)?;
+	verify_leaves_proof::<H, L>(root, vec![leaf], proof)
+}
+
+/// Stateless MMR proof verification for a contiguous range of leaves.
+///
+/// The `leaves` have to be given in the order of the range (see
+/// [Pallet::generate_range_proof]). The proof is verified the same way [verify_leaves_proof]
+/// does.
+pub fn verify_range_proof<H, L>(
+	root: H::Output,
+	leaves: Vec<mmr::Node<H, L>>,
+	proof: primitives::RangeProof<H::Output>,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	// Checked before expanding the range, which is given by the (untrusted) proof.
+	if proof.last_leaf_index >= proof.leaf_count {
+		return Err(primitives::Error::Verify.log_debug(("Range beyond the MMR.", proof.leaf_count)))
+	}
+	let range_len = proof
+		.last_leaf_index
+		.checked_sub(proof.first_leaf_index)
+		.and_then(|len| len.checked_add(1));
+	if leaves.is_empty() || range_len != Some(leaves.len() as LeafIndex) {
+		return Err(primitives::Error::Verify.log_debug(("Range not matching leaves.", range_len)))
+	}
+	verify_leaves_proof::<H, L>(root, leaves, proof.into())
+}
+
+/// Stateless import of the leaves of a [primitives::PortableMmr] (see
+/// [Pallet::export_portable]).
+///
+/// The MMR is rebuilt from the leaves and the anchoring nodes, and its root has to match the
+/// embedded root. Returns the decoded leaves, e.g. to append them to another MMR. Fails with
+/// [primitives::Error::UnsupportedProofVersion] if the format version is not known, and with
+/// [primitives::Error::Verify] if the export was made with a different hashing than `H` or
+/// doesn't match its root.
+pub fn import_portable<H, L>(
+	portable: primitives::PortableMmr<H::Output>,
+) -> Result<Vec<L>, primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf + codec::Decode,
+{
+	if portable.version != primitives::PORTABLE_MMR_VERSION {
+		return Err(primitives::Error::UnsupportedProofVersion { version: portable.version })
+	}
+	if portable.hashing != H::hash(&[]) {
+		return Err(primitives::Error::Verify.log_debug(("Unexpected hashing", portable.hashing)))
+	}
+
+	let leaves = portable
+		.leaves
+		.iter()
+		.map(|leaf| codec::Decode::decode(&mut &leaf[..]))
+		.collect::<Result<Vec<L>, _>>()
+		.map_err(|e| primitives::Error::Verify.log_debug(e))?;
+	let last_leaf_index = portable
+		.first_leaf_index
+		.checked_add(leaves.len() as LeafIndex)
+		.and_then(|end| end.checked_sub(1))
+		.ok_or_else(|| primitives::Error::Verify.log_debug("No leaves exported"))?;
+	let proof = primitives::RangeProof {
+		first_leaf_index: portable.first_leaf_index,
+		last_leaf_index,
+		leaf_count: portable.leaf_count,
+		items: portable.items,
+	};
+	let nodes = leaves.iter().cloned().map(mmr::Node::Data).collect();
+	verify_range_proof::<H, L>(portable.root, nodes, proof)?;
+	Ok(leaves)
+}
+
+/// Stateless MMR proof verification for a single leaf, against the individual MMR `peaks`.
+///
+/// Meant for verifiers which know the hashes of the peaks (e.g. from separate storage proofs)
+/// rather than the MMR root. The `peaks` are pairs of MMR node positions and hashes. The peak of
+/// the leaf's mountain is reconstructed from the `proof` and has to be one of the `peaks`, the
+/// proof items committing to the other peaks are not checked.
+pub fn verify_against_peaks<H, L>(
+	peaks: &[(NodeIndex, H::Output)],
+	leaf_index: LeafIndex,
+	leaf: mmr::Node<H, L>,
+	proof: primitives::Proof<H::Output>,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	let peak = mmr::reconstruct_leaf_peak::<H, L>(leaf, leaf_index, proof)?;
+	if peaks.contains(&peak) {
+		Ok(())
+	} else {
+		Err(primitives::Error::Verify.log_debug(("The proof doesn't match the peaks.", peak.0)))
+	}
+}
+
+/// A proof of a single MMR leaf, bundled with everything needed to verify it.
+///
+/// Meant to be passed around as a whole (e.g. by relayers), so that the verifier doesn't have
+/// to assemble the leaf, its proof and the claimed MMR root itself. See [Self::verify].
+#[derive(
+	codec::Encode,
+	codec::Decode,
+	sp_runtime::RuntimeDebug,
+	Clone,
+	PartialEq,
+	Eq,
+	scale_info::TypeInfo,
+)]
+pub struct ProofBundle<Hash, L> {
+	/// The leaf being proven.
+	pub leaf: L,
+	/// The index of the leaf in the MMR.
+	pub leaf_index: LeafIndex,
+	/// The proof of the leaf.
+	pub proof: primitives::Proof<Hash>,
+	/// The claimed root of the MMR.
+	pub root: Hash,
+	/// The number of nodes in the MMR the proof was generated for.
+	pub mmr_size: NodeIndex,
+}
+
+impl<Hash, L: primitives::FullLeaf> ProofBundle<Hash, L> {
+	/// Verify the bundle, using `H` as the MMR hasher.
+	///
+	/// Checks that the proof is for the bundled leaf index, that it was generated for an MMR of
+	/// `mmr_size` nodes, and that it reconstructs the claimed root from the leaf (see
+	/// [verify_leaves_proof_with_size]).
+	pub fn verify<H>(&self) -> Result<(), primitives::Error>
+	where
+		H: traits::Hash<Output = Hash>,
+	{
+		if self.proof.leaf_indices != [self.leaf_index] {
+			return Err(primitives::Error::Verify
+				.log_debug(("The proof is not for the bundled leaf.", self.leaf_index)))
+		}
+
+		verify_leaves_proof_with_size::<H, L>(
+			self.root,
+			vec![mmr::Node::Data(self.leaf.clone())],
+			self.proof.clone(),
+			primitives::MmrSize::NodeCount(self.mmr_size),
+		)
+	}
+}
+
+/// Incremental verification of the proof of a single leaf, as its nodes arrive one at a time.
+///
+/// The proof nodes have to be fed (see [Self::feed_node]) with their positions, in the order
+/// given by [Pallet::generate_proof_with_positions]. Only the hash of the node on the path
+/// from the leaf up to its peak and the other peaks are kept, so the whole proof doesn't have to
+/// be buffered. Once all the nodes are fed, the proof is checked against the MMR root with
+/// [Self::finalize].
+pub struct ProofVerifier<H: traits::Hash> {
+	/// Positions of the peaks of the MMR.
+	peaks: Vec<NodeIndex>,
+	/// Positions of the proof nodes which are still expected, in reverse order.
+	expected: Vec<NodeIndex>,
+	/// Position and hash of the node on the path from the leaf up to its peak.
+	node: (NodeIndex, H::Output),
+	/// Hashes of the peaks on the left of the leaf's peak.
+	lhs_peaks: Vec<H::Output>,
+	/// Hashes of the peaks on the right of the leaf's peak.
+	rhs_peaks: Vec<H::Output>,
+	/// Whether an unexpected node was fed.
+	failed: bool,
+}
+
+impl<H: traits::Hash> ProofVerifier<H> {
+	/// Start verifying the proof of `leaf`, at `leaf_index` of the MMR with `leaf_count`
+	/// leaves.
+	pub fn new<L: primitives::FullLeaf>(
+		leaf: L,
+		leaf_index: LeafIndex,
+		leaf_count: LeafIndex,
+	) -> Result<Self, primitives::Error> {
+		if leaf_index >= leaf_count {
+			return Err(primitives::Error::Verify.log_debug((
+				"Leaf index out of range",
+				leaf_index,
+				leaf_count,
+			)))
+		}
+		let utils = NodesUtils::new(leaf_count);
+		let mut expected = utils
+			.proof_node_indices(TypedLeafIndex(leaf_index))
+			.into_iter()
+			.map(|pos| pos.0)
+			.collect::<Vec<_>>();
+		expected.reverse();
+		let leaf_pos = primitives::mmr_lib::leaf_index_to_pos(leaf_index);
+		Ok(Self {
+			peaks: primitives::mmr_lib::helper::get_peaks(utils.size()),
+			expected,
+			node: (leaf_pos, mmr::Node::<H, L>::Data(leaf).hash()),
+			lhs_peaks: Vec::new(),
+			rhs_peaks: Vec::new(),
+			failed: false,
+		})
+	}
+
+	/// Feed the next proof node, at `pos` with `hash`.
+	///
+	/// Fails if the node is not the one expected next, in which case the proof can no longer
+	/// be verified.
+	pub fn feed_node(&mut self, pos: NodeIndex, hash: H::Output) -> Result<(), primitives::Error> {
+		if self.failed || self.expected.last() != Some(&pos) {
+			self.failed = true;
+			return Err(primitives::Error::Verify.log_debug(("Unexpected proof node", pos)))
+		}
+		self.expected.pop();
+
+		let (node_pos, node_hash) = self.node;
+		if !self.peaks.contains(&pos) {
+			// A sibling on the path to the leaf's peak, the parent follows the right child.
+			let (left, right) = if pos < node_pos { (hash, node_hash) } else { (node_hash, hash) };
+			let parent = <mmr::Hasher<H, ()> as primitives::mmr_lib::Merge>::merge(
+				&mmr::Node::Hash(left),
+				&mmr::Node::Hash(right),
+			)
+			.map_err(|e| primitives::Error::Verify.log_debug(e))?;
+			self.node = (node_pos.max(pos) + 1, parent.hash());
+		} else if pos < node_pos {
+			self.lhs_peaks.push(hash);
+		} else {
+			self.rhs_peaks.push(hash);
+		}
+		Ok(())
+	}
+
+	/// Check that the fed proof nodes reconstruct the MMR `root` from the leaf.
+	///
+	/// Fails if any of the proof nodes is missing, or an unexpected one was fed.
+	pub fn finalize(self, root: H::Output) -> Result<(), primitives::Error> {
+		if self.failed || !self.expected.is_empty() {
+			return Err(
+				primitives::Error::Verify.log_debug(("Missing proof nodes", self.expected.len()))
+			)
+		}
+
+		let mut peaks = self.lhs_peaks;
+		peaks.push(self.node.1);
+		peaks.extend(self.rhs_peaks);
+		if mmr::bag_peaks::<H>(peaks)? == Some(root) {
+			Ok(())
+		} else {
+			Err(primitives::Error::Verify.log_debug(("The proof is incorrect.", root)))
+		}
+	}
+}
+
+/// Stateless computation of the MMR root from one of its peaks.
+///
+/// The peak at `peak_index` (among the `number_of_peaks` peaks of the MMR, ordered by position)
+/// is bagged with the other peaks as provided by its `proof` (see
+/// [`Pallet::peak_bagging_proof`]). Combined with a proof of a leaf to its peak, this allows
+/// splitting the proof of the leaf to the root.
+pub fn root_from_peak<H>(
+	peak: H::Output,
+	peak_index: usize,
+	number_of_peaks: usize,
+	proof: Vec<H::Output>,
+) -> Result<H::Output, primitives::Error>
+where
+	H: traits::Hash,
+{
+	mmr::root_from_peak::<H>(peak, peak_index, number_of_peaks, proof)
+}
+
+/// Stateless computation of the MMR root that the `proof` for given `leaves` reconstructs.
+///
+/// Unlike [verify_leaves_proof], this returns the root instead of comparing it with a known one,
+/// which is useful to inspect why a proof doesn't verify, or to build test vectors.
+/// Note, the leaves should be sorted the same way as for [verify_leaves_proof].
+pub fn reconstruct_root<H, L>(
+	leaves: Vec<mmr::Node<H, L>>,
+	proof: primitives::Proof<H::Output>,
+) -> Result<H::Output, primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	mmr::reconstruct_root::<H, L>(leaves, proof)
+}
+
+/// Stateless verification of an MMR [primitives::AncestryProof] against the current `root`.
+///
+/// Returns the root of the prior MMR, which has to be compared with the trusted prior root.
+pub fn verify_ancestry_proof<H>(
+	root: H::Output,
+	proof: primitives::AncestryProof<H::Output>,
+) -> Result<H::Output, primitives::Error>
+where
+	H: traits::Hash,
+{
+	mmr::verify_ancestry_proof::<H>(root, proof)
+}
+
+/// Stateless verification that a sequence of MMR `roots` forms an append-only chain.
+///
+/// The `roots` are `(leaf_count, root)` pairs ordered by the number of leaves, and
+/// `ancestry_proofs[i]` has to prove that the MMR with root `roots[i]` is a prefix of the MMR
+/// with root `roots[i + 1]`.
+pub fn verify_root_chain<H>(
+	roots: &[(LeafIndex, H::Output)],
+	ancestry_proofs: &[primitives::AncestryProof<H::Output>],
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+{
+	if roots.len() != ancestry_proofs.len().saturating_add(1) {
+		return Err(primitives::Error::Verify
+			.log_debug("The number of ancestry proofs doesn't match the number of roots."))
+	}
+
+	for (pair, proof) in roots.windows(2).zip(ancestry_proofs) {
+		let ((prev_leaf_count, prev_root), (leaf_count, root)) = (pair[0], pair[1]);
+		if proof.prev_leaf_count != prev_leaf_count || proof.leaf_count != leaf_count {
+			return Err(primitives::Error::Verify
+				.log_debug(("Ancestry proof for unexpected leaf counts.", leaf_count)))
+		}
+		if verify_ancestry_proof::<H>(root, proof.clone())? != prev_root {
+			return Err(primitives::Error::Verify
+				.log_debug(("Root is not an extension of the previous one.", root)))
+		}
+	}
+
+	Ok(())
+}
+
+/// Stateless verification of an MMR proof generated with a [primitives::CompressionHint].
+///
+/// The `known_leaves` are the leaves described by the hint, which the verifier already has
+/// (ordered as in the hint, skipping duplicates and the ones which are part of `leaves` anyway).
+/// They are used to reconstruct the authentication nodes omitted from the `proof`.
+pub fn verify_compressed_leaves_proof<H, L>(
+	root: H::Output,
+	leaves: Vec<mmr::Node<H, L>>,
+	known_leaves: Vec<mmr::Node<H, L>>,
+	proof: primitives::Proof<H::Output>,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+{
+	let proof = mmr::decompress_proof::<H, L>(known_leaves, proof)?;
+	verify_leaves_proof::<H, L>(root, leaves, proof)
+}
+
+/// Stateless MMR proof verification against a root obtained from a state proof.
+///
+/// The MMR root is read from the `state_proof` under the `storage_key` (usually the key of
+/// [RootHash]), after checking the proof against the trusted `state_root`, using the trie
+/// layout of the given `state_version` (i.e. the one of the runtime whose state is proven).
+/// The `leaf` is then verified against that root the same way [verify_leaves_proof] does.
+///
+/// This allows light clients which only trust a state root to verify MMR leaves.
+pub fn verify_with_state_proof<H, L, StateHasher>(
+	state_root: StateHasher::Out,
+	state_proof: sp_trie::StorageProof,
+	state_version: sp_runtime::StateVersion,
+	storage_key: &[u8],
+	leaf: mmr::Node<H, L>,
+	mmr_proof: primitives::Proof<H::Output>,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+	StateHasher: sp_core::Hasher,
+{
+	use sp_runtime::StateVersion;
+	use sp_trie::{read_trie_value, LayoutV0, LayoutV1};
+
+	let db = state_proof.into_memory_db::<StateHasher>();
+	let encoded_root = match state_version {
+		StateVersion::V0 =>
+			read_trie_value::<LayoutV0<StateHasher>, _>(&db, &state_root, storage_key, None, None),
+		StateVersion::V1 =>
+			read_trie_value::<LayoutV1<StateHasher>, _>(&db, &state_root, storage_key, None, None),
+	}
+	.map_err(|e| primitives::Error::Verify.log_debug(("Invalid state proof.", e)))?
+	.ok_or_else(|| primitives::Error::Verify.log_debug("No MMR root in state proof."))?;
+	let root = codec::Decode::decode(&mut &*encoded_root)
+		.map_err(|e| primitives::Error::Verify.log_debug(("Undecodable MMR root.", e)))?;
+
+	verify_leaves_proof::<H, L>(root, vec![leaf], mmr_proof)
+}
+
+/// Stateless MMR proof verification against a root committed in a block header digest.
+///
+/// The MMR root is extracted from the [`primitives::MMR_ENGINE_ID`] consensus digest item of
+/// the `header` (see [DepositMmrRootDigest]) and the `leaves` are then verified against it
+/// the same way [verify_leaves_proof] does.
+///
+/// Returns [primitives::Error::MissingRootDigest] if the header carries no MMR root digest.
+///
+/// Note the digest has to contain the raw MMR root, i.e. the [`Config::RootWrapper`] of the
+/// pallet depositing it must be `()`.
+pub fn verify_proof_against_header<H, L, Header>(
+	header: &Header,
+	leaves: Vec<mmr::Node<H, L>>,
+	proof: primitives::Proof<H::Output>,
+) -> Result<(), primitives::Error>
+where
+	H: traits::Hash,
+	L: primitives::FullLeaf,
+	Header: traits::Header,
+{
+	let root = header
+		.digest()
+		.convert_first(|log| log.consensus_try_to::<H::Output>(&primitives::MMR_ENGINE_ID))
+		.ok_or_else(|| {
+			primitives::Error::MissingRootDigest.log_debug("No MMR root digest in header.")
+		})?;
+	verify_leaves_proof::<H, L>(root, leaves, proof)
+}
+
+/// An [primitives::OnNewRoot] hook depositing the new MMR root into the header digest.
+///
+/// The root is SCALE-encoded into a [`sp_runtime::generic::DigestItem::Consensus`] item
+/// tagged with [`primitives::MMR_ENGINE_ID`], so that it can be later picked up by
+/// [verify_proof_against_header].
+pub struct DepositMmrRootDigest<T, I = ()>(sp_std::marker::PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> primitives::OnNewRoot<<T as Config<I>>::Hash>
+	for DepositMmrRootDigest<T, I>
+{
+	fn on_new_root(root: &<T as Config<I>>::Hash) {
+		let digest = sp_runtime::generic::DigestItem::Consensus(
+			primitives::MMR_ENGINE_ID,
+			codec::Encode::encode(root),
+		);
+		<frame_system::Pallet<T>>::deposit_log(digest);
+	}
+}
+
+impl<T: Config<I>, I: 'static> Pallet<T, I> {
+	/// Append the leaf of block `n` to the MMR, returning the weight consumed.
+	fn append_leaf(n: T::BlockNumber) -> Weight {
+		let leaves = Self::mmr_leaves();
+		let peaks_before = NodesUtils::new(leaves).number_of_peaks();
+		let (data, hash) = T::LeafData::leaf_data_with_hash::<HashingOf<T, I>>();
+		let (data, hash) = T::LeafTransform::transform_with_hash::<HashingOf<T, I>>(data, hash);
+		if let Err(e) = <T::LeafValidator as primitives::LeafValidator<_>>::validate(&data) {
+			log::debug!(target: "runtime::mmr", "MMR leaf rejected: {:?}", e);
+			return T::WeightInfo::on_initialize(peaks_before)
+		}
+
+		// append new leaf to MMR, the precomputed hash (if any) is the leaf's own commitment, so
+		// it's of no use with the cross-chain one
+		let mut mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> = mmr::Mmr::new(leaves);
+		let data = NodeLeafOf::<T, I>::new(data);
+		let position = match hash.filter(|_| !T::CROSS_CHAIN_COMMITMENT) {
+			Some(hash) => mmr.push_prehashed(data, hash),
+			None => mmr.push(data),
+		};
+		// MMR push only fails for leaves not fitting the `LEAF_ENCODING`.
+		if position.is_none() {
+			log::error!(target: "runtime::mmr", "MMR push failed");
+			return T::WeightInfo::on_initialize(peaks_before)
+		}
+		// Update the size, skipping the leaf if its nodes can't be appended (e.g. under an
+		// unknown parent hash).
+		let (leaves, root) = match mmr.finalize() {
+			Ok((leaves, root)) => (leaves, root),
+			Err(e) => {
+				log::error!(target: "runtime::mmr", "MMR finalize failed, leaf skipped: {:?}", e);
+				return T::WeightInfo::on_initialize(peaks_before)
+			},
+		};
+		Self::notify_new_root(&root);
+
+		<NumberOfLeaves<T, I>>::put(leaves);
+		<RootHash<T, I>>::put(root);
+
+		let peaks_after = NodesUtils::new(leaves).number_of_peaks();
+
+		// Retain the parent hash, so that proofs can be generated beyond `frame_system`'s
+		// `BlockHashCount` window.
+		if T::PARENT_HASH_RETENTION > 0 {
+			let parent_block_num = n.saturating_sub(One::one());
+			<ParentHashes<T, I>>::insert(
+				Self::parent_hash_slot(parent_block_num),
+				(parent_block_num, <frame_system::Pallet<T>>::parent_hash()),
+			);
+		}
+
+		Self::append_weight(peaks_before.max(peaks_after))
+	}
+
+	/// Return the weight of [`Pallet::clear_mmr`] removing (at most) `nodes` on-chain nodes,
+	/// with (at most) `ranges` entries in [`NotIndexedLeaves`].
+	fn clear_mmr_weight(nodes: NodeIndex, ranges: u32) -> Weight {
+		let weight = T::WeightInfo::clear_mmr(nodes, ranges);
+		// The retained parent hashes, the kept peaks and the recent and finalized roots are
+		// cleared, along with the finalized root numbers, the leaf hashes of the block, the block
+		// to leaves mapping, the cached peaks and the off-chain size.
+		let writes = u64::from(T::PARENT_HASH_RETENTION)
+			.saturating_add(u64::from(T::MAX_PEAK_HISTORY))
+			.saturating_add(u64::from(T::MAX_FINALIZED_ROOTS).saturating_mul(2))
+			.saturating_add(7);
+		weight.saturating_add(T::DbWeight::get().writes(writes))
+	}
+
+	/// Return the weight of [`Pallet::force_set_leaf_count`] removing (at most) `nodes` on-chain
+	/// nodes, with (at most) `ranges` entries in [`NotIndexedLeaves`].
+	fn force_set_leaf_count_weight(nodes: NodeIndex, ranges: u32) -> Weight {
+		let weight = T::WeightInfo::force_set_leaf_count(nodes, ranges);
+		// The mapping of the blocks to the leaves is truncated.
+		weight.saturating_add(T::DbWeight::get().reads_writes(4, 3))
+	}
+
+	/// Return the weight of appending a leaf to the MMR with (at most) `peaks` peaks.
+	fn append_weight(peaks: NodeIndex) -> Weight {
+		// The block is noted in the mapping of the blocks to the leaves, and the cached peaks
+		// are updated.
+		let mut weight = T::WeightInfo::on_initialize(peaks)
+			.saturating_add(T::DbWeight::get().reads_writes(3, 4));
+		if T::PARENT_HASH_RETENTION > 0 {
+			weight = weight.saturating_add(T::DbWeight::get().writes(1));
+		}
+		if T::TRACK_OFFCHAIN_SIZE {
+			weight = weight.saturating_add(T::DbWeight::get().reads_writes(1, 1));
+		}
+		if !T::IndexingEnabled::get() {
+			// The leaf is noted in the ranges of the not indexed leaves.
+			weight = weight.saturating_add(T::DbWeight::get().reads_writes(1, 1));
+		}
+		if T::MAX_PEAK_HISTORY > 0 {
+			// Every pruned peak is kept (possibly evicting the oldest one).
+			let writes = peaks.saturating_mul(3).saturating_add(1);
+			weight = weight.saturating_add(T::DbWeight::get().reads_writes(1 + peaks, writes));
+		}
+		weight
+	}
+
+	/// Return the common prefix of the offchain keys of all the MMR nodes.
+	///
+	/// External tools can use it to enumerate the nodes in the Off-chain DB with a prefix scan.
+	/// Note the prefix is derived from [`Config::INDEXING_PREFIX`], but it's not equal to it.
+	pub fn offchain_prefix() -> sp_std::prelude::Vec<u8> {
+		NodesUtils::node_offchain_key_prefix(&T::INDEXING_PREFIX)
+	}
+
+	/// Build offchain key from `parent_hash` of block that originally added node `pos` to MMR.
+	///
+	/// This combination makes the offchain (key,value) entry resilient to chain forks.
+	fn node_temp_offchain_key(
+		pos: TypedNodeIndex,
+		parent_hash: <T as frame_system::Config>::Hash,
+	) -> sp_std::prelude::Vec<u8> {
+		NodesUtils::node_temp_offchain_key::<<T as frame_system::Config>::Header>(
+			&T::INDEXING_PREFIX,
+			pos,
+			parent_hash,
+		)
+	}
+
+	/// Build canonical offchain key for node `pos` in MMR.
+	///
+	/// Used for nodes added by now finalized blocks.
+	/// Never read keys using `node_canon_offchain_key` unless you sure that
+	/// there's no `node_offchain_key` key in the storage.
+	fn node_canon_offchain_key(pos: TypedNodeIndex) -> sp_std::prelude::Vec<u8> {
 		NodesUtils::node_canon_offchain_key(&T::INDEXING_PREFIX, pos)
 	}
 
-	/// Provide the parent number for the block that added `leaf_index` to the MMR.
-	fn leaf_index_to_parent_block_num(
-		leaf_index: LeafIndex,
-		leaves_count: LeafIndex,
-	) -> <T as frame_system::Config>::BlockNumber {
-		// leaves are zero-indexed and were added one per block since pallet activation,
-		// while block numbers are one-indexed, so block number that added `leaf_idx` is:
-		// `block_num = block_num_when_pallet_activated + leaf_idx + 1`
-		// `block_num = (current_block_num - leaves_count) + leaf_idx + 1`
-		// `parent_block_num = current_block_num - leaves_count + leaf_idx`.
-		<frame_system::Pallet<T>>::block_number()
-			.saturating_sub(leaves_count.saturated_into())
-			.saturating_add(leaf_index.saturated_into())
-	}
-
-	/// Convert a block number into a leaf index.
-	fn block_num_to_leaf_index(block_num: T::BlockNumber) -> Result<LeafIndex, Error>
-	where
-		T: frame_system::Config,
-	{
-		let first_mmr_block = utils::first_mmr_block_num::<T::Header>(
-			<frame_system::Pallet<T>>::block_number(),
-			Self::mmr_leaves(),
-		)?;
+	/// Build offchain key for the mirror of peak `pos` (see [`Config::MIRROR_PEAKS_OFFCHAIN`]).
+	pub fn peak_offchain_key(pos: TypedNodeIndex) -> sp_std::prelude::Vec<u8> {
+		NodesUtils::peak_offchain_key(&T::INDEXING_PREFIX, pos)
+	}
+
+	/// Build offchain key for the cursor of the offchain worker pruning
+	/// (see [`Config::OFFCHAIN_RETENTION_BLOCKS`]).
+	pub fn prune_cursor_offchain_key() -> sp_std::prelude::Vec<u8> {
+		NodesUtils::prune_cursor_offchain_key(&T::INDEXING_PREFIX)
+	}
+
+	/// Build offchain key for the leaves added by the blocks number `block_num` the offchain
+	/// worker has seen (see [`Config::OFFCHAIN_RETENTION_BLOCKS`]).
+	pub fn fork_leaves_offchain_key(block_num: T::BlockNumber) -> sp_std::prelude::Vec<u8> {
+		NodesUtils::fork_leaves_offchain_key::<<T as frame_system::Config>::Header>(
+			&T::INDEXING_PREFIX,
+			block_num,
+		)
+	}
+
+	/// Record the leaves added by the block `n` along with its parent hash, so that the nodes
+	/// indexed under it can be found once the block turns out to be stale.
+	fn note_fork_leaves_offchain(n: T::BlockNumber) {
+		use sp_runtime::offchain::storage::StorageValueRef;
+
+		let (start, end) = match Self::leaves_added_in_block(n) {
+			Ok((start, end)) if start < end => (start, end),
+			_ => return,
+		};
+		let entry = (<frame_system::Pallet<T>>::parent_hash(), start, end);
+		let key = Self::fork_leaves_offchain_key(n);
+		let record = StorageValueRef::persistent(&key);
+		let mut forks = record.get::<ForkLeavesOf<T>>().ok().flatten().unwrap_or_default();
+		if !forks.contains(&entry) {
+			forks.push(entry);
+			record.set(&forks);
+		}
+	}
+
+	/// Canonicalize the Off-chain DB nodes added by the blocks at least `retention` blocks older
+	/// than the latest block noted as finalized (see [`Config::OFFCHAIN_RETENTION_BLOCKS`]).
+	///
+	/// The number of the next block to prune is persisted in the Off-chain DB (under
+	/// [`Self::prune_cursor_offchain_key`]), so every block is processed once. About
+	/// [`MAX_PRUNED_LEAVES_PER_RUN`] leaves are processed at a time.
+	fn prune_offchain(retention: u32) {
+		use sp_runtime::offchain::storage::StorageValueRef;
+
+		// Only the finalized blocks are pruned, the current one may still be reverted.
+		let finalized = match <FinalizedRootNumbers<T, I>>::get().last() {
+			Some(finalized) => *finalized,
+			None => return,
+		};
+		if finalized <= retention.into() {
+			return
+		}
+		let last_block = finalized - retention.into();
+
+		let cursor_key = Self::prune_cursor_offchain_key();
+		let cursor = StorageValueRef::persistent(&cursor_key);
+		// The nodes of the genesis leaves were never fork-aware.
+		let first_block = cursor
+			.get::<T::BlockNumber>()
+			.ok()
+			.flatten()
+			.unwrap_or_else(One::one)
+			.max(<FirstMappedBlock<T, I>>::get().unwrap_or_else(One::one));
+		if first_block > last_block {
+			return
+		}
+
+		let mut block = first_block;
+		let mut budget = MAX_PRUNED_LEAVES_PER_RUN;
+		while block <= last_block && budget > 0 {
+			budget = budget.saturating_sub(Self::prune_block_offchain(block).saturating_add(1));
+			block += One::one();
+		}
+		log::debug!(
+			target: "runtime::mmr::offchain",
+			"Canonicalized the nodes of blocks {:?}..{:?}", first_block, block
+		);
+		cursor.set(&block);
+	}
+
+	/// Canonicalize the Off-chain DB nodes added by the finalized `block` and remove the ones
+	/// added by the other blocks of the same number the offchain worker has seen.
+	///
+	/// Returns the number of processed leaves. The nodes are left to the MMR client gadget if
+	/// the hash of the parent of `block` is not known anymore.
+	fn prune_block_offchain(block: T::BlockNumber) -> LeafIndex {
+		use mmr::storage::{OffchainStorage, Storage};
+		use sp_runtime::offchain::storage::StorageValueRef;
+
+		let key = Self::fork_leaves_offchain_key(block);
+		let mut record = StorageValueRef::persistent(&key);
+		let forks = record.get::<ForkLeavesOf<T>>().ok().flatten().unwrap_or_default();
+		record.clear();
+
+		let parent_num = block.saturating_sub(One::one());
+		let parent_hash = Self::retained_block_hash(parent_num)
+			.unwrap_or_else(|| <frame_system::Pallet<T>>::block_hash(parent_num));
+		if parent_hash == Default::default() {
+			return 0
+		}
+
+		let mut processed = 0;
+		if let Ok((start, end)) = Self::leaves_added_in_block(block) {
+			for leaf_index in (start..end).map(TypedLeafIndex) {
+				Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::canonicalize_leaf(leaf_index);
+			}
+			processed += end - start;
+		}
+		for (fork_parent_hash, start, end) in forks {
+			if fork_parent_hash == parent_hash {
+				continue
+			}
+			for leaf_index in (start..end).map(TypedLeafIndex) {
+				Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::clear_fork_leaf(
+					leaf_index,
+					fork_parent_hash,
+				);
+			}
+			processed += end - start;
+		}
+		processed
+	}
+
+	/// Provide the parent number for the block that added `leaf_index` to the MMR.
+	///
+	/// The leaves appended at genesis (see [`GenesisLeaves`]) were not added by any block, they
+	/// are all attributed to the genesis block instead (i.e. their parent block number is `0`).
+	fn leaf_index_to_parent_block_num(
+		leaf_index: TypedLeafIndex,
+	) -> Result<<T as frame_system::Config>::BlockNumber, primitives::Error> {
+		Self::block_for_leaf(leaf_index).map(|block| block.saturating_sub(One::one()))
+	}
+
+	/// Check that the leaf type matches the declared [`Config::LEAF_SCHEMA`], if any.
+	pub fn check_leaf_schema() -> Result<(), primitives::Error>
+	where
+		LeafOf<T, I>: scale_info::TypeInfo + 'static,
+	{
+		match T::LEAF_SCHEMA {
+			Some(schema) => primitives::check_leaf_schema::<LeafOf<T, I>>(schema),
+			None => Ok(()),
+		}
+	}
+
+	/// Return the number of the block that added (or will add) `leaf_index` to the MMR.
+	///
+	/// The future leaves (i.e. `leaf_index >= mmr_leaves()`) are assumed to be added one per
+	/// block from the next block on. The leaves appended at genesis (see [`GenesisLeaves`])
+	/// belong to the genesis block. Fails with [`primitives::Error::InvalidNumericOp`] if the
+	/// block is not known anymore (see [`FirstMappedBlock`]).
+	pub fn block_for_leaf(
+		leaf_index: TypedLeafIndex,
+	) -> Result<<T as frame_system::Config>::BlockNumber, primitives::Error> {
+		let leaf_index = leaf_index.0;
+		if leaf_index < Self::genesis_leaves() {
+			return Ok(Zero::zero())
+		}
+		let checkpoints = Self::leaf_count_checkpoints();
+		// The leaf belongs to the last block starting at or before it.
+		let next = checkpoints.partition_point(|(_, leaves)| *leaves <= leaf_index);
+		let block = match next.checked_sub(1).map(|index| checkpoints[index]) {
+			Some((block, leaves)) => {
+				let block = block.saturating_add((leaf_index - leaves).saturated_into());
+				match checkpoints.get(next) {
+					Some((next_block, _)) => block.min(next_block.saturating_sub(One::one())),
+					None => block,
+				}
+			},
+			// Before all the noted blocks, the leaves were added one per block.
+			None if <FirstMappedBlock<T, I>>::get().is_none() => {
+				let (block, leaves) = checkpoints[0];
+				block.saturating_sub((leaves - leaf_index).saturated_into())
+			},
+			None =>
+				return Err(primitives::Error::InvalidNumericOp
+					.log_debug(("The block of the leaf is not known anymore", leaf_index))),
+		};
+		Ok(block)
+	}
+
+	/// Return the half-open range of the indices of the leaves added by `block`.
+	///
+	/// This is the inverse of [`Self::block_for_leaf`]: blocks usually add a single leaf, but
+	/// some of them add none (e.g. because the leaf was rejected) or several leaves (see
+	/// [`Config::MAX_LEAVES_PER_BLOCK`]), which is tracked in [`LeafCountCheckpoints`]. The
+	/// future blocks are assumed to add one leaf each. The genesis block holds the leaves
+	/// appended at genesis (see [`GenesisLeaves`]), while the range is empty for the other
+	/// blocks before the activation. Fails with [`primitives::Error::InvalidNumericOp`] if the
+	/// leaves of `block` are not known anymore (see [`FirstMappedBlock`]).
+	pub fn leaves_added_in_block(
+		block: <T as frame_system::Config>::BlockNumber,
+	) -> Result<(LeafIndex, LeafIndex), primitives::Error> {
+		if block.is_zero() {
+			return Ok((0, Self::genesis_leaves()))
+		}
+		if <FirstMappedBlock<T, I>>::get().map_or(false, |first| block < first) {
+			return Err(primitives::Error::InvalidNumericOp
+				.log_debug(("The leaves of the block are not known anymore", block)))
+		}
+		let checkpoints = Self::leaf_count_checkpoints();
+		let genesis_leaves = Self::genesis_leaves();
+		let first_leaf = |block: <T as frame_system::Config>::BlockNumber| {
+			// Leaves are added one per block since the last block noted before `block`, up to
+			// the number of leaves at the start of the next noted one.
+			let next = checkpoints.partition_point(|(number, _)| *number <= block);
+			let first_leaf = match next.checked_sub(1).map(|index| checkpoints[index]) {
+				Some((number, leaves)) => {
+					let first_leaf =
+						leaves.saturating_add((block - number).saturated_into::<LeafIndex>());
+					match checkpoints.get(next) {
+						Some((_, next_leaves)) => first_leaf.min(*next_leaves),
+						None => first_leaf,
+					}
+				},
+				None => {
+					let (number, leaves) = checkpoints[0];
+					leaves.saturating_sub((number - block).saturated_into::<LeafIndex>())
+				},
+			};
+			first_leaf.max(genesis_leaves)
+		};
+		Ok((first_leaf(block), first_leaf(block.saturating_add(One::one()))))
+	}
+
+	/// Return the number of leaves the MMR had at the end of `block`.
+	///
+	/// This allows reconstructing the shape of the MMR at any past block (see
+	/// [`Self::leaves_added_in_block`]), e.g. to validate historical proofs. For the blocks
+	/// before the activation it's the number of leaves appended at genesis, usually `0`. The
+	/// leaves of blocks after the current one are not known yet, so these are rejected with
+	/// [`primitives::Error::InvalidBestKnownBlock`].
+	pub fn leaf_count_at_block(
+		block: <T as frame_system::Config>::BlockNumber,
+	) -> Result<LeafIndex, primitives::Error> {
+		if block > <frame_system::Pallet<T>>::block_number() {
+			return Err(primitives::Error::InvalidBestKnownBlock.log_debug(("Future block", block)))
+		}
+		Ok(Self::leaves_added_in_block(block)?.1)
+	}
+
+	/// Convert a block number into the indices of the leaves added by the block.
+	///
+	/// Fails with [`primitives::Error::LeafNotFound`] if the block didn't add any leaf. Note the
+	/// leaves appended at genesis can't be referred to by a block number, see
+	/// [`Self::generate_genesis_leaves_proof`].
+	fn block_num_to_leaf_indices(
+		block_num: T::BlockNumber,
+	) -> Result<sp_std::ops::Range<LeafIndex>, primitives::Error> {
+		let (first_leaf, end) = match block_num.is_zero() {
+			true => (0, 0),
+			false => Self::leaves_added_in_block(block_num)?,
+		};
+		if first_leaf == end {
+			return Err(primitives::Error::LeafNotFound
+				.log_debug(("The block didn't add any leaf", block_num)))
+		}
+		Ok(first_leaf..end)
+	}
+
+	/// Return the blocks noted in [`LeafCountCheckpoints`], followed by the ones implied by the
+	/// current state (the last block which appended leaves and the next one), with the number
+	/// of leaves at their start.
+	///
+	/// The result is never empty: before any block is noted, the blocks up to the current one are
+	/// assumed to have added one leaf each.
+	fn leaf_count_checkpoints() -> Vec<(<T as frame_system::Config>::BlockNumber, LeafIndex)> {
+		let mut checkpoints = <LeafCountCheckpoints<T, I>>::get().into_inner();
+		let leaves = Self::mmr_leaves();
+		let next_block = <frame_system::Pallet<T>>::block_number().saturating_add(One::one());
+		match <BlockFirstLeaf<T, I>>::get() {
+			Some((block, first_leaf)) => {
+				checkpoints.push((block, first_leaf));
+				let after_block = block.saturating_add(One::one());
+				checkpoints.push((after_block, leaves));
+				// The blocks since then didn't append any leaf.
+				if after_block < next_block {
+					checkpoints.push((next_block, leaves));
+				}
+			},
+			None => checkpoints.push((next_block, leaves)),
+		}
+		checkpoints
+	}
+
+	/// Return the index of the first leaf appended by the current block, given the current number
+	/// of `leaves`.
+	pub(crate) fn block_first_leaf(leaves: LeafIndex) -> LeafIndex {
+		match <BlockFirstLeaf<T, I>>::get() {
+			Some((block, first_leaf)) if block == <frame_system::Pallet<T>>::block_number() =>
+				first_leaf,
+			_ => leaves,
+		}
+	}
+
+	/// Note that the current block appends leaves to the MMR of `leaves` leaves.
+	///
+	/// The first call of a block replaces the [`BlockFirstLeaf`] of the previous block which
+	/// appended leaves, noting the previous block in [`LeafCountCheckpoints`] unless it appended
+	/// a single leaf right before the current block.
+	pub(crate) fn note_block_start(leaves: LeafIndex) {
+		let block = <frame_system::Pallet<T>>::block_number();
+		match <BlockFirstLeaf<T, I>>::get() {
+			Some((number, _)) if number == block => return,
+			Some((number, first_leaf)) => {
+				let after_number = number.saturating_add(One::one());
+				if after_number != block || leaves != first_leaf.saturating_add(1) {
+					Self::note_checkpoints(number, first_leaf, leaves, block);
+				}
+			},
+			None => (),
+		}
+		<BlockFirstLeaf<T, I>>::put((block, leaves));
+	}
+
+	/// Note in [`LeafCountCheckpoints`] that the leaves from `first_leaf` to `end` (exclusive)
+	/// were added by `block`, while the subsequent blocks didn't add any leaf until `next_block`.
+	fn note_checkpoints(
+		block: <T as frame_system::Config>::BlockNumber,
+		first_leaf: LeafIndex,
+		end: LeafIndex,
+		next_block: <T as frame_system::Config>::BlockNumber,
+	) {
+		<LeafCountCheckpoints<T, I>>::mutate(|checkpoints| {
+			// The start of `block` is implied by the last checkpoint, unless that's the first
+			// block which didn't add exactly one leaf.
+			let implied_first_leaf = checkpoints.last().map(|(number, leaves)| {
+				leaves.saturating_add(block.saturating_sub(*number).saturated_into::<LeafIndex>())
+			});
+			let after_block = block.saturating_add(One::one());
+			let mut new_checkpoints = Vec::new();
+			if implied_first_leaf != Some(first_leaf) {
+				new_checkpoints.push((block, first_leaf));
+			}
+			new_checkpoints.push((after_block, end));
+			if after_block < next_block {
+				new_checkpoints.push((next_block, end));
+			}
+
+			for checkpoint in new_checkpoints {
+				if checkpoints.is_full() {
+					checkpoints.remove(0);
+					<FirstMappedBlock<T, I>>::put(checkpoints[0].0);
+				}
+				checkpoints.try_push(checkpoint).expect("An entry was removed if full; qed");
+			}
+		});
+	}
+
+	/// Truncate the mapping of the blocks to the leaves to the first `leaves` leaves, keeping
+	/// the blocks of the remaining leaves.
+	///
+	/// The block which added the first removed leaf ends up with the remaining leaves it added,
+	/// while the subsequent blocks didn't add any leaf.
+	fn truncate_block_leaves(leaves: LeafIndex) {
+		if leaves >= Self::mmr_leaves() {
+			return
+		}
+		match Self::block_for_leaf(TypedLeafIndex(leaves)) {
+			Ok(block) => {
+				let (first_leaf, _) =
+					Self::leaves_added_in_block(block).unwrap_or((leaves, leaves));
+				<LeafCountCheckpoints<T, I>>::mutate(|checkpoints| {
+					checkpoints.retain(|(number, _)| *number < block)
+				});
+				<BlockFirstLeaf<T, I>>::put((block, first_leaf.min(leaves)));
+			},
+			// The blocks of the remaining leaves are not known anymore either.
+			Err(_) => {
+				<LeafCountCheckpoints<T, I>>::kill();
+				<BlockFirstLeaf<T, I>>::kill();
+				<FirstMappedBlock<T, I>>::put(
+					<frame_system::Pallet<T>>::block_number().saturating_add(One::one()),
+				);
+			},
+		}
+	}
+
+	/// Keep the peak `pos` with `hash`, which was just pruned from [`Nodes`], in
+	/// [`HistoricalPeaks`] (see [`Config::MAX_PEAK_HISTORY`]).
+	fn note_pruned_peak(pos: TypedNodeIndex, hash: <T as Config<I>>::Hash) {
+		<HistoricalPeakPositions<T, I>>::mutate(|positions| {
+			if positions.is_full() {
+				<HistoricalPeaks<T, I>>::remove(positions.remove(0));
+			}
+			positions.try_push(pos.0).expect("An entry was removed if full; qed");
+		});
+		let block_num = <frame_system::Pallet<T>>::block_number();
+		<HistoricalPeaks<T, I>>::insert(pos.0, (block_num, hash));
+	}
+
+	/// Return the root of the past MMR with `leaf_count` leaves, rebuilt from on-chain data.
+	///
+	/// The peaks of the past MMR are taken from [`Nodes`] if they are still there, or from
+	/// [`HistoricalPeaks`] otherwise. Returns `None` if any of them is in neither (e.g. if
+	/// [`Config::MAX_PEAK_HISTORY`] isn't set), or if the MMR never had `leaf_count` leaves.
+	pub fn historical_root(leaf_count: LeafIndex) -> Option<<T as Config<I>>::Hash> {
+		if leaf_count > Self::mmr_leaves() {
+			return None
+		}
+		let peaks = Self::peaks(leaf_count)
+			.into_iter()
+			.map(|pos| {
+				<Nodes<T, I>>::get(pos)
+					.or_else(|| <HistoricalPeaks<T, I>>::get(pos).map(|(_, hash)| hash))
+			})
+			.collect::<Option<Vec<_>>>()?;
+		Self::bag_peaks(peaks).ok().flatten()
+	}
+
+	/// Return the roots the MMR would have after appending each of the `candidates` leaves.
+	///
+	/// Every candidate is appended on its own to the current MMR, so the roots are the
+	/// alternatives for the next leaf, e.g. for block builders choosing which data to commit.
+	/// They are computed in memory from the current peaks (see [`Nodes`]), without modifying
+	/// the storage. Candidates which wouldn't be appended (see [`Config::LeafValidator`] and
+	/// [`Config::LEAF_ENCODING`]) leave the root unchanged. Fails with
+	/// [`primitives::Error::GetRoot`] if any of the current peaks is missing.
+	pub fn roots_after_candidates(
+		candidates: Vec<LeafOf<T, I>>,
+	) -> Result<Vec<<T as Config<I>>::Hash>, primitives::Error> {
+		use primitives::mmr_lib::Merge;
+
+		let leaves = Self::mmr_leaves();
+		let peaks = Self::peaks(leaves)
+			.into_iter()
+			.map(<Nodes<T, I>>::get)
+			.collect::<Option<Vec<_>>>()
+			.ok_or_else(|| primitives::Error::GetRoot.log_debug("Missing current peaks"))?;
+		let root = Self::mmr_root_hash();
+
+		candidates
+			.into_iter()
+			.map(|leaf| {
+				if <T::LeafValidator as primitives::LeafValidator<_>>::validate(&leaf).is_err() ||
+					mmr::storage::check_leaf_encoding::<T, I, _>(&leaf).is_err()
+				{
+					return Ok(root)
+				}
+
+				// Appending a leaf merges as many peaks as there are trailing ones in its index.
+				let mut peaks = peaks.clone();
+				let mut node = mmr::Node::<HashingOf<T, I>, _>::Data(NodeLeafOf::<T, I>::new(leaf));
+				for _ in 0..leaves.trailing_ones() {
+					let left = peaks.pop().map(mmr::Node::Hash).ok_or_else(|| {
+						primitives::Error::GetRoot.log_error("Missing peak to merge")
+					})?;
+					node = mmr::Hasher::<HashingOf<T, I>, NodeLeafOf<T, I>>::merge(&left, &node)
+						.map_err(|e| primitives::Error::GetRoot.log_error(e))?;
+				}
+				peaks.push(node.hash());
+				Self::bag_peaks(peaks)?
+					.ok_or_else(|| primitives::Error::GetRoot.log_error("No peaks to bag"))
+			})
+			.collect()
+	}
+
+	/// Record that the leaf `leaf_index` was not indexed in the Off-chain DB.
+	///
+	/// The leaves are noted in ascending order, so the ranges stay sorted. The latest range is
+	/// extended if it ends right before the leaf, or if no more ranges can be kept.
+	fn note_not_indexed_leaf(leaf_index: TypedLeafIndex) {
+		let leaf_index = leaf_index.0;
+		<NotIndexedLeaves<T, I>>::mutate(|ranges| {
+			let is_full = ranges.len() >= T::MAX_NOT_INDEXED_RANGES as usize;
+			match ranges.last_mut() {
+				Some((_, end)) if *end == leaf_index.saturating_sub(1) || is_full =>
+					*end = leaf_index,
+				// Only fails if the ranges are not tracked at all.
+				_ => {
+					let _ = ranges.try_push((leaf_index, leaf_index));
+				},
+			}
+		});
+	}
 
-		utils::block_num_to_leaf_index::<T::Header>(block_num, first_mmr_block)
+	/// Return `true` if the leaf `leaf_index` was indexed in the Off-chain DB.
+	fn is_leaf_indexed(leaf_index: TypedLeafIndex) -> bool {
+		let ranges = <NotIndexedLeaves<T, I>>::get();
+		// The ranges are sorted, so only the last one starting at or before the leaf can
+		// contain it.
+		let idx = ranges.partition_point(|(start, _)| *start <= leaf_index.0);
+		idx.checked_sub(1).map_or(true, |idx| ranges[idx].1 < leaf_index.0)
 	}
 
-	/// Generate an MMR proof for the given `block_numbers`.
-	/// If `best_known_block_number = Some(n)`, this generates a historical proof for
-	/// the chain with head at height `n`.
-	/// Else it generates a proof for the MMR at the current block height.
+	/// Return the root at the end of block `block_num` to be noted in [`FinalizedRoots`].
+	fn finalized_root_to_note(
+		block_num: T::BlockNumber,
+	) -> Result<<T as Config<I>>::Hash, pallet::Error<T, I>> {
+		if T::MAX_FINALIZED_ROOTS == 0 {
+			return Err(pallet::Error::<T, I>::FinalizedRootsDisabled)
+		}
+		let last_noted = <FinalizedRootNumbers<T, I>>::get().last().copied();
+		if last_noted.map_or(false, |last| last >= block_num) {
+			return Err(pallet::Error::<T, I>::StaleFinalizedBlock)
+		}
+		<RecentRoots<T, I>>::get(Self::recent_root_slot(block_num))
+			.filter(|(recent_num, _)| *recent_num == block_num)
+			.map(|(_, root)| root)
+			.ok_or(pallet::Error::<T, I>::UnknownBlockRoot)
+	}
+
+	/// Slot of the [`RecentRoots`] ring buffer used for `block_num`.
+	fn recent_root_slot(block_num: T::BlockNumber) -> u32 {
+		(block_num % T::MAX_FINALIZED_ROOTS.into()).saturated_into()
+	}
+
+	/// Slot of the [`ParentHashes`] ring buffer used for `block_num`.
+	fn parent_hash_slot(block_num: T::BlockNumber) -> u32 {
+		(block_num % T::PARENT_HASH_RETENTION.into()).saturated_into()
+	}
+
+	/// Hash of block `block_num`, if retained in the [`ParentHashes`] ring buffer.
+	fn retained_block_hash(block_num: T::BlockNumber) -> Option<<T as frame_system::Config>::Hash> {
+		if T::PARENT_HASH_RETENTION == 0 {
+			return None
+		}
+		<ParentHashes<T, I>>::get(Self::parent_hash_slot(block_num))
+			.filter(|(retained_num, _)| *retained_num == block_num)
+			.map(|(_, hash)| hash)
+	}
+
+	/// Positions of the peaks of the current MMR, bounded by [`MaxPeaks`].
 	///
-	/// Note this method can only be used from an off-chain context
-	/// (Offchain Worker or Runtime API call), since it requires
-	/// all the leaves to be present.
-	/// It may return an error or panic if used incorrectly.
-	pub fn generate_proof(
-		block_numbers: Vec<T::BlockNumber>,
+	/// Returns [`pallet::Error::TooManyPeaks`] if the MMR grew beyond [`Config::MAX_LEAVES`]
+	/// and the number of peaks exceeds the bound.
+	pub fn current_peaks_bounded(
+	) -> Result<BoundedVec<NodeIndex, MaxPeaks<T, I>>, pallet::Error<T, I>> {
+		BoundedVec::try_from(Self::peaks(Self::mmr_leaves()))
+			.map_err(|_| pallet::Error::<T, I>::TooManyPeaks)
+	}
+
+	/// Return the current number of leaves if all the peaks of the MMR of that size are stored
+	/// on-chain, or `0` if the number of leaves can't be trusted.
+	fn verified_leaf_count() -> LeafIndex {
+		let leaves = Self::mmr_leaves();
+		if Self::peaks(leaves).iter().all(|peak| Nodes::<T, I>::contains_key(peak)) {
+			leaves
+		} else {
+			0
+		}
+	}
+
+	/// Positions of the peaks of the MMR with `leaves` leaves.
+	fn peaks(leaves: LeafIndex) -> Vec<NodeIndex> {
+		if leaves == 0 {
+			return Vec::new()
+		}
+		primitives::mmr_lib::helper::get_peaks(NodesUtils::new(leaves).size())
+	}
+
+	/// Positions of the on-chain nodes to remove when truncating the MMR to `new_count` leaves.
+	///
+	/// These are the nodes beyond the size of the truncated MMR if all the nodes are stored
+	/// on-chain, or the current peaks which are not peaks of the truncated MMR otherwise.
+	fn nodes_to_remove(new_count: LeafIndex) -> Vec<NodeIndex> {
+		let leaves = Self::mmr_leaves();
+		if new_count >= leaves {
+			return Vec::new()
+		}
+
+		if T::STORE_ALL_NODES {
+			(NodesUtils::new(new_count).size()..NodesUtils::new(leaves).size()).collect()
+		} else {
+			let new_peaks = Self::peaks(new_count);
+			Self::peaks(leaves)
+				.into_iter()
+				.filter(|peak| !new_peaks.contains(peak))
+				.collect()
+		}
+	}
+
+	/// Remove the MMR from the runtime storage, leaving an empty MMR behind.
+	fn clear_onchain_mmr() {
+		for pos in Self::nodes_to_remove(0) {
+			<Nodes<T, I>>::remove(pos);
+		}
+		// The leaf indices and node positions are going to be reused.
+		<NotIndexedLeaves<T, I>>::kill();
+		for pos in <HistoricalPeakPositions<T, I>>::take() {
+			<HistoricalPeaks<T, I>>::remove(pos);
+		}
+
+		// The kept roots and parent hashes are of the blocks which added the removed leaves.
+		let _ = <ParentHashes<T, I>>::clear(u32::MAX, None);
+		let _ = <RecentRoots<T, I>>::clear(u32::MAX, None);
+		for block_num in <FinalizedRootNumbers<T, I>>::take() {
+			<FinalizedRoots<T, I>>::remove(block_num);
+		}
+		<BlockFirstLeaf<T, I>>::kill();
+		<LeafCountCheckpoints<T, I>>::kill();
+		<FirstMappedBlock<T, I>>::kill();
+		<TotalOffchainBytes<T, I>>::kill();
+
+		<NumberOfLeaves<T, I>>::kill();
+		<GenesisLeaves<T, I>>::kill();
+		<PeakHashes<T, I>>::kill();
+		<RootHash<T, I>>::kill();
+		Self::notify_new_root(&Default::default());
+	}
+
+	/// Note that the `pruned` rightmost peaks of the MMR of `leaves` leaves were replaced by the
+	/// `stored` ones, updating [`PeakHashes`].
+	///
+	/// The cached peaks are dropped (to be rebuilt) if they're not the ones of `leaves` leaves.
+	pub(crate) fn note_new_peaks(
+		leaves: LeafIndex,
+		pruned: usize,
+		stored: Vec<<T as Config<I>>::Hash>,
+		new_leaves: LeafIndex,
+	) {
+		<PeakHashes<T, I>>::mutate(|cached| {
+			*cached = match cached.take() {
+				Some((count, mut peaks)) if count == leaves && pruned <= peaks.len() => {
+					peaks.truncate(peaks.len() - pruned);
+					stored
+						.into_iter()
+						.try_for_each(|hash| peaks.try_push(hash))
+						.ok()
+						.map(|_| (new_leaves, peaks))
+				},
+				_ => None,
+			}
+		});
+	}
+
+	/// Return the root of the MMR of `leaves` leaves, bagged from the cached [`PeakHashes`].
+	///
+	/// The cached peaks are rebuilt from [`Nodes`] if they're stale.
+	pub(crate) fn cached_root(
+		leaves: LeafIndex,
+	) -> Result<<T as Config<I>>::Hash, primitives::Error> {
+		let peaks = match <PeakHashes<T, I>>::get() {
+			Some((count, peaks)) if count == leaves => peaks,
+			_ => {
+				let peaks = Self::peaks(leaves)
+					.into_iter()
+					.map(<Nodes<T, I>>::get)
+					.collect::<Option<Vec<_>>>()
+					.and_then(|peaks| BoundedVec::try_from(peaks).ok())
+					.ok_or_else(|| primitives::Error::GetRoot.log_error("Missing peaks"))?;
+				<PeakHashes<T, I>>::put((leaves, &peaks));
+				peaks
+			},
+		};
+		Self::bag_peaks(peaks.into_inner())?
+			.ok_or_else(|| primitives::Error::GetRoot.log_debug("No peaks to bag"))
+	}
+
+	/// Bag the `peaks` (ordered by position) into the root, right to left.
+	pub(crate) fn bag_peaks(
+		peaks: Vec<<T as Config<I>>::Hash>,
+	) -> Result<Option<<T as Config<I>>::Hash>, primitives::Error> {
+		mmr::bag_peaks::<HashingOf<T, I>>(peaks)
+	}
+
+	/// Convert block numbers into the indices of all the leaves added by these blocks.
+	fn block_nums_to_leaf_indices(
+		block_numbers: &[T::BlockNumber],
+	) -> Result<Vec<LeafIndex>, primitives::Error> {
+		let mut leaf_indices = Vec::with_capacity(block_numbers.len());
+		for block_num in block_numbers {
+			leaf_indices.extend(Self::block_num_to_leaf_indices(*block_num)?);
+		}
+		Ok(leaf_indices)
+	}
+
+	/// Return the number of leaves of the MMR at `best_known_block_number` (or the current
+	/// block) and the indices of the leaves added by `block_numbers`.
+	fn proof_leaf_indices(
+		block_numbers: &[T::BlockNumber],
 		best_known_block_number: Option<T::BlockNumber>,
-	) -> Result<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error> {
+	) -> Result<(LeafIndex, Vec<LeafIndex>), primitives::Error> {
 		// check whether best_known_block_number provided, else use current best block
 		let best_known_block_number =
 			best_known_block_number.unwrap_or_else(|| <frame_system::Pallet<T>>::block_number());
 
-		let leaves_count =
-			Self::block_num_to_leaf_index(best_known_block_number)?.saturating_add(1);
+		let leaves_count = Self::leaf_count_at_block(best_known_block_number)?;
 
 		// we need to translate the block_numbers into leaf indices.
-		let leaf_indices = block_numbers
-			.iter()
-			.map(|block_num| -> Result<LeafIndex, primitives::Error> {
-				Self::block_num_to_leaf_index(*block_num)
-			})
-			.collect::<Result<Vec<LeafIndex>, _>>()?;
+		let leaf_indices = Self::block_nums_to_leaf_indices(block_numbers)?;
+		Ok((leaves_count, leaf_indices))
+	}
 
-		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves_count);
-		mmr.generate_proof(leaf_indices)
+	/// Generate an MMR proof for the given `block_numbers` using on-chain nodes only.
+	///
+	/// Unlike [Self::generate_proof] this method does not require the Off-chain DB, but it
+	/// only works if [`Config::STORE_ALL_NODES`] is set (it will return an error otherwise).
+	/// Note that only the hashes of the leaves are kept on-chain, so the leaves content is
+	/// not returned.
+	pub fn generate_onchain_proof(
+		block_numbers: Vec<T::BlockNumber>,
+	) -> Result<primitives::Proof<<T as Config<I>>::Hash>, primitives::Error> {
+		let leaf_indices = Self::block_nums_to_leaf_indices(&block_numbers)?;
+
+		let mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> = mmr::Mmr::new(Self::mmr_leaves());
+		mmr.generate_onchain_proof(leaf_indices)
+	}
+
+	/// Rebuild the Off-chain DB entries of the MMR from given `leaves`.
+	///
+	/// Replays the `leaves` (consecutive, starting from leaf index `0`) to recompute all the
+	/// inner nodes and writes them back to the Off-chain DB, so that proofs can be generated
+	/// again after the Off-chain DB was lost. Returns the number of reindexed leaves. Nothing is
+	/// written (failing with [`primitives::Error::Verify`]) unless the peaks of the rebuilt MMR
+	/// bag into its on-chain root, i.e. [`RootHash`] if all the leaves are given, or the
+	/// [`Pallet::historical_root`] otherwise.
+	///
+	/// Note this method can only be used from an off-chain context (Offchain Worker).
+	pub fn reindex_offchain(
+		leaves: impl Iterator<Item = (TypedLeafIndex, LeafOf<T, I>)>,
+	) -> Result<LeafIndex, primitives::Error> {
+		use mmr::storage::{OffchainStorage, Storage};
+		Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::reindex_offchain(
+			leaves.map(|(leaf_index, leaf)| (leaf_index, NodeLeafOf::<T, I>::new(leaf))),
+		)
+	}
+
+	/// Return the positions and hashes of all the MMR nodes appending `leaves` would create.
+	///
+	/// This includes the leaves and all the inner nodes created by merging them, even those
+	/// which are not going to be peaks (and hence not stored on-chain). External indexers can use
+	/// it to maintain a complete copy of the MMR, e.g. by calling it with the leaf of the next
+	/// block at the state of the current one. Note the changes to the MMR are not persisted.
+	pub fn nodes_created_by_append(
+		leaves: Vec<LeafOf<T, I>>,
+	) -> Result<Vec<(NodeIndex, <T as Config<I>>::Hash)>, primitives::Error> {
+		let mut mmr: ModuleMmr<mmr::storage::RuntimeStorage, T, I> =
+			mmr::Mmr::new(Self::mmr_leaves());
+		mmr.append_collecting(leaves.into_iter().map(NodeLeafOf::<T, I>::new).collect())
+	}
+
+	/// Return the storage keys a light client needs to read to recompute the current MMR root.
+	///
+	/// These are the key of [`NumberOfLeaves`], followed by the keys of all the current peaks in
+	/// [`Nodes`] (ordered by position). A state proof of these keys is enough to trustlessly
+	/// recompute the root, by bagging the peaks of the MMR with the proven number of leaves.
+	pub fn root_state_proof_keys() -> Vec<Vec<u8>> {
+		let leaves_key = <NumberOfLeaves<T, I>>::hashed_key().to_vec();
+		sp_std::iter::once(leaves_key)
+			.chain(Self::peaks(Self::mmr_leaves()).into_iter().map(<Nodes<T, I>>::hashed_key_for))
+			.collect()
 	}
 
 	/// Return the on-chain MMR root hash.
@@ -364,6 +2641,162 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
 		Self::mmr_root_hash()
 	}
 
+	/// Return the commitment to the on-chain MMR root, see [`Config::RootWrapper`].
+	pub fn mmr_root_commitment() -> <T as Config<I>>::Hash {
+		<T::RootWrapper as primitives::RootWrapper<_>>::wrap_root(&Self::mmr_root_hash())
+	}
+
+	/// Pass the commitment to the new MMR `root` to [`Config::OnNewRoot`].
+	fn notify_new_root(root: &<T as Config<I>>::Hash) {
+		let commitment = <T::RootWrapper as primitives::RootWrapper<_>>::wrap_root(root);
+		<T::OnNewRoot as primitives::OnNewRoot<_>>::on_new_root(&commitment);
+	}
+
+	/// Build a proof that the peak at `peak_index` (ordered by position) of the current MMR is
+	/// bagged into its root.
+	///
+	/// The proof consists of the hashes of the other peaks needed to reconstruct the root from
+	/// the peak, see [root_from_peak].
+	pub fn peak_bagging_proof(
+		peak_index: usize,
+	) -> Result<Vec<<T as Config<I>>::Hash>, primitives::Error> {
+		let peaks = Self::peaks(Self::mmr_leaves())
+			.into_iter()
+			.map(|pos| {
+				<Nodes<T, I>>::get(pos).ok_or_else(|| {
+					primitives::Error::GenerateProof.log_error(("Missing peak", pos))
+				})
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		mmr::peak_bagging_proof::<HashingOf<T, I>>(peaks, peak_index)
+	}
+
+	/// Return the positions of all the MMR nodes currently present in the [`Nodes`] storage.
+	///
+	/// These are exactly the peaks of the MMR, or all of its nodes if
+	/// [`Config::STORE_ALL_NODES`] is set. The positions are returned in ascending order.
+	pub fn onchain_node_indices() -> Vec<TypedNodeIndex> {
+		let mut indices = <Nodes<T, I>>::iter_keys().map(TypedNodeIndex).collect::<Vec<_>>();
+		indices.sort();
+		indices
+	}
+
+	/// Return the weight of generating a proof of the leaf `leaf_index` on-chain.
+	///
+	/// The weight is derived from the number of nodes in the proof of the leaf in the current
+	/// MMR (see [`WeightInfo::generate_proof`]), so that on-chain proof generation (e.g. see
+	/// [`Pallet::generate_onchain_proof`]) can be charged for.
+	pub fn proof_weight(leaf_index: TypedLeafIndex) -> Weight {
+		let nodes = Self::proof_node_indices(leaf_index).len();
+		T::WeightInfo::generate_proof(nodes as u32)
+	}
+
+	/// Return the positions of the nodes in the proof of the leaf `leaf_index` in the current
+	/// MMR (see [`NodesUtils::proof_node_indices`]).
+	pub fn proof_node_indices(leaf_index: TypedLeafIndex) -> Vec<TypedNodeIndex> {
+		Self::compute_proof_positions(leaf_index, NodesUtils::new(Self::mmr_leaves()).size())
+	}
+
+	/// Return the positions of the nodes in the proof of the leaf `leaf_index` in the MMR of
+	/// given `size`.
+	///
+	/// Unlike [`Self::proof_node_indices`], this doesn't access the storage: the positions only
+	/// depend on the leaf index and the size of the MMR, so they can be memoized e.g. by an RPC
+	/// server serving proofs at a fixed size. Returns an empty collection if `size` is not a
+	/// valid MMR size, or the leaf is not part of the MMR.
+	pub fn compute_proof_positions(
+		leaf_index: TypedLeafIndex,
+		size: NodeIndex,
+	) -> Vec<TypedNodeIndex> {
+		NodesUtils::from_size(size)
+			.map(|utils| utils.proof_node_indices(leaf_index))
+			.unwrap_or_default()
+	}
+
+	/// Return the size in bytes of the SCALE-encoded proof of the leaf `leaf_index` in the
+	/// current MMR, without generating the proof.
+	///
+	/// Useful e.g. for an RPC server to enforce limits on the size of its responses before doing
+	/// the work of generating a proof.
+	pub fn estimate_proof_encoded_bytes(leaf_index: TypedLeafIndex) -> u64 {
+		use codec::{Compact, CompactLen, MaxEncodedLen};
+
+		let utils = NodesUtils::new(Self::mmr_leaves());
+		let nodes = utils.proof_node_indices(leaf_index);
+		// The peaks on the right of the leaf's peak get bagged into a single proof item.
+		let leaf_pos = NodesUtils::leaf_to_node_index(leaf_index);
+		let rhs_peaks = primitives::mmr_lib::helper::get_peaks(utils.size())
+			.into_iter()
+			.map(TypedNodeIndex)
+			.filter(|peak| *peak > leaf_pos && nodes.contains(peak))
+			.count();
+		let items = (nodes.len() - rhs_peaks + rhs_peaks.min(1)) as u64;
+
+		let hash_size = <T as Config<I>>::Hash::max_encoded_len() as u64;
+		// A single leaf index, the number of leaves and the items.
+		let leaf_indices = Compact::<u32>::compact_len(&1) as u64 + 8;
+		let leaf_count = 8;
+		let items_len = Compact::<u64>::compact_len(&items) as u64;
+		leaf_indices + leaf_count + items_len + items * hash_size
+	}
+
+	/// Return the hash of the MMR node `pos` read from the Off-chain DB, if it's there.
+	///
+	/// Useful to recover the hash of a peak missing from the on-chain [`Nodes`], see
+	/// [`Pallet::repair_peak`].
+	///
+	/// Note this method can only be used from an off-chain context.
+	pub fn offchain_node_hash(pos: TypedNodeIndex) -> Option<<T as Config<I>>::Hash> {
+		use mmr::storage::{OffchainStorage, Storage};
+		let store = Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::default();
+		primitives::mmr_lib::MMRStore::get_elem(&store, pos.0)
+			.ok()
+			.flatten()
+			.map(|node| node.hash())
+	}
+
+	/// Return the hashes of the current MMR peaks read from the Off-chain DB.
+	///
+	/// Returns `None` if any of the peaks is missing there. The peaks are returned in ascending
+	/// position order, as expected by [`Pallet::rebuild_onchain_peaks`].
+	///
+	/// Note this method can only be used from an off-chain context.
+	pub fn offchain_peaks() -> Option<Vec<<T as Config<I>>::Hash>> {
+		Self::peaks(Self::mmr_leaves())
+			.into_iter()
+			.map(|pos| Self::offchain_node_hash(TypedNodeIndex(pos)))
+			.collect()
+	}
+
+	/// Check that the Off-chain DB nodes of the current MMR peaks match the on-chain ones.
+	///
+	/// Every peak in the [`Nodes`] storage is looked up in the Off-chain DB, and the positions
+	/// of the peaks which are missing there or whose hash differs are returned. This is a cheap
+	/// (`O(log n)`) health check of the Off-chain DB.
+	///
+	/// Note this method can only be used from an off-chain context.
+	pub fn verify_offchain_peaks() -> Result<(), Vec<TypedNodeIndex>> {
+		use mmr::storage::{OffchainStorage, Storage};
+		let store = Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::default();
+		let mismatched = Self::peaks(Self::mmr_leaves())
+			.into_iter()
+			.filter(|pos| {
+				let offchain = primitives::mmr_lib::MMRStore::get_elem(&store, *pos)
+					.ok()
+					.flatten()
+					.map(|node| node.hash());
+				offchain.is_none() || offchain != <Nodes<T, I>>::get(pos)
+			})
+			.map(TypedNodeIndex)
+			.collect::<Vec<_>>();
+
+		if mismatched.is_empty() {
+			Ok(())
+		} else {
+			Err(mismatched)
+		}
+	}
+
 	/// Verify MMR proof for given `leaves`.
 	///
 	/// This method is safe to use within the runtime code.
@@ -383,6 +2816,7 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
 		}
 
 		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(proof.leaf_count);
+		let leaves = leaves.into_iter().map(NodeLeafOf::<T, I>::new).collect();
 		let is_valid = mmr.verify_leaves_proof(leaves, proof)?;
 		if is_valid {
 			Ok(())
@@ -390,4 +2824,355 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
 			Err(primitives::Error::Verify.log_debug("The proof is incorrect."))
 		}
 	}
+
+	/// Generate an MMR proof for the given `block_numbers`.
+	/// If `best_known_block_number = Some(n)`, this generates a historical proof for
+	/// the chain with head at height `n`.
+	/// Else it generates a proof for the MMR at the current block height.
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), since it requires
+	/// all the leaves to be present.
+	/// It may return an error or panic if used incorrectly.
+	pub fn generate_proof(
+		block_numbers: Vec<T::BlockNumber>,
+		best_known_block_number: Option<T::BlockNumber>,
+	) -> Result<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error> {
+		let (leaves_count, leaf_indices) =
+			Self::proof_leaf_indices(&block_numbers, best_known_block_number)?;
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves_count);
+		mmr.generate_proof(leaf_indices).map(unwrap_leaves::<T, I, _>)
+	}
+
+	/// Generate an MMR proof for the given `block_numbers`, giving up after `deadline`.
+	///
+	/// Same as [Self::generate_proof], but fails with [primitives::Error::ProofGenerationTimeout]
+	/// (reporting the number of Off-chain DB reads done so far) if generating the proof takes
+	/// longer than `deadline`, so that huge requests can't monopolize the node.
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), since it requires
+	/// all the leaves to be present.
+	pub fn generate_proof_with_deadline(
+		block_numbers: Vec<T::BlockNumber>,
+		best_known_block_number: Option<T::BlockNumber>,
+		deadline: sp_core::offchain::Duration,
+	) -> Result<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error> {
+		use mmr::storage::{OffchainStorage, Storage};
+
+		let (leaves_count, leaf_indices) =
+			Self::proof_leaf_indices(&block_numbers, best_known_block_number)?;
+
+		let store = Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::default();
+		store.set_deadline(sp_io::offchain::timestamp().add(deadline));
+		let mmr: ModuleMmr<OffchainStorage, T, I> = mmr::Mmr::with_storage(leaves_count, store);
+		mmr.generate_proof(leaf_indices)
+			.map(unwrap_leaves::<T, I, _>)
+			.map_err(|e| match e {
+				primitives::Error::ProofGenerationTimeout { .. } =>
+					primitives::Error::ProofGenerationTimeout {
+						offchain_reads: mmr.stats().offchain_reads,
+					},
+				e => e,
+			})
+	}
+
+	/// Generate an MMR proof for the most recently added leaf.
+	///
+	/// If the latest leaf is a peak on its own (i.e. the number of leaves is odd), its proof only
+	/// consists of the other peaks, which are taken from the on-chain [`Nodes`], so only the leaf
+	/// itself is read from the Off-chain DB. Otherwise (or if the leaf can't be read) this falls
+	/// back to the regular proof generation.
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), since it requires the leaf to be present.
+	pub fn generate_latest_leaf_proof(
+	) -> Result<(LeafOf<T, I>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error> {
+		use mmr::storage::{OffchainStorage, Storage};
+
+		let leaves = Self::mmr_leaves();
+		let leaf_index = leaves
+			.checked_sub(1)
+			.ok_or_else(|| primitives::Error::LeafNotFound.log_debug("The MMR is empty."))?;
+
+		let mut peaks = Self::peaks(leaves);
+		let leaf_pos = NodesUtils::leaf_to_node_index(TypedLeafIndex(leaf_index)).0;
+		if peaks.pop() == Some(leaf_pos) {
+			let items = peaks.into_iter().map(<Nodes<T, I>>::get).collect::<Option<Vec<_>>>();
+			let store = Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::default();
+			let leaf = store.get_leaves(TypedLeafIndex(leaf_index), 1)?.pop().flatten();
+			if let (Some(items), Some(leaf)) = (items, leaf) {
+				let proof =
+					primitives::Proof { leaf_indices: vec![leaf_index], leaf_count: leaves, items };
+				return Ok((leaf.into_inner(), proof))
+			}
+		}
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves);
+		let (leaves, proof) = mmr.generate_proof(vec![leaf_index]).map(unwrap_leaves::<T, I, _>)?;
+		let leaf = leaves.into_iter().next().ok_or(primitives::Error::LeafNotFound)?;
+		Ok((leaf, proof))
+	}
+
+	/// Generate an MMR proof for the leaf `leaf_index`, pairing every proof item with the
+	/// position of its node in the MMR.
+	///
+	/// The proof is generated for the MMR at the current block height, so it has to be verified
+	/// (see [verify_leaf_proof_with_positions]) with the current [`Pallet::mmr_leaves`].
+	/// Positioned proofs are self-describing, which allows auditing them and merging the
+	/// overlapping ones.
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), since it requires
+	/// all the leaves to be present.
+	pub fn generate_proof_with_positions(
+		leaf_index: LeafIndex,
+	) -> Result<(LeafOf<T, I>, Vec<(NodeIndex, <T as Config<I>>::Hash)>), primitives::Error> {
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(Self::mmr_leaves());
+		mmr.generate_proof_with_positions(leaf_index)
+			.map(|(leaf, items)| (leaf.into_inner(), items))
+	}
+
+	/// Generate an MMR proof for the contiguous range of leaves from `from_leaf` to `to_leaf`
+	/// (inclusive).
+	///
+	/// The proof only contains the nodes anchoring the whole range to the root, which makes it
+	/// much smaller than separate proofs of the leaves. It's generated for the MMR at the
+	/// current block height and can be verified with [verify_range_proof].
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), since it requires
+	/// all the leaves to be present.
+	pub fn generate_range_proof(
+		from_leaf: LeafIndex,
+		to_leaf: LeafIndex,
+	) -> Result<
+		(Vec<LeafOf<T, I>>, primitives::RangeProof<<T as Config<I>>::Hash>),
+		primitives::Error,
+	> {
+		let leaves_count = Self::mmr_leaves();
+		if to_leaf >= leaves_count {
+			return Err(primitives::Error::LeafIndexOutOfRange {
+				requested: to_leaf,
+				count: leaves_count,
+			})
+		}
+		if from_leaf > to_leaf {
+			return Err(primitives::Error::InvalidLeafIndex.log_debug(("Empty range", from_leaf)))
+		}
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves_count);
+		let (leaves, proof) = mmr
+			.generate_proof((from_leaf..=to_leaf).collect())
+			.map(unwrap_leaves::<T, I, _>)?;
+		let proof = primitives::RangeProof {
+			first_leaf_index: from_leaf,
+			last_leaf_index: to_leaf,
+			leaf_count: proof.leaf_count,
+			items: proof.items,
+		};
+		Ok((leaves, proof))
+	}
+
+	/// Generate an MMR proof for all the leaves of the perfect subtree of `subtree_height`
+	/// containing the leaf `leaf_index`.
+	///
+	/// Returns the leaves of the subtree (ordered by their index) and the proof anchoring them
+	/// to the root, which allows the verifier to recompute the root of the subtree on its own.
+	/// If the subtree is not complete yet (i.e. it extends past the current leaf count), only
+	/// its existing leaves are returned and proven. The proof is generated for the MMR at the
+	/// current block height.
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), since it requires
+	/// all the leaves to be present.
+	pub fn generate_proof_with_subtree(
+		leaf_index: LeafIndex,
+		subtree_height: u32,
+	) -> Result<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error>
+	{
+		let leaves_count = Self::mmr_leaves();
+		if leaf_index >= leaves_count {
+			return Err(primitives::Error::LeafIndexOutOfRange {
+				requested: leaf_index,
+				count: leaves_count,
+			})
+		}
+
+		// Subtrees too high to be indexed span the whole MMR.
+		let (first_leaf, last_leaf) = match (1 as LeafIndex).checked_shl(subtree_height) {
+			Some(width) => {
+				let first_leaf = leaf_index - leaf_index % width;
+				(first_leaf, first_leaf.saturating_add(width - 1).min(leaves_count - 1))
+			},
+			None => (0, leaves_count - 1),
+		};
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves_count);
+		mmr.generate_proof((first_leaf..=last_leaf).collect())
+			.map(unwrap_leaves::<T, I, _>)
+	}
+
+	/// Export the leaves from `from_leaf` to `to_leaf` (inclusive) as a
+	/// [`primitives::PortableMmr`], along with the nodes anchoring them to the current root.
+	///
+	/// The export can be imported with [import_portable], possibly by another MMR
+	/// implementation. Exporting all the leaves (from `0`) moves the whole MMR.
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), since it requires
+	/// all the leaves to be present.
+	pub fn export_portable(
+		from_leaf: LeafIndex,
+		to_leaf: LeafIndex,
+	) -> Result<primitives::PortableMmr<<T as Config<I>>::Hash>, primitives::Error> {
+		use primitives::FullLeaf;
+
+		let (leaves, proof) = Self::generate_range_proof(from_leaf, to_leaf)?;
+		Ok(primitives::PortableMmr {
+			version: primitives::PORTABLE_MMR_VERSION,
+			hashing: <HashingOf<T, I> as traits::Hash>::hash(&[]),
+			leaf_count: proof.leaf_count,
+			first_leaf_index: from_leaf,
+			leaves: leaves
+				.iter()
+				.map(|leaf| leaf.using_encoded(|bytes| bytes.to_vec(), false))
+				.collect(),
+			items: proof.items,
+			root: Self::mmr_root_hash(),
+		})
+	}
+
+	/// Generate an MMR proof for the given leaves appended at genesis (see [`GenesisConfig`]).
+	///
+	/// These leaves were not added by any block, hence they are identified by their
+	/// `leaf_indices` (all lower than [`Pallet::genesis_leaves`]) instead of block numbers.
+	/// The proof is generated for the MMR at the current block height.
+	///
+	/// Note this method can only be used from an off-chain context
+	/// (Offchain Worker or Runtime API call), after the genesis leaves were reindexed
+	/// (see [`Pallet::reindex_offchain`]).
+	pub fn generate_genesis_leaves_proof(
+		leaf_indices: Vec<LeafIndex>,
+	) -> Result<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error> {
+		let genesis_leaves = Self::genesis_leaves();
+		if let Some(leaf_index) = leaf_indices.iter().find(|i| **i >= genesis_leaves) {
+			return Err(
+				primitives::Error::InvalidLeafIndex.log_debug(("Not a genesis leaf", *leaf_index))
+			)
+		}
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(Self::mmr_leaves());
+		mmr.generate_proof(leaf_indices).map(unwrap_leaves::<T, I, _>)
+	}
+
+	/// Generate an MMR proof for the given `block_numbers`, along with the statistics of the
+	/// Off-chain DB accesses it required.
+	///
+	/// Same as [Self::generate_proof], but also reports how expensive generating the proof was,
+	/// which is useful for profiling (e.g. tuning [Self::generate_proofs] batching).
+	pub fn generate_proof_with_stats(
+		block_numbers: Vec<T::BlockNumber>,
+		best_known_block_number: Option<T::BlockNumber>,
+	) -> Result<
+		(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>, primitives::ProofGenStats),
+		primitives::Error,
+	> {
+		let (leaves_count, leaf_indices) =
+			Self::proof_leaf_indices(&block_numbers, best_known_block_number)?;
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves_count);
+		let (leaves, proof) = mmr.generate_proof(leaf_indices).map(unwrap_leaves::<T, I, _>)?;
+		Ok((leaves, proof, mmr.stats()))
+	}
+
+	/// Generate a separate MMR proof for each of the `batches` of block numbers.
+	///
+	/// All the nodes required by the proofs are read from the Off-chain DB once, up front,
+	/// so the nodes shared by several proofs (and the hashes of the blocks that added them)
+	/// are only looked up once. See [Self::generate_proof] for details on
+	/// `best_known_block_number`.
+	///
+	/// Note this method can only be used from an off-chain context.
+	pub fn generate_proofs(
+		batches: Vec<Vec<T::BlockNumber>>,
+		best_known_block_number: Option<T::BlockNumber>,
+	) -> Result<
+		Vec<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>)>,
+		primitives::Error,
+	> {
+		let (leaves_count, _) = Self::proof_leaf_indices(&[], best_known_block_number)?;
+		let batches = batches
+			.iter()
+			.map(|batch| Self::block_nums_to_leaf_indices(batch))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		use mmr::storage::{OffchainStorage, Storage};
+		let mut store = Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::default();
+		store.prefetch(&batches.concat().into_iter().map(TypedLeafIndex).collect::<Vec<_>>())?;
+		let mmr: ModuleMmr<OffchainStorage, T, I> = mmr::Mmr::with_storage(leaves_count, store);
+		batches
+			.into_iter()
+			.map(|leaf_indices| mmr.generate_proof(leaf_indices).map(unwrap_leaves::<T, I, _>))
+			.collect()
+	}
+
+	/// Generate an MMR proof for the given `block_numbers` on the fork described by `fork_view`.
+	///
+	/// Unlike [Self::generate_proof], which reads the nodes of the canonical chain, the nodes
+	/// added by the leaves present in the [primitives::ForkView] are read from the Off-chain DB
+	/// entries of that fork. See [Self::generate_proof] for details on `best_known_block_number`.
+	///
+	/// Note this method can only be used from an off-chain context.
+	pub fn generate_proof_on_fork(
+		block_numbers: Vec<T::BlockNumber>,
+		best_known_block_number: Option<T::BlockNumber>,
+		fork_view: primitives::ForkView<<T as frame_system::Config>::Hash>,
+	) -> Result<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error> {
+		let (leaves_count, leaf_indices) =
+			Self::proof_leaf_indices(&block_numbers, best_known_block_number)?;
+
+		use mmr::storage::{OffchainStorage, Storage};
+		let store =
+			Storage::<OffchainStorage, T, I, NodeLeafOf<T, I>>::default().with_fork_view(fork_view);
+		let mmr: ModuleMmr<OffchainStorage, T, I> = mmr::Mmr::with_storage(leaves_count, store);
+		mmr.generate_proof(leaf_indices).map(unwrap_leaves::<T, I, _>)
+	}
+
+	/// Generate an MMR proof for the given `block_numbers`, omitting the nodes derivable from the
+	/// leaves already known to the verifier (as described by the `hint`).
+	///
+	/// The leaves listed in the `hint` are appended to the proof's `leaf_indices` (unless they
+	/// were requested anyway), but their content is not returned, the verifier has to provide
+	/// them (see [verify_compressed_leaves_proof]). See [Self::generate_proof] for details on
+	/// `best_known_block_number`.
+	///
+	/// Note this method can only be used from an off-chain context.
+	pub fn generate_compressed_proof(
+		block_numbers: Vec<T::BlockNumber>,
+		best_known_block_number: Option<T::BlockNumber>,
+		hint: primitives::CompressionHint,
+	) -> Result<(Vec<LeafOf<T, I>>, primitives::Proof<<T as Config<I>>::Hash>), primitives::Error> {
+		let (leaves_count, leaf_indices) =
+			Self::proof_leaf_indices(&block_numbers, best_known_block_number)?;
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves_count);
+		mmr.generate_compressed_proof(leaf_indices, &hint).map(unwrap_leaves::<T, I, _>)
+	}
+
+	/// Generate an MMR ancestry proof, proving that the MMR at `prev_block_number` is a prefix of
+	/// the MMR at `best_known_block_number` (or at the current block height if `None`).
+	///
+	/// Note this method can only be used from an off-chain context.
+	pub fn generate_ancestry_proof(
+		prev_block_number: T::BlockNumber,
+		best_known_block_number: Option<T::BlockNumber>,
+	) -> Result<primitives::AncestryProof<<T as Config<I>>::Hash>, primitives::Error> {
+		let (leaves_count, _) = Self::proof_leaf_indices(&[], best_known_block_number)?;
+		let prev_leaves_count = Self::leaf_count_at_block(prev_block_number)?;
+
+		let mmr: ModuleMmr<mmr::storage::OffchainStorage, T, I> = mmr::Mmr::new(leaves_count);
+		mmr.generate_ancestry_proof(prev_leaves_count)
+	}
 }
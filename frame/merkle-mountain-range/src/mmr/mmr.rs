@@ -17,14 +17,18 @@
 
 use crate::{
 	mmr::{
-		storage::{OffchainStorage, RuntimeStorage, Storage},
+		storage::{self, OffchainStorage, RuntimeStorage, Storage},
 		Hasher, Node, NodeOf,
 	},
-	primitives::{self, Error, NodeIndex},
+	primitives::{self, Error, LeafIndex, NodeIndex, TypedLeafIndex, TypedNodeIndex},
 	Config, HashingOf,
 };
-use sp_mmr_primitives::{mmr_lib, utils::NodesUtils};
-use sp_std::prelude::*;
+use sp_mmr_primitives::{mmr_lib, mmr_lib::helper, utils::NodesUtils};
+use sp_std::{
+	collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+	fmt,
+	prelude::*,
+};
 
 /// Stateless verification of the proof for a batch of leaves.
 /// Note, the leaves should be sorted such that corresponding leaves and leaf indices have the
@@ -35,6 +39,20 @@ pub fn verify_leaves_proof<H, L>(
 	leaves: Vec<Node<H, L>>,
 	proof: primitives::Proof<H::Output>,
 ) -> Result<bool, Error>
+where
+	H: sp_runtime::traits::Hash,
+	L: primitives::FullLeaf,
+{
+	Ok(reconstruct_root::<H, L>(leaves, proof)? == root)
+}
+
+/// Compute the root of the MMR from a proof for a batch of leaves.
+///
+/// The leaves are expected to be sorted the same way as for [verify_leaves_proof].
+pub fn reconstruct_root<H, L>(
+	leaves: Vec<Node<H, L>>,
+	proof: primitives::Proof<H::Output>,
+) -> Result<H::Output, Error>
 where
 	H: sp_runtime::traits::Hash,
 	L: primitives::FullLeaf,
@@ -56,10 +74,506 @@ where
 		size,
 		proof.items.into_iter().map(Node::Hash).collect(),
 	);
-	p.verify(Node::Hash(root), leaves_and_position_data)
+	p.calculate_root(leaves_and_position_data)
+		.map(|root| root.hash())
 		.map_err(|e| Error::Verify.log_debug(e))
 }
 
+/// A node of an MMR whose hasher is only known at runtime.
+#[derive(Clone)]
+struct DynNode<'a, Hash> {
+	hash: Hash,
+	hasher: &'a dyn Fn(&[u8]) -> Hash,
+}
+
+impl<'a, Hash: PartialEq> PartialEq for DynNode<'a, Hash> {
+	fn eq(&self, other: &Self) -> bool {
+		self.hash == other.hash
+	}
+}
+
+impl<'a, Hash: fmt::Debug> fmt::Debug for DynNode<'a, Hash> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.hash.fmt(f)
+	}
+}
+
+/// Merging of [DynNode]s, with the hasher they carry.
+struct DynHasher<'a, Hash>(sp_std::marker::PhantomData<(&'a (), Hash)>);
+
+impl<'a, Hash: AsRef<[u8]>> mmr_lib::Merge for DynHasher<'a, Hash> {
+	type Item = DynNode<'a, Hash>;
+
+	fn merge(left: &Self::Item, right: &Self::Item) -> mmr_lib::Result<Self::Item> {
+		let mut concat = left.hash.as_ref().to_vec();
+		concat.extend_from_slice(right.hash.as_ref());
+
+		Ok(DynNode { hash: (left.hasher)(&concat), hasher: left.hasher })
+	}
+}
+
+/// Stateless verification of the proof for a batch of leaves, hashing with `hasher`.
+///
+/// Same as [verify_leaves_proof], except that the leaves are given by their hashes (see
+/// [Node::hash]) and the nodes are merged with the `hasher` supplied at runtime.
+pub fn verify_leaves_proof_with_hasher<Hash>(
+	hasher: &dyn Fn(&[u8]) -> Hash,
+	root: Hash,
+	leaf_hashes: Vec<Hash>,
+	proof: primitives::Proof<Hash>,
+) -> Result<bool, Error>
+where
+	Hash: AsRef<[u8]> + Clone + PartialEq + fmt::Debug,
+{
+	let size = NodesUtils::new(proof.leaf_count).size();
+
+	if leaf_hashes.len() != proof.leaf_indices.len() {
+		return Err(Error::Verify.log_debug("Proof leaf_indices not same length with leaves"))
+	}
+
+	let node = |hash| DynNode { hash, hasher };
+	let leaves_and_position_data = proof
+		.leaf_indices
+		.into_iter()
+		.map(|index| mmr_lib::leaf_index_to_pos(index))
+		.zip(leaf_hashes.into_iter().map(node))
+		.collect();
+
+	let p = mmr_lib::MerkleProof::<DynNode<Hash>, DynHasher<Hash>>::new(
+		size,
+		proof.items.into_iter().map(node).collect(),
+	);
+	p.calculate_root(leaves_and_position_data)
+		.map(|computed| computed.hash == root)
+		.map_err(|e| Error::Verify.log_debug(e))
+}
+
+/// Stateless verification of an ancestry proof against the current MMR `root`.
+///
+/// Returns the root of the prior MMR (computed from the proof's `prev_peaks`), which the caller
+/// should compare against the prior root it trusts. The proof `items` have to be exactly the
+/// roots of the subtrees containing only the leaves appended since the prior MMR, so that all
+/// the current peaks are rebuilt from the prior ones.
+pub fn verify_ancestry_proof<H>(
+	root: H::Output,
+	proof: primitives::AncestryProof<H::Output>,
+) -> Result<H::Output, Error>
+where
+	H: sp_runtime::traits::Hash,
+{
+	if proof.prev_leaf_count == 0 || proof.prev_leaf_count > proof.leaf_count {
+		return Err(Error::Verify.log_debug(("Invalid leaf counts", proof.prev_leaf_count)))
+	}
+
+	let prev_size = NodesUtils::new(proof.prev_leaf_count).size();
+	let prev_peaks = helper::get_peaks(prev_size);
+	if prev_peaks.len() != proof.prev_peaks.len() {
+		return Err(Error::Verify.log_debug("Unexpected number of prior peaks"))
+	}
+
+	// Only the nodes the prover is expected to provide are accepted, so that the current peaks
+	// have to be rebuilt from the prior ones, rather than being provided by the proof.
+	let size = NodesUtils::new(proof.leaf_count).size();
+	let positions = ancestry_proof_item_positions(prev_size, size)
+		.map_err(|pos| Error::Verify.log_debug(("Inconsistent prior peaks", pos)))?;
+	if !proof.items.iter().map(|(pos, _)| pos).eq(positions.iter()) {
+		return Err(Error::Verify.log_debug("Unexpected ancestry proof items"))
+	}
+
+	let mut nodes: BTreeMap<NodeIndex, Node<H, ()>> = prev_peaks
+		.into_iter()
+		.zip(proof.prev_peaks.into_iter().map(Node::Hash))
+		.collect();
+	let prev_root = NodesStore(nodes.clone()).root(prev_size)?;
+
+	nodes.extend(proof.items.into_iter().map(|(pos, hash)| (pos, Node::Hash(hash))));
+	let peaks = helper::get_peaks(size)
+		.into_iter()
+		.map(|peak| -> Result<_, Error> {
+			reconstruct_node::<H>(&mut nodes, peak)?;
+			Ok((peak, nodes[&peak].clone()))
+		})
+		.collect::<Result<BTreeMap<_, _>, _>>()?;
+
+	if NodesStore(peaks).root(size)? != Node::Hash(root) {
+		return Err(Error::Verify.log_debug(("The ancestry proof is incorrect.", root)))
+	}
+	Ok(prev_root.hash())
+}
+
+/// Positions of the items of an ancestry proof from the MMR of `prev_size` to the one of `size`.
+///
+/// These are the roots of the maximal subtrees containing only the leaves appended since the
+/// prior MMR, found by descending from the current peaks until reaching either the prior peaks
+/// or such subtrees. Returns the position of the leaf the descent got stuck at if the prior MMR
+/// isn't a prefix of the current one.
+fn ancestry_proof_item_positions(
+	prev_size: NodeIndex,
+	size: NodeIndex,
+) -> Result<Vec<NodeIndex>, NodeIndex> {
+	let prev_peaks = helper::get_peaks(prev_size);
+	let mut items = Vec::new();
+	let mut to_visit = helper::get_peaks(size);
+	to_visit.reverse();
+	while let Some(pos) = to_visit.pop() {
+		let height = helper::pos_height_in_tree(pos);
+		if prev_peaks.contains(&pos) {
+			continue
+		} else if pos + 2 >= prev_size + (2 << height) {
+			// The leftmost leaf of the subtree is at `pos + 2 - 2^(height + 1)`.
+			items.push(pos);
+		} else if height > 0 {
+			// Visit the right child after the left one, to keep the items ordered.
+			to_visit.push(pos - 1);
+			to_visit.push(pos - (1 << height));
+		} else {
+			return Err(pos)
+		}
+	}
+	Ok(items)
+}
+
+/// Compute node `pos` from its descendants in `nodes`, unless it's already known.
+fn reconstruct_node<H: sp_runtime::traits::Hash>(
+	nodes: &mut BTreeMap<NodeIndex, Node<H, ()>>,
+	pos: NodeIndex,
+) -> Result<(), Error> {
+	if nodes.contains_key(&pos) {
+		return Ok(())
+	}
+	let height = helper::pos_height_in_tree(pos);
+	if height == 0 {
+		return Err(Error::Verify.log_debug(("Missing node", pos)))
+	}
+
+	let (left, right) = (pos - (1 << height), pos - 1);
+	reconstruct_node::<H>(nodes, left)?;
+	reconstruct_node::<H>(nodes, right)?;
+	let node = <Hasher<H, ()> as mmr_lib::Merge>::merge(&nodes[&left], &nodes[&right])
+		.map_err(|e| Error::Verify.log_debug(e))?;
+	nodes.insert(pos, node);
+	Ok(())
+}
+
+/// An in-memory store of a subset of MMR nodes, used to bag the peaks.
+struct NodesStore<H: sp_runtime::traits::Hash>(BTreeMap<NodeIndex, Node<H, ()>>);
+
+impl<H: sp_runtime::traits::Hash> mmr_lib::MMRStore<Node<H, ()>> for NodesStore<H> {
+	fn get_elem(&self, pos: NodeIndex) -> mmr_lib::Result<Option<Node<H, ()>>> {
+		Ok(self.0.get(&pos).cloned())
+	}
+
+	fn append(&mut self, _: NodeIndex, _: Vec<Node<H, ()>>) -> mmr_lib::Result<()> {
+		Err(mmr_lib::Error::InconsistentStore)
+	}
+}
+
+impl<H: sp_runtime::traits::Hash> NodesStore<H> {
+	/// Root of the MMR of given `size`, bagging the peaks present in the store.
+	fn root(self, size: NodeIndex) -> Result<Node<H, ()>, Error> {
+		mmr_lib::MMR::<_, Hasher<H, ()>, _>::new(size, self)
+			.get_root()
+			.map_err(|e| Error::Verify.log_debug(e))
+	}
+}
+
+/// An MMR store which holds the position of every node, instead of its hash.
+struct PositionsStore;
+
+impl mmr_lib::MMRStore<Vec<NodeIndex>> for PositionsStore {
+	fn get_elem(&self, pos: NodeIndex) -> mmr_lib::Result<Option<Vec<NodeIndex>>> {
+		Ok(Some(vec![pos]))
+	}
+
+	fn append(&mut self, _: NodeIndex, _: Vec<Vec<NodeIndex>>) -> mmr_lib::Result<()> {
+		Err(mmr_lib::Error::InconsistentStore)
+	}
+}
+
+/// Merging of the nodes of the [PositionsStore], collecting the positions of the merged nodes.
+struct PositionsMerge;
+
+impl mmr_lib::Merge for PositionsMerge {
+	type Item = Vec<NodeIndex>;
+
+	fn merge(left: &Self::Item, right: &Self::Item) -> mmr_lib::Result<Self::Item> {
+		Ok(left.iter().chain(right).copied().collect())
+	}
+}
+
+/// Whether the node at `pos` can be computed from the leaves at the `known` positions.
+fn is_derivable(known: &BTreeSet<NodeIndex>, pos: NodeIndex) -> bool {
+	match helper::pos_height_in_tree(pos) {
+		0 => known.contains(&pos),
+		height => is_derivable(known, pos - (1 << height)) && is_derivable(known, pos - 1),
+	}
+}
+
+/// List the items of the proof for the `leaf_indices` in the MMR with `leaf_count` leaves, as
+/// the positions of the nodes they commit to, along with whether they can be derived from the
+/// leaves at `known_leaf_indices` (and thus omitted from a compressed proof).
+///
+/// Each item is a single node, except for the bag of the peaks on the right of the proven
+/// leaves. The items are ordered the same way as in the proof generated by [mmr_lib].
+fn compressible_proof_items(
+	leaf_count: LeafIndex,
+	leaf_indices: &[LeafIndex],
+	known_leaf_indices: &[LeafIndex],
+) -> mmr_lib::Result<Vec<(Vec<NodeIndex>, bool)>> {
+	let size = NodesUtils::new(leaf_count).size();
+	let positions = leaf_indices.iter().map(|index| mmr_lib::leaf_index_to_pos(*index)).collect();
+	let known = known_leaf_indices
+		.iter()
+		.map(|index| mmr_lib::leaf_index_to_pos(*index))
+		.collect::<BTreeSet<_>>();
+
+	let proof =
+		mmr_lib::MMR::<_, PositionsMerge, _>::new(size, PositionsStore).gen_proof(positions)?;
+	Ok(proof
+		.proof_items()
+		.iter()
+		.map(|item| {
+			let derivable = item.iter().all(|pos| is_derivable(&known, *pos));
+			(item.clone(), derivable)
+		})
+		.collect())
+}
+
+/// Remove the items derivable from the leaves at `known_leaf_indices` from the `proof`.
+///
+/// The indices of the known leaves (unless proven anyway) are appended to the proof's
+/// `leaf_indices`, so that the verifier knows which leaves to supply (see [decompress_proof]).
+pub fn compress_proof<Hash>(
+	mut proof: primitives::Proof<Hash>,
+	known_leaf_indices: &[LeafIndex],
+) -> Result<primitives::Proof<Hash>, Error> {
+	let mut known = Vec::with_capacity(known_leaf_indices.len());
+	for index in known_leaf_indices {
+		if !proof.leaf_indices.contains(index) && !known.contains(index) {
+			known.push(*index);
+		}
+	}
+	let known_leaf_indices = known;
+
+	let items =
+		compressible_proof_items(proof.leaf_count, &proof.leaf_indices, &known_leaf_indices)
+			.map_err(|e| Error::GenerateProof.log_debug(e))?;
+	if items.len() != proof.items.len() {
+		return Err(Error::GenerateProof.log_error("Unexpected number of proof items"))
+	}
+	proof.items = proof
+		.items
+		.into_iter()
+		.zip(items)
+		.filter_map(|(hash, (_, derivable))| (!derivable).then_some(hash))
+		.collect();
+	proof.leaf_indices.extend(known_leaf_indices);
+	Ok(proof)
+}
+
+/// Restore the items of a proof generated by [compress_proof], which were omitted since they can
+/// be derived from the `known_leaves`.
+///
+/// The `known_leaves` have to be ordered the same way as their indices at the end of the proof's
+/// `leaf_indices`. Returns the regular proof of the remaining leaves.
+pub fn decompress_proof<H, L>(
+	known_leaves: Vec<Node<H, L>>,
+	mut proof: primitives::Proof<H::Output>,
+) -> Result<primitives::Proof<H::Output>, Error>
+where
+	H: sp_runtime::traits::Hash,
+	L: primitives::FullLeaf,
+{
+	let proven_count = proof
+		.leaf_indices
+		.len()
+		.checked_sub(known_leaves.len())
+		.ok_or_else(|| Error::Verify.log_debug("More known leaves than leaf indices"))?;
+	let known_leaf_indices = proof.leaf_indices.split_off(proven_count);
+	let items =
+		compressible_proof_items(proof.leaf_count, &proof.leaf_indices, &known_leaf_indices)
+			.map_err(|e| Error::Verify.log_debug(e))?;
+
+	let mut nodes: BTreeMap<NodeIndex, Node<H, ()>> = known_leaf_indices
+		.into_iter()
+		.map(mmr_lib::leaf_index_to_pos)
+		.zip(known_leaves.into_iter().map(|leaf| Node::Hash(leaf.hash())))
+		.collect();
+	let mut sent_items = proof.items.into_iter();
+	proof.items = items
+		.into_iter()
+		.map(|(positions, derivable)| {
+			if !derivable {
+				return sent_items
+					.next()
+					.ok_or_else(|| Error::Verify.log_debug("Missing proof items"))
+			}
+			let peaks = positions
+				.into_iter()
+				.map(|pos| {
+					reconstruct_node::<H>(&mut nodes, pos)?;
+					Ok(nodes[&pos].hash())
+				})
+				.collect::<Result<Vec<_>, Error>>()?;
+			bag_peaks::<H>(peaks)?.ok_or_else(|| Error::Verify.log_debug("Empty proof item"))
+		})
+		.collect::<Result<_, _>>()?;
+	if sent_items.next().is_some() {
+		return Err(Error::Verify.log_debug("Unexpected proof items"))
+	}
+	Ok(proof)
+}
+
+/// Merge two peaks the same way the MMR bags them into the root.
+fn merge_peaks<H: sp_runtime::traits::Hash>(
+	right: H::Output,
+	left: H::Output,
+) -> Result<H::Output, Error> {
+	<Hasher<H, ()> as mmr_lib::Merge>::merge(&Node::Hash(right), &Node::Hash(left))
+		.map(|node| node.hash())
+		.map_err(|e| Error::Verify.log_debug(e))
+}
+
+/// Build a proof that the peak at `peak_index` (among the `peaks`, ordered by position) is
+/// bagged into the MMR root.
+///
+/// The proof consists of the bag of the peaks to the right of the peak (if there are any),
+/// followed by the peaks to the left of it, from right to left (see [root_from_peak]).
+pub fn peak_bagging_proof<H: sp_runtime::traits::Hash>(
+	mut peaks: Vec<H::Output>,
+	peak_index: usize,
+) -> Result<Vec<H::Output>, Error> {
+	if peak_index >= peaks.len() {
+		return Err(Error::GenerateProof.log_debug(("Invalid peak index", peak_index)))
+	}
+
+	let mut proof = Vec::with_capacity(peak_index + 1);
+	if let Some(bag) = bag_peaks::<H>(peaks.split_off(peak_index + 1))? {
+		proof.push(bag);
+	}
+	proof.extend(peaks[..peak_index].iter().rev());
+	Ok(proof)
+}
+
+/// Bag the `peaks` (ordered by position) into a single hash, or return `None` if there are
+/// no peaks.
+pub fn bag_peaks<H: sp_runtime::traits::Hash>(
+	mut peaks: Vec<H::Output>,
+) -> Result<Option<H::Output>, Error> {
+	// The peaks are bagged right to left.
+	let mut bag = match peaks.pop() {
+		Some(peak) => peak,
+		None => return Ok(None),
+	};
+	while let Some(left) = peaks.pop() {
+		bag = merge_peaks::<H>(bag, left)?;
+	}
+	Ok(Some(bag))
+}
+
+/// Convert the positioned proof `items` of the leaf `leaf_index` (in the MMR with `leaf_count`
+/// leaves) into a regular [primitives::Proof].
+///
+/// The positions of the `items` have to be exactly the ones listed by
+/// [NodesUtils::proof_node_indices]. The peaks on the right of the leaf's peak get bagged into
+/// a single proof item, the same way [mmr_lib] does.
+pub fn proof_from_positioned_items<H: sp_runtime::traits::Hash>(
+	leaf_index: LeafIndex,
+	leaf_count: LeafIndex,
+	items: Vec<(NodeIndex, H::Output)>,
+) -> Result<primitives::Proof<H::Output>, Error> {
+	if leaf_index >= leaf_count {
+		return Err(Error::Verify.log_debug(("Leaf index out of range", leaf_index, leaf_count)))
+	}
+	let positions = NodesUtils::new(leaf_count).proof_node_indices(TypedLeafIndex(leaf_index));
+	if positions.len() != items.len() ||
+		positions.iter().zip(&items).any(|(expected, (pos, _))| expected.0 != *pos)
+	{
+		return Err(Error::Verify.log_debug(("Unexpected positions of the proof items", leaf_index)))
+	}
+
+	// All the peaks on the left of the leaf are lower than its position.
+	let leaf_pos = mmr_lib::leaf_index_to_pos(leaf_index);
+	let peaks = helper::get_peaks(NodesUtils::new(leaf_count).size());
+	let rhs_peaks_count = peaks.iter().filter(|peak| **peak > leaf_pos).count() -
+		usize::from(!peaks.contains(&leaf_pos));
+
+	let mut items = items.into_iter().map(|(_, hash)| hash).collect::<Vec<_>>();
+	let rhs_peaks = items.split_off(items.len() - rhs_peaks_count);
+	items.extend(bag_peaks::<H>(rhs_peaks)?);
+	Ok(primitives::Proof { leaf_indices: vec![leaf_index], leaf_count, items })
+}
+
+/// Compute the peak of the mountain containing the leaf `leaf_index` from the leaf's `proof`.
+///
+/// Returns the position and the hash of the peak. Only the items on the path from the leaf up
+/// to its peak are used, the items committing to the other peaks are ignored.
+pub fn reconstruct_leaf_peak<H, L>(
+	leaf: Node<H, L>,
+	leaf_index: LeafIndex,
+	proof: primitives::Proof<H::Output>,
+) -> Result<(NodeIndex, H::Output), Error>
+where
+	H: sp_runtime::traits::Hash,
+	L: primitives::FullLeaf,
+{
+	if proof.leaf_indices != [leaf_index] || leaf_index >= proof.leaf_count {
+		return Err(Error::Verify.log_debug(("Not a proof of the leaf", leaf_index)))
+	}
+
+	let utils = NodesUtils::new(proof.leaf_count);
+	let peaks = helper::get_peaks(utils.size());
+	let positions = utils.proof_node_indices(TypedLeafIndex(leaf_index));
+	let lhs_peaks_count = positions.iter().take_while(|pos| peaks.contains(&pos.0)).count();
+	let path = positions
+		.iter()
+		.skip(lhs_peaks_count)
+		.take_while(|pos| !peaks.contains(&pos.0))
+		.map(|pos| pos.0)
+		.collect::<Vec<_>>();
+	let has_rhs_peaks = positions.len() > lhs_peaks_count + path.len();
+	if proof.items.len() != lhs_peaks_count + path.len() + has_rhs_peaks as usize {
+		return Err(Error::Verify.log_debug(("Unexpected number of proof items", leaf_index)))
+	}
+
+	let mut pos = mmr_lib::leaf_index_to_pos(leaf_index);
+	let mut node = leaf;
+	for (sibling_pos, sibling) in path.into_iter().zip(&proof.items[lhs_peaks_count..]) {
+		let sibling = Node::Hash(*sibling);
+		// The parent follows the right child.
+		let (left, right) = if sibling_pos < pos { (sibling, node) } else { (node, sibling) };
+		node = <Hasher<H, L> as mmr_lib::Merge>::merge(&left, &right)
+			.map_err(|e| Error::Verify.log_debug(e))?;
+		pos = pos.max(sibling_pos) + 1;
+	}
+	Ok((pos, node.hash()))
+}
+
+/// Compute the root of the MMR with `number_of_peaks` peaks from the peak at `peak_index`
+/// and its bagging `proof` (see [peak_bagging_proof]).
+pub fn root_from_peak<H: sp_runtime::traits::Hash>(
+	peak: H::Output,
+	peak_index: usize,
+	number_of_peaks: usize,
+	proof: Vec<H::Output>,
+) -> Result<H::Output, Error> {
+	let has_rhs_peaks = peak_index + 1 < number_of_peaks;
+	if peak_index >= number_of_peaks || proof.len() != peak_index + has_rhs_peaks as usize {
+		return Err(Error::Verify.log_debug(("Unexpected peak bagging proof", peak_index)))
+	}
+
+	let mut items = proof.into_iter();
+	let mut root = peak;
+	if has_rhs_peaks {
+		let rhs_bag = items.next().expect("the proof length was checked above; qed");
+		root = merge_peaks::<H>(rhs_bag, root)?;
+	}
+	for left in items {
+		root = merge_peaks::<H>(root, left)?;
+	}
+	Ok(root)
+}
+
 /// A wrapper around an MMR library to expose limited functionality.
 ///
 /// Available functions depend on the storage kind ([Runtime](crate::mmr::storage::RuntimeStorage)
@@ -72,7 +586,10 @@ where
 	Storage<StorageType, T, I, L>: mmr_lib::MMRStore<NodeOf<T, I, L>>,
 {
 	mmr: mmr_lib::MMR<NodeOf<T, I, L>, Hasher<HashingOf<T, I>, L>, Storage<StorageType, T, I, L>>,
+	store: Storage<StorageType, T, I, L>,
 	leaves: NodeIndex,
+	/// Positions and data of the leaves pushed along with their hash, to be indexed off-chain.
+	prehashed: Vec<(TypedNodeIndex, L)>,
 }
 
 impl<StorageType, T, I, L> Mmr<StorageType, T, I, L>
@@ -84,8 +601,21 @@ where
 {
 	/// Create a pointer to an existing MMR with given number of leaves.
 	pub fn new(leaves: NodeIndex) -> Self {
+		Self::with_storage(leaves, Default::default())
+	}
+
+	/// Create a pointer to an existing MMR with given number of leaves, using given `store`.
+	///
+	/// Useful to share a pre-populated storage (see [Storage::prefetch]) between multiple
+	/// MMR instances.
+	pub fn with_storage(leaves: NodeIndex, store: Storage<StorageType, T, I, L>) -> Self {
 		let size = NodesUtils::new(leaves).size();
-		Self { mmr: mmr_lib::MMR::new(size, Default::default()), leaves }
+		Self { mmr: mmr_lib::MMR::new(size, store.clone()), store, leaves, prehashed: Vec::new() }
+	}
+
+	/// Return the statistics of the Off-chain DB accesses performed by this MMR so far.
+	pub fn stats(&self) -> primitives::ProofGenStats {
+		self.store.stats()
 	}
 
 	/// Verify proof for a set of leaves.
@@ -133,8 +663,12 @@ where
 {
 	/// Push another item to the MMR.
 	///
-	/// Returns element position (index) in the MMR.
+	/// Returns element position (index) in the MMR, or `None` if the leaf couldn't be pushed
+	/// (e.g. because it doesn't fit the [`Config::LEAF_ENCODING`]).
 	pub fn push(&mut self, leaf: L) -> Option<NodeIndex> {
+		storage::check_leaf_encoding::<T, I, L>(&leaf)
+			.map_err(|e| e.log_error("Leaf not pushed"))
+			.ok()?;
 		let position =
 			self.mmr.push(Node::Data(leaf)).map_err(|e| Error::Push.log_error(e)).ok()?;
 
@@ -143,12 +677,107 @@ where
 		Some(position)
 	}
 
+	/// Push `leaves` to the MMR, collecting the positions and hashes of all the created nodes.
+	///
+	/// Unlike the on-chain storage, which only keeps the peaks, the returned collection includes
+	/// the leaves and all the inner nodes created by merging them, even if they are not peaks of
+	/// the resulting MMR. The nodes are ordered by their position.
+	pub fn append_collecting(
+		&mut self,
+		leaves: Vec<L>,
+	) -> Result<Vec<(NodeIndex, <T as Config<I>>::Hash)>, Error> {
+		let mut created = BTreeMap::new();
+		for leaf in leaves {
+			let leaf_index = self.leaves;
+			let mut node = Node::Data(leaf.clone());
+			let mut pos = self.push(leaf).ok_or(Error::Push)?;
+			created.insert(pos, node.hash());
+			// Adding a leaf merges as many peaks as there are trailing ones in its index.
+			for height in 0..leaf_index.trailing_ones() {
+				let left_pos = pos + 1 - (2 << height);
+				let left = match created.get(&left_pos) {
+					Some(hash) => Node::Hash(*hash),
+					None => mmr_lib::MMRStore::get_elem(&self.store, left_pos)
+						.ok()
+						.flatten()
+						.ok_or_else(|| Error::Push.log_error(("Missing peak", left_pos)))?,
+				};
+				node = <Hasher<HashingOf<T, I>, L> as mmr_lib::Merge>::merge(&left, &node)
+					.map_err(|e| Error::Push.log_error(e))?;
+				pos += 1;
+				created.insert(pos, node.hash());
+			}
+		}
+
+		Ok(created.into_iter().collect())
+	}
+
+	/// Push another item, whose `hash` is already known, to the MMR.
+	///
+	/// The `hash` is trusted to be the hash of the `leaf` (see [Node::hash]), so the leaf is not
+	/// hashed again (except for debug builds, where the `hash` is verified, see
+	/// [`Config::STRICT_NO_PANIC`]). The full `leaf` is still indexed off-chain when the MMR is
+	/// finalized.
+	///
+	/// Returns element position (index) in the MMR, or `None` if the leaf couldn't be pushed.
+	pub fn push_prehashed(&mut self, leaf: L, hash: <T as Config<I>>::Hash) -> Option<NodeIndex> {
+		storage::check_leaf_encoding::<T, I, L>(&leaf)
+			.map_err(|e| e.log_error("Leaf not pushed"))
+			.ok()?;
+		if cfg!(debug_assertions) && Node::<HashingOf<T, I>, L>::Data(leaf.clone()).hash() != hash {
+			if !T::STRICT_NO_PANIC {
+				panic!("The precomputed hash doesn't match the leaf.");
+			}
+			Error::Push.log_error(("The precomputed hash doesn't match the leaf.", hash));
+			return None
+		}
+		let position =
+			self.mmr.push(Node::Hash(hash)).map_err(|e| Error::Push.log_error(e)).ok()?;
+
+		self.leaves += 1;
+		self.prehashed.push((TypedNodeIndex(position), leaf));
+
+		Some(position)
+	}
+
 	/// Commit the changes to underlying storage, return current number of leaves and
 	/// calculate the new MMR's root hash.
+	///
+	/// The root is bagged from the peaks cached in [`crate::PeakHashes`], which are updated
+	/// along with the appended nodes.
 	pub fn finalize(self) -> Result<(NodeIndex, <T as Config<I>>::Hash), Error> {
-		let root = self.mmr.get_root().map_err(|e| Error::GetRoot.log_error(e))?;
-		self.mmr.commit().map_err(|e| Error::Commit.log_error(e))?;
-		Ok((self.leaves, root.hash()))
+		self.mmr.commit().map_err(|e| self.store.into_mmr_error(e, Error::Commit))?;
+		for (position, leaf) in self.prehashed {
+			Storage::<RuntimeStorage, T, I, L>::index_leaf(position, Node::Data(leaf));
+		}
+		let root = crate::Pallet::<T, I>::cached_root(self.leaves)?;
+		Ok((self.leaves, root))
+	}
+
+	/// Generate a proof for given leaf indices using on-chain storage only.
+	///
+	/// This requires all the nodes to be persisted on-chain (see [`Config::STORE_ALL_NODES`]),
+	/// otherwise the proof generation will fail due to missing inner nodes.
+	/// Note the leaves are only stored on-chain as hashes, so unlike the off-chain variant
+	/// this function does not return the leaves content.
+	pub fn generate_onchain_proof(
+		&self,
+		leaf_indices: Vec<NodeIndex>,
+	) -> Result<primitives::Proof<<T as Config<I>>::Hash>, Error> {
+		let positions = leaf_indices
+			.iter()
+			.map(|index| mmr_lib::leaf_index_to_pos(*index))
+			.collect::<Vec<_>>();
+
+		let leaf_count = self.leaves;
+		self.mmr
+			.gen_proof(positions)
+			.map_err(|e| Error::GenerateProof.log_error(e))
+			.map(|p| primitives::Proof {
+				leaf_indices,
+				leaf_count,
+				items: p.proof_items().iter().map(|x| x.hash()).collect(),
+			})
 	}
 }
 
@@ -167,28 +796,155 @@ where
 		&self,
 		leaf_indices: Vec<NodeIndex>,
 	) -> Result<(Vec<L>, primitives::Proof<<T as Config<I>>::Hash>), Error> {
+		self.ensure_in_range(&leaf_indices)?;
+		self.limit_reads(&leaf_indices)?;
 		let positions = leaf_indices
 			.iter()
 			.map(|index| mmr_lib::leaf_index_to_pos(*index))
 			.collect::<Vec<_>>();
-		let store = <Storage<OffchainStorage, T, I, L>>::default();
 		let leaves = positions
 			.iter()
-			.map(|pos| match mmr_lib::MMRStore::get_elem(&store, *pos) {
+			.map(|pos| match mmr_lib::MMRStore::get_elem(&self.store, *pos) {
 				Ok(Some(Node::Data(leaf))) => Ok(leaf),
+				Err(e) => Err(self.store.into_mmr_error(e, Error::LeafNotFound)),
 				e => Err(Error::LeafNotFound.log_debug(e)),
 			})
-			.collect::<Result<Vec<_>, Error>>()?;
+			.collect::<Result<Vec<_>, Error>>()
+			.map_err(|e| self.explain_missing_nodes(&leaf_indices, e))?;
 
-		let leaf_count = self.leaves;
-		self.mmr
+		let proof = self
+			.mmr
 			.gen_proof(positions)
-			.map_err(|e| Error::GenerateProof.log_error(e))
-			.map(|p| primitives::Proof {
-				leaf_indices,
-				leaf_count,
-				items: p.proof_items().iter().map(|x| x.hash()).collect(),
+			.map_err(|e| self.store.into_mmr_error(e, Error::GenerateProof))
+			.map_err(|e| self.explain_missing_nodes(&leaf_indices, e))?;
+		let proof = primitives::Proof {
+			leaf_indices,
+			leaf_count: self.leaves,
+			items: proof.proof_items().iter().map(|x| x.hash()).collect(),
+		};
+		Ok((leaves, proof))
+	}
+
+	/// Generate a proof for the leaf `leaf_index`, listing every proof item with its position.
+	///
+	/// Unlike in [Self::generate_proof], the peaks on the right of the leaf's peak are not
+	/// bagged, so the items are exactly the nodes listed by [NodesUtils::proof_node_indices].
+	/// See [proof_from_positioned_items] to convert it to a regular [primitives::Proof].
+	pub fn generate_proof_with_positions(
+		&self,
+		leaf_index: LeafIndex,
+	) -> Result<(L, Vec<(NodeIndex, <T as Config<I>>::Hash)>), Error> {
+		let leaf_indices = [leaf_index];
+		self.ensure_in_range(&leaf_indices)?;
+		self.limit_reads(&leaf_indices)?;
+		let leaf_pos = mmr_lib::leaf_index_to_pos(leaf_index);
+		let leaf = match mmr_lib::MMRStore::get_elem(&self.store, leaf_pos) {
+			Ok(Some(Node::Data(leaf))) => Ok(leaf),
+			Err(e) => Err(self.store.into_mmr_error(e, Error::LeafNotFound)),
+			e => Err(Error::LeafNotFound.log_debug(e)),
+		}
+		.map_err(|e| self.explain_missing_nodes(&leaf_indices, e))?;
+
+		let items = NodesUtils::new(self.leaves)
+			.proof_node_indices(TypedLeafIndex(leaf_index))
+			.into_iter()
+			.map(|pos| match mmr_lib::MMRStore::get_elem(&self.store, pos.0) {
+				Ok(Some(node)) => Ok((pos.0, node.hash())),
+				Err(e) => Err(self.store.into_mmr_error(e, Error::GenerateProof)),
+				Ok(None) => Err(Error::GenerateProof.log_debug(("Missing node", pos))),
 			})
-			.map(|p| (leaves, p))
+			.collect::<Result<Vec<_>, Error>>()
+			.map_err(|e| self.explain_missing_nodes(&leaf_indices, e))?;
+		Ok((leaf, items))
+	}
+
+	/// Return [Error::LeafIndexOutOfRange] if any of the `leaf_indices` is not part of the MMR.
+	///
+	/// This is checked upfront, without touching the Off-chain DB.
+	fn ensure_in_range(&self, leaf_indices: &[LeafIndex]) -> Result<(), Error> {
+		match leaf_indices.iter().find(|leaf_index| **leaf_index >= self.leaves) {
+			Some(requested) =>
+				Err(Error::LeafIndexOutOfRange { requested: *requested, count: self.leaves }
+					.log_debug("Requested leaf is not part of the MMR.")),
+			None => Ok(()),
+		}
+	}
+
+	/// Bound the number of nodes read for the proof of `leaf_indices` by its maximal size.
+	///
+	/// Every leaf requires reading at most itself and the nodes listed by
+	/// [NodesUtils::proof_node_indices]. The size of the MMR is only trusted if it doesn't
+	/// exceed the current number of leaves, and the peaks of the latter are stored on-chain,
+	/// otherwise [Error::ReadLimitExceeded] is returned without reading anything.
+	fn limit_reads(&self, leaf_indices: &[LeafIndex]) -> Result<(), Error> {
+		if self.leaves > crate::Pallet::<T, I>::verified_leaf_count() {
+			return Err(Error::ReadLimitExceeded
+				.log_error(("The number of leaves doesn't match the on-chain peaks", self.leaves)))
+		}
+
+		let utils = NodesUtils::new(self.leaves);
+		let limit = leaf_indices.iter().fold(0u32, |limit, leaf_index| {
+			let nodes = utils.proof_node_indices(TypedLeafIndex(*leaf_index)).len() as u32;
+			limit.saturating_add(nodes).saturating_add(1)
+		});
+		self.store.limit_node_reads(limit);
+		Ok(())
+	}
+
+	/// Replace the error `e` with [Error::LeafNotIndexed] if it was caused by nodes which are
+	/// permanently missing from the Off-chain DB, since they were never indexed.
+	fn explain_missing_nodes(&self, leaf_indices: &[LeafIndex], e: Error) -> Error {
+		let leaf_indices = leaf_indices.iter().copied().map(TypedLeafIndex).collect::<Vec<_>>();
+		Storage::<OffchainStorage, T, I, L>::ensure_indexed(self.leaves, &leaf_indices)
+			.err()
+			.unwrap_or(e)
+	}
+
+	/// Generate a proof for given leaf indices, omitting nodes derivable from the `hint`.
+	///
+	/// The indices of the leaves described by the [primitives::CompressionHint] (except the ones
+	/// which are requested anyway) are appended to the proof's `leaf_indices`, but the leaves are
+	/// not returned. The verifier has to supply them (in the same order) to verify the proof,
+	/// see [decompress_proof].
+	pub fn generate_compressed_proof(
+		&self,
+		leaf_indices: Vec<NodeIndex>,
+		hint: &primitives::CompressionHint,
+	) -> Result<(Vec<L>, primitives::Proof<<T as Config<I>>::Hash>), Error> {
+		let (leaves, proof) = self.generate_proof(leaf_indices)?;
+		Ok((leaves, compress_proof(proof, &hint.known_leaf_indices)?))
+	}
+
+	/// Generate a proof that the MMR with `prev_leaf_count` leaves is a prefix of this one.
+	///
+	/// Besides the peaks of the prior MMR, the proof contains the roots of the maximal subtrees
+	/// covering only the leaves appended since, which are required to rebuild the current peaks.
+	pub fn generate_ancestry_proof(
+		&self,
+		prev_leaf_count: LeafIndex,
+	) -> Result<primitives::AncestryProof<<T as Config<I>>::Hash>, Error> {
+		if prev_leaf_count == 0 || prev_leaf_count > self.leaves {
+			return Err(Error::GenerateProof.log_debug(("Invalid prior leaf count", prev_leaf_count)))
+		}
+
+		let prev_size = NodesUtils::new(prev_leaf_count).size();
+		let prev_peaks = helper::get_peaks(prev_size);
+		let items = ancestry_proof_item_positions(prev_size, self.mmr.mmr_size())
+			.map_err(|pos| Error::GenerateProof.log_error(("Inconsistent prior peaks", pos)))?;
+
+		let hash_of = |pos| match mmr_lib::MMRStore::get_elem(&self.store, pos) {
+			Ok(Some(node)) => Ok(node.hash()),
+			Err(e) => Err(self.store.into_mmr_error(e, Error::GenerateProof)),
+			e => Err(Error::GenerateProof.log_debug(e)),
+		};
+		Ok(primitives::AncestryProof {
+			prev_peaks: prev_peaks.into_iter().map(hash_of).collect::<Result<_, _>>()?,
+			prev_leaf_count,
+			leaf_count: self.leaves,
+			items: items
+				.into_iter()
+				.map(|pos| hash_of(pos).map(|hash| (pos, hash)))
+				.collect::<Result<_, _>>()?,
+		})
 	}
 }
@@ -21,7 +21,11 @@ pub mod storage;
 use sp_mmr_primitives::{mmr_lib, DataOrHash, FullLeaf};
 use sp_runtime::traits;
 
-pub use self::mmr::{verify_leaves_proof, Mmr};
+pub use self::mmr::{
+	bag_peaks, decompress_proof, peak_bagging_proof, proof_from_positioned_items,
+	reconstruct_leaf_peak, reconstruct_root, root_from_peak, verify_ancestry_proof,
+	verify_leaves_proof, verify_leaves_proof_with_hasher, Mmr,
+};
 
 /// Node type for runtime `T`.
 pub type NodeOf<T, I, L> = Node<<T as crate::Config<I>>::Hashing, L>;
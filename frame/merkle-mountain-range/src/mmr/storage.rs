@@ -18,18 +18,32 @@
 //! An MMR storage implementation.
 
 use codec::Encode;
-use frame_support::log::{debug, trace};
-use sp_core::offchain::StorageKind;
+use frame_support::{
+	log::{debug, trace},
+	traits::Get,
+};
+use sp_core::offchain::{StorageKind, Timestamp};
 use sp_io::offchain_index;
 use sp_mmr_primitives::{mmr_lib, mmr_lib::helper, utils::NodesUtils};
-use sp_std::iter::Peekable;
+use sp_runtime::{
+	traits::{SaturatedConversion, Zero},
+	RuntimeDebug,
+};
 #[cfg(not(feature = "std"))]
 use sp_std::prelude::*;
+use sp_std::{
+	cell::{Cell, RefCell},
+	collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+	iter::Peekable,
+	rc::Rc,
+};
 
 use crate::{
-	mmr::{Node, NodeOf},
-	primitives::{self, NodeIndex},
-	Config, Nodes, NumberOfLeaves, Pallet,
+	mmr::{Hasher, Node, NodeOf},
+	primitives::{
+		self, Error, ForkView, LeafIndex, NodeIndex, ProofGenStats, TypedLeafIndex, TypedNodeIndex,
+	},
+	Config, GenesisLeaves, HashingOf, Nodes, NumberOfLeaves, Pallet, TotalOffchainBytes,
 };
 
 /// A marker type for runtime-specific storage implementation.
@@ -48,15 +62,103 @@ pub struct RuntimeStorage;
 /// DOES NOT support adding new items to the MMR.
 pub struct OffchainStorage;
 
+/// The reason why [`Storage::self_check`] failed for a range of leaves.
+#[derive(RuntimeDebug, PartialEq, Eq)]
+pub enum SelfCheckError {
+	/// The node at given position is missing from the Off-chain DB.
+	MissingNode(TypedNodeIndex),
+	/// The node at given position is corrupted, or doesn't match the nodes below it.
+	InconsistentNode(TypedNodeIndex),
+	/// The check could not be performed.
+	Other(Error),
+}
+
 /// A storage layer for MMR.
 ///
 /// There are two different implementations depending on the use case.
 /// See docs for [RuntimeStorage] and [OffchainStorage].
-pub struct Storage<StorageType, T, I, L>(sp_std::marker::PhantomData<(StorageType, T, I, L)>);
+pub struct Storage<StorageType, T: frame_system::Config, I, L> {
+	/// Encoded nodes read ahead of time from the Off-chain DB (see [Storage::prefetch]).
+	cache: BTreeMap<TypedNodeIndex, Vec<u8>>,
+	/// The fork to read the Off-chain DB nodes of (see [Storage::with_fork_view]).
+	fork_view: ForkView<<T as frame_system::Config>::Hash>,
+	/// Statistics of the Off-chain DB accesses, shared by all the clones of the storage.
+	stats: Rc<Cell<ProofGenStats>>,
+	/// Number of nodes which can still be read, shared by all the clones of the storage
+	/// (see [Storage::limit_node_reads]).
+	node_reads_left: Rc<Cell<Option<u32>>>,
+	/// Time after which reading nodes fails, shared by all the clones of the storage
+	/// (see [Storage::set_deadline]).
+	deadline: Rc<Cell<Option<Timestamp>>>,
+	/// The error behind the last [mmr_lib::Error::StoreError] raised by the storage, shared by
+	/// all the clones of the storage (see [Storage::into_mmr_error]).
+	error: Rc<RefCell<Option<Error>>>,
+	_phantom: sp_std::marker::PhantomData<(StorageType, T, I, L)>,
+}
 
-impl<StorageType, T, I, L> Default for Storage<StorageType, T, I, L> {
+impl<StorageType, T: frame_system::Config, I, L> Default for Storage<StorageType, T, I, L> {
 	fn default() -> Self {
-		Self(Default::default())
+		Self {
+			cache: Default::default(),
+			fork_view: Default::default(),
+			stats: Default::default(),
+			node_reads_left: Default::default(),
+			deadline: Default::default(),
+			error: Default::default(),
+			_phantom: Default::default(),
+		}
+	}
+}
+
+impl<StorageType, T: frame_system::Config, I, L> Clone for Storage<StorageType, T, I, L> {
+	fn clone(&self) -> Self {
+		Self {
+			cache: self.cache.clone(),
+			fork_view: self.fork_view.clone(),
+			stats: self.stats.clone(),
+			node_reads_left: self.node_reads_left.clone(),
+			deadline: self.deadline.clone(),
+			error: self.error.clone(),
+			_phantom: Default::default(),
+		}
+	}
+}
+
+impl<StorageType, T: frame_system::Config, I, L> Storage<StorageType, T, I, L> {
+	/// Return the statistics of the Off-chain DB accesses performed by this storage (and its
+	/// clones) so far.
+	pub fn stats(&self) -> ProofGenStats {
+		self.stats.get()
+	}
+
+	/// Update the statistics of the Off-chain DB accesses with `f`.
+	fn note_stats(&self, f: impl FnOnce(&mut ProofGenStats)) {
+		let mut stats = self.stats.get();
+		f(&mut stats);
+		self.stats.set(stats);
+	}
+
+	/// Raise `error` through the MMR library, keeping it to be recovered by
+	/// [Self::into_mmr_error].
+	fn store_error(&self, error: Error) -> mmr_lib::Error {
+		*self.error.borrow_mut() = Some(error);
+		mmr_lib::Error::StoreError(STORE_ERROR.into())
+	}
+
+	/// Convert an error coming from the MMR library into [Error].
+	///
+	/// Errors raised by this storage (or its clones) are recovered as they were raised, while
+	/// any other error is reported as the given `fallback`.
+	pub(crate) fn into_mmr_error(&self, e: mmr_lib::Error, fallback: Error) -> Error {
+		let error = self.error.borrow_mut().take();
+		match (e, error) {
+			(mmr_lib::Error::StoreError(_), Some(error)) => match error {
+				Error::UnknownAncestorHash { .. } | Error::ProofGenerationTimeout { .. } =>
+					error.log_debug("Storage error"),
+				error => error.log_error("Storage error"),
+			},
+			(e, _) => fallback.log_error(e),
+		}
 	}
 }
 
@@ -67,10 +169,541 @@ where
 	L: primitives::FullLeaf + codec::Decode,
 {
 	fn get_elem(&self, pos: NodeIndex) -> mmr_lib::Result<Option<NodeOf<T, I, L>>> {
+		let pos = TypedNodeIndex(pos);
+		if let Some(left) = self.node_reads_left.get() {
+			if left == 0 {
+				debug!(target: "runtime::mmr::offchain", "offchain db get {}: read limit", pos);
+				return Err(self.store_error(Error::ReadLimitExceeded))
+			}
+			self.node_reads_left.set(Some(left - 1));
+		}
+		if let Some(deadline) = self.deadline.get() {
+			if sp_io::offchain::timestamp() >= deadline {
+				debug!(target: "runtime::mmr::offchain", "offchain db get {}: deadline", pos);
+				return Err(self.store_error(Error::ProofGenerationTimeout { offchain_reads: 0 }))
+			}
+		}
+		// Nodes prefetched earlier don't need to be looked up again.
+		if let Some(elem) = self.cache.get(&pos) {
+			trace!(target: "runtime::mmr::offchain", "offchain db get {}: cache hit", pos);
+			self.note_stats(|stats| stats.cache_hits = stats.cache_hits.saturating_add(1));
+			return Self::decode_node(pos, elem).map_err(|e| self.store_error(e))
+		}
+
+		match self.get_encoded(pos).map_err(|e| self.store_error(e))? {
+			Some(elem) => Self::decode_node(pos, &elem).map_err(|e| self.store_error(e)),
+			None => Ok(None),
+		}
+	}
+
+	fn append(&mut self, _: NodeIndex, _: Vec<NodeOf<T, I, L>>) -> mmr_lib::Result<()> {
+		if T::STRICT_NO_PANIC {
+			return Err(mmr_lib::Error::StoreError(OFFCHAIN_APPEND.into()))
+		}
+		panic!("MMR must not be altered in the off-chain context.")
+	}
+}
+
+impl<T, I, L> Storage<OffchainStorage, T, I, L>
+where
+	T: Config<I>,
+	I: 'static,
+	L: primitives::FullLeaf + codec::Decode,
+{
+	/// Read the Off-chain DB nodes of the fork described by `fork_view`.
+	///
+	/// By default the nodes of the canonical chain (as known to `frame_system`) are read.
+	/// With a fork view, the nodes added by the leaves present in the view are read from their
+	/// fork-specific keys, allowing to generate proofs for non-canonical forks.
+	pub fn with_fork_view(
+		mut self,
+		fork_view: ForkView<<T as frame_system::Config>::Hash>,
+	) -> Self {
+		self.fork_view = fork_view;
+		self
+	}
+
+	/// Allow at most `limit` more nodes to be read by this storage (and its clones).
+	///
+	/// Reading more fails with [Error::ReadLimitExceeded], which bounds the work done for
+	/// malformed requests.
+	pub fn limit_node_reads(&self, limit: u32) {
+		self.node_reads_left.set(Some(limit));
+	}
+
+	/// Fail reading nodes with this storage (and its clones) from the `deadline` on.
+	///
+	/// Reading nodes afterwards fails with [Error::ProofGenerationTimeout], which bounds the
+	/// time spent on huge requests. Note this can only be used from an off-chain context.
+	pub fn set_deadline(&self, deadline: Timestamp) {
+		self.deadline.set(Some(deadline));
+	}
+
+	/// Read nodes required to generate proofs for given `leaf_indices` into the cache.
+	///
+	/// All the subsequent reads of these nodes (see [mmr_lib::MMRStore::get_elem]) will be
+	/// served from the cache, instead of the Off-chain DB. This amortizes the cost of looking up
+	/// the blocks that added the nodes, when many proofs touching the same nodes are requested.
+	///
+	/// Note the node positions are computed for the current size of the MMR.
+	/// Nodes which can't be found in the Off-chain DB are not cached.
+	pub fn prefetch(&mut self, leaf_indices: &[TypedLeafIndex]) -> Result<(), Error> {
+		let utils = NodesUtils::new(NumberOfLeaves::<T, I>::get());
+		let positions = leaf_indices
+			.iter()
+			.flat_map(|&leaf_index| {
+				let leaf_pos = NodesUtils::leaf_to_node_index(leaf_index);
+				sp_std::iter::once(leaf_pos).chain(utils.proof_node_indices(leaf_index))
+			})
+			.collect::<BTreeSet<_>>();
+
+		for pos in positions {
+			if self.cache.contains_key(&pos) {
+				continue
+			}
+			if let Some(elem) = self.get_encoded(pos)? {
+				self.cache.insert(pos, elem);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Find the (inclusive) range of leaves whose nodes are present in the Off-chain DB.
+	///
+	/// The leaves are assumed to be available in a contiguous range, either starting from the
+	/// first leaf or ending with the latest one (e.g. when the node started indexing late, or
+	/// the nodes of the old leaves were removed), which is then found with a binary search on
+	/// the presence of the leaves. Returns `None` if neither the first nor the latest leaf is
+	/// available.
+	///
+	/// Note only the presence of the leaves themselves is probed, the proofs of the first leaves
+	/// of the range may still require nodes added by the leaves before it.
+	pub fn available_leaf_range(&self) -> Option<(TypedLeafIndex, TypedLeafIndex)> {
+		let is_available = |leaf_index| {
+			let pos = NodesUtils::leaf_to_node_index(TypedLeafIndex(leaf_index));
+			self.cache.contains_key(&pos) || matches!(self.get_encoded(pos), Ok(Some(_)))
+		};
+
+		let last = NumberOfLeaves::<T, I>::get().checked_sub(1)?;
+		if is_available(last) {
+			// Find the first available leaf.
+			let (mut low, mut high) = (0, last);
+			while low < high {
+				let mid = low + (high - low) / 2;
+				if is_available(mid) {
+					high = mid;
+				} else {
+					low = mid + 1;
+				}
+			}
+			Some((TypedLeafIndex(low), TypedLeafIndex(last)))
+		} else if is_available(0) {
+			// Find the last available leaf, knowing that `high` is missing.
+			let (mut low, mut high) = (0, last);
+			while low + 1 < high {
+				let mid = low + (high - low) / 2;
+				if is_available(mid) {
+					low = mid;
+				} else {
+					high = mid;
+				}
+			}
+			Some((TypedLeafIndex(0), TypedLeafIndex(low)))
+		} else {
+			None
+		}
+	}
+
+	/// Read `count` consecutive leaves, starting from `from_leaf`, from the Off-chain DB.
+	///
+	/// Meant for bulk reads, e.g. to backfill an external index. Every leaf is looked up under
+	/// the keys of the block that added it, so the range costs at most one block hash lookup
+	/// per leaf. Leaves which can't be found (e.g. because they were not indexed, or the hash of
+	/// their block is not known anymore) are returned as `None` instead of failing the batch.
+	///
+	/// Fails if the range exceeds the current number of leaves, or a leaf is corrupted.
+	pub fn get_leaves(
+		&self,
+		from_leaf: TypedLeafIndex,
+		count: LeafIndex,
+	) -> Result<Vec<Option<L>>, Error> {
 		let leaves = NumberOfLeaves::<T, I>::get();
+		let end = from_leaf.0.saturating_add(count);
+		if end > leaves {
+			return Err(Error::LeafIndexOutOfRange { requested: end - 1, count: leaves }
+				.log_debug("Requested leaves are not part of the MMR."))
+		}
+
+		(from_leaf.0..end)
+			.map(|leaf_index| {
+				let pos = NodesUtils::leaf_to_node_index(TypedLeafIndex(leaf_index));
+				match mmr_lib::MMRStore::get_elem(self, pos.0) {
+					Ok(Some(Node::Data(leaf))) => Ok(Some(leaf)),
+					Ok(_) => Ok(None),
+					Err(e) => match self.into_mmr_error(e, Error::LeafNotFound) {
+						Error::UnknownAncestorHash { .. } => Ok(None),
+						e => Err(e),
+					},
+				}
+			})
+			.collect()
+	}
+
+	/// Check that the Off-chain DB can serve the leaves from `from_leaf` to `to_leaf`
+	/// (inclusive), and return the MMR root they imply.
+	///
+	/// The leaves of the range are read along with the nodes required to prove them, and the
+	/// trees containing the range are reconstructed up to their peaks. Inner nodes present in
+	/// the Off-chain DB are compared with their reconstructed hashes on the way. The first
+	/// missing or inconsistent node (bottom up) fails the check. Meant as a readiness probe
+	/// before serving proofs, the returned root should match the on-chain one.
+	pub fn self_check(
+		&self,
+		from_leaf: TypedLeafIndex,
+		to_leaf: TypedLeafIndex,
+	) -> Result<<T as Config<I>>::Hash, SelfCheckError> {
+		let leaves = NumberOfLeaves::<T, I>::get();
+		if to_leaf.0 >= leaves {
+			return Err(SelfCheckError::Other(Error::LeafIndexOutOfRange {
+				requested: to_leaf.0,
+				count: leaves,
+			}))
+		}
+		if from_leaf > to_leaf {
+			return Err(SelfCheckError::Other(
+				Error::InvalidLeafIndex.log_debug(("Empty range", from_leaf)),
+			))
+		}
+
+		let missing = |pos| SelfCheckError::MissingNode(TypedNodeIndex(pos));
+		let read = |pos: NodeIndex| match mmr_lib::MMRStore::get_elem(self, pos) {
+			Ok(node) => Ok(node.map(|node| node.hash())),
+			Err(e) => Err(match self.into_mmr_error(e, Error::GenerateProof) {
+				Error::UnknownAncestorHash { .. } => missing(pos),
+				Error::OffchainChecksumMismatch | Error::LeafTypeMismatch =>
+					SelfCheckError::InconsistentNode(TypedNodeIndex(pos)),
+				e => SelfCheckError::Other(e),
+			}),
+		};
+		let merge = |left, right| {
+			<Hasher<HashingOf<T, I>, L> as mmr_lib::Merge>::merge(
+				&Node::Hash(left),
+				&Node::Hash(right),
+			)
+			.map(|node| node.hash())
+			.map_err(|e| SelfCheckError::Other(Error::GenerateProof.log_error(e)))
+		};
+
+		let peaks = helper::get_peaks(NodesUtils::new(leaves).size());
+		let mut peak_hashes = BTreeMap::new();
+		// The reconstructed nodes of the current height, ordered by their position.
+		let mut level = (from_leaf.0..=to_leaf.0)
+			.map(|leaf_index| {
+				let pos = NodesUtils::leaf_to_node_index(TypedLeafIndex(leaf_index)).0;
+				Ok((pos, read(pos)?.ok_or_else(|| missing(pos))?))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		while !level.is_empty() {
+			let mut parents = Vec::with_capacity(level.len() / 2 + 1);
+			let mut nodes = level.into_iter().peekable();
+			while let Some((pos, hash)) = nodes.next() {
+				if peaks.contains(&pos) {
+					peak_hashes.insert(pos, hash);
+					continue
+				}
+
+				let height = helper::pos_height_in_tree(pos);
+				let (parent, left, right) = if helper::pos_height_in_tree(pos + 1) > height {
+					let sibling = pos + 1 - (2 << height);
+					(pos + 1, read(sibling)?.ok_or_else(|| missing(sibling))?, hash)
+				} else {
+					let sibling = pos + (2 << height) - 1;
+					let sibling_hash = match nodes.next_if(|(next, _)| *next == sibling) {
+						Some((_, sibling_hash)) => sibling_hash,
+						None => read(sibling)?.ok_or_else(|| missing(sibling))?,
+					};
+					(sibling + 1, hash, sibling_hash)
+				};
+				let parent_hash = merge(left, right)?;
+				if read(parent)?.map_or(false, |stored| stored != parent_hash) {
+					return Err(SelfCheckError::InconsistentNode(TypedNodeIndex(parent)))
+				}
+				parents.push((parent, parent_hash));
+			}
+			level = parents;
+		}
+
+		let peaks = peaks
+			.into_iter()
+			.map(|pos| match peak_hashes.remove(&pos) {
+				Some(hash) => Ok(hash),
+				None => read(pos)?.ok_or_else(|| missing(pos)),
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		Pallet::<T, I>::bag_peaks(peaks)
+			.map_err(SelfCheckError::Other)?
+			.ok_or(SelfCheckError::Other(Error::GenerateProof))
+	}
+
+	/// Find the variants of node `pos` indexed by the forks with given parent hashes.
+	///
+	/// Every one of the `candidate_parent_hashes` is probed as the parent hash of the block that
+	/// added the node, and the variants present in the Off-chain DB are returned along with the
+	/// parent hash they were found under. Meant for investigating proof generation failures
+	/// after reorgs; the canonical key of the node is not probed.
+	pub fn fork_variants(
+		&self,
+		pos: TypedNodeIndex,
+		candidate_parent_hashes: &[<T as frame_system::Config>::Hash],
+	) -> Vec<(<T as frame_system::Config>::Hash, NodeOf<T, I, L>)> {
+		candidate_parent_hashes
+			.iter()
+			.filter_map(|parent_hash| {
+				let key = Pallet::<T, I>::node_temp_offchain_key(pos, *parent_hash);
+				let elem = self.local_storage_get(&key)?;
+				match Self::decode_node(pos, &elem) {
+					Ok(Some(node)) => Some((*parent_hash, node)),
+					_ => {
+						debug!(
+							target: "runtime::mmr::offchain",
+							"offchain db get {}: undecodable variant of fork {:?}", pos, parent_hash
+						);
+						None
+					},
+				}
+			})
+			.collect()
+	}
+
+	/// Rebuild the Off-chain DB entries of the MMR from its `leaves`.
+	///
+	/// The leaves are appended to an in-memory MMR to recompute all the inner nodes, and every
+	/// node is then written to the Off-chain DB under the key it was originally indexed with by
+	/// the runtime. Nodes added by blocks whose hashes are no longer known to `frame_system`, as
+	/// well as the nodes of the genesis leaves (see [crate::GenesisLeaves]), are written under
+	/// their canonical key instead.
+	///
+	/// `leaves` have to be consecutive, starting from the first leaf of the MMR (index `0`),
+	/// and must be part of the current MMR. Returns the number of reindexed leaves.
+	///
+	/// Nothing is written unless the peaks of the rebuilt MMR bag into the on-chain root of the
+	/// MMR with as many leaves, i.e. [crate::RootHash] if all the leaves were given, or the
+	/// [Pallet::historical_root] otherwise. This fails with [Error::Verify] if the root doesn't
+	/// match (e.g. because of a corrupted leaf) or the historical root is not known. All the
+	/// rebuilt nodes are kept in memory until then.
+	///
+	/// Note this can only be used from an off-chain context (Offchain Worker).
+	pub fn reindex_offchain(
+		leaves: impl Iterator<Item = (TypedLeafIndex, L)>,
+	) -> Result<LeafIndex, Error> {
+		let leaves_count = NumberOfLeaves::<T, I>::get();
+		// Peaks of the in-memory MMR, ordered by their position.
+		let mut peaks: Vec<NodeOf<T, I, L>> = Vec::new();
+		let mut nodes = Vec::new();
+		let mut reindexed = 0;
+		for (leaf_index, leaf) in leaves {
+			if leaf_index.0 != reindexed || leaf_index.0 >= leaves_count {
+				return Err(Error::InvalidLeafIndex.log_debug(("Unexpected leaf index", leaf_index)))
+			}
+
+			let mut pos = NodesUtils::leaf_to_node_index(leaf_index);
+			let mut node = Node::Data(leaf);
+			nodes.push((pos, node.clone()));
+			// Adding a leaf merges as many peaks as there are trailing ones in its index.
+			for _ in 0..leaf_index.0.trailing_ones() {
+				let left = peaks.pop().ok_or_else(|| Error::Push.log_error("Missing peak"))?;
+				node = <Hasher<HashingOf<T, I>, L> as mmr_lib::Merge>::merge(&left, &node)
+					.map_err(|e| Error::Push.log_error(e))?;
+				pos = TypedNodeIndex(pos.0 + 1);
+				nodes.push((pos, node.clone()));
+			}
+			peaks.push(node);
+			reindexed += 1;
+		}
+		if reindexed == 0 {
+			return Ok(0)
+		}
+
+		let expected_root = if reindexed == leaves_count {
+			Some(Pallet::<T, I>::mmr_root_hash())
+		} else {
+			Pallet::<T, I>::historical_root(reindexed)
+		};
+		let root = Pallet::<T, I>::bag_peaks(peaks.iter().map(|peak| peak.hash()).collect())?;
+		if expected_root.is_none() || root != expected_root {
+			return Err(Error::Verify.log_error((
+				"The rebuilt MMR doesn't match the on-chain root",
+				reindexed,
+				root,
+			)))
+		}
+
+		for (pos, node) in nodes {
+			Self::write_to_offchain(pos, &node);
+		}
+		Ok(reindexed)
+	}
+
+	/// Decode the node `pos` read from the Off-chain DB, verifying its checksum if
+	/// [`Config::OFFCHAIN_CHECKSUM`] is set.
+	fn decode_node(pos: TypedNodeIndex, elem: &[u8]) -> Result<Option<NodeOf<T, I, L>>, Error> {
+		let mut encoded_node = if T::OFFCHAIN_CHECKSUM {
+			if elem.len() < CHECKSUM_LEN ||
+				elem[..CHECKSUM_LEN] != node_checksum(&elem[CHECKSUM_LEN..])
+			{
+				debug!(target: "runtime::mmr::offchain", "offchain db get {}: bad checksum", pos);
+				return Err(Error::OffchainChecksumMismatch)
+			}
+			&elem[CHECKSUM_LEN..]
+		} else {
+			elem
+		};
+		if is_tagged_leaf::<T, I>(pos) {
+			let leaf_type_id = <u16 as codec::Decode>::decode(&mut encoded_node).ok();
+			if leaf_type_id != Some(T::LEAF_TYPE_ID) {
+				debug!(
+					target: "runtime::mmr::offchain", "offchain db get {}: leaf type {:?}",
+					pos, leaf_type_id
+				);
+				return Err(Error::LeafTypeMismatch)
+			}
+		}
+		Ok(codec::Decode::decode(&mut encoded_node).ok())
+	}
+
+	/// Write node `pos` to the Off-chain DB under its fork-aware key, if possible.
+	fn write_to_offchain(pos: TypedNodeIndex, node: &NodeOf<T, I, L>) {
+		// Nodes of the genesis leaves are final from the start, so they don't need a
+		// fork-specific key.
+		let is_genesis_node =
+			NodesUtils::leaf_index_that_added_node(pos).0 < GenesisLeaves::<T, I>::get();
+		let key = match Self::parent_hash_of_ancestor_that_added_node(pos) {
+			Ok(parent_hash) if T::FORK_SAFE_KEYS && !is_genesis_node =>
+				Pallet::<T, I>::node_temp_offchain_key(pos, parent_hash),
+			_ => Pallet::<T, I>::node_canon_offchain_key(pos),
+		};
+		debug!(
+			target: "runtime::mmr::offchain", "offchain db reindex: pos {} key {:?}",
+			pos, key
+		);
+		sp_io::offchain::local_storage_set(
+			StorageKind::PERSISTENT,
+			&key,
+			&encode_node::<T, I, _>(pos, node),
+		);
+	}
+
+	/// Move the nodes added by the leaf `leaf_index` from their fork-aware keys to the canonical
+	/// ones, the same way the MMR client gadget does once the block adding them is finalized.
+	///
+	/// Returns the number of moved nodes. Nothing is moved if the hash of the block which
+	/// added the leaf is not known anymore, since the fork-aware keys can't be built then.
+	///
+	/// Note this can only be used from an off-chain context (Offchain Worker).
+	pub fn canonicalize_leaf(leaf_index: TypedLeafIndex) -> usize {
+		let leaf_pos = NodesUtils::leaf_to_node_index(leaf_index);
+		let parent_hash = match Self::parent_hash_of_ancestor_that_added_node(leaf_pos) {
+			Ok(parent_hash) if parent_hash != Default::default() => parent_hash,
+			_ => return 0,
+		};
+
+		let mut moved = 0;
+		for pos in NodesUtils::right_branch_ending_in_leaf(leaf_index) {
+			let temp_key = Pallet::<T, I>::node_temp_offchain_key(pos, parent_hash);
+			if let Some(elem) =
+				sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, &temp_key)
+			{
+				let canon_key = Pallet::<T, I>::node_canon_offchain_key(pos);
+				sp_io::offchain::local_storage_set(StorageKind::PERSISTENT, &canon_key, &elem);
+				sp_io::offchain::local_storage_clear(StorageKind::PERSISTENT, &temp_key);
+				moved += 1;
+			}
+		}
+		debug!(
+			target: "runtime::mmr::offchain", "offchain db canonicalize: leaf {} moved {}",
+			leaf_index, moved
+		);
+		moved
+	}
+
+	/// Remove the nodes added by the leaf `leaf_index` on the fork where the block adding it has
+	/// `parent_hash` as parent, once that block is known to be stale.
+	///
+	/// Returns the number of removed nodes. Like [`Self::fork_variants`], only the fork-aware
+	/// keys are touched, the canonical ones are kept.
+	///
+	/// Note this can only be used from an off-chain context (Offchain Worker).
+	pub fn clear_fork_leaf(
+		leaf_index: TypedLeafIndex,
+		parent_hash: <T as frame_system::Config>::Hash,
+	) -> usize {
+		let mut removed = 0;
+		for pos in NodesUtils::right_branch_ending_in_leaf(leaf_index) {
+			let temp_key = Pallet::<T, I>::node_temp_offchain_key(pos, parent_hash);
+			if sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, &temp_key).is_some() {
+				sp_io::offchain::local_storage_clear(StorageKind::PERSISTENT, &temp_key);
+				removed += 1;
+			}
+		}
+		debug!(
+			target: "runtime::mmr::offchain", "offchain db clear fork {:?}: leaf {} removed {}",
+			parent_hash, leaf_index, removed
+		);
+		removed
+	}
+
+	/// Return `true` if node `pos` is present in the cache.
+	pub fn is_cached(&self, pos: TypedNodeIndex) -> bool {
+		self.cache.contains_key(&pos)
+	}
+
+	/// Return [Error::LeafNotIndexed] if any of the nodes required to generate proofs for
+	/// `leaf_indices` (in the MMR with `leaves_count` leaves) was added by a leaf which was not
+	/// indexed in the Off-chain DB.
+	///
+	/// Such nodes are permanently missing, unlike the ones which can't be found transiently.
+	pub fn ensure_indexed(
+		leaves_count: LeafIndex,
+		leaf_indices: &[TypedLeafIndex],
+	) -> Result<(), Error> {
+		let utils = NodesUtils::new(leaves_count);
+		for &leaf_index in leaf_indices {
+			let leaf_pos = NodesUtils::leaf_to_node_index(leaf_index);
+			for pos in sp_std::iter::once(leaf_pos).chain(utils.proof_node_indices(leaf_index)) {
+				let ancestor_leaf_idx = NodesUtils::leaf_index_that_added_node(pos);
+				if !Pallet::<T, I>::is_leaf_indexed(ancestor_leaf_idx) {
+					return Err(Error::LeafNotIndexed { leaf_index: ancestor_leaf_idx.0 }
+						.log_debug(("Node was not indexed", pos)))
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Retrieve the encoded node `pos` from the Off-chain DB.
+	fn get_encoded(&self, pos: TypedNodeIndex) -> Result<Option<Vec<u8>>, Error> {
+		// Without fork-aware keys, nodes are only ever stored under their canonical key.
+		if !T::FORK_SAFE_KEYS {
+			let key = Pallet::<T, I>::node_canon_offchain_key(pos);
+			debug!(target: "runtime::mmr::offchain", "offchain db get {}: key {:?}", pos, key);
+			return Ok(self.local_storage_get(&key))
+		}
+
 		// Find out which leaf added node `pos` in the MMR.
 		let ancestor_leaf_idx = NodesUtils::leaf_index_that_added_node(pos);
 
+		// Nodes of the requested fork are only available under their fork-specific key.
+		if let Some(parent_hash) = self.fork_view.parent_hash(ancestor_leaf_idx.0) {
+			let temp_key = Pallet::<T, I>::node_temp_offchain_key(pos, parent_hash);
+			debug!(
+				target: "runtime::mmr::offchain",
+				"offchain db get {}: leaf idx {:?}, fork hash {:?}, temp key {:?}",
+				pos, ancestor_leaf_idx, parent_hash, temp_key
+			);
+			return Ok(self.local_storage_get(&temp_key))
+		}
+
 		// We should only get here when trying to generate proofs. The client requests
 		// for proofs for finalized blocks, which should usually be already canonicalized,
 		// unless the MMR client gadget has a delay.
@@ -80,14 +713,13 @@ where
 			pos, ancestor_leaf_idx, key
 		);
 		// Try to retrieve the element from Off-chain DB.
-		if let Some(elem) = sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, &key) {
-			return Ok(codec::Decode::decode(&mut &*elem).ok())
+		if let Some(elem) = self.local_storage_get(&key) {
+			return Ok(Some(elem))
 		}
 
 		// Fall through to searching node using fork-specific key.
-		let ancestor_parent_block_num =
-			Pallet::<T, I>::leaf_index_to_parent_block_num(ancestor_leaf_idx, leaves);
-		let ancestor_parent_hash = <frame_system::Pallet<T>>::block_hash(ancestor_parent_block_num);
+		self.note_stats(|stats| stats.block_hash_reads = stats.block_hash_reads.saturating_add(1));
+		let ancestor_parent_hash = Self::parent_hash_of_ancestor_that_added_node(pos)?;
 		let temp_key = Pallet::<T, I>::node_temp_offchain_key(pos, ancestor_parent_hash);
 		debug!(
 			target: "runtime::mmr::offchain",
@@ -95,15 +727,103 @@ where
 			pos, ancestor_leaf_idx, ancestor_parent_hash, temp_key
 		);
 		// Retrieve the element from Off-chain DB.
-		Ok(sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, &temp_key)
-			.and_then(|v| codec::Decode::decode(&mut &*v).ok()))
+		Ok(self.local_storage_get(&temp_key))
 	}
 
-	fn append(&mut self, _: NodeIndex, _: Vec<NodeOf<T, I, L>>) -> mmr_lib::Result<()> {
-		panic!("MMR must not be altered in the off-chain context.")
+	/// Read the value under `key` from the Off-chain DB.
+	fn local_storage_get(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.note_stats(|stats| stats.offchain_reads = stats.offchain_reads.saturating_add(1));
+		sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, key)
+	}
+
+	/// Find the `parent_hash` of the block that added node `pos` to the MMR.
+	///
+	/// Returns [Error::UnknownAncestorHash] if the hash of that (past) block is not known
+	/// anymore, i.e. `frame_system` returns the default hash for it (for instance because it was
+	/// pruned). In such case the fork-aware offchain key of the node can't be built.
+	fn parent_hash_of_ancestor_that_added_node(
+		pos: TypedNodeIndex,
+	) -> Result<<T as frame_system::Config>::Hash, Error> {
+		let ancestor_leaf_idx = NodesUtils::leaf_index_that_added_node(pos);
+		let ancestor_parent_block_num =
+			Pallet::<T, I>::leaf_index_to_parent_block_num(ancestor_leaf_idx)?;
+		let ancestor_parent_hash = Pallet::<T, I>::retained_block_hash(ancestor_parent_block_num)
+			.unwrap_or_else(|| <frame_system::Pallet<T>>::block_hash(ancestor_parent_block_num));
+
+		// Nodes which would be added by future blocks simply don't exist yet, so we only
+		// complain about unknown hashes of past blocks.
+		if ancestor_parent_hash == Default::default() &&
+			ancestor_parent_block_num < <frame_system::Pallet<T>>::block_number()
+		{
+			return Err(Error::UnknownAncestorHash {
+				block_num: ancestor_parent_block_num.saturated_into(),
+			}
+			.log_debug("Unknown hash of block"))
+		}
+
+		Ok(ancestor_parent_hash)
 	}
 }
 
+/// Message of the [mmr_lib::Error::StoreError] raised by [Storage::store_error], the actual
+/// error is recovered with [Storage::into_mmr_error].
+const STORE_ERROR: &str = "MMR storage error";
+
+/// Message of the [mmr_lib::Error::StoreError] raised when the MMR would be altered in the
+/// off-chain context, if [Config::STRICT_NO_PANIC] is set.
+const OFFCHAIN_APPEND: &str = "MMR must not be altered in the off-chain context";
+
+/// Length of the checksum prepended to the off-chain nodes (see [Config::OFFCHAIN_CHECKSUM]).
+const CHECKSUM_LEN: usize = 4;
+
+/// Compute the checksum of the `encoded_node`.
+fn node_checksum(encoded_node: &[u8]) -> [u8; CHECKSUM_LEN] {
+	let hash = sp_io::hashing::twox_64(encoded_node);
+	[hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Return `true` if node `pos` is a leaf stored along with its type id (see
+/// [Config::LEAF_TYPE_ID]).
+fn is_tagged_leaf<T: Config<I>, I: 'static>(pos: TypedNodeIndex) -> bool {
+	T::LEAF_TYPE_ID != 0 && helper::pos_height_in_tree(pos.0) == 0
+}
+
+/// Check that `leaf` fits the Off-chain DB values with the [Config::LEAF_ENCODING].
+pub(crate) fn check_leaf_encoding<T: Config<I>, I: 'static, L: primitives::FullLeaf>(
+	leaf: &L,
+) -> Result<(), Error> {
+	if T::LEAF_ENCODING == primitives::LeafEncoding::Scale {
+		return Ok(())
+	}
+	let type_id_len = if T::LEAF_TYPE_ID != 0 { T::LEAF_TYPE_ID.encoded_size() } else { 0 };
+	let node_len = NodeOf::<T, I, L>::Data(leaf.clone()).encoded_size();
+	T::LEAF_ENCODING.check(type_id_len + node_len)
+}
+
+/// Encode node `pos` for the Off-chain DB, prepending the leaf type id to the leaves (see
+/// [Config::LEAF_TYPE_ID]), padding them according to [Config::LEAF_ENCODING] and
+/// prepending the checksum if [Config::OFFCHAIN_CHECKSUM] is set.
+fn encode_node<T: Config<I>, I: 'static, L: primitives::FullLeaf>(
+	pos: TypedNodeIndex,
+	node: &NodeOf<T, I, L>,
+) -> Vec<u8> {
+	let mut encoded_node = Vec::new();
+	if is_tagged_leaf::<T, I>(pos) {
+		T::LEAF_TYPE_ID.encode_to(&mut encoded_node);
+	}
+	node.encode_to(&mut encoded_node);
+	if helper::pos_height_in_tree(pos.0) == 0 {
+		T::LEAF_ENCODING.pad(&mut encoded_node);
+	}
+	if !T::OFFCHAIN_CHECKSUM {
+		return encoded_node
+	}
+	let mut elem = Vec::with_capacity(CHECKSUM_LEN + encoded_node.len());
+	elem.extend_from_slice(&node_checksum(&encoded_node));
+	elem.extend_from_slice(&encoded_node);
+	elem
+}
+
 impl<T, I, L> mmr_lib::MMRStore<NodeOf<T, I, L>> for Storage<RuntimeStorage, T, I, L>
 where
 	T: Config<I>,
@@ -115,10 +835,6 @@ where
 	}
 
 	fn append(&mut self, pos: NodeIndex, elems: Vec<NodeOf<T, I, L>>) -> mmr_lib::Result<()> {
-		if elems.is_empty() {
-			return Ok(())
-		}
-
 		trace!(
 			target: "runtime::mmr", "elems: {:?}",
 			elems.iter().map(|elem| elem.hash()).collect::<Vec<_>>()
@@ -127,46 +843,123 @@ where
 		let leaves = NumberOfLeaves::<T, I>::get();
 		let size = NodesUtils::new(leaves).size();
 
+		// Even an empty append is expected to happen at the end of the MMR.
 		if pos != size {
 			return Err(mmr_lib::Error::InconsistentStore)
 		}
 
+		if elems.is_empty() {
+			return Ok(())
+		}
+
+		// Leaves are told apart from inner nodes by their position, so the appended nodes have
+		// to complete a valid MMR, with the data only at leaf positions.
 		let new_size = size + elems.len() as NodeIndex;
+		let mut appended_leaves = 0;
+		for (pos, elem) in (size..new_size).zip(&elems) {
+			let is_leaf = helper::pos_height_in_tree(pos) == 0;
+			if !is_leaf && matches!(elem, Node::Data(..)) {
+				return Err(mmr_lib::Error::InconsistentStore)
+			}
+			appended_leaves += is_leaf as LeafIndex;
+		}
+		if NodesUtils::new(leaves + appended_leaves).size() != new_size {
+			return Err(mmr_lib::Error::InconsistentStore)
+		}
+
+		// Genesis leaves are appended in the genesis block, they are not limited.
+		let is_genesis = <frame_system::Pallet<T>>::block_number().is_zero();
+		if let Some(limit) = T::MAX_LEAVES_PER_BLOCK {
+			let block_leaves = leaves - Pallet::<T, I>::block_first_leaf(leaves) + appended_leaves;
+			if block_leaves > limit as LeafIndex && !is_genesis {
+				return Err(self.store_error(Error::TooManyLeavesInBlock))
+			}
+		}
+
+		// Use parent hash of block adding new nodes (this block) as extra identifier
+		// in offchain DB to avoid DB collisions and overwrites in case of forks.
+		// Nodes indexed under the default hash could never be looked up consistently. The
+		// genesis block has no parent, but the Off-chain DB isn't available there anyway.
+		let parent_hash = <frame_system::Pallet<T>>::parent_hash();
+		if T::IndexingEnabled::get() && parent_hash == Default::default() && !is_genesis {
+			return Err(self.store_error(Error::UnknownParentHash))
+		}
+
+		#[cfg(debug_assertions)]
+		if !is_genesis {
+			Self::check_unique_leaves(size, &elems).map_err(|e| self.store_error(e))?;
+		}
+		// Keep track of the blocks adding the leaves (see `LeafCountCheckpoints`), the genesis
+		// leaves are tracked by `GenesisLeaves` instead.
+		if !is_genesis {
+			Pallet::<T, I>::note_block_start(leaves);
+		}
 
 		// A sorted (ascending) iterator over peak indices to prune and persist.
 		let (peaks_to_prune, mut peaks_to_store) = peaks_to_prune_and_store(size, new_size);
 
 		// Now we are going to iterate over elements to insert
 		// and keep track of the current `node_index` and `leaf_index`.
-		let mut leaf_index = leaves;
-		let mut node_index = size;
+		let mut leaf_index = TypedLeafIndex(leaves);
+		let mut node_index = TypedNodeIndex(size);
 
-		// Use parent hash of block adding new nodes (this block) as extra identifier
-		// in offchain DB to avoid DB collisions and overwrites in case of forks.
-		let parent_hash = <frame_system::Pallet<T>>::parent_hash();
+		let mut offchain_bytes = 0;
+		let mut new_peaks = Vec::new();
 		for elem in elems {
-			// On-chain we are going to only store new peaks.
-			if peaks_to_store.next_if_eq(&node_index).is_some() {
-				<Nodes<T, I>>::insert(node_index, elem.hash());
+			// On-chain we are going to only store new peaks (or all nodes, if configured to).
+			let is_peak = peaks_to_store.next_if_eq(&node_index).is_some();
+			if is_peak || T::STORE_ALL_NODES {
+				<Nodes<T, I>>::insert(node_index.0, elem.hash());
+			}
+			if is_peak {
+				new_peaks.push(elem.hash());
+			}
+			// We are storing full node off-chain (using indexing API), if enabled.
+			// Note leaves pushed as hashes are indexed separately, along with their full data
+			// (see [Self::index_leaf]).
+			let is_leaf = helper::pos_height_in_tree(node_index.0) == 0;
+			let indexing_enabled = T::IndexingEnabled::get();
+			if is_peak && indexing_enabled && T::MIRROR_PEAKS_OFFCHAIN {
+				let key = Pallet::<T, I>::peak_offchain_key(node_index);
+				offchain_index::set(&key, &elem.hash().encode());
+			}
+			if indexing_enabled && !(is_leaf && matches!(elem, Node::Hash(..))) {
+				offchain_bytes += Self::store_to_offchain(node_index, parent_hash, &elem);
 			}
-			// We are storing full node off-chain (using indexing API).
-			Self::store_to_offchain(node_index, parent_hash, &elem);
 
 			// Increase the indices.
-			if let Node::Data(..) = elem {
-				leaf_index += 1;
+			if is_leaf {
+				if !indexing_enabled {
+					Pallet::<T, I>::note_not_indexed_leaf(leaf_index);
+				}
+				leaf_index = TypedLeafIndex(leaf_index.0 + 1);
 			}
-			node_index += 1;
+			node_index = TypedNodeIndex(node_index.0 + 1);
 		}
 
 		// Update current number of leaves.
-		NumberOfLeaves::<T, I>::put(leaf_index);
+		NumberOfLeaves::<T, I>::put(leaf_index.0);
+		Self::note_offchain_bytes(offchain_bytes);
 
 		// And remove all remaining items from `peaks_before` collection.
+		let peaks_to_prune = peaks_to_prune.collect::<Vec<_>>();
+		Pallet::<T, I>::note_new_peaks(leaves, peaks_to_prune.len(), new_peaks, leaf_index.0);
 		for pos in peaks_to_prune {
-			<Nodes<T, I>>::remove(pos);
+			if !T::STORE_ALL_NODES {
+				if T::MAX_PEAK_HISTORY == 0 {
+					<Nodes<T, I>>::remove(pos.0);
+				} else if let Some(hash) = <Nodes<T, I>>::take(pos.0) {
+					Pallet::<T, I>::note_pruned_peak(pos, hash);
+				}
+			}
+			if T::IndexingEnabled::get() && T::MIRROR_PEAKS_OFFCHAIN {
+				offchain_index::clear(&Pallet::<T, I>::peak_offchain_key(pos));
+			}
 		}
 
+		let peaks = helper::get_peaks(new_size);
+		<T::OnAppend as primitives::OnAppend>::on_append(leaf_index.0, &peaks);
+
 		Ok(())
 	}
 }
@@ -177,12 +970,57 @@ where
 	I: 'static,
 	L: primitives::FullLeaf,
 {
+	/// Index the full data of leaf `pos` off-chain (if enabled), after it was appended to the
+	/// MMR as a hash (see [crate::mmr::Mmr::push_prehashed]).
+	pub fn index_leaf(pos: TypedNodeIndex, leaf: NodeOf<T, I, L>) {
+		if T::IndexingEnabled::get() {
+			let parent_hash = <frame_system::Pallet<T>>::parent_hash();
+			let bytes = Self::store_to_offchain(pos, parent_hash, &leaf);
+			Self::note_offchain_bytes(bytes);
+		}
+	}
+
+	/// Fail if any two leaves among the `elems` appended at `size` are identical.
+	///
+	/// The leaves committed together are added by the same block and expected to differ, so a
+	/// duplicate usually indicates a misconfigured [`Config::LeafData`] provider. This is only
+	/// checked in debug builds, the production runtimes don't pay for it.
+	#[cfg(debug_assertions)]
+	fn check_unique_leaves(size: NodeIndex, elems: &[NodeOf<T, I, L>]) -> Result<(), Error> {
+		let mut hashes = Vec::new();
+		for (pos, elem) in (size..).zip(elems) {
+			if helper::pos_height_in_tree(pos) != 0 {
+				continue
+			}
+			let hash = elem.hash();
+			if hashes.contains(&hash) {
+				return Err(Error::Push.log_error(("Duplicate leaf in block", pos, hash)))
+			}
+			hashes.push(hash);
+		}
+		Ok(())
+	}
+
+	/// Add `bytes` to [`crate::TotalOffchainBytes`], if [`Config::TRACK_OFFCHAIN_SIZE`] is set.
+	fn note_offchain_bytes(bytes: u64) {
+		if T::TRACK_OFFCHAIN_SIZE && bytes > 0 {
+			<TotalOffchainBytes<T, I>>::mutate(|total| *total = total.saturating_add(bytes));
+		}
+	}
+
+	/// Index `node` off-chain, returning the size of the stored value.
 	fn store_to_offchain(
-		pos: NodeIndex,
+		pos: TypedNodeIndex,
 		parent_hash: <T as frame_system::Config>::Hash,
 		node: &NodeOf<T, I, L>,
-	) {
-		let encoded_node = node.encode();
+	) -> u64 {
+		let encoded_node = encode_node::<T, I, _>(pos, node);
+		if !T::FORK_SAFE_KEYS {
+			let key = Pallet::<T, I>::node_canon_offchain_key(pos);
+			debug!(target: "runtime::mmr::offchain", "offchain db set: pos {} key {:?}", pos, key);
+			offchain_index::set(&key, &encoded_node);
+			return encoded_node.len() as u64
+		}
 		// We store this leaf offchain keyed by `(parent_hash, node_index)` to make it
 		// fork-resistant. The MMR client gadget task will "canonicalize" it on the first
 		// finality notification that follows, when we are not worried about forks anymore.
@@ -193,21 +1031,22 @@ where
 		);
 		// Indexing API is used to store the full node content.
 		offchain_index::set(&temp_key, &encoded_node);
+		encoded_node.len() as u64
 	}
 }
 
 fn peaks_to_prune_and_store(
 	old_size: NodeIndex,
 	new_size: NodeIndex,
-) -> (impl Iterator<Item = NodeIndex>, Peekable<impl Iterator<Item = NodeIndex>>) {
+) -> (impl Iterator<Item = TypedNodeIndex>, Peekable<impl Iterator<Item = TypedNodeIndex>>) {
 	// A sorted (ascending) collection of peak indices before and after insertion.
 	// both collections may share a common prefix.
 	let peaks_before = if old_size == 0 { vec![] } else { helper::get_peaks(old_size) };
 	let peaks_after = helper::get_peaks(new_size);
 	trace!(target: "runtime::mmr", "peaks_before: {:?}", peaks_before);
 	trace!(target: "runtime::mmr", "peaks_after: {:?}", peaks_after);
-	let mut peaks_before = peaks_before.into_iter().peekable();
-	let mut peaks_after = peaks_after.into_iter().peekable();
+	let mut peaks_before = peaks_before.into_iter().map(TypedNodeIndex).peekable();
+	let mut peaks_after = peaks_after.into_iter().map(TypedNodeIndex).peekable();
 
 	// Consume a common prefix between `peaks_before` and `peaks_after`,
 	// since that's something we will not be touching anyway.
@@ -21,13 +21,17 @@ use crate::*;
 use codec::{Decode, Encode};
 use frame_support::{
 	parameter_types,
-	traits::{ConstU32, ConstU64},
+	traits::{ConstBool, ConstU32, ConstU64},
 };
 use sp_core::H256;
-use sp_mmr_primitives::{Compact, LeafDataProvider};
+use sp_mmr_primitives::{
+	Compact, FullLeaf, LeafCommitment, LeafDataProvider, LeafEncoding, LeafValidator, OnAppend,
+	OnNewRoot, RootWrapper,
+};
 use sp_runtime::{
 	testing::Header,
-	traits::{BlakeTwo256, IdentityLookup, Keccak256},
+	traits::{BlakeTwo256, Hash as _, IdentityLookup, Keccak256},
+	DispatchError,
 };
 
 type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
@@ -40,7 +44,12 @@ frame_support::construct_runtime!(
 		UncheckedExtrinsic = UncheckedExtrinsic,
 	{
 		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
-		MMR: pallet_mmr::{Pallet, Storage},
+		MMR: pallet_mmr::{Pallet, Call, Storage, Event<T>},
+		MMRAllNodes: pallet_mmr::<Instance1>::{Pallet, Call, Storage, Event<T>},
+		MMRBlake2: pallet_mmr::<Instance2>::{Pallet, Call, Storage, Event<T>},
+		MMRFinalize: pallet_mmr::<Instance3>::{Pallet, Call, Storage, Event<T>},
+		MMRTagged: pallet_mmr::<Instance4>::{Pallet, Call, Storage, Event<T>},
+		MMRCrossChain: pallet_mmr::<Instance5>::{Pallet, Call, Storage, Event<T>},
 	}
 );
 
@@ -74,14 +83,143 @@ impl frame_system::Config for Test {
 impl Config for Test {
 	const INDEXING_PREFIX: &'static [u8] = b"mmr-";
 
+	type RuntimeEvent = RuntimeEvent;
+	type Hashing = Keccak256;
+	type Hash = H256;
+	type LeafData = Compact<Keccak256, (ParentNumberAndHash<Test>, LeafData)>;
+	type LeafTransform = ();
+	type LeafValidator = ();
+	type CrossChainCommitment = ();
+	type OnNewRoot = ();
+	type RootWrapper = ();
+	type OnAppend = RecordAppend;
+	type IndexingEnabled = IndexingEnabled;
+	type FinalizedRootOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
+}
+
+impl Config<Instance1> for Test {
+	const INDEXING_PREFIX: &'static [u8] = b"mmr-all-nodes-";
+	const STORE_ALL_NODES: bool = true;
+	const ALLOW_FORCE_RESET: bool = true;
+	const ALLOW_CLEAR: bool = true;
+	const PARENT_HASH_RETENTION: u32 = 4;
+	const MIRROR_PEAKS_OFFCHAIN: bool = true;
+	const TRACK_OFFCHAIN_SIZE: bool = true;
+	const OFFCHAIN_CHECKSUM: bool = true;
+	const MAX_LEAVES: LeafIndex = 1000;
+	const OFFCHAIN_RETENTION_BLOCKS: Option<u32> = Some(3);
+	const MAX_FINALIZED_ROOTS: u32 = 3;
+	const STRICT_NO_PANIC: bool = true;
+
+	type RuntimeEvent = RuntimeEvent;
+	type Hashing = Keccak256;
+	type Hash = H256;
+	type LeafData = Compact<Keccak256, (ParentNumberAndHash<Test>, LeafData)>;
+	type LeafTransform = ();
+	type LeafValidator = ();
+	type CrossChainCommitment = ();
+	type OnNewRoot = RecordRootCommitment;
+	type RootWrapper = WrapWithNextAuthoritySet;
+	type OnAppend = ();
+	type IndexingEnabled = ConstBool<true>;
+	type FinalizedRootOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
+}
+
+impl Config<Instance2> for Test {
+	const INDEXING_PREFIX: &'static [u8] = b"mmr-blake2-";
+	const MAX_PEAK_HISTORY: u32 = 3;
+	const ALLOW_FORCE_RESET: bool = true;
+	const FORK_SAFE_KEYS: bool = false;
+	const LEAF_TYPE_ID: u16 = 1;
+
+	type RuntimeEvent = RuntimeEvent;
+	type Hashing = BlakeTwo256;
+	type Hash = H256;
+	type LeafData = Compact<BlakeTwo256, (ParentNumberAndHash<Test>, LeafData)>;
+	type LeafTransform = ();
+	type LeafValidator = RejectLargeLeaves;
+	type CrossChainCommitment = ();
+	type OnNewRoot = pallet_mmr::DepositMmrRootDigest<Test, Instance2>;
+	type RootWrapper = ();
+	type OnAppend = ();
+	type IndexingEnabled = ConstBool<true>;
+	type FinalizedRootOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
+}
+
+impl Config<Instance3> for Test {
+	const INDEXING_PREFIX: &'static [u8] = b"mmr-finalize-";
+	const COMMIT_IN_FINALIZE: bool = true;
+	const MAX_LEAVES_PER_BLOCK: Option<u32> = Some(4);
+
+	type RuntimeEvent = RuntimeEvent;
 	type Hashing = Keccak256;
 	type Hash = H256;
 	type LeafData = Compact<Keccak256, (ParentNumberAndHash<Test>, LeafData)>;
+	type LeafTransform = ();
+	type LeafValidator = ();
+	type CrossChainCommitment = ();
 	type OnNewRoot = ();
+	type RootWrapper = ();
+	type OnAppend = ();
+	type IndexingEnabled = ConstBool<true>;
+	type FinalizedRootOrigin = frame_system::EnsureRoot<Self::AccountId>;
 	type WeightInfo = ();
 }
 
-#[derive(Encode, Decode, Clone, Default, Eq, PartialEq, Debug)]
+impl Config<Instance4> for Test {
+	const INDEXING_PREFIX: &'static [u8] = b"mmr-tagged-";
+	const LEAF_ENCODING: LeafEncoding = LeafEncoding::FixedWidth(32);
+	const STRICT_NO_PANIC: bool = true;
+
+	type RuntimeEvent = RuntimeEvent;
+	type Hashing = Keccak256;
+	type Hash = H256;
+	type LeafData = LeafData;
+	type LeafTransform = EmbedBlockNumber<Test>;
+	type LeafValidator = ();
+	type CrossChainCommitment = ();
+	type OnNewRoot = ();
+	type RootWrapper = ();
+	type OnAppend = ();
+	type IndexingEnabled = ConstBool<true>;
+	type FinalizedRootOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
+}
+
+impl Config<Instance5> for Test {
+	const INDEXING_PREFIX: &'static [u8] = b"mmr-cross-chain-";
+	const CROSS_CHAIN_COMMITMENT: bool = true;
+
+	type RuntimeEvent = RuntimeEvent;
+	type Hashing = Keccak256;
+	type Hash = H256;
+	type LeafData = ParentNumberAndHash<Test>;
+	type LeafTransform = ();
+	type LeafValidator = ();
+	type CrossChainCommitment = AbiCommitment;
+	type OnNewRoot = ();
+	type RootWrapper = ();
+	type OnAppend = ();
+	type IndexingEnabled = ConstBool<true>;
+	type FinalizedRootOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
+}
+
+#[derive(
+	Encode,
+	Decode,
+	Clone,
+	Default,
+	Eq,
+	PartialEq,
+	Debug,
+	frame_support::Serialize,
+	frame_support::Deserialize,
+)]
+#[serde(crate = "frame_support::serde")]
 pub struct LeafData {
 	pub a: u64,
 	pub b: Vec<u8>,
@@ -95,6 +233,65 @@ impl LeafData {
 
 parameter_types! {
 	pub static LeafDataTestValue: LeafData = Default::default();
+	pub static IndexingEnabled: bool = true;
+	pub static LastRootCommitment: Option<H256> = None;
+	pub static LastAppend: Option<(LeafIndex, Vec<NodeIndex>)> = None;
+	pub const NextAuthoritySetCommitment: H256 = H256::repeat_byte(0xbe);
+}
+
+/// Commits to the MMR root together with a (mock) next authority set.
+pub struct WrapWithNextAuthoritySet;
+
+impl RootWrapper<H256> for WrapWithNextAuthoritySet {
+	fn wrap_root(root: &H256) -> H256 {
+		Keccak256::hash_of(&(root, NextAuthoritySetCommitment::get()))
+	}
+}
+
+/// Records the latest root commitment in [LastRootCommitment].
+pub struct RecordRootCommitment;
+
+impl OnNewRoot<H256> for RecordRootCommitment {
+	fn on_new_root(root: &H256) {
+		LastRootCommitment::set(Some(*root));
+	}
+}
+
+/// The maximum size of the leaves accepted by [RejectLargeLeaves].
+pub const MAX_LEAF_SIZE: usize = 64;
+
+/// Rejects the leaves encoded in more than [MAX_LEAF_SIZE] bytes.
+pub struct RejectLargeLeaves;
+
+impl<L: FullLeaf> LeafValidator<L> for RejectLargeLeaves {
+	fn validate(leaf: &L) -> Result<(), DispatchError> {
+		if leaf.using_encoded(|bytes| bytes.len(), false) > MAX_LEAF_SIZE {
+			return Err(DispatchError::Other("Leaf too large"))
+		}
+		Ok(())
+	}
+}
+
+/// Commits to the `(block_number, parent_hash)` leaves as
+/// `keccak256(abi.encode(uint256(block_number), parent_hash))`, like an EVM verifier would.
+pub struct AbiCommitment;
+
+impl LeafCommitment<(u64, H256)> for AbiCommitment {
+	fn commit<H: sp_runtime::traits::Hash>((block_number, parent_hash): &(u64, H256)) -> H::Output {
+		let mut preimage = [0u8; 64];
+		preimage[24..32].copy_from_slice(&block_number.to_be_bytes());
+		preimage[32..].copy_from_slice(parent_hash.as_bytes());
+		H::hash(&preimage)
+	}
+}
+
+/// Records the state of the MMR after the latest append in [LastAppend].
+pub struct RecordAppend;
+
+impl OnAppend for RecordAppend {
+	fn on_append(leaf_count: LeafIndex, peaks: &[NodeIndex]) {
+		LastAppend::set(Some((leaf_count, peaks.to_vec())));
+	}
 }
 
 impl LeafDataProvider for LeafData {
@@ -0,0 +1,59 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Utilities for testing the MMR integration in downstream crates.
+
+use crate::primitives::FullLeaf;
+use codec::Decode;
+use sp_runtime::traits;
+
+/// Assert that the [FullLeaf] implementation of the leaf type behaves as the MMR expects.
+///
+/// Meant to be called from the tests of crates implementing [FullLeaf] for their own leaf
+/// types, with a representative `sample` of the leaf. The following is checked:
+/// - both the full and the compact encoding of the leaf are deterministic,
+/// - the full encoding decodes back into the same leaf,
+/// - the compact encoding decodes into a leaf with the same commitment,
+/// - the commitment is stable across calls.
+///
+/// Panics with a description of the violated invariant otherwise.
+pub fn validate_full_leaf_impl<H, L>(sample: L)
+where
+	H: traits::Hash,
+	L: FullLeaf + Decode,
+{
+	for compact in [false, true] {
+		let first = sample.using_encoded(|encoded| encoded.to_vec(), compact);
+		let second = sample.using_encoded(|encoded| encoded.to_vec(), compact);
+		assert_eq!(first, second, "Non-deterministic encoding of leaf: {:?}", sample);
+	}
+
+	let full = sample.using_encoded(|encoded| encoded.to_vec(), false);
+	let decoded = L::decode(&mut &*full).expect("The full encoding of a leaf has to decode");
+	assert_eq!(decoded, sample, "Full encoding doesn't round-trip");
+
+	let commitment = sample.compact_commitment::<H>();
+	assert_eq!(sample.compact_commitment::<H>(), commitment, "Non-deterministic commitment");
+
+	let compact = sample.using_encoded(|encoded| encoded.to_vec(), true);
+	let decoded = L::decode(&mut &*compact).expect("The compact encoding of a leaf has to decode");
+	assert_eq!(
+		decoded.compact_commitment::<H>(),
+		commitment,
+		"Compact encoding doesn't preserve the commitment"
+	);
+}
@@ -22,7 +22,7 @@ use sp_core::{
 	offchain::{testing::TestOffchainExt, OffchainDbExt, OffchainWorkerExt},
 	H256,
 };
-use sp_mmr_primitives::{mmr_lib::helper, utils, Compact, Proof};
+use sp_mmr_primitives::{mmr_lib::helper, utils, Compact, Proof, TypedLeafIndex, TypedNodeIndex};
 
 pub(crate) fn new_test_ext() -> sp_io::TestExternalities {
 	frame_system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
@@ -34,6 +34,13 @@ fn register_offchain_ext(ext: &mut sp_io::TestExternalities) {
 	ext.register_extension(OffchainWorkerExt::new(offchain));
 }
 
+/// Build the test externalities with the Off-chain DB available, e.g. for the benchmarks.
+pub(crate) fn new_offchain_test_ext() -> sp_io::TestExternalities {
+	let mut ext = new_test_ext();
+	register_offchain_ext(&mut ext);
+	ext
+}
+
 fn new_block() -> Weight {
 	let number = frame_system::Pallet::<Test>::block_number() + 1;
 	let hash = H256::repeat_byte(number as u8);
@@ -71,6 +78,21 @@ fn decode_node(
 	}
 }
 
+/// Assert that each of the `samples` is encoded identically every time.
+///
+/// MMR correctness relies on leaves hashing to the same value every time they are encoded,
+/// which is not the case for leaf types with non-deterministic encoding (e.g. ones containing
+/// a `HashMap`).
+pub(crate) fn assert_deterministic_encoding<L: primitives::FullLeaf>(samples: &[L]) {
+	for sample in samples {
+		for compact in [false, true] {
+			let first = sample.using_encoded(|encoded| encoded.to_vec(), compact);
+			let second = sample.using_encoded(|encoded| encoded.to_vec(), compact);
+			assert_eq!(first, second, "Non-deterministic encoding of leaf: {:?}", sample);
+		}
+	}
+}
+
 fn add_blocks(blocks: usize) {
 	// given
 	for _ in 0..blocks {
@@ -167,13 +189,17 @@ fn should_append_to_mmr_when_on_initialize_is_called() {
 
 	let expected = Some(mmr::Node::Data(((0, H256::repeat_byte(1)), LeafData::new(1))));
 	assert_eq!(
-		offchain_db.get(&MMR::node_temp_offchain_key(0, parent_b1)).map(decode_node),
+		offchain_db
+			.get(&MMR::node_temp_offchain_key(TypedNodeIndex(0), parent_b1))
+			.map(decode_node),
 		expected
 	);
 
 	let expected = Some(mmr::Node::Data(((1, H256::repeat_byte(2)), LeafData::new(2))));
 	assert_eq!(
-		offchain_db.get(&MMR::node_temp_offchain_key(1, parent_b2)).map(decode_node),
+		offchain_db
+			.get(&MMR::node_temp_offchain_key(TypedNodeIndex(1), parent_b2))
+			.map(decode_node),
 		expected
 	);
 
@@ -181,11 +207,13 @@ fn should_append_to_mmr_when_on_initialize_is_called() {
 		"672c04a9cd05a644789d769daa552d35d8de7c33129f8a7cbf49e595234c4854",
 	)));
 	assert_eq!(
-		offchain_db.get(&MMR::node_temp_offchain_key(2, parent_b2)).map(decode_node),
+		offchain_db
+			.get(&MMR::node_temp_offchain_key(TypedNodeIndex(2), parent_b2))
+			.map(decode_node),
 		expected
 	);
 
-	assert_eq!(offchain_db.get(&MMR::node_temp_offchain_key(3, parent_b2)), None);
+	assert_eq!(offchain_db.get(&MMR::node_temp_offchain_key(TypedNodeIndex(3), parent_b2)), None);
 }
 
 #[test]
@@ -468,6 +496,63 @@ fn should_generate_batch_proof_correctly() {
 	});
 }
 
+#[test]
+fn should_generate_proofs_from_onchain_nodes_when_storing_all_nodes() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+	});
+	ext.persist_offchain_overlay();
+
+	// NOTE the offchain extensions are not registered, proof generation relies on
+	// on-chain storage only.
+	ext.execute_with(|| {
+		// then all the nodes are kept on-chain
+		let size = utils::NodesUtils::new(7).size();
+		for pos in 0..size {
+			assert!(crate::Nodes::<Test, Instance1>::get(pos).is_some());
+		}
+		let root = crate::RootHash::<Test, Instance1>::get();
+		assert_eq!(root, crate::RootHash::<Test>::get());
+
+		// when
+		let proof =
+			crate::Pallet::<Test, Instance1>::generate_onchain_proof(vec![1, 5, 6]).unwrap();
+
+		// then
+		assert_eq!(
+			proof,
+			Proof {
+				leaf_indices: vec![0, 4, 5],
+				leaf_count: 7,
+				items: vec![
+					hex("ad4cbc033833612ccd4626d5f023b9dfc50a35e838514dd1f3c86f8506728705"),
+					hex("cb24f4614ad5b2a5430344c99545b421d9af83c46fd632d70a332200884b4d46"),
+					hex("611c2174c6164952a66d985cfe1ec1a623794393e3acff96b136d198f37a648c"),
+				],
+			}
+		);
+		let leaves = vec![1u64, 5, 6]
+			.into_iter()
+			.map(|n| {
+				crate::primitives::DataOrHash::Data(Compact::new((
+					(n - 1, H256::repeat_byte(n as u8)).into(),
+					LeafData::new(n).into(),
+				)))
+			})
+			.collect();
+		assert_eq!(
+			crate::verify_leaves_proof::<<Test as Config>::Hashing, _>(root, leaves, proof),
+			Ok(())
+		);
+	});
+}
+
 #[test]
 fn should_verify() {
 	let _ = env_logger::try_init();
@@ -699,6 +784,54 @@ fn should_verify_batch_proof_statelessly() {
 	);
 }
 
+#[test]
+fn should_verify_against_root_in_header_digest() {
+	type Blake2 = <Test as Config<Instance2>>::Hashing;
+	let _ = env_logger::try_init();
+
+	// given 7 blocks, the BLAKE2 MMR depositing its root into their digests
+	let mut ext = new_test_ext();
+	let (root, header, header_without_digest) = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		let header = frame_system::Pallet::<Test>::finalize();
+		// and a block not appending to it
+		new_block();
+		(MMRBlake2::mmr_root_hash(), header, frame_system::Pallet::<Test>::finalize())
+	});
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	let (leaves, proof5) = ext.execute_with(|| {
+		// when
+		crate::Pallet::<Test, Instance2>::generate_proof(vec![5], Some(7)).unwrap()
+	});
+	let leaves: Vec<_> = leaves
+		.into_iter()
+		.map(|leaf| crate::primitives::DataOrHash::Data(leaf))
+		.collect();
+
+	// then
+	assert_eq!(
+		header.digest.convert_first(|log| {
+			log.consensus_try_to::<<Test as Config<Instance2>>::Hash>(
+				&crate::primitives::MMR_ENGINE_ID,
+			)
+		}),
+		Some(root)
+	);
+	assert_eq!(
+		crate::verify_proof_against_header::<Blake2, _, _>(&header, leaves.clone(), proof5.clone()),
+		Ok(())
+	);
+	assert_eq!(
+		crate::verify_proof_against_header::<Blake2, _, _>(&header_without_digest, leaves, proof5),
+		Err(Error::MissingRootDigest)
+	);
+}
+
 #[test]
 fn should_verify_on_the_next_block_since_there_is_no_pruning_yet() {
 	let _ = env_logger::try_init();
@@ -774,7 +907,10 @@ fn does_not_panic_when_generating_historical_proofs() {
 	register_offchain_ext(&mut ext);
 	ext.execute_with(|| {
 		// when leaf index is invalid
-		assert_eq!(crate::Pallet::<Test>::generate_proof(vec![10], None), Err(Error::LeafNotFound),);
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof(vec![10], None),
+			Err(Error::LeafIndexOutOfRange { requested: 9, count: 7 }),
+		);
 
 		// when leaves count is invalid
 		assert_eq!(
@@ -789,3 +925,3964 @@ fn does_not_panic_when_generating_historical_proofs() {
 		);
 	});
 }
+
+#[test]
+fn should_fail_generating_proofs_when_ancestor_hash_is_unknown() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (7 MMR leaves)
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when the hash of the parent of block 7 is not known anymore
+		// (`frame_system` returns the default hash for it).
+		frame_system::BlockHash::<Test>::remove(6);
+		assert_eq!(frame_system::Pallet::<Test>::block_hash(6), H256::default());
+
+		// then generating a proof for the leaf added by block 7 fails
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof(vec![7], None),
+			Err(Error::UnknownAncestorHash { block_num: 6 }),
+		);
+		// and so does generating a proof requiring the peak added by block 7
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof(vec![5], None),
+			Err(Error::UnknownAncestorHash { block_num: 6 }),
+		);
+		// while proofs not touching nodes added by block 7 can still be generated.
+		assert!(crate::Pallet::<Test>::generate_proof(vec![5], Some(6)).is_ok());
+	});
+}
+
+#[test]
+fn should_serve_prefetched_nodes_from_cache() {
+	use crate::mmr::storage::{OffchainStorage, Storage};
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (7 MMR leaves)
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let expected = crate::Pallet::<Test>::generate_proof(vec![1, 5], None).unwrap();
+
+		// when
+		let mut store = Storage::<OffchainStorage, Test, (), crate::LeafOf<Test, ()>>::default();
+		store.prefetch(&[TypedLeafIndex(0), TypedLeafIndex(4)]).unwrap();
+
+		// then all the nodes required to generate the proofs are cached
+		let nodes_utils = utils::NodesUtils::new(7);
+		for leaf_index in [TypedLeafIndex(0), TypedLeafIndex(4)] {
+			assert!(store.is_cached(utils::NodesUtils::leaf_to_node_index(leaf_index)));
+			for pos in nodes_utils.proof_node_indices(leaf_index) {
+				assert!(store.is_cached(pos));
+			}
+		}
+
+		// when the nodes can't be looked up in the offchain DB anymore
+		for block_num in 0..7 {
+			frame_system::BlockHash::<Test>::remove(block_num);
+		}
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof(vec![1, 5], None),
+			Err(Error::UnknownAncestorHash { block_num: 0 })
+		);
+
+		// then the proofs are still generated using the cache
+		let mmr: crate::ModuleMmr<OffchainStorage, Test, ()> = mmr::Mmr::with_storage(7, store);
+		assert_eq!(mmr.generate_proof(vec![0, 4]), Ok(expected));
+	});
+}
+
+#[test]
+fn leaves_should_have_deterministic_encoding() {
+	let _ = env_logger::try_init();
+
+	let leaves = (1..=7)
+		.map(|n: u64| {
+			Compact::<<Test as Config>::Hashing, _>::new((
+				(n - 1, H256::repeat_byte(n as u8)).into(),
+				LeafData { a: n, b: vec![n as u8; n as usize] }.into(),
+			))
+		})
+		.collect::<Vec<crate::LeafOf<Test, ()>>>();
+
+	assert_deterministic_encoding(&leaves);
+}
+
+#[test]
+#[should_panic(expected = "Non-deterministic encoding of leaf")]
+fn should_detect_non_deterministic_leaf_encoding() {
+	/// A leaf type which encodes differently every time, similarly to types with unstable
+	/// iteration order.
+	#[derive(Clone, PartialEq, Debug, Default)]
+	struct NonDeterministicLeaf {
+		encodings: sp_std::cell::Cell<u8>,
+	}
+
+	impl primitives::FullLeaf for NonDeterministicLeaf {
+		fn using_encoded<R, F: FnOnce(&[u8]) -> R>(&self, f: F, _compact: bool) -> R {
+			let encodings = self.encodings.get();
+			self.encodings.set(encodings.wrapping_add(1));
+			f(&[encodings])
+		}
+	}
+
+	assert_deterministic_encoding(&[NonDeterministicLeaf::default()]);
+}
+
+#[test]
+fn should_generate_proofs_after_reindexing_offchain_db() {
+	use sp_core::offchain::StorageKind;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (7 MMR leaves)
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let block_numbers = (1..=7).collect::<Vec<_>>();
+		let (leaves, proof) =
+			crate::Pallet::<Test>::generate_proof(block_numbers.clone(), None).unwrap();
+
+		// when the offchain DB is wiped
+		for pos in 0..utils::NodesUtils::new(7).size() {
+			let pos = TypedNodeIndex(pos);
+			let leaf_index = utils::NodesUtils::leaf_index_that_added_node(pos);
+			let parent_hash = frame_system::Pallet::<Test>::block_hash(leaf_index.0);
+			sp_io::offchain::local_storage_clear(
+				StorageKind::PERSISTENT,
+				&MMR::node_temp_offchain_key(pos, parent_hash),
+			);
+		}
+		assert_eq!(crate::Pallet::<Test>::generate_proof(vec![5], None), Err(Error::LeafNotFound));
+
+		// then a corrupted rebuild is rejected
+		let mut corrupted = leaves.clone();
+		corrupted[3] = corrupted[2].clone();
+		let reindexed =
+			crate::Pallet::<Test>::reindex_offchain((0..).map(TypedLeafIndex).zip(corrupted));
+		assert_eq!(reindexed, Err(Error::Verify));
+		// without writing anything
+		assert_eq!(crate::Pallet::<Test>::generate_proof(vec![5], None), Err(Error::LeafNotFound));
+
+		// when reindexed from the leaves
+		let reindexed =
+			crate::Pallet::<Test>::reindex_offchain((0..).map(TypedLeafIndex).zip(leaves.clone()));
+		assert_eq!(reindexed, Ok(7));
+
+		// then
+		assert_eq!(crate::Pallet::<Test>::generate_proof(block_numbers, None), Ok((leaves, proof)));
+	});
+}
+
+#[test]
+fn should_generate_smaller_proofs_for_known_leaves() {
+	use crate::primitives::{CompressionHint, DataOrHash};
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (7 MMR leaves)
+	let root = ext.execute_with(|| {
+		add_blocks(7);
+		crate::Pallet::<Test>::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6  7
+		//
+		// we're proving 3 => we need { 4, A, C++7 }
+		let (_, full_proof) = crate::Pallet::<Test>::generate_proof(vec![3], None).unwrap();
+		assert_eq!(full_proof.items.len(), 3);
+
+		for (known_blocks, expected_items) in [
+			// the verifier can compute A itself
+			(vec![1, 2], 2),
+			// and C++7
+			(vec![5, 6, 7], 2),
+			(vec![1, 2, 5, 6, 7], 1),
+			// but knowing a part of C doesn't help, nor does it make the proof larger
+			(vec![6], 3),
+			(vec![3, 5, 7], 3),
+		] {
+			let (known_leaves, _) =
+				crate::Pallet::<Test>::generate_proof(known_blocks.clone(), None).unwrap();
+			let known_leaf_indices = known_blocks.iter().map(|block| block - 1).collect();
+
+			// when
+			let hint = CompressionHint { known_leaf_indices };
+			let (leaves, proof) =
+				crate::Pallet::<Test>::generate_compressed_proof(vec![3], None, hint).unwrap();
+
+			// then
+			assert_eq!(leaves.len(), 1);
+			assert_eq!(proof.items.len(), expected_items, "known blocks {:?}", known_blocks);
+			let known_leaves = known_leaves
+				.into_iter()
+				.zip(known_blocks)
+				// the proven leaf is not listed twice
+				.filter(|(_, block)| *block != 3)
+				.map(|(leaf, _)| DataOrHash::Data(leaf))
+				.collect::<Vec<_>>();
+			assert_eq!(proof.leaf_indices.len(), 1 + known_leaves.len());
+			assert_eq!(
+				crate::verify_compressed_leaves_proof::<<Test as Config>::Hashing, _>(
+					root,
+					leaves.clone().into_iter().map(DataOrHash::Data).collect(),
+					known_leaves.clone(),
+					proof.clone(),
+				),
+				Ok(())
+			);
+
+			// and the proof doesn't verify without the known leaves
+			if expected_items < full_proof.items.len() {
+				let mut wrong_leaves = known_leaves;
+				wrong_leaves[0] = DataOrHash::Data(leaves[0].clone());
+				assert_eq!(
+					crate::verify_compressed_leaves_proof::<<Test as Config>::Hashing, _>(
+						root,
+						leaves.into_iter().map(DataOrHash::Data).collect(),
+						wrong_leaves,
+						proof,
+					),
+					Err(Error::Verify)
+				);
+			}
+		}
+	});
+}
+
+#[test]
+fn should_force_set_leaf_count() {
+	use frame_support::{assert_noop, assert_ok};
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks
+		let mut root_at_4 = Default::default();
+		for n in 1..=7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+			if n == 4 {
+				root_at_4 = crate::RootHash::<Test, Instance1>::get();
+			}
+		}
+
+		// and some leaves which were not indexed
+		crate::NotIndexedLeaves::<Test, Instance1>::put(
+			BoundedVec::try_from(vec![(1, 1), (3, 5), (6, 6)]).unwrap(),
+		);
+
+		// then only root can force-set the count, and only if allowed
+		assert_noop!(
+			MMRAllNodes::force_set_leaf_count(
+				RuntimeOrigin::signed(sp_core::sr25519::Public::from_raw([1; 32])),
+				4,
+				4,
+				3
+			),
+			sp_runtime::DispatchError::BadOrigin
+		);
+		assert_noop!(
+			MMR::force_set_leaf_count(RuntimeOrigin::root(), 4, 4, 3),
+			crate::pallet::Error::<Test>::ForceResetNotAllowed
+		);
+		assert_noop!(
+			MMRAllNodes::force_set_leaf_count(RuntimeOrigin::root(), 8, 0, 3),
+			crate::pallet::Error::<Test, Instance1>::LeafCountTooLarge
+		);
+		// and only with the witnesses covering the removed entries
+		assert_noop!(
+			MMRAllNodes::force_set_leaf_count(RuntimeOrigin::root(), 4, 3, 3),
+			crate::pallet::Error::<Test, Instance1>::BadWitness
+		);
+		assert_noop!(
+			MMRAllNodes::force_set_leaf_count(RuntimeOrigin::root(), 4, 4, 2),
+			crate::pallet::Error::<Test, Instance1>::BadWitness
+		);
+
+		// when
+		let post_info =
+			MMRAllNodes::force_set_leaf_count(RuntimeOrigin::root(), 4, 100, 100).unwrap();
+
+		// then the unused weight is refunded
+		assert_eq!(
+			post_info.actual_weight,
+			Some(<() as crate::WeightInfo>::force_set_leaf_count(4, 3).saturating_add(
+				<Test as frame_system::Config>::DbWeight::get().reads_writes(4, 3)
+			))
+		);
+		// and the truncated MMR is consistent
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance1>::get(), 4);
+		assert_eq!(crate::RootHash::<Test, Instance1>::get(), root_at_4);
+		assert_eq!(
+			LastRootCommitment::get(),
+			Some(Keccak256::hash_of(&(root_at_4, NextAuthoritySetCommitment::get())))
+		);
+		let size = utils::NodesUtils::new(4).size();
+		for peak in peaks_from_leaves_count(4) {
+			assert!(crate::Nodes::<Test, Instance1>::get(peak).is_some());
+		}
+		for pos in size..utils::NodesUtils::new(7).size() {
+			assert_eq!(crate::Nodes::<Test, Instance1>::get(pos), None);
+		}
+		// with the not indexed leaves of the removed leaves gone
+		assert_eq!(
+			crate::NotIndexedLeaves::<Test, Instance1>::get().into_inner(),
+			vec![(1, 1), (3, 3)]
+		);
+		// while the remaining leaves stay with their blocks
+		assert_eq!(crate::Pallet::<Test, Instance1>::block_for_leaf(TypedLeafIndex(3)), Ok(4));
+		assert_eq!(crate::Pallet::<Test, Instance1>::leaves_added_in_block(4), Ok((3, 4)));
+		assert_eq!(crate::Pallet::<Test, Instance1>::leaves_added_in_block(6), Ok((4, 4)));
+
+		// and new leaves can be appended to it
+		new_block();
+		MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance1>::get(), 5);
+		assert!(crate::Nodes::<Test, Instance1>::get(size).is_some());
+		assert_eq!(crate::Pallet::<Test, Instance1>::block_for_leaf(TypedLeafIndex(4)), Ok(8));
+
+		// and the new root is reported when truncating to an empty MMR too
+		assert_ok!(MMRAllNodes::force_set_leaf_count(RuntimeOrigin::root(), 0, 8, 2));
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance1>::get(), 0);
+		assert_eq!(crate::Nodes::<Test, Instance1>::iter_keys().count(), 0);
+		assert!(crate::NotIndexedLeaves::<Test, Instance1>::get().is_empty());
+		assert_eq!(
+			LastRootCommitment::get(),
+			Some(Keccak256::hash_of(&(H256::default(), NextAuthoritySetCommitment::get())))
+		);
+	});
+}
+
+#[test]
+fn should_force_set_leaf_count_restoring_pruned_peaks() {
+	use frame_support::{assert_noop, assert_ok};
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks, keeping the roots along the way
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6   7
+		let mut roots = vec![];
+		for _ in 0..7 {
+			new_block();
+			MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+			roots.push(MMRBlake2::mmr_root_hash());
+		}
+		assert_eq!(
+			crate::HistoricalPeakPositions::<Test, Instance2>::get().into_inner(),
+			vec![2, 3, 7]
+		);
+
+		// then the MMR can't be truncated to the leaf whose pruned peak was evicted
+		assert_noop!(
+			MMRBlake2::force_set_leaf_count(RuntimeOrigin::root(), 1, 3, 0),
+			crate::pallet::Error::<Test, Instance2>::MissingPeak
+		);
+
+		// when truncating it to 5 leaves, removing `C` and `7`
+		assert_ok!(MMRBlake2::force_set_leaf_count(RuntimeOrigin::root(), 5, 2, 0));
+
+		// then leaf `5` is restored from the peak history
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance2>::get(), 5);
+		assert_eq!(crate::RootHash::<Test, Instance2>::get(), roots[4]);
+		let mut nodes = crate::Nodes::<Test, Instance2>::iter_keys().collect::<Vec<_>>();
+		nodes.sort();
+		assert_eq!(nodes, vec![6, 7]);
+		assert_eq!(
+			crate::HistoricalPeakPositions::<Test, Instance2>::get().into_inner(),
+			vec![2, 3]
+		);
+		assert_eq!(crate::HistoricalPeaks::<Test, Instance2>::get(7), None);
+
+		// and new leaves can be appended to it
+		new_block();
+		MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance2>::get(), 6);
+		let mut nodes = crate::Nodes::<Test, Instance2>::iter_keys().collect::<Vec<_>>();
+		nodes.sort();
+		assert_eq!(nodes, vec![6, 9]);
+	});
+}
+
+#[test]
+fn should_clear_mmr() {
+	use frame_support::{assert_noop, assert_ok, traits::OnFinalize};
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks, the last one noted as finalized
+		let empty_root = crate::RootHash::<Test, Instance1>::get();
+		for _ in 0..7 {
+			new_block();
+			let n = frame_system::Pallet::<Test>::block_number();
+			MMRAllNodes::on_initialize(n);
+			MMRAllNodes::on_finalize(n);
+		}
+		assert_ok!(MMRAllNodes::note_finalized_root(RuntimeOrigin::root(), 7));
+		assert_ne!(crate::RootHash::<Test, Instance1>::get(), empty_root);
+		assert_ne!(crate::ParentHashes::<Test, Instance1>::iter_keys().count(), 0);
+		assert_ne!(crate::RecentRoots::<Test, Instance1>::iter_keys().count(), 0);
+		assert_ne!(crate::TotalOffchainBytes::<Test, Instance1>::get(), 0);
+
+		// then only root can clear the MMR, and only if allowed
+		assert_noop!(
+			MMRAllNodes::clear_mmr(
+				RuntimeOrigin::signed(sp_core::sr25519::Public::from_raw([1; 32])),
+				11,
+				0
+			),
+			sp_runtime::DispatchError::BadOrigin
+		);
+		assert_noop!(
+			MMR::clear_mmr(RuntimeOrigin::root(), 11, 0),
+			crate::pallet::Error::<Test>::ClearNotAllowed
+		);
+		// and only with the witnesses covering the removed entries
+		assert_noop!(
+			MMRAllNodes::clear_mmr(RuntimeOrigin::root(), 10, 0),
+			crate::pallet::Error::<Test, Instance1>::BadWitness
+		);
+
+		// when
+		let post_info = MMRAllNodes::clear_mmr(RuntimeOrigin::root(), 100, 100).unwrap();
+
+		// then the unused weight is refunded
+		assert_eq!(post_info.actual_weight, Some(MMRAllNodes::clear_mmr_weight(11, 0)));
+
+		// and the MMR is empty
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance1>::get(), 0);
+		assert_eq!(crate::RootHash::<Test, Instance1>::get(), empty_root);
+		assert_eq!(crate::Nodes::<Test, Instance1>::iter_keys().count(), 0);
+		// along with the state kept for the past blocks
+		assert_eq!(crate::ParentHashes::<Test, Instance1>::iter_keys().count(), 0);
+		assert_eq!(crate::RecentRoots::<Test, Instance1>::iter_keys().count(), 0);
+		assert_eq!(MMRAllNodes::finalized_root(7), None);
+		assert!(crate::FinalizedRootNumbers::<Test, Instance1>::get().is_empty());
+		assert_eq!(crate::TotalOffchainBytes::<Test, Instance1>::get(), 0);
+		// and the new root is notified
+		assert_eq!(LastRootCommitment::get(), Some(MMRAllNodes::mmr_root_commitment()));
+
+		// and it starts over with the next block
+		new_block();
+		MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance1>::get(), 1);
+		assert_eq!(crate::Nodes::<Test, Instance1>::iter_keys().collect::<Vec<_>>(), vec![0]);
+	});
+}
+
+#[test]
+fn should_repair_missing_peak() {
+	use frame_support::{assert_noop, assert_ok};
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	let root = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		crate::RootHash::<Test, Instance1>::get()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// and a peak missing on-chain
+		assert_eq!(peaks_from_leaves_count(7), vec![6, 9, 10]);
+		let peak = crate::Nodes::<Test, Instance1>::take(9).unwrap();
+
+		// when its hash is recovered from the Off-chain DB
+		let hash = MMRAllNodes::offchain_node_hash(TypedNodeIndex(9)).unwrap();
+		assert_eq!(hash, peak);
+
+		// then only a hash reproducing the root is accepted
+		assert_noop!(
+			MMRAllNodes::repair_peak(RuntimeOrigin::root(), 9, H256::repeat_byte(0x37)),
+			crate::pallet::Error::<Test, Instance1>::RootMismatch
+		);
+		assert_noop!(
+			MMRAllNodes::repair_peak(RuntimeOrigin::root(), 8, hash),
+			crate::pallet::Error::<Test, Instance1>::NotAPeak
+		);
+		assert_noop!(
+			MMRAllNodes::repair_peak(RuntimeOrigin::root(), 6, hash),
+			crate::pallet::Error::<Test, Instance1>::PeakNotMissing
+		);
+		assert_noop!(
+			MMR::repair_peak(RuntimeOrigin::root(), 9, hash),
+			crate::pallet::Error::<Test>::ForceResetNotAllowed
+		);
+
+		// when
+		assert_ok!(MMRAllNodes::repair_peak(RuntimeOrigin::root(), 9, hash));
+
+		// then the root can be recomputed from the peaks again
+		assert_eq!(crate::Nodes::<Test, Instance1>::get(9), Some(peak));
+		let mmr: crate::ModuleMmr<mmr::storage::RuntimeStorage, Test, Instance1> = mmr::Mmr::new(7);
+		assert_eq!(mmr.finalize().map(|(_, root)| root), Ok(root));
+	});
+}
+
+#[test]
+fn should_verify_proof_against_root_from_state_proof() {
+	use sp_runtime::StateVersion;
+	use sp_trie::{
+		LayoutV0, LayoutV1, MemoryDB, Recorder, StorageProof, Trie, TrieDBBuilder,
+		TrieDBMutBuilder, TrieLayout, TrieMut,
+	};
+	type StateHasher = sp_core::Blake2Hasher;
+
+	// Build a state trie with the `layout` containing `entries`, and prove the value of `key`.
+	fn prove<Layout: TrieLayout<Hash = StateHasher>>(
+		entries: &[(&[u8], Vec<u8>)],
+		key: &[u8],
+	) -> (H256, StorageProof) {
+		let mut db = MemoryDB::<StateHasher>::default();
+		let mut state_root = Default::default();
+		{
+			let mut trie = TrieDBMutBuilder::<Layout>::new(&mut db, &mut state_root).build();
+			for (key, value) in entries {
+				trie.insert(key, value).unwrap();
+			}
+		}
+		let mut recorder = Recorder::<Layout>::new();
+		{
+			let trie = TrieDBBuilder::<Layout>::new(&db, &state_root)
+				.with_recorder(&mut recorder)
+				.build();
+			assert!(trie.get(key).unwrap().is_some());
+		}
+		(state_root, StorageProof::new(recorder.drain().into_iter().map(|r| r.data)))
+	}
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given
+	let (root, root_key) = ext.execute_with(|| {
+		add_blocks(7);
+		(crate::Pallet::<Test>::mmr_root_hash(), crate::RootHash::<Test>::hashed_key().to_vec())
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+	let (leaves, proof) =
+		ext.execute_with(|| crate::Pallet::<Test>::generate_proof(vec![5], None).unwrap());
+	let leaf = mmr::Node::Data(leaves[0].clone());
+
+	// and state tries of both versions containing the MMR root
+	let entries = [
+		(&b"some other key"[..], b"some other value".to_vec()),
+		(&b"some large value"[..], vec![42; 64]),
+		(&root_key[..], codec::Encode::encode(&root)),
+	];
+	for (state_version, (state_root, state_proof)) in [
+		(StateVersion::V0, prove::<LayoutV0<StateHasher>>(&entries, &root_key)),
+		(StateVersion::V1, prove::<LayoutV1<StateHasher>>(&entries, &root_key)),
+	] {
+		let verify = |state_root, storage_key: &[u8]| {
+			crate::verify_with_state_proof::<<Test as Config>::Hashing, _, StateHasher>(
+				state_root,
+				state_proof.clone(),
+				state_version,
+				storage_key,
+				leaf.clone(),
+				proof.clone(),
+			)
+		};
+
+		// then
+		assert_eq!(verify(state_root, &root_key), Ok(()));
+		// the value under another key is not the MMR root
+		assert_eq!(verify(state_root, b"some other key"), Err(Error::Verify));
+		// the state proof doesn't match an untrusted state root
+		assert_eq!(verify(Default::default(), &root_key), Err(Error::Verify));
+	}
+}
+
+#[test]
+fn should_generate_proofs_using_retained_parent_hashes() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// then the last 4 parent hashes are retained by the pallet
+		for block_num in 3..7u64 {
+			assert_eq!(
+				crate::ParentHashes::<Test, Instance1>::get((block_num % 4) as u32),
+				Some((block_num, frame_system::Pallet::<Test>::block_hash(block_num)))
+			);
+		}
+		let expected = crate::Pallet::<Test, Instance1>::generate_proof(vec![7], None).unwrap();
+
+		// when the block hashes are gone from `frame_system`
+		for block_num in 0..7 {
+			frame_system::BlockHash::<Test>::remove(block_num);
+		}
+
+		// then the proof can still be generated for the blocks within the retention
+		assert_eq!(crate::Pallet::<Test, Instance1>::generate_proof(vec![7], None), Ok(expected));
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof(vec![7], None),
+			Err(Error::UnknownAncestorHash { block_num: 6 })
+		);
+		// but not for the older ones
+		assert_eq!(
+			crate::Pallet::<Test, Instance1>::generate_proof(vec![1], None),
+			Err(Error::UnknownAncestorHash { block_num: 0 })
+		);
+	});
+}
+
+#[test]
+fn should_verify_chain_of_roots() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given the roots after each of 7 blocks
+	let roots = ext.execute_with(|| {
+		(1..=7u64)
+			.map(|leaf_count| {
+				new_block();
+				(leaf_count, crate::Pallet::<Test>::mmr_root_hash())
+			})
+			.collect::<Vec<_>>()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let ancestry_proofs = (1..7u64)
+			.map(|block_num| {
+				crate::Pallet::<Test>::generate_ancestry_proof(block_num, Some(block_num + 1))
+					.unwrap()
+			})
+			.collect::<Vec<_>>();
+
+		// then
+		assert_eq!(
+			verify_root_chain::<<Test as Config>::Hashing>(&roots, &ancestry_proofs),
+			Ok(())
+		);
+		// an ancestry proof spanning multiple blocks is fine as well
+		let proof = crate::Pallet::<Test>::generate_ancestry_proof(3, None).unwrap();
+		assert_eq!(proof.prev_leaf_count, 3);
+		assert_eq!(proof.leaf_count, 7);
+		assert_eq!(
+			verify_ancestry_proof::<<Test as Config>::Hashing>(roots[6].1, proof),
+			Ok(roots[2].1)
+		);
+
+		// when a root in the middle of the chain is tampered with
+		let mut tampered_roots = roots.clone();
+		tampered_roots[3].1 = H256::repeat_byte(0x42);
+
+		// then
+		assert_eq!(
+			verify_root_chain::<<Test as Config>::Hashing>(&tampered_roots, &ancestry_proofs),
+			Err(Error::Verify)
+		);
+		assert_eq!(
+			verify_root_chain::<<Test as Config>::Hashing>(&roots, &ancestry_proofs[1..]),
+			Err(Error::Verify)
+		);
+	});
+}
+
+#[test]
+fn should_reject_forged_ancestry_proofs() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	//       D
+	//     /   \
+	//    /     \
+	//   A       B       C
+	//  / \     / \     / \
+	// 1   2   3   4   5   6   7
+	let root = ext.execute_with(|| {
+		add_blocks(7);
+		crate::Pallet::<Test>::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let verify = |proof| verify_ancestry_proof::<<Test as Config>::Hashing>(root, proof);
+		let proof = crate::Pallet::<Test>::generate_ancestry_proof(4, None).unwrap();
+		assert_eq!(proof.items.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(), vec![9, 10]);
+		assert!(verify(proof.clone()).is_ok());
+
+		// when the current peaks are given as the items, along with made up prior peaks
+		let mut forged = proof.clone();
+		forged.prev_peaks = vec![H256::repeat_byte(0x42)];
+		forged.items = peaks_from_leaves_count(7)
+			.into_iter()
+			.map(|pos| (pos, crate::Nodes::<Test>::get(pos).unwrap()))
+			.collect();
+
+		// then the proof is rejected, rather than vouching for a made up prior root
+		assert_eq!(verify(forged), Err(Error::Verify));
+
+		// when an item overrides the prior peak
+		let mut forged = proof.clone();
+		forged.items.insert(0, (6, crate::Nodes::<Test>::get(6).unwrap()));
+
+		// then
+		assert_eq!(verify(forged), Err(Error::Verify));
+
+		// when the prior leaf count is changed to another one with the same number of peaks
+		let mut forged = proof;
+		forged.prev_leaf_count = 2;
+
+		// then
+		assert_eq!(verify(forged), Err(Error::Verify));
+	});
+}
+
+#[test]
+fn should_generate_proofs_for_different_forks() {
+	use sp_core::offchain::OffchainStorage;
+
+	let _ = env_logger::try_init();
+
+	// given two forks sharing the first 6 blocks, but with different hashes of block 6
+	let build_fork = |block_6_hash: H256| {
+		let mut ext = new_test_ext();
+		let root = ext.execute_with(|| {
+			add_blocks(6);
+			let number = frame_system::Pallet::<Test>::block_number() + 1;
+			LeafDataTestValue::mutate(|r| r.a = number);
+			frame_system::Pallet::<Test>::initialize(&number, &block_6_hash, &Default::default());
+			MMR::on_initialize(number);
+			crate::Pallet::<Test>::mmr_root_hash()
+		});
+		ext.persist_offchain_overlay();
+		(ext, root)
+	};
+	let (mut ext, root_a) = build_fork(H256::repeat_byte(7));
+	let (fork_b_ext, root_b) = build_fork(H256::repeat_byte(0x77));
+	assert_ne!(root_a, root_b);
+
+	// and a node which has indexed both of them (block 7 added leaf 6 at position 10)
+	let key = MMR::node_temp_offchain_key(TypedNodeIndex(10), H256::repeat_byte(0x77));
+	let node = fork_b_ext.offchain_db().get(&key).unwrap();
+	ext.offchain_db().set(b"", &key, &node);
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when
+		let (leaves_a, proof_a) = crate::Pallet::<Test>::generate_proof(vec![7], None).unwrap();
+		let fork_view = primitives::ForkView {
+			parent_hashes: vec![(6, H256::repeat_byte(0x77))].into_iter().collect(),
+		};
+		let (leaves_b, proof_b) =
+			crate::Pallet::<Test>::generate_proof_on_fork(vec![7], None, fork_view).unwrap();
+
+		// then
+		assert_ne!(leaves_a, leaves_b);
+		assert_eq!(
+			verify_leaves_proof::<<Test as Config>::Hashing, _>(
+				root_a,
+				leaves_a.into_iter().map(mmr::Node::Data).collect(),
+				proof_a
+			),
+			Ok(())
+		);
+		assert_eq!(
+			verify_leaves_proof::<<Test as Config>::Hashing, _>(
+				root_b,
+				leaves_b.into_iter().map(mmr::Node::Data).collect(),
+				proof_b
+			),
+			Ok(())
+		);
+	});
+}
+
+#[test]
+fn should_enumerate_fork_variants_of_a_node() {
+	use crate::mmr::storage::{OffchainStorage, Storage};
+	use sp_core::offchain::OffchainStorage as _;
+
+	let _ = env_logger::try_init();
+
+	// given two forks sharing the first 6 blocks, but with different hashes of block 6
+	let build_fork = |block_6_hash: H256| {
+		let mut ext = new_test_ext();
+		ext.execute_with(|| {
+			add_blocks(6);
+			let number = frame_system::Pallet::<Test>::block_number() + 1;
+			LeafDataTestValue::mutate(|r| r.a = number);
+			frame_system::Pallet::<Test>::initialize(&number, &block_6_hash, &Default::default());
+			MMR::on_initialize(number);
+		});
+		ext.persist_offchain_overlay();
+		ext
+	};
+	let mut ext = build_fork(H256::repeat_byte(7));
+	let fork_b_ext = build_fork(H256::repeat_byte(0x77));
+
+	// and a node which has indexed both of them (block 7 added leaf 6 at position 10)
+	let key = MMR::node_temp_offchain_key(TypedNodeIndex(10), H256::repeat_byte(0x77));
+	let node = fork_b_ext.offchain_db().get(&key).unwrap();
+	ext.offchain_db().set(b"", &key, &node);
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let storage = Storage::<OffchainStorage, Test, (), crate::LeafOf<Test, ()>>::default();
+
+		// when
+		let candidates = [H256::repeat_byte(7), H256::repeat_byte(0x42), H256::repeat_byte(0x77)];
+		let variants = storage.fork_variants(TypedNodeIndex(10), &candidates);
+
+		// then both variants are found
+		assert_eq!(
+			variants.iter().map(|(parent_hash, _)| *parent_hash).collect::<Vec<_>>(),
+			vec![H256::repeat_byte(7), H256::repeat_byte(0x77)]
+		);
+		assert_ne!(variants[0].1.hash(), variants[1].1.hash());
+	});
+}
+
+#[test]
+fn offchain_keys_should_start_with_offchain_prefix() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		let prefix = MMR::offchain_prefix();
+		let all_nodes_prefix = MMRAllNodes::offchain_prefix();
+		assert_ne!(prefix, all_nodes_prefix);
+
+		for pos in (0..100).map(TypedNodeIndex) {
+			let parent_hash = H256::repeat_byte(pos.0 as u8);
+			assert!(MMR::node_temp_offchain_key(pos, parent_hash).starts_with(&prefix));
+			assert!(MMR::node_canon_offchain_key(pos).starts_with(&prefix));
+			// the keys of other instances don't clash, even if their prefixes do.
+			assert!(!MMRAllNodes::node_canon_offchain_key(pos).starts_with(&prefix));
+			assert!(MMRAllNodes::node_canon_offchain_key(pos).starts_with(&all_nodes_prefix));
+		}
+	});
+}
+
+#[test]
+fn should_report_leaves_which_were_not_indexed() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 8 blocks, with the offchain indexing disabled for blocks 5 and 6
+	ext.execute_with(|| {
+		add_blocks(4);
+		IndexingEnabled::set(false);
+		add_blocks(2);
+		IndexingEnabled::set(true);
+		add_blocks(2);
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		//                 N
+		//              /     \
+		//            /         \
+		//          A             B
+		//        /   \         /   \
+		//       /     \       C     D
+		//      / \   / \     / \   / \
+		//     1   2 3   4   5   6 7   8
+		//
+		// then leaves 5 and 6 (indices 4 and 5) were not indexed
+		assert_eq!(crate::NotIndexedLeaves::<Test>::get().into_inner(), vec![(4, 5)]);
+
+		// a proof which doesn't require their nodes can still be generated
+		assert!(crate::Pallet::<Test>::generate_proof(vec![1], None).is_ok());
+		// while the ones requiring them report the leaf which was not indexed
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof(vec![5], None),
+			Err(Error::LeafNotIndexed { leaf_index: 4 })
+		);
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof(vec![7], None),
+			Err(Error::LeafNotIndexed { leaf_index: 5 })
+		);
+	});
+}
+
+#[test]
+fn should_bound_the_ranges_of_leaves_which_were_not_indexed() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given
+		let max_ranges = <Test as crate::Config>::MAX_NOT_INDEXED_RANGES as LeafIndex;
+		IndexingEnabled::set(false);
+		let not_indexed_weight = crate::Pallet::<Test>::append_weight(1);
+		IndexingEnabled::set(true);
+		let indexed_weight = crate::Pallet::<Test>::append_weight(1);
+
+		// then noting the leaves which were not indexed is weighed
+		assert_eq!(
+			not_indexed_weight,
+			indexed_weight
+				.saturating_add(<Test as frame_system::Config>::DbWeight::get().reads_writes(1, 1))
+		);
+
+		// when every other leaf is not indexed, beyond the maximum number of ranges
+		add_blocks(1);
+		for _ in 0..=max_ranges {
+			IndexingEnabled::set(false);
+			add_blocks(1);
+			IndexingEnabled::set(true);
+			add_blocks(1);
+		}
+
+		// then the latest range is extended to the leaves which were not indexed
+		let ranges = crate::NotIndexedLeaves::<Test>::get();
+		assert_eq!(ranges.len() as LeafIndex, max_ranges);
+		assert_eq!(ranges[0], (1, 1));
+		assert_eq!(ranges[ranges.len() - 2], (2 * max_ranges - 3, 2 * max_ranges - 3));
+		assert_eq!(ranges[ranges.len() - 1], (2 * max_ranges - 1, 2 * max_ranges + 1));
+		assert!(crate::Pallet::<Test>::is_leaf_indexed(TypedLeafIndex(0)));
+		assert!(!crate::Pallet::<Test>::is_leaf_indexed(TypedLeafIndex(1)));
+		assert!(crate::Pallet::<Test>::is_leaf_indexed(TypedLeafIndex(2 * max_ranges - 2)));
+		assert!(!crate::Pallet::<Test>::is_leaf_indexed(TypedLeafIndex(2 * max_ranges)));
+		assert!(crate::Pallet::<Test>::is_leaf_indexed(TypedLeafIndex(2 * max_ranges + 2)));
+	});
+}
+
+#[test]
+fn should_generate_batch_of_proofs() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (7 MMR leaves)
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let batches = vec![vec![1], vec![1, 5], vec![6, 7], vec![3]];
+
+		// when
+		let proofs = crate::Pallet::<Test>::generate_proofs(batches.clone(), None).unwrap();
+
+		// then each proof matches the one generated on its own
+		assert_eq!(proofs.len(), batches.len());
+		for (block_numbers, proof) in batches.into_iter().zip(proofs) {
+			assert_eq!(crate::Pallet::<Test>::generate_proof(block_numbers, None), Ok(proof));
+		}
+
+		// and historical proofs can be generated as well
+		let proofs = crate::Pallet::<Test>::generate_proofs(vec![vec![2], vec![4]], Some(5));
+		assert_eq!(
+			proofs,
+			Ok(vec![
+				crate::Pallet::<Test>::generate_proof(vec![2], Some(5)).unwrap(),
+				crate::Pallet::<Test>::generate_proof(vec![4], Some(5)).unwrap(),
+			])
+		);
+	});
+}
+
+#[test]
+fn should_list_onchain_node_indices() {
+	let _ = env_logger::try_init();
+
+	new_test_ext().execute_with(|| {
+		// given an empty MMR
+		assert!(crate::Pallet::<Test>::onchain_node_indices().is_empty());
+
+		for leaves_count in 1..=21 {
+			// when
+			new_block();
+			MMRAllNodes::on_initialize(leaves_count);
+
+			// then only the peaks are kept on-chain
+			assert_eq!(
+				crate::Pallet::<Test>::onchain_node_indices(),
+				peaks_from_leaves_count(leaves_count)
+					.into_iter()
+					.map(TypedNodeIndex)
+					.collect::<Vec<_>>()
+			);
+			// unless all the nodes are stored
+			let size = utils::NodesUtils::new(leaves_count).size();
+			assert_eq!(
+				crate::Pallet::<Test, Instance1>::onchain_node_indices(),
+				(0..size).map(TypedNodeIndex).collect::<Vec<_>>()
+			);
+		}
+	});
+}
+
+#[test]
+fn should_append_prehashed_leaves() {
+	let _ = env_logger::try_init();
+
+	let append_leaves = |prehashed: bool| {
+		let mut ext = new_test_ext();
+		let result = ext.execute_with(|| {
+			let mut mmr = mmr::Mmr::<mmr::storage::RuntimeStorage, Test, (), u64>::new(0);
+			for leaf in 0..7 {
+				if prehashed {
+					let hash = mmr::Node::<<Test as Config>::Hashing, u64>::Data(leaf).hash();
+					mmr.push_prehashed(leaf, hash).unwrap();
+				} else {
+					mmr.push(leaf).unwrap();
+				}
+			}
+			mmr.finalize().unwrap()
+		});
+		ext.persist_offchain_overlay();
+		(result, ext.offchain_db())
+	};
+
+	// when
+	let (result, offchain_db) = append_leaves(false);
+	let (prehashed_result, prehashed_offchain_db) = append_leaves(true);
+
+	// then the root is the same
+	assert_eq!(prehashed_result, result);
+	// and so are the nodes indexed off-chain, including the full leaves
+	for pos in (0..utils::NodesUtils::new(7).size()).map(TypedNodeIndex) {
+		let key = MMR::node_temp_offchain_key(pos, Default::default());
+		assert!(offchain_db.get(&key).is_some());
+		assert_eq!(prehashed_offchain_db.get(&key), offchain_db.get(&key));
+	}
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn should_reject_duplicate_leaves_within_a_block() {
+	type FinalizeMmr = mmr::Mmr<mmr::storage::RuntimeStorage, Test, Instance3, u64>;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		new_block();
+
+		// when a leaf data provider emits the same leaf twice
+		let mut mmr = FinalizeMmr::new(0);
+		for leaf in [1, 2, 1] {
+			mmr.push(leaf).unwrap();
+		}
+
+		// then none of the leaves are appended
+		assert_eq!(mmr.finalize(), Err(Error::Push));
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance3>::get(), 0);
+
+		// while distinct leaves are fine
+		let mut mmr = FinalizeMmr::new(0);
+		for leaf in [1, 2, 3] {
+			mmr.push(leaf).unwrap();
+		}
+		assert_eq!(mmr.finalize().map(|(leaves, _)| leaves), Ok(3));
+	});
+}
+
+#[test]
+fn should_request_reindex() {
+	use frame_support::{assert_noop, assert_ok};
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks
+		add_blocks(7);
+
+		// then only root can request reindexing, and only of existing leaves
+		assert_noop!(
+			MMR::request_reindex(
+				RuntimeOrigin::signed(sp_core::sr25519::Public::from_raw([1; 32])),
+				0,
+				6
+			),
+			sp_runtime::DispatchError::BadOrigin
+		);
+		assert_noop!(
+			MMR::request_reindex(RuntimeOrigin::root(), 2, 7),
+			crate::pallet::Error::<Test>::InvalidLeafRange
+		);
+		assert_noop!(
+			MMR::request_reindex(RuntimeOrigin::root(), 3, 2),
+			crate::pallet::Error::<Test>::InvalidLeafRange
+		);
+
+		// when
+		assert_ok!(MMR::request_reindex(RuntimeOrigin::root(), 2, 6));
+
+		// then
+		frame_system::Pallet::<Test>::assert_last_event(
+			crate::Event::<Test>::ReindexRequested { from: 2, to: 6 }.into(),
+		);
+	});
+}
+
+#[test]
+fn should_report_mmr_state() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given
+		add_blocks(7);
+
+		// then only signed origins can report the state
+		assert_noop!(
+			MMR::report_state(RuntimeOrigin::none()),
+			sp_runtime::DispatchError::BadOrigin
+		);
+
+		// when
+		let who = sp_core::sr25519::Public::from_raw([1; 32]);
+		assert_ok!(MMR::report_state(RuntimeOrigin::signed(who)));
+
+		// then
+		frame_system::Pallet::<Test>::assert_last_event(
+			crate::Event::<Test>::MmrState { root: MMR::mmr_root_hash(), leaves: 7, size: 11 }
+				.into(),
+		);
+	});
+}
+
+#[test]
+#[should_panic(expected = "MMR must not be altered in the off-chain context.")]
+fn should_panic_on_offchain_append() {
+	use mmr::storage::{OffchainStorage, Storage};
+	use sp_mmr_primitives::mmr_lib::MMRStore;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		let mut store = Storage::<OffchainStorage, Test, (), crate::LeafOf<Test, ()>>::default();
+		let _ = store.append(0, vec![]);
+	});
+}
+
+#[test]
+fn should_not_panic_on_offchain_append_in_strict_mode() {
+	use mmr::storage::{OffchainStorage, Storage};
+	use sp_mmr_primitives::mmr_lib::MMRStore;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given `MMRAllNodes` is configured to never panic
+		let mut store =
+			Storage::<OffchainStorage, Test, Instance1, crate::LeafOf<Test, Instance1>>::default();
+
+		// then altering the MMR off-chain is reported as an error
+		assert!(matches!(
+			store.append(0, vec![]),
+			Err(sp_mmr_primitives::mmr_lib::Error::StoreError(_))
+		));
+	});
+}
+
+#[test]
+fn should_reject_empty_append_at_wrong_position() {
+	use mmr::storage::{RuntimeStorage, Storage};
+	use sp_mmr_primitives::mmr_lib::MMRStore;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks (11 MMR nodes)
+		add_blocks(7);
+		let mut store = Storage::<RuntimeStorage, Test, (), crate::LeafOf<Test, ()>>::default();
+
+		// then empty appends are only accepted at the end of the MMR
+		assert!(matches!(
+			store.append(10, vec![]),
+			Err(sp_mmr_primitives::mmr_lib::Error::InconsistentStore)
+		));
+		assert!(matches!(
+			store.append(12, vec![]),
+			Err(sp_mmr_primitives::mmr_lib::Error::InconsistentStore)
+		));
+		assert!(store.append(11, vec![]).is_ok());
+		assert_eq!(crate::NumberOfLeaves::<Test>::get(), 7);
+	});
+}
+
+#[test]
+fn should_reject_appends_not_completing_an_mmr() {
+	use mmr::storage::{RuntimeStorage, Storage};
+	use sp_mmr_primitives::mmr_lib::MMRStore;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks (11 MMR nodes)
+		add_blocks(7);
+		let mut store = Storage::<RuntimeStorage, Test, (), crate::LeafOf<Test, ()>>::default();
+		let inner = |byte| mmr::Node::Hash(H256::repeat_byte(byte));
+		let leaf = mmr::Node::Data(Compact::new((
+			(0, H256::repeat_byte(1)).into(),
+			LeafData::new(8).into(),
+		)));
+		let inconsistent =
+			|result| matches!(result, Err(sp_mmr_primitives::mmr_lib::Error::InconsistentStore));
+
+		// then a leaf without the inner nodes it completes is rejected
+		assert!(inconsistent(store.append(11, vec![leaf.clone()])));
+		// or with only some of them
+		assert!(inconsistent(store.append(11, vec![leaf.clone(), inner(2), inner(3)])));
+		// and leaf data at the position of an inner node
+		assert!(inconsistent(store.append(11, vec![inner(1), leaf.clone(), inner(3), inner(4)])));
+
+		// and the store is left untouched
+		assert_eq!(crate::NumberOfLeaves::<Test>::get(), 7);
+		assert!(!crate::Nodes::<Test>::contains_key(11));
+
+		// while a leaf with all the nodes it completes is accepted
+		assert!(store.append(11, vec![leaf, inner(2), inner(3), inner(4)]).is_ok());
+		assert_eq!(crate::NumberOfLeaves::<Test>::get(), 8);
+	});
+}
+
+#[test]
+fn should_reconstruct_root_from_proof() {
+	let _ = env_logger::try_init();
+
+	// given 7 blocks (7 MMR leaves)
+	let mut ext = new_test_ext();
+	let root = ext.execute_with(|| {
+		add_blocks(7);
+		crate::Pallet::<Test>::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+	let (leaves, proof) =
+		ext.execute_with(|| crate::Pallet::<Test>::generate_proof(vec![5], None).unwrap());
+	let reconstruct = |leaves: Vec<crate::LeafOf<Test, ()>>, proof: Proof<H256>| {
+		crate::reconstruct_root::<<Test as Config>::Hashing, _>(
+			leaves
+				.into_iter()
+				.map(|leaf| crate::primitives::DataOrHash::Data(leaf))
+				.collect(),
+			proof,
+		)
+	};
+
+	// then a valid proof reconstructs the live root
+	assert_eq!(reconstruct(leaves.clone(), proof.clone()), Ok(root));
+
+	// while a tampered one reconstructs a different one
+	let mut tampered_leaves = leaves.clone();
+	tampered_leaves[0] = Compact::new(((4, H256::repeat_byte(4)).into(), LeafData::new(4).into()));
+	let tampered_root = reconstruct(tampered_leaves, proof.clone()).unwrap();
+	assert_ne!(tampered_root, root);
+	let mut tampered_proof = proof;
+	tampered_proof.items[0] = H256::repeat_byte(0xff);
+	let tampered_root = reconstruct(leaves, tampered_proof).unwrap();
+	assert_ne!(tampered_root, root);
+}
+
+#[test]
+fn should_track_size_of_offchain_nodes() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	let (total, keys) = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		// the size is only tracked if enabled
+		assert_eq!(crate::TotalOffchainBytes::<Test>::get(), 0);
+
+		let keys = (0..utils::NodesUtils::new(7).size())
+			.map(|pos| {
+				let pos = TypedNodeIndex(pos);
+				let leaf_index = utils::NodesUtils::leaf_index_that_added_node(pos);
+				let parent_hash = frame_system::Pallet::<Test>::block_hash(leaf_index.0);
+				crate::Pallet::<Test, Instance1>::node_temp_offchain_key(pos, parent_hash)
+			})
+			.collect::<Vec<_>>();
+		(crate::TotalOffchainBytes::<Test, Instance1>::get(), keys)
+	});
+	ext.persist_offchain_overlay();
+
+	// then the total matches the size of all the nodes in the offchain DB
+	let offchain_db = ext.offchain_db();
+	let expected = keys
+		.iter()
+		.map(|key| offchain_db.get(key).expect("All nodes are indexed").len() as u64)
+		.sum::<u64>();
+	assert!(total > 0);
+	assert_eq!(total, expected);
+}
+
+#[test]
+fn should_collect_all_nodes_created_by_append() {
+	let _ = env_logger::try_init();
+
+	new_test_ext().execute_with(|| {
+		// given 3 blocks
+		for _ in 0..3 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		let size_3 = utils::NodesUtils::new(3).size();
+
+		// when
+		let leaves = (4..=7)
+			.map(|n: u64| {
+				Compact::new(((n - 1, H256::repeat_byte(n as u8)).into(), LeafData::new(n).into()))
+			})
+			.collect();
+		let created = crate::Pallet::<Test>::nodes_created_by_append(leaves).unwrap();
+
+		// then nothing is persisted
+		assert_eq!(crate::NumberOfLeaves::<Test>::get(), 3);
+		assert_eq!(
+			crate::Pallet::<Test>::onchain_node_indices(),
+			peaks_from_leaves_count(3).into_iter().map(TypedNodeIndex).collect::<Vec<_>>()
+		);
+
+		// and the collected nodes match the ones of an MMR storing all the nodes
+		for _ in 4..=7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		let expected = (size_3..utils::NodesUtils::new(7).size())
+			.map(|pos| (pos, crate::Nodes::<Test, Instance1>::get(pos).unwrap()))
+			.collect::<Vec<_>>();
+		assert_eq!(created, expected);
+	});
+}
+
+#[test]
+fn should_verify_proof_against_declared_size() {
+	use crate::primitives::{DataOrHash, MmrSize};
+
+	let _ = env_logger::try_init();
+
+	// given a proof for 7 blocks (7 MMR leaves, 11 MMR nodes)
+	let mut ext = new_test_ext();
+	let root = ext.execute_with(|| {
+		add_blocks(7);
+		crate::Pallet::<Test>::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+	let (leaves, proof) =
+		ext.execute_with(|| crate::Pallet::<Test>::generate_proof(vec![1, 5], None).unwrap());
+	let verify = |size| {
+		crate::verify_leaves_proof_with_size::<<Test as Config>::Hashing, _>(
+			root,
+			leaves.iter().cloned().map(DataOrHash::Data).collect(),
+			proof.clone(),
+			size,
+		)
+	};
+
+	// then the proof is accepted for either form of the matching size
+	assert_eq!(verify(MmrSize::LeafCount(7)), Ok(()));
+	assert_eq!(verify(MmrSize::NodeCount(11)), Ok(()));
+
+	// and rejected for a mismatched one
+	assert_eq!(verify(MmrSize::LeafCount(6)), Err(Error::Verify));
+	assert_eq!(verify(MmrSize::NodeCount(10)), Err(Error::Verify));
+	// or an invalid one
+	assert_eq!(verify(MmrSize::NodeCount(12)), Err(Error::Verify));
+}
+
+#[test]
+fn should_reject_proofs_beyond_trusted_leaf_count() {
+	use crate::primitives::DataOrHash;
+
+	let _ = env_logger::try_init();
+
+	// given a proof for 7 blocks (7 MMR leaves)
+	let mut ext = new_test_ext();
+	let root = ext.execute_with(|| {
+		add_blocks(7);
+		crate::Pallet::<Test>::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+	let (leaves, proof) =
+		ext.execute_with(|| crate::Pallet::<Test>::generate_proof(vec![1, 5], None).unwrap());
+	let verify = |proof, trusted_leaf_count| {
+		crate::verify_leaves_proof_with_trusted_leaf_count::<<Test as Config>::Hashing, _>(
+			root,
+			leaves.iter().cloned().map(DataOrHash::Data).collect(),
+			proof,
+			trusted_leaf_count,
+		)
+	};
+
+	// then the proof is accepted by verifiers trusting all its leaves
+	assert_eq!(verify(proof.clone(), None), Ok(()));
+	assert_eq!(verify(proof.clone(), Some(7)), Ok(()));
+	assert_eq!(verify(proof.clone(), Some(9)), Ok(()));
+
+	// and rejected by the ones trusting fewer leaves
+	assert_eq!(
+		verify(proof.clone(), Some(6)),
+		Err(Error::UntrustedLeafCount { leaf_count: 7, trusted: 6 })
+	);
+	// even if the claimed leaf count was raised by a malicious server
+	let mut untrusted = proof;
+	untrusted.leaf_count = 8;
+	assert_eq!(
+		verify(untrusted, Some(7)),
+		Err(Error::UntrustedLeafCount { leaf_count: 8, trusted: 7 })
+	);
+}
+
+#[test]
+fn should_mirror_peaks_offchain() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+	});
+	ext.persist_offchain_overlay();
+
+	let offchain_db = ext.offchain_db();
+	ext.execute_with(|| {
+		// then each on-chain peak is mirrored offchain
+		let peaks = peaks_from_leaves_count(7);
+		for peak in &peaks {
+			let hash = crate::Nodes::<Test, Instance1>::get(peak).unwrap();
+			let key = crate::Pallet::<Test, Instance1>::peak_offchain_key(TypedNodeIndex(*peak));
+			assert_eq!(offchain_db.get(&key), Some(codec::Encode::encode(&hash)));
+		}
+		// while the pruned ones are not
+		for pos in (0..utils::NodesUtils::new(7).size()).filter(|pos| !peaks.contains(pos)) {
+			let key = crate::Pallet::<Test, Instance1>::peak_offchain_key(TypedNodeIndex(pos));
+			assert_eq!(offchain_db.get(&key), None);
+		}
+		// and the peaks are only mirrored if configured to
+		let key = crate::Pallet::<Test>::peak_offchain_key(TypedNodeIndex(peaks[0]));
+		assert_eq!(offchain_db.get(&key), None);
+	});
+}
+
+#[test]
+fn should_reconstruct_root_from_peak_bagging_proof() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (peaks at positions 6, 9 and 10)
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+
+	ext.execute_with(|| {
+		let root = crate::Pallet::<Test>::mmr_root_hash();
+		let peaks = peaks_from_leaves_count(7);
+		assert_eq!(peaks, vec![6, 9, 10]);
+		let root_from_peak = |peak, index, proof| {
+			crate::root_from_peak::<<Test as Config>::Hashing>(peak, index, peaks.len(), proof)
+		};
+
+		// then the root is reconstructed from every peak and its bagging proof
+		for (index, pos) in peaks.iter().enumerate() {
+			let peak = crate::Nodes::<Test>::get(pos).unwrap();
+			let proof = crate::Pallet::<Test>::peak_bagging_proof(index).unwrap();
+			assert_eq!(proof.len(), index + (index + 1 < peaks.len()) as usize);
+			assert_eq!(root_from_peak(peak, index, proof), Ok(root));
+		}
+
+		// and a proof does not work for a different peak index
+		let peak = crate::Nodes::<Test>::get(peaks[1]).unwrap();
+		let proof = crate::Pallet::<Test>::peak_bagging_proof(1).unwrap();
+		assert_ne!(root_from_peak(peak, 0, proof.clone()), Ok(root));
+		assert_eq!(root_from_peak(peak, 2, proof), Err(Error::Verify));
+
+		// nor for a non-existent peak
+		assert_eq!(crate::Pallet::<Test>::peak_bagging_proof(3), Err(Error::GenerateProof));
+	});
+}
+
+#[test]
+fn should_detect_corrupted_offchain_nodes() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (block 5 added leaf 4 at position 7)
+	let key = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		let parent_hash = frame_system::Pallet::<Test>::block_hash(4);
+		crate::Pallet::<Test, Instance1>::node_temp_offchain_key(TypedNodeIndex(7), parent_hash)
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	// then the nodes with valid checksums are read
+	ext.execute_with(|| {
+		assert!(crate::Pallet::<Test, Instance1>::generate_proof(vec![5], None).is_ok());
+	});
+
+	// when a byte of the leaf is flipped
+	let mut node = ext.offchain_db().get(&key).unwrap();
+	*node.last_mut().unwrap() ^= 0xff;
+	ext.offchain_db().set(b"", &key, &node);
+
+	// then the corruption is reported
+	ext.execute_with(|| {
+		assert_eq!(
+			crate::Pallet::<Test, Instance1>::generate_proof(vec![5], None),
+			Err(Error::OffchainChecksumMismatch)
+		);
+	});
+}
+
+#[test]
+fn should_expose_raw_root_and_wrapped_commitment() {
+	use sp_runtime::traits::{Hash, Keccak256};
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+
+		// then the raw root is the same regardless of the wrapper
+		let root = crate::Pallet::<Test, Instance1>::mmr_root_hash();
+		assert_eq!(root, crate::Pallet::<Test>::mmr_root_hash());
+		assert_eq!(crate::Pallet::<Test>::mmr_root_commitment(), root);
+
+		// while the commitment wraps it along with the next authority set
+		let commitment = Keccak256::hash_of(&(root, NextAuthoritySetCommitment::get()));
+		assert_ne!(commitment, root);
+		assert_eq!(crate::Pallet::<Test, Instance1>::mmr_root_commitment(), commitment);
+		// and is the value passed to the `OnNewRoot` hook
+		assert_eq!(LastRootCommitment::get(), Some(commitment));
+	});
+}
+
+#[test]
+fn should_verify_proof_bundle() {
+	use crate::primitives::DataOrHash;
+	use codec::{Decode, Encode};
+	type Hashing = <Test as Config>::Hashing;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let (leaves, proof) = crate::Pallet::<Test>::generate_proof(vec![5], None).unwrap();
+		// the leaf in its compact form, which commits to the same hash
+		let leaf = &leaves[0].tuple;
+		let leaf = (
+			DataOrHash::<Hashing, (BlockNumber, H256)>::Hash(leaf.0.hash()),
+			DataOrHash::<Hashing, LeafData>::Hash(leaf.1.hash()),
+		);
+		let bundle = crate::ProofBundle {
+			leaf,
+			leaf_index: 4,
+			proof,
+			root: crate::Pallet::<Test>::mmr_root_hash(),
+			mmr_size: utils::NodesUtils::new(7).size(),
+		};
+
+		// then the bundle survives a round trip over the wire
+		let decoded = crate::ProofBundle::decode(&mut &*bundle.encode()).unwrap();
+		assert_eq!(decoded, bundle);
+		// and is verified as a whole
+		assert_eq!(decoded.verify::<Hashing>(), Ok(()));
+
+		// but not if any part of it is inconsistent
+		let mut wrong_index = bundle.clone();
+		wrong_index.leaf_index = 3;
+		assert_eq!(wrong_index.verify::<Hashing>(), Err(Error::Verify));
+		let mut wrong_size = bundle.clone();
+		wrong_size.mmr_size = utils::NodesUtils::new(6).size();
+		assert_eq!(wrong_size.verify::<Hashing>(), Err(Error::Verify));
+		let mut wrong_root = bundle.clone();
+		wrong_root.root = H256::repeat_byte(0x13);
+		assert_eq!(wrong_root.verify::<Hashing>(), Err(Error::Verify));
+		let mut wrong_leaf = bundle;
+		wrong_leaf.leaf.1 = DataOrHash::Hash(H256::repeat_byte(0x37));
+		assert_eq!(wrong_leaf.verify::<Hashing>(), Err(Error::Verify));
+	});
+}
+
+#[test]
+fn should_find_available_leaf_range() {
+	use crate::mmr::storage::{OffchainStorage, Storage};
+	use sp_core::offchain::OffchainStorage as _;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	let keys = ext.execute_with(|| {
+		add_blocks(7);
+		(0..7)
+			.map(|leaf_index| {
+				let pos = utils::NodesUtils::leaf_to_node_index(TypedLeafIndex(leaf_index));
+				let parent_hash = frame_system::Pallet::<Test>::block_hash(leaf_index);
+				MMR::node_temp_offchain_key(pos, parent_hash)
+			})
+			.collect::<Vec<_>>()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+	let mut offchain_db = ext.offchain_db();
+	let available_leaf_range = |ext: &mut sp_io::TestExternalities| {
+		ext.execute_with(|| {
+			Storage::<OffchainStorage, Test, (), crate::LeafOf<Test, ()>>::default()
+				.available_leaf_range()
+				.map(|(first, last)| (first.0, last.0))
+		})
+	};
+
+	// then all the leaves are available
+	assert_eq!(available_leaf_range(&mut ext), Some((0, 6)));
+
+	// when the latest leaves are missing
+	let latest = keys[5..].iter().map(|key| offchain_db.get(key).unwrap()).collect::<Vec<_>>();
+	for key in &keys[5..] {
+		offchain_db.remove(b"", key);
+	}
+	// then the range ends before them
+	assert_eq!(available_leaf_range(&mut ext), Some((0, 4)));
+
+	// when the first leaf is missing as well
+	offchain_db.remove(b"", &keys[0]);
+	// then no range is detected
+	assert_eq!(available_leaf_range(&mut ext), None);
+
+	// when the latest leaves are back
+	for (key, value) in keys[5..].iter().zip(latest) {
+		offchain_db.set(b"", key, &value);
+	}
+	// then the range starts after the missing leaf
+	assert_eq!(available_leaf_range(&mut ext), Some((1, 6)));
+}
+
+#[test]
+fn should_read_a_range_of_leaves_with_gaps() {
+	use crate::mmr::storage::{OffchainStorage, Storage};
+	use sp_core::offchain::OffchainStorage as _;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 12 blocks
+	let key = ext.execute_with(|| {
+		add_blocks(12);
+		let pos = utils::NodesUtils::leaf_to_node_index(TypedLeafIndex(5));
+		MMR::node_temp_offchain_key(pos, frame_system::Pallet::<Test>::block_hash(5))
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+	// and one of the leaves missing from the Off-chain DB
+	ext.offchain_db().remove(b"", &key);
+
+	ext.execute_with(|| {
+		let storage = Storage::<OffchainStorage, Test, (), crate::LeafOf<Test, ()>>::default();
+
+		// when
+		let leaves = storage.get_leaves(TypedLeafIndex(1), 10).unwrap();
+
+		// then the missing leaf is `None`
+		let expected = (2..=11)
+			.map(|n: u64| {
+				let leaf = Compact::new((
+					(n - 1, H256::repeat_byte(n as u8)).into(),
+					LeafData::new(n).into(),
+				));
+				(n != 6).then_some(leaf)
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(leaves, expected);
+
+		// and ranges beyond the MMR are rejected
+		assert_eq!(
+			storage.get_leaves(TypedLeafIndex(5), 8),
+			Err(Error::LeafIndexOutOfRange { requested: 12, count: 12 })
+		);
+	});
+}
+
+#[test]
+fn should_self_check_offchain_db_for_a_range_of_leaves() {
+	use crate::mmr::storage::{OffchainStorage, SelfCheckError, Storage};
+	use sp_core::offchain::OffchainStorage as _;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 12 blocks
+	let (root, keys) = ext.execute_with(|| {
+		add_blocks(12);
+		let keys = (0..utils::NodesUtils::new(12).size())
+			.map(|pos| {
+				let pos = TypedNodeIndex(pos);
+				let leaf_index = utils::NodesUtils::leaf_index_that_added_node(pos);
+				let parent_hash = frame_system::Pallet::<Test>::block_hash(leaf_index.0);
+				MMR::node_temp_offchain_key(pos, parent_hash)
+			})
+			.collect::<Vec<_>>();
+		(MMR::mmr_root_hash(), keys)
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+	let mut offchain_db = ext.offchain_db();
+	let self_check = |ext: &mut sp_io::TestExternalities, from_leaf, to_leaf| {
+		ext.execute_with(|| {
+			Storage::<OffchainStorage, Test, (), crate::LeafOf<Test, ()>>::default()
+				.self_check(TypedLeafIndex(from_leaf), TypedLeafIndex(to_leaf))
+		})
+	};
+
+	// then the complete store implies the current root
+	assert_eq!(self_check(&mut ext, 0, 11), Ok(root));
+	assert_eq!(self_check(&mut ext, 4, 6), Ok(root));
+	assert_eq!(self_check(&mut ext, 11, 11), Ok(root));
+	assert_eq!(
+		self_check(&mut ext, 5, 12),
+		Err(SelfCheckError::Other(Error::LeafIndexOutOfRange { requested: 12, count: 12 }))
+	);
+
+	// when a node proving the first leaves is missing
+	offchain_db.remove(b"", &keys[5]);
+	// then the gap is reported
+	assert_eq!(self_check(&mut ext, 0, 1), Err(SelfCheckError::MissingNode(TypedNodeIndex(5))));
+	// but ranges not depending on it are fine
+	assert_eq!(self_check(&mut ext, 8, 11), Ok(root));
+	assert_eq!(self_check(&mut ext, 0, 3), Ok(root));
+
+	// when an inner node doesn't match its children
+	let other_node = offchain_db.get(&keys[13]).unwrap();
+	offchain_db.set(b"", &keys[6], &other_node);
+	// then it's reported
+	assert_eq!(
+		self_check(&mut ext, 0, 3),
+		Err(SelfCheckError::InconsistentNode(TypedNodeIndex(6)))
+	);
+}
+
+#[test]
+fn should_keep_cached_root_in_sync_with_bagged_peaks() {
+	use frame_support::assert_ok;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		let bagged_root = |leaves| {
+			let peaks = crate::Pallet::<Test, Instance1>::peaks(leaves)
+				.into_iter()
+				.map(|pos| crate::Nodes::<Test, Instance1>::get(pos).unwrap())
+				.collect::<Vec<_>>();
+			mmr::bag_peaks::<Keccak256>(peaks).unwrap().unwrap()
+		};
+
+		for leaves in 1..=40 {
+			// when
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+
+			// then the peaks are updated incrementally
+			assert_eq!(crate::NumberOfLeaves::<Test, Instance1>::get(), leaves);
+			let (count, peaks) = crate::PeakHashes::<Test, Instance1>::get().unwrap();
+			assert_eq!(count, leaves);
+			assert_eq!(peaks.len(), leaves.count_ones() as usize);
+			// and the cached root matches the one bagged from the current peaks
+			assert_eq!(crate::RootHash::<Test, Instance1>::get(), bagged_root(leaves));
+		}
+
+		// when the cached peaks went stale
+		crate::PeakHashes::<Test, Instance1>::mutate(|cached| {
+			cached.as_mut().unwrap().1[0] = H256::repeat_byte(1);
+		});
+		assert_ok!(MMRAllNodes::force_set_leaf_count(RuntimeOrigin::root(), 40, 0, 0));
+
+		// then the root is still bagged from the peaks in `Nodes`
+		assert_eq!(crate::RootHash::<Test, Instance1>::get(), bagged_root(40));
+
+		// when the peaks change without appending
+		assert_ok!(MMRAllNodes::force_set_leaf_count(RuntimeOrigin::root(), 27, 100, 0));
+		new_block();
+		MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+
+		// then the cached peaks are rebuilt
+		assert_eq!(crate::PeakHashes::<Test, Instance1>::get().map(|(count, _)| count), Some(28));
+		assert_eq!(crate::RootHash::<Test, Instance1>::get(), bagged_root(28));
+	});
+}
+
+#[test]
+fn should_report_offchain_peaks_mismatch() {
+	use sp_core::offchain::OffchainStorage;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (peaks at positions 6, 9 and 10)
+	let (key_6, key_9) = ext.execute_with(|| {
+		add_blocks(7);
+		let key = |pos, leaf_index| {
+			let parent_hash = frame_system::Pallet::<Test>::block_hash(leaf_index);
+			MMR::node_temp_offchain_key(TypedNodeIndex(pos), parent_hash)
+		};
+		(key(6, 3), key(9, 5))
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	// then the offchain peaks match the on-chain ones
+	ext.execute_with(|| assert_eq!(crate::Pallet::<Test>::verify_offchain_peaks(), Ok(())));
+
+	// when one of the offchain peaks gets replaced by another node
+	let mut offchain_db = ext.offchain_db();
+	let node_6 = offchain_db.get(&key_6).unwrap();
+	offchain_db.set(b"", &key_9, &node_6);
+
+	// then the mismatch is reported
+	ext.execute_with(|| {
+		assert_eq!(crate::Pallet::<Test>::verify_offchain_peaks(), Err(vec![TypedNodeIndex(9)]))
+	});
+
+	// and so is a missing peak
+	offchain_db.remove(b"", &key_6);
+	ext.execute_with(|| {
+		assert_eq!(
+			crate::Pallet::<Test>::verify_offchain_peaks(),
+			Err(vec![TypedNodeIndex(6), TypedNodeIndex(9)])
+		)
+	});
+}
+
+#[test]
+fn should_compute_block_for_leaf() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 3 blocks before the activation of `MMRAllNodes`, and 4 blocks after it
+		add_blocks(3);
+		for _ in 0..4 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+
+		// then the blocks of past leaves are found
+		assert_eq!(crate::Pallet::<Test>::block_for_leaf(TypedLeafIndex(0)), Ok(1));
+		assert_eq!(crate::Pallet::<Test>::block_for_leaf(TypedLeafIndex(6)), Ok(7));
+		assert_eq!(crate::Pallet::<Test, Instance1>::block_for_leaf(TypedLeafIndex(0)), Ok(4));
+		assert_eq!(crate::Pallet::<Test, Instance1>::block_for_leaf(TypedLeafIndex(3)), Ok(7));
+
+		// and so are the blocks of future leaves
+		assert_eq!(crate::Pallet::<Test>::block_for_leaf(TypedLeafIndex(7)), Ok(8));
+		assert_eq!(crate::Pallet::<Test>::block_for_leaf(TypedLeafIndex(10)), Ok(11));
+		assert_eq!(crate::Pallet::<Test, Instance1>::block_for_leaf(TypedLeafIndex(5)), Ok(9));
+	});
+}
+
+#[test]
+fn should_compute_leaves_added_in_block() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 3 blocks before the activation of `MMRAllNodes`, and 4 blocks after it
+		add_blocks(3);
+		for _ in 0..4 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+
+		// then each block added a single leaf
+		assert_eq!(crate::Pallet::<Test>::leaves_added_in_block(1), Ok((0, 1)));
+		assert_eq!(crate::Pallet::<Test>::leaves_added_in_block(7), Ok((6, 7)));
+		assert_eq!(crate::Pallet::<Test, Instance1>::leaves_added_in_block(4), Ok((0, 1)));
+		assert_eq!(crate::Pallet::<Test, Instance1>::leaves_added_in_block(7), Ok((3, 4)));
+		assert_eq!(crate::Pallet::<Test, Instance1>::leaves_added_in_block(9), Ok((5, 6)));
+		for leaf in 0..4 {
+			let block =
+				crate::Pallet::<Test, Instance1>::block_for_leaf(TypedLeafIndex(leaf)).unwrap();
+			assert_eq!(
+				crate::Pallet::<Test, Instance1>::leaves_added_in_block(block),
+				Ok((leaf, leaf + 1))
+			);
+		}
+
+		// except for the blocks before the activation
+		assert_eq!(crate::Pallet::<Test>::leaves_added_in_block(0), Ok((0, 0)));
+		assert_eq!(crate::Pallet::<Test, Instance1>::leaves_added_in_block(0), Ok((0, 0)));
+		assert_eq!(crate::Pallet::<Test, Instance1>::leaves_added_in_block(3), Ok((0, 0)));
+	});
+}
+
+#[test]
+fn should_compute_leaf_count_at_block() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 3 blocks before the activation of `MMRAllNodes`, and 4 blocks after it
+		add_blocks(3);
+		for _ in 0..4 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+
+		// then the count grows by a leaf per block since the activation
+		let leaf_count_at_block = crate::Pallet::<Test, Instance1>::leaf_count_at_block;
+		assert_eq!(leaf_count_at_block(0), Ok(0));
+		assert_eq!(leaf_count_at_block(3), Ok(0));
+		assert_eq!(leaf_count_at_block(4), Ok(1));
+		assert_eq!(leaf_count_at_block(6), Ok(3));
+		assert_eq!(leaf_count_at_block(7), Ok(MMRAllNodes::mmr_leaves()));
+		assert_eq!(crate::Pallet::<Test>::leaf_count_at_block(1), Ok(1));
+		assert_eq!(crate::Pallet::<Test>::leaf_count_at_block(7), Ok(7));
+
+		// but future blocks are rejected
+		assert_eq!(leaf_count_at_block(8), Err(Error::InvalidBestKnownBlock));
+	});
+}
+
+#[test]
+fn should_bound_current_peaks() {
+	use frame_support::traits::Get;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given the bounds derived from the maximum number of leaves
+		assert_eq!(crate::MaxPeaks::<Test>::get(), 64);
+		assert_eq!(crate::MaxPeaks::<Test, Instance1>::get(), 10);
+
+		// when the MMR has a large number of leaves (and as many peaks as set bits)
+		let leaves = (1 << 40) - 1;
+		crate::NumberOfLeaves::<Test>::put(leaves);
+
+		// then the bounded vec holds all the peaks
+		let peaks = crate::Pallet::<Test>::current_peaks_bounded().unwrap();
+		assert_eq!(peaks.len(), 40);
+		assert_eq!(peaks.into_inner(), peaks_from_leaves_count(leaves));
+
+		// when the number of peaks exceeds the bound
+		crate::NumberOfLeaves::<Test, Instance1>::put((1 << 11) - 1);
+
+		// then it's reported
+		assert_eq!(
+			crate::Pallet::<Test, Instance1>::current_peaks_bounded(),
+			Err(crate::pallet::Error::<Test, Instance1>::TooManyPeaks)
+		);
+	});
+}
+
+#[test]
+fn should_verify_versioned_proofs() {
+	use crate::primitives::VersionedProof;
+	use codec::Encode;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let (leaves, proof) = crate::Pallet::<Test>::generate_proof(vec![5], None).unwrap();
+		let root = crate::Pallet::<Test>::mmr_root_hash();
+		let verify = |encoded_proof: &[u8]| {
+			crate::verify_versioned_leaves_proof::<<Test as Config>::Hashing, _>(
+				root,
+				vec![crate::primitives::DataOrHash::Data(leaves[0].clone())],
+				encoded_proof,
+			)
+		};
+
+		// when the proof is encoded in the v0 format
+		let encoded_proof = VersionedProof::from(proof.clone()).encode();
+		assert_eq!(encoded_proof, [vec![0], proof.encode()].concat());
+		// then it's verified
+		assert_eq!(verify(&encoded_proof), Ok(()));
+
+		// when the proof claims a future version
+		let encoded_proof = [vec![1], proof.encode()].concat();
+		// then it's rejected
+		assert_eq!(verify(&encoded_proof), Err(Error::UnsupportedProofVersion { version: 1 }));
+		// as is an empty proof
+		assert_eq!(verify(&[]), Err(Error::Verify));
+	});
+}
+
+#[test]
+fn should_report_proof_generation_stats() {
+	use crate::mmr::storage::{OffchainStorage, Storage};
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when
+		let (_, proof, stats) =
+			crate::Pallet::<Test>::generate_proof_with_stats(vec![5], None).unwrap();
+
+		// then the leaf and every proof item were read from the Off-chain DB, each after a
+		// miss of its canonical key (the nodes are not canonicalized in tests)
+		let nodes = proof.items.len() as u32 + 1;
+		assert_eq!(
+			stats,
+			primitives::ProofGenStats {
+				offchain_reads: 2 * nodes,
+				cache_hits: 0,
+				block_hash_reads: nodes,
+			}
+		);
+
+		// when the nodes are prefetched
+		let mut store = Storage::<OffchainStorage, Test, (), crate::LeafOf<Test, ()>>::default();
+		store.prefetch(&[TypedLeafIndex(4)]).unwrap();
+		let prefetched = store.stats();
+		let mmr: crate::ModuleMmr<OffchainStorage, Test, ()> = mmr::Mmr::with_storage(7, store);
+		mmr.generate_proof(vec![4]).unwrap();
+
+		// then the proof generation itself is served from the cache
+		assert_eq!(prefetched, primitives::ProofGenStats { cache_hits: 0, ..stats });
+		assert_eq!(mmr.stats(), primitives::ProofGenStats { cache_hits: nodes, ..stats });
+	});
+}
+
+#[test]
+fn should_reject_offchain_leaves_of_another_type() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (block 4 added leaf 3 at position 4), indexed by an MMR with a leaf type id
+	let (tagged_key, untagged_key) = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		let parent_hash = frame_system::Pallet::<Test>::block_hash(3);
+		(
+			MMRBlake2::node_canon_offchain_key(TypedNodeIndex(4)),
+			MMR::node_temp_offchain_key(TypedNodeIndex(4), parent_hash),
+		)
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	// then the leaf is stored along with its type id
+	let tagged = ext.offchain_db().get(&tagged_key).unwrap();
+	let untagged = ext.offchain_db().get(&untagged_key).unwrap();
+	assert_eq!(tagged, [codec::Encode::encode(&1u16), untagged].concat());
+
+	// and it's read back as the same type
+	ext.execute_with(|| {
+		assert!(crate::Pallet::<Test, Instance2>::generate_proof(vec![3], None).is_ok());
+	});
+
+	// when the leaf is replaced by one of another type
+	let mut node = tagged;
+	node[..2].copy_from_slice(&codec::Encode::encode(&2u16));
+	ext.offchain_db().set(b"", &tagged_key, &node);
+
+	// then it's rejected
+	ext.execute_with(|| {
+		assert_eq!(
+			crate::Pallet::<Test, Instance2>::generate_proof(vec![3], None),
+			Err(Error::LeafTypeMismatch)
+		);
+	});
+}
+
+#[test]
+fn should_prove_leaves_appended_at_genesis() {
+	use frame_support::traits::GenesisBuild;
+	let _ = env_logger::try_init();
+
+	// given an MMR seeded with 5 leaves at genesis
+	let genesis_leaves: Vec<_> = (0..5)
+		.map(|i| Compact::new(((i, H256::repeat_byte(0xaa)).into(), LeafData::new(i).into())))
+		.collect();
+	let mut storage = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	let config = crate::GenesisConfig::<Test> {
+		leaves: genesis_leaves.clone(),
+		phantom: Default::default(),
+	};
+	GenesisBuild::<Test>::assimilate_storage(&config, &mut storage).unwrap();
+	let mut ext: sp_io::TestExternalities = storage.into();
+
+	// and 2 blocks adding their leaves on top of them
+	let root = ext.execute_with(|| {
+		assert_eq!(crate::Pallet::<Test>::mmr_leaves(), 5);
+		assert_eq!(crate::Pallet::<Test>::genesis_leaves(), 5);
+		assert_eq!(
+			crate::Pallet::<Test>::onchain_node_indices(),
+			vec![TypedNodeIndex(6), TypedNodeIndex(7)]
+		);
+		add_blocks(2);
+		assert_eq!(crate::Pallet::<Test>::mmr_leaves(), 7);
+		assert_eq!(crate::Pallet::<Test>::block_for_leaf(TypedLeafIndex(4)), Ok(0));
+		assert_eq!(crate::Pallet::<Test>::block_for_leaf(TypedLeafIndex(5)), Ok(1));
+		assert_eq!(crate::Pallet::<Test>::leaves_added_in_block(0), Ok((0, 5)));
+		assert_eq!(crate::Pallet::<Test>::leaves_added_in_block(1), Ok((5, 6)));
+		crate::Pallet::<Test>::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when the genesis leaves are reindexed off-chain, along with the leaves of the blocks
+		let block_leaves = (1..=2).map(|number: u64| {
+			Compact::new((
+				(number - 1, H256::repeat_byte(number as u8)).into(),
+				LeafData::new(number).into(),
+			))
+		});
+		let leaves = (0..)
+			.map(TypedLeafIndex)
+			.zip(genesis_leaves.iter().cloned().chain(block_leaves));
+		let reindexed = crate::Pallet::<Test>::reindex_offchain(leaves);
+		assert_eq!(reindexed, Ok(7));
+
+		// then a genesis leaf can be proven
+		let (leaves, proof) =
+			crate::Pallet::<Test>::generate_genesis_leaves_proof(vec![2]).unwrap();
+		assert_eq!(leaves, vec![genesis_leaves[2].clone()]);
+		assert_eq!(proof.leaf_indices, vec![2]);
+		assert_eq!(proof.leaf_count, 7);
+		assert_eq!(crate::Pallet::<Test>::verify_leaves(leaves.clone(), proof.clone()), Ok(()));
+		let nodes = leaves.into_iter().map(mmr::Node::Data).collect();
+		type Hashing = <Test as Config>::Hashing;
+		assert_eq!(crate::verify_leaves_proof::<Hashing, _>(root, nodes, proof), Ok(()));
+
+		// and the leaves added by blocks are still found by their block numbers
+		let (_, proof) = crate::Pallet::<Test>::generate_proof(vec![1], None).unwrap();
+		assert_eq!(proof.leaf_indices, vec![5]);
+
+		// but leaves added by blocks are not genesis leaves
+		assert_eq!(
+			crate::Pallet::<Test>::generate_genesis_leaves_proof(vec![5]),
+			Err(Error::InvalidLeafIndex)
+		);
+	});
+}
+
+#[test]
+fn should_generate_and_verify_proofs_with_positions() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given 7 blocks (7 MMR leaves)
+	let root = ext.execute_with(|| {
+		add_blocks(7);
+		crate::Pallet::<Test>::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		type Hashing = <Test as Config>::Hashing;
+		for leaf_index in 0..7 {
+			// when
+			let (leaf, items) =
+				crate::Pallet::<Test>::generate_proof_with_positions(leaf_index).unwrap();
+
+			// then the items are positioned exactly at the proof nodes
+			let positions: Vec<_> = items.iter().map(|(pos, _)| TypedNodeIndex(*pos)).collect();
+			let expected = utils::NodesUtils::new(7).proof_node_indices(TypedLeafIndex(leaf_index));
+			assert_eq!(positions, expected);
+
+			// and the proof items are the same as in the regular proof (after bagging)
+			let (leaves, proof) =
+				crate::Pallet::<Test>::generate_proof(vec![leaf_index + 1], None).unwrap();
+			assert_eq!(leaves, vec![leaf.clone()]);
+			assert_eq!(
+				mmr::proof_from_positioned_items::<Hashing>(leaf_index, 7, items.clone()),
+				Ok(proof)
+			);
+
+			// and the positioned proof verifies
+			let node = mmr::Node::Data(leaf);
+			assert_eq!(
+				crate::verify_leaf_proof_with_positions::<Hashing, _>(
+					root,
+					node.clone(),
+					leaf_index,
+					7,
+					items.clone()
+				),
+				Ok(())
+			);
+
+			// but not with misplaced items
+			let mut misplaced = items;
+			misplaced[0].0 += 1;
+			assert_eq!(
+				crate::verify_leaf_proof_with_positions::<Hashing, _>(
+					root, node, leaf_index, 7, misplaced
+				),
+				Err(Error::Verify)
+			);
+		}
+
+		// and leaves which are not in the MMR can't be proven
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof_with_positions(7),
+			Err(Error::LeafIndexOutOfRange { requested: 7, count: 7 })
+		);
+	});
+}
+
+#[test]
+fn should_limit_leaves_appended_in_a_block() {
+	type FinalizeMmr = mmr::Mmr<mmr::storage::RuntimeStorage, Test, Instance3, u64>;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given `MMRFinalize` appends at most 4 leaves per block, except for genesis
+		let mut mmr = FinalizeMmr::new(0);
+		for leaf in 0..5 {
+			mmr.push(leaf).unwrap();
+		}
+		let (leaves, _) = mmr.finalize().unwrap();
+		assert_eq!(leaves, 5);
+		new_block();
+
+		// when more leaves are pushed in a block
+		let mut mmr = FinalizeMmr::new(5);
+		for leaf in 5..10 {
+			mmr.push(leaf).unwrap();
+		}
+
+		// then none of them are appended
+		assert_eq!(mmr.finalize(), Err(Error::TooManyLeavesInBlock));
+		assert_eq!(
+			crate::Pallet::<Test, Instance3>::onchain_node_indices(),
+			vec![TypedNodeIndex(6), TypedNodeIndex(7)]
+		);
+
+		// while up to the limit is fine, even over several commits
+		let mut mmr = FinalizeMmr::new(5);
+		for leaf in 5..8 {
+			mmr.push(leaf).unwrap();
+		}
+		assert_eq!(mmr.finalize().map(|(leaves, _)| leaves), Ok(8));
+		let mut mmr = FinalizeMmr::new(8);
+		mmr.push(8).unwrap();
+		assert_eq!(mmr.finalize().map(|(leaves, _)| leaves), Ok(9));
+		assert_eq!(crate::Pallet::<Test, Instance3>::leaves_added_in_block(1), Ok((5, 9)));
+
+		// when a further commit of the same block exceeds the limit
+		let mut mmr = FinalizeMmr::new(9);
+		mmr.push(9).unwrap();
+
+		// then its leaf is not appended
+		assert_eq!(mmr.finalize(), Err(Error::TooManyLeavesInBlock));
+		assert_eq!(crate::NumberOfLeaves::<Test, Instance3>::get(), 9);
+
+		// while the count starts over in the next block
+		new_block();
+		let mut mmr = FinalizeMmr::new(9);
+		mmr.push(9).unwrap();
+		assert_eq!(mmr.finalize().map(|(leaves, _)| leaves), Ok(10));
+		assert_eq!(crate::Pallet::<Test, Instance3>::leaves_added_in_block(2), Ok((9, 10)));
+		assert_eq!(crate::Pallet::<Test, Instance3>::leaves_added_in_block(1), Ok((5, 9)));
+		assert_eq!(crate::Pallet::<Test, Instance3>::block_for_leaf(TypedLeafIndex(8)), Ok(1));
+	});
+}
+
+#[test]
+fn should_prove_all_the_leaves_of_a_block_by_block_number() {
+	type FinalizeMmr =
+		mmr::Mmr<mmr::storage::RuntimeStorage, Test, Instance3, crate::LeafOf<Test, Instance3>>;
+	let leaf = |number: u64, a: u64| -> crate::LeafOf<Test, Instance3> {
+		Compact::new((
+			(number - 1, H256::repeat_byte(number as u8)).into(),
+			LeafData::new(a).into(),
+		))
+	};
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given a block appending 2 leaves between blocks appending a single leaf
+	ext.execute_with(|| {
+		let mut leaves = 0;
+		for (number, count) in [(1, 1), (2, 2), (3, 1)] {
+			new_block();
+			let mut mmr = FinalizeMmr::new(leaves);
+			for a in 0..count {
+				mmr.push(leaf(number, a)).unwrap();
+			}
+			leaves = mmr.finalize().unwrap().0;
+		}
+		assert_eq!(leaves, 4);
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance3>::generate_proof(vec![2], None).unwrap();
+
+		// then both leaves of the block are proven
+		assert_eq!(leaves, vec![leaf(2, 0), leaf(2, 1)]);
+		assert_eq!(proof.leaf_indices, vec![1, 2]);
+		assert_eq!(proof.leaf_count, 4);
+		assert_eq!(crate::Pallet::<Test, Instance3>::verify_leaves(leaves, proof), Ok(()));
+
+		// and the leaf of the next block is found under its own block number
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance3>::generate_proof(vec![3], None).unwrap();
+		assert_eq!(leaves, vec![leaf(3, 0)]);
+		assert_eq!(proof.leaf_indices, vec![3]);
+		assert_eq!(crate::Pallet::<Test, Instance3>::verify_leaves(leaves, proof), Ok(()));
+	});
+}
+
+#[test]
+fn should_not_append_nodes_with_unknown_parent_hash() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given a block with the default parent hash
+		frame_system::Pallet::<Test>::initialize(&1, &H256::default(), &Default::default());
+
+		// when
+		let mut mmr: mmr::Mmr<mmr::storage::RuntimeStorage, Test, (), LeafData> = mmr::Mmr::new(0);
+		mmr.push(LeafData::new(1));
+
+		// then the nodes are not appended
+		assert_eq!(mmr.finalize(), Err(Error::UnknownParentHash));
+		MMR::on_initialize(1);
+		assert_eq!(crate::Pallet::<Test>::mmr_leaves(), 0);
+		assert!(crate::Pallet::<Test>::onchain_node_indices().is_empty());
+
+		// unless they are not indexed off-chain anyway
+		IndexingEnabled::set(false);
+		MMR::on_initialize(1);
+		IndexingEnabled::set(true);
+		assert_eq!(crate::Pallet::<Test>::mmr_leaves(), 1);
+	});
+}
+
+#[test]
+fn should_append_genesis_leaves_without_parent_hash() {
+	use frame_support::traits::GenesisBuild;
+	let _ = env_logger::try_init();
+
+	// given the genesis block without the parent hash set by `frame_system`
+	let mut storage = sp_runtime::Storage::default();
+	let config = crate::GenesisConfig::<Test> {
+		leaves: vec![Compact::new(((0, H256::repeat_byte(0xaa)).into(), LeafData::new(0).into()))],
+		phantom: Default::default(),
+	};
+
+	// when
+	GenesisBuild::<Test>::assimilate_storage(&config, &mut storage).unwrap();
+
+	// then the leaves are appended, even though they can't be indexed off-chain
+	sp_io::TestExternalities::from(storage).execute_with(|| {
+		assert_eq!(frame_system::Pallet::<Test>::parent_hash(), H256::default());
+		assert_eq!(crate::Pallet::<Test>::mmr_leaves(), 1);
+		assert_eq!(crate::Pallet::<Test>::genesis_leaves(), 1);
+	});
+}
+
+#[test]
+fn should_skip_genesis_leaves_which_cant_be_appended_without_panicking() {
+	use frame_support::traits::GenesisBuild;
+	let _ = env_logger::try_init();
+
+	// given `MMRTagged` never panicking, with a genesis leaf not fitting its width
+	let mut storage = sp_runtime::Storage::default();
+	let config = crate::GenesisConfig::<Test, Instance4> {
+		leaves: vec![
+			(0, LeafData::new(0)),
+			(0, LeafData { a: 1, b: vec![7; 20] }),
+			(0, LeafData::new(1)),
+		],
+		phantom: Default::default(),
+	};
+
+	// when
+	GenesisBuild::<Test, Instance4>::assimilate_storage(&config, &mut storage).unwrap();
+
+	// then only the fitting leaves are appended
+	sp_io::TestExternalities::from(storage).execute_with(|| {
+		assert_eq!(MMRTagged::mmr_leaves(), 2);
+		assert_eq!(MMRTagged::genesis_leaves(), 2);
+	});
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn should_not_push_leaves_with_mismatching_hash_without_panicking() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given `MMRAllNodes` never panicking
+		let mut mmr: mmr::Mmr<mmr::storage::RuntimeStorage, Test, Instance1, u64> =
+			mmr::Mmr::new(0);
+
+		// when a leaf is pushed along with a wrong hash, then it's rejected
+		assert_eq!(mmr.push_prehashed(1, H256::repeat_byte(1)), None);
+
+		// while the right hash is fine
+		let hash = mmr::Node::<<Test as Config<Instance1>>::Hashing, u64>::Data(1).hash();
+		assert_eq!(mmr.push_prehashed(1, hash), Some(0));
+	});
+}
+
+#[test]
+fn should_canonicalize_nodes_older_than_retention() {
+	use frame_support::{assert_ok, traits::Hooks};
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	register_offchain_ext(&mut ext);
+
+	// given 10 blocks (10 `MMRAllNodes` leaves), with the offchain worker running after each
+	// block and canonicalizing nodes finalized more than 3 blocks ago
+	let run_block = |ext: &mut sp_io::TestExternalities| {
+		let number = ext.execute_with(|| {
+			new_block();
+			let number = frame_system::Pallet::<Test>::block_number();
+			<MMRAllNodes as Hooks<BlockNumber>>::on_initialize(number);
+			<MMRAllNodes as Hooks<BlockNumber>>::on_finalize(number);
+			number
+		});
+		ext.persist_offchain_overlay();
+		ext.execute_with(|| {
+			<MMR as Hooks<BlockNumber>>::offchain_worker(number);
+			<MMRAllNodes as Hooks<BlockNumber>>::offchain_worker(number);
+		});
+	};
+	for _ in 0..10 {
+		run_block(&mut ext);
+	}
+	let offchain_db = |key: &[u8]| {
+		sp_io::offchain::local_storage_get(sp_core::offchain::StorageKind::PERSISTENT, key)
+	};
+	let canonicalized = |leaf_index: LeafIndex| {
+		let parent_hash = frame_system::Pallet::<Test>::block_hash(leaf_index);
+		utils::NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(leaf_index))
+			.into_iter()
+			.all(|pos| {
+				offchain_db(&MMRAllNodes::node_temp_offchain_key(pos, parent_hash)).is_none() &&
+					offchain_db(&MMRAllNodes::node_canon_offchain_key(pos)).is_some()
+			})
+	};
+
+	// then nothing is canonicalized before any block is noted as finalized
+	ext.execute_with(|| {
+		assert!((0..10).all(|leaf_index| !canonicalized(leaf_index)));
+		assert_eq!(offchain_db(&MMRAllNodes::prune_cursor_offchain_key()), None);
+		assert_ok!(MMRAllNodes::note_finalized_root(RuntimeOrigin::root(), 9));
+	});
+
+	// when the offchain worker runs after block 9 was noted as finalized
+	run_block(&mut ext);
+
+	ext.execute_with(|| {
+		// then the nodes added by blocks 1..=6 were moved to their canonical keys, while the
+		// more recent ones are still fork-aware
+		for leaf_index in 0..11 {
+			assert_eq!(canonicalized(leaf_index), leaf_index < 6, "leaf {}", leaf_index);
+		}
+
+		// and the cursor points to the next block to prune, the records of the pruned blocks
+		// are removed
+		let cursor = offchain_db(&MMRAllNodes::prune_cursor_offchain_key()).unwrap();
+		assert_eq!(<BlockNumber as codec::Decode>::decode(&mut &cursor[..]).unwrap(), 7);
+		assert_eq!(offchain_db(&MMRAllNodes::fork_leaves_offchain_key(6)), None);
+		assert!(offchain_db(&MMRAllNodes::fork_leaves_offchain_key(7)).is_some());
+
+		// and the proofs can still be generated for all the blocks
+		let block_numbers = (1..=11).collect();
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance1>::generate_proof(block_numbers, None).unwrap();
+		assert_eq!(crate::Pallet::<Test, Instance1>::verify_leaves(leaves, proof), Ok(()));
+
+		// while the default instance (without retention) doesn't canonicalize anything
+		assert_eq!(offchain_db(&MMR::prune_cursor_offchain_key()), None);
+	});
+}
+
+#[test]
+fn should_remove_nodes_of_stale_forks_when_pruning() {
+	use frame_support::{assert_ok, traits::Hooks};
+	use sp_core::offchain::OffchainStorage as _;
+	let _ = env_logger::try_init();
+
+	let run_block = |ext: &mut sp_io::TestExternalities, parent_hash: Option<H256>| {
+		let number = ext.execute_with(|| {
+			new_block();
+			let number = frame_system::Pallet::<Test>::block_number();
+			if let Some(parent_hash) = parent_hash {
+				frame_system::Pallet::<Test>::initialize(
+					&number,
+					&parent_hash,
+					&Default::default(),
+				);
+			}
+			<MMRAllNodes as Hooks<BlockNumber>>::on_initialize(number);
+			<MMRAllNodes as Hooks<BlockNumber>>::on_finalize(number);
+			number
+		});
+		ext.persist_offchain_overlay();
+		ext.execute_with(|| <MMRAllNodes as Hooks<BlockNumber>>::offchain_worker(number));
+	};
+
+	// given a stale fork where block 5 has another parent, seen by the offchain worker
+	let mut fork_ext = new_test_ext();
+	register_offchain_ext(&mut fork_ext);
+	for _ in 0..4 {
+		run_block(&mut fork_ext, None);
+	}
+	let stale_parent_hash = H256::repeat_byte(0x55);
+	run_block(&mut fork_ext, Some(stale_parent_hash));
+
+	// and the canonical chain of 10 blocks, sharing the Off-chain DB with the fork
+	let mut ext = new_test_ext();
+	register_offchain_ext(&mut ext);
+	for _ in 0..4 {
+		run_block(&mut ext, None);
+	}
+	let stale_keys = utils::NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(4))
+		.into_iter()
+		.map(|pos| MMRAllNodes::node_temp_offchain_key(pos, stale_parent_hash))
+		.chain(std::iter::once(MMRAllNodes::fork_leaves_offchain_key(5)))
+		.collect::<Vec<_>>();
+	for key in &stale_keys {
+		let value = fork_ext.offchain_db().get(key).unwrap();
+		ext.offchain_db().set(b"", key, &value);
+	}
+	for _ in 4..10 {
+		run_block(&mut ext, None);
+	}
+
+	ext.execute_with(|| {
+		let offchain_db = |key: &[u8]| {
+			sp_io::offchain::local_storage_get(sp_core::offchain::StorageKind::PERSISTENT, key)
+		};
+		// both forks of block 5 are recorded
+		let forks = offchain_db(&MMRAllNodes::fork_leaves_offchain_key(5)).unwrap();
+		let forks = <Vec<(H256, LeafIndex, LeafIndex)> as codec::Decode>::decode(&mut &forks[..]);
+		assert_eq!(forks.unwrap().len(), 2);
+
+		// when block 9 is noted as finalized and the offchain worker runs
+		assert_ok!(MMRAllNodes::note_finalized_root(RuntimeOrigin::root(), 9));
+		<MMRAllNodes as Hooks<BlockNumber>>::offchain_worker(10);
+
+		// then the nodes of the stale fork are removed, along with the record
+		for key in &stale_keys {
+			assert_eq!(offchain_db(key), None);
+		}
+		// while the canonical ones are kept under their canonical keys
+		for pos in utils::NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(4)) {
+			assert!(offchain_db(&MMRAllNodes::node_canon_offchain_key(pos)).is_some());
+		}
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance1>::generate_proof(vec![5], None).unwrap();
+		assert_eq!(crate::Pallet::<Test, Instance1>::verify_leaves(leaves, proof), Ok(()));
+	});
+}
+
+#[test]
+fn should_verify_partially_revealed_leaves() {
+	use crate::primitives::{DataOrHash, Salted};
+	type Hashing = <Test as Config>::Hashing;
+	type Leaf =
+		Compact<Hashing, (DataOrHash<Hashing, Salted<u64>>, DataOrHash<Hashing, Salted<Vec<u8>>>)>;
+	let leaf = |i: u8| -> Leaf {
+		Compact::new((
+			Salted::new([i; 32], i as u64).into(),
+			Salted::new([i + 100; 32], vec![i; 4]).into(),
+		))
+	};
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given an MMR of 5 leaves with salted elements (all the nodes are stored on-chain)
+		let mut mmr: mmr::Mmr<mmr::storage::RuntimeStorage, Test, Instance1, Leaf> =
+			mmr::Mmr::new(0);
+		for i in 0..5 {
+			mmr.push(leaf(i));
+		}
+		let (_, root) = mmr.finalize().unwrap();
+		let mmr: mmr::Mmr<mmr::storage::RuntimeStorage, Test, Instance1, Leaf> = mmr::Mmr::new(5);
+		let proof = mmr.generate_onchain_proof(vec![2]).unwrap();
+
+		// when only the first element of the leaf is revealed
+		let full_leaf = leaf(2);
+		let partial_leaf: Leaf = Compact::new((full_leaf.0.clone(), full_leaf.1.conceal()));
+		assert_eq!(partial_leaf.1, DataOrHash::Hash(full_leaf.1.hash()));
+
+		// then the leaf membership can still be verified
+		let verify = |leaf: Leaf| {
+			let leaves = vec![mmr::Node::Data(leaf)];
+			crate::verify_leaves_proof::<Hashing, _>(root, leaves, proof.clone())
+		};
+		assert_eq!(verify(partial_leaf), Ok(()));
+		assert_eq!(verify(full_leaf.clone()), Ok(()));
+
+		// but not with a different revealed value
+		let mut forged = full_leaf.0.clone();
+		if let DataOrHash::Data(ref mut salted) = forged {
+			salted.value += 1;
+		}
+		assert_eq!(verify(Compact::new((forged, full_leaf.1.conceal()))), Err(Error::Verify));
+	});
+}
+
+#[test]
+fn should_reject_out_of_range_leaf_index_before_reading_offchain() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (7 MMR leaves)
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+
+	// without the offchain extensions, any Off-chain DB access would panic
+	ext.execute_with(|| {
+		let mmr: crate::ModuleMmr<mmr::storage::OffchainStorage, Test, ()> = mmr::Mmr::new(7);
+
+		// when the leaf right past the end of the MMR is requested, then
+		assert_eq!(
+			mmr.generate_proof(vec![7]).map(|_| ()),
+			Err(Error::LeafIndexOutOfRange { requested: 7, count: 7 })
+		);
+		assert_eq!(
+			mmr.generate_proof(vec![2, 8]).map(|_| ()),
+			Err(Error::LeafIndexOutOfRange { requested: 8, count: 7 })
+		);
+	});
+}
+
+#[test]
+fn should_generate_proofs_for_different_hashings_of_same_leaves() {
+	type Keccak = <Test as Config>::Hashing;
+	type Blake2 = <Test as Config<Instance2>>::Hashing;
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks, adding the same leaves to a Keccak256 and a BLAKE2 MMR
+	let (keccak_root, blake2_root) = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		(MMR::mmr_root_hash(), MMRBlake2::mmr_root_hash())
+	});
+	assert_ne!(keccak_root, blake2_root);
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when
+		let (keccak_leaves, keccak_proof) =
+			crate::Pallet::<Test>::generate_proof(vec![3], None).unwrap();
+		let (blake2_leaves, blake2_proof) =
+			crate::Pallet::<Test, Instance2>::generate_proof(vec![3], None).unwrap();
+
+		// then both proofs are for the same leaf data
+		assert_eq!(
+			codec::Encode::encode(&keccak_leaves[0].tuple),
+			codec::Encode::encode(&blake2_leaves[0].tuple)
+		);
+		assert_eq!(keccak_proof.leaf_indices, blake2_proof.leaf_indices);
+		assert_ne!(keccak_proof.items, blake2_proof.items);
+
+		// and each of them verifies with its own hashing
+		let keccak_leaves = keccak_leaves.into_iter().map(mmr::Node::Data).collect();
+		assert_eq!(
+			crate::verify_leaves_proof::<Keccak, _>(keccak_root, keccak_leaves, keccak_proof),
+			Ok(())
+		);
+		let blake2_leaves = blake2_leaves.into_iter().map(mmr::Node::Data).collect();
+		assert_eq!(
+			crate::verify_leaves_proof::<Blake2, _>(blake2_root, blake2_leaves, blake2_proof),
+			Ok(())
+		);
+	});
+}
+
+#[test]
+fn should_not_append_leaves_rejected_by_validator() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		let add_block = || {
+			new_block();
+			MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+		};
+		// given 3 blocks
+		for _ in 0..3 {
+			add_block();
+		}
+		let root = MMRBlake2::mmr_root_hash();
+
+		// when the leaf of the next block is too large for the validator
+		LeafDataTestValue::mutate(|r| r.b = vec![7; MAX_LEAF_SIZE]);
+		add_block();
+
+		// then it's not committed
+		assert_eq!(MMRBlake2::mmr_leaves(), 3);
+		assert_eq!(MMRBlake2::mmr_root_hash(), root);
+		// while the MMR without a validator appends it
+		assert_eq!(MMR::mmr_leaves(), 4);
+
+		// when the leaves are small again
+		LeafDataTestValue::mutate(|r| r.b.clear());
+		add_block();
+
+		// then they are appended again
+		assert_eq!(MMRBlake2::mmr_leaves(), 4);
+		assert_ne!(MMRBlake2::mmr_root_hash(), root);
+	});
+}
+
+#[test]
+fn should_key_offchain_nodes_by_position_only_without_fork_safe_keys() {
+	use sp_core::offchain::StorageKind;
+	type Blake2 = <Test as Config<Instance2>>::Hashing;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks, indexed by an MMR without fork-aware keys
+	let root = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		MMRBlake2::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		let get = |key: &[u8]| sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, key);
+
+		// then all the nodes are stored under their position-only key
+		for pos in (0..utils::NodesUtils::new(7).size()).map(TypedNodeIndex) {
+			let leaf_index = utils::NodesUtils::leaf_index_that_added_node(pos);
+			let parent_hash = frame_system::Pallet::<Test>::block_hash(leaf_index.0);
+			assert!(get(&MMRBlake2::node_canon_offchain_key(pos)).is_some());
+			assert_eq!(get(&MMRBlake2::node_temp_offchain_key(pos, parent_hash)), None);
+		}
+
+		// and they are read back from there
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance2>::generate_proof(vec![1, 5], None).unwrap();
+		let leaves = leaves.into_iter().map(mmr::Node::Data).collect();
+		assert_eq!(crate::verify_leaves_proof::<Blake2, _>(root, leaves, proof), Ok(()));
+	});
+}
+
+#[test]
+fn should_append_leaf_in_finalize_when_configured() {
+	use frame_support::traits::OnFinalize;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given a block applying an extrinsic
+		new_block();
+		let weight = MMRFinalize::on_initialize(1);
+		frame_system::Pallet::<Test>::note_finished_initialize();
+		LeafDataTestValue::mutate(|r| r.b = vec![1, 2, 3]);
+		frame_system::Pallet::<Test>::note_applied_extrinsic(&Ok(().into()), Default::default());
+		frame_system::Pallet::<Test>::note_finished_extrinsics();
+
+		// then nothing is appended until the block is finalized
+		assert!(weight != Weight::zero());
+		assert_eq!(MMRFinalize::mmr_leaves(), 0);
+
+		// when
+		MMRFinalize::on_finalize(1);
+
+		// then the leaf reflects the state at the end of the block
+		let leaf: crate::LeafOf<Test, Instance3> = Compact::new((
+			(0, H256::repeat_byte(1)).into(),
+			LeafData { a: 1, b: vec![1, 2, 3] }.into(),
+		));
+		assert_eq!(MMRFinalize::mmr_leaves(), 1);
+		assert_eq!(
+			MMRFinalize::mmr_root_hash(),
+			mmr::Node::<<Test as Config<Instance3>>::Hashing, _>::Data(leaf).hash()
+		);
+
+		// given a block without any extrinsic
+		new_block();
+		MMRFinalize::on_initialize(2);
+		frame_system::Pallet::<Test>::note_finished_extrinsics();
+
+		// when
+		MMRFinalize::on_finalize(2);
+
+		// then no leaf is appended
+		assert_eq!(MMRFinalize::mmr_leaves(), 1);
+	});
+}
+
+#[test]
+fn should_not_generate_proofs_for_inflated_leaf_count() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks (7 MMR leaves)
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when the number of leaves gets corrupted
+		crate::NumberOfLeaves::<Test>::put(1 << 40);
+
+		// then no proof is generated for the inflated MMR
+		let mmr: crate::ModuleMmr<mmr::storage::OffchainStorage, Test, ()> = mmr::Mmr::new(1 << 40);
+		assert_eq!(mmr.generate_proof(vec![5]).map(|_| ()), Err(Error::ReadLimitExceeded));
+		assert_eq!(mmr.generate_proof_with_positions(5).map(|_| ()), Err(Error::ReadLimitExceeded));
+		assert_eq!(mmr.stats(), Default::default());
+
+		// but it is once the number of leaves is restored
+		crate::NumberOfLeaves::<Test>::put(7);
+		let mmr: crate::ModuleMmr<mmr::storage::OffchainStorage, Test, ()> = mmr::Mmr::new(7);
+		assert!(mmr.generate_proof(vec![5]).is_ok());
+	});
+}
+
+#[test]
+fn should_store_transformed_leaves() {
+	use codec::Decode;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	let parent_hash = ext.execute_with(|| {
+		// given
+		new_block();
+		new_block();
+
+		// when
+		MMRTagged::on_initialize(2);
+
+		// then the leaf is tagged with the block number before being hashed
+		let leaf: crate::LeafOf<Test, Instance4> = (2, LeafData::new(2));
+		assert_eq!(MMRTagged::mmr_leaves(), 1);
+		assert_eq!(
+			MMRTagged::mmr_root_hash(),
+			mmr::Node::<<Test as Config<Instance4>>::Hashing, _>::Data(leaf).hash()
+		);
+
+		<frame_system::Pallet<Test>>::parent_hash()
+	});
+	ext.persist_offchain_overlay();
+
+	// and it's stored that way in the offchain DB
+	type Node = mmr::Node<<Test as Config<Instance4>>::Hashing, (u64, LeafData)>;
+	let node: Option<Node> = ext
+		.offchain_db()
+		.get(&MMRTagged::node_temp_offchain_key(TypedNodeIndex(0), parent_hash))
+		.map(|v| Decode::decode(&mut &v[..]).unwrap());
+	assert_eq!(node, Some(mmr::Node::Data((2, LeafData::new(2)))));
+}
+
+#[test]
+fn should_pad_leaves_to_fixed_width() {
+	use codec::Encode;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given `MMRTagged` pads its leaves to 32 bytes
+	let parent_hashes = ext.execute_with(|| {
+		new_block();
+		new_block();
+		LeafDataTestValue::set(LeafData { a: 1, b: vec![7; 10] });
+		MMRTagged::on_initialize(2);
+		assert_eq!(MMRTagged::mmr_leaves(), 1);
+		let first = <frame_system::Pallet<Test>>::parent_hash();
+
+		// when the leaf no longer fits the width
+		new_block();
+		LeafDataTestValue::set(LeafData { a: 1, b: vec![7; 20] });
+		MMRTagged::on_initialize(3);
+
+		// then it's not appended
+		assert_eq!(MMRTagged::mmr_leaves(), 1);
+
+		// and a fitting leaf can be appended again
+		new_block();
+		LeafDataTestValue::set(LeafData::new(1));
+		MMRTagged::on_initialize(4);
+		assert_eq!(MMRTagged::mmr_leaves(), 2);
+		(first, <frame_system::Pallet<Test>>::parent_hash())
+	});
+	ext.persist_offchain_overlay();
+
+	// then the leaves are zero-padded, while inner nodes are kept as is
+	let value = |pos, parent_hash| {
+		ext.offchain_db()
+			.get(&MMRTagged::node_temp_offchain_key(TypedNodeIndex(pos), parent_hash))
+			.unwrap()
+	};
+	let leaf: crate::LeafOf<Test, Instance4> = (2, LeafData { a: 1, b: vec![7; 10] });
+	let encoded = mmr::Node::<<Test as Config<Instance4>>::Hashing, _>::Data(leaf.clone()).encode();
+	assert_eq!(encoded.len(), 29);
+	let first = value(0, parent_hashes.0);
+	assert_eq!(first.len(), 32);
+	assert_eq!(&first[..29], &encoded[..]);
+	assert_eq!(&first[29..], &[0; 3]);
+	assert_eq!(value(1, parent_hashes.1).len(), 32);
+	assert_eq!(value(2, parent_hashes.1).len(), 33);
+
+	// and the padded leaves are still read back fine
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance4>::generate_proof(vec![0], None).unwrap();
+		assert_eq!(leaves, vec![leaf]);
+		assert_eq!(MMRTagged::verify_leaves(leaves, proof), Ok(()));
+	});
+}
+
+#[test]
+fn should_reserve_worst_case_append_weight_at_power_of_two_boundaries() {
+	// the number of peaks merged when appending a leaf to an MMR with `n` leaves
+	let merged = |n: LeafIndex| {
+		utils::NodesUtils::new(n).number_of_peaks() + 1 -
+			utils::NodesUtils::new(n + 1).number_of_peaks()
+	};
+
+	// then most peaks are merged right before the power of two boundaries
+	for k in 1..16 {
+		let boundary = 1 << k;
+		assert_eq!(merged(boundary - 1), k);
+		assert!((0..boundary - 1).all(|n| merged(n) < k));
+	}
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// when appending 16 leaves
+		let weights = (0..16).map(|_| new_block()).collect::<Vec<_>>();
+
+		// then the weight reserved at the boundaries covers all the previous appends
+		assert!(weights[..8].iter().all(|weight| weights[7].all_gte(*weight)));
+		assert!(weights.iter().all(|weight| weights[15].all_gte(*weight)));
+		assert!(weights[15].any_gt(weights[7]));
+	});
+}
+
+#[test]
+fn should_scale_proof_weight_with_node_count() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6   7
+		add_blocks(7);
+		let nodes = |leaf| utils::NodesUtils::new(7).proof_node_indices(TypedLeafIndex(leaf)).len();
+		assert_eq!(nodes(0), 4);
+		assert_eq!(nodes(6), 2);
+
+		// then
+		assert_eq!(MMR::proof_weight(TypedLeafIndex(0)), <() as WeightInfo>::generate_proof(4));
+		assert_eq!(MMR::proof_weight(TypedLeafIndex(6)), <() as WeightInfo>::generate_proof(2));
+		assert!(MMR::proof_weight(TypedLeafIndex(0)).any_gt(MMR::proof_weight(TypedLeafIndex(6))));
+	});
+}
+
+#[test]
+fn should_compute_proof_positions_without_storage() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6   7
+		add_blocks(7);
+		let size = utils::NodesUtils::new(7).size();
+
+		// then the positions computed for the size match those of the current MMR
+		assert_eq!(
+			crate::Pallet::<Test>::compute_proof_positions(TypedLeafIndex(0), size),
+			[1, 5, 9, 10].map(TypedNodeIndex).to_vec()
+		);
+		for leaf in (0..8).map(TypedLeafIndex) {
+			assert_eq!(
+				crate::Pallet::<Test>::compute_proof_positions(leaf, size),
+				crate::Pallet::<Test>::proof_node_indices(leaf)
+			);
+		}
+
+		// and nothing is computed for leaves out of the MMR, or invalid sizes
+		let compute_proof_positions = crate::Pallet::<Test>::compute_proof_positions;
+		assert!(compute_proof_positions(TypedLeafIndex(7), size).is_empty());
+		assert!(compute_proof_positions(TypedLeafIndex(0), size + 1).is_empty());
+	});
+}
+
+#[test]
+fn should_embed_block_number_in_leaves() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	ext.execute_with(|| {
+		for _ in 0..5 {
+			new_block();
+			MMRTagged::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+	});
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		// when
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance4>::generate_proof(vec![3], None).unwrap();
+
+		// then the proven leaf carries the number of the block which added it
+		assert_eq!(leaves, vec![(3, LeafData::new(3))]);
+		assert_eq!(MMRTagged::verify_leaves(leaves, proof), Ok(()));
+	});
+}
+
+#[test]
+fn should_rebuild_corrupted_peaks() {
+	use frame_support::{assert_noop, assert_ok};
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given 7 blocks
+	let root = ext.execute_with(|| {
+		for _ in 0..7 {
+			new_block();
+			MMRAllNodes::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		crate::RootHash::<Test, Instance1>::get()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// and all the peaks corrupted on-chain
+		let peaks = peaks_from_leaves_count(7);
+		let expected = peaks
+			.iter()
+			.map(|pos| crate::Nodes::<Test, Instance1>::get(pos).unwrap())
+			.collect::<Vec<_>>();
+		for pos in &peaks {
+			crate::Nodes::<Test, Instance1>::insert(pos, H256::repeat_byte(0x37));
+		}
+
+		// when the peaks are recovered from the Off-chain DB
+		let recovered = MMRAllNodes::offchain_peaks().unwrap();
+		assert_eq!(recovered, expected);
+
+		// then they're only accepted if they reproduce the current root
+		assert_noop!(
+			MMRAllNodes::rebuild_onchain_peaks(
+				RuntimeOrigin::root(),
+				recovered.clone().try_into().unwrap(),
+				H256::repeat_byte(0x37),
+			),
+			crate::pallet::Error::<Test, Instance1>::RootMismatch
+		);
+		// and the root can't be replaced by peaks reproducing another one
+		let other = vec![H256::repeat_byte(0x37); recovered.len()];
+		let other_root = MMRAllNodes::bag_peaks(other.clone()).unwrap().unwrap();
+		assert_noop!(
+			MMRAllNodes::rebuild_onchain_peaks(
+				RuntimeOrigin::root(),
+				other.try_into().unwrap(),
+				other_root,
+			),
+			crate::pallet::Error::<Test, Instance1>::RootMismatch
+		);
+		// even if the expected root is the current one
+		let mut wrong = recovered.clone();
+		wrong[1] = H256::repeat_byte(0x37);
+		assert_noop!(
+			MMRAllNodes::rebuild_onchain_peaks(
+				RuntimeOrigin::root(),
+				wrong.try_into().unwrap(),
+				root,
+			),
+			crate::pallet::Error::<Test, Instance1>::RootMismatch
+		);
+		assert_noop!(
+			MMRAllNodes::rebuild_onchain_peaks(
+				RuntimeOrigin::root(),
+				recovered[1..].to_vec().try_into().unwrap(),
+				root,
+			),
+			crate::pallet::Error::<Test, Instance1>::PeakCountMismatch
+		);
+		assert_noop!(
+			MMR::rebuild_onchain_peaks(
+				RuntimeOrigin::root(),
+				recovered.clone().try_into().unwrap(),
+				root,
+			),
+			crate::pallet::Error::<Test>::ForceResetNotAllowed
+		);
+
+		// when
+		assert_ok!(MMRAllNodes::rebuild_onchain_peaks(
+			RuntimeOrigin::root(),
+			recovered.try_into().unwrap(),
+			root,
+		));
+
+		// then the root can be recomputed from the peaks again
+		for (pos, hash) in peaks.iter().zip(expected) {
+			assert_eq!(crate::Nodes::<Test, Instance1>::get(pos), Some(hash));
+		}
+		let mmr: crate::ModuleMmr<mmr::storage::RuntimeStorage, Test, Instance1> = mmr::Mmr::new(7);
+		assert_eq!(mmr.finalize().map(|(_, root)| root), Ok(root));
+		assert_eq!(crate::RootHash::<Test, Instance1>::get(), root);
+	});
+}
+
+#[test]
+fn should_generate_latest_leaf_proof() {
+	let _ = env_logger::try_init();
+
+	// given the latest leaf being a peak (7 leaves) or not (6 leaves)
+	for (num_blocks, peaks) in [(7, vec![6, 9, 10]), (6, vec![6, 9])] {
+		let mut ext = new_test_ext();
+		ext.execute_with(|| add_blocks(num_blocks));
+		ext.persist_offchain_overlay();
+		register_offchain_ext(&mut ext);
+
+		ext.execute_with(|| {
+			assert_eq!(peaks_from_leaves_count(num_blocks as u64), peaks);
+
+			// when
+			let (leaf, proof) = crate::Pallet::<Test>::generate_latest_leaf_proof().unwrap();
+
+			// then it's the same as the regular proof of the latest leaf
+			let (leaves, expected) =
+				crate::Pallet::<Test>::generate_proof(vec![num_blocks as u64], None).unwrap();
+			assert_eq!(leaves, vec![leaf.clone()]);
+			assert_eq!(proof, expected);
+			assert_eq!(MMR::verify_leaves(vec![leaf], proof), Ok(()));
+		});
+	}
+
+	// and an empty MMR has no latest leaf
+	new_test_ext().execute_with(|| {
+		assert_eq!(crate::Pallet::<Test>::generate_latest_leaf_proof(), Err(Error::LeafNotFound));
+	});
+}
+
+#[test]
+fn should_validate_built_in_leaf_types() {
+	use crate::test_utils::validate_full_leaf_impl;
+	use sp_runtime::traits::Keccak256;
+
+	new_test_ext().execute_with(|| {
+		new_block();
+		validate_full_leaf_impl::<Keccak256, _>(ParentNumberAndHash::<Test>::leaf_data());
+		validate_full_leaf_impl::<Keccak256, _>(<Test as Config>::LeafData::leaf_data());
+	});
+}
+
+#[test]
+fn should_note_finalized_roots() {
+	use frame_support::{assert_noop, assert_ok, traits::OnFinalize};
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		let add_blocks_with_roots = |blocks| {
+			for _ in 0..blocks {
+				new_block();
+				let n = frame_system::Pallet::<Test>::block_number();
+				MMRAllNodes::on_initialize(n);
+				MMRAllNodes::on_finalize(n);
+			}
+		};
+		let note = |n| MMRAllNodes::note_finalized_root(RuntimeOrigin::root(), n);
+
+		// given 5 blocks, with the roots of the last 3 ones kept
+		add_blocks_with_roots(5);
+		let root_5 = MMRAllNodes::mmr_root_hash();
+
+		// then only the finalized root origin can note the roots
+		assert_noop!(
+			MMRAllNodes::note_finalized_root(RuntimeOrigin::none(), 4),
+			sp_runtime::DispatchError::BadOrigin
+		);
+		assert_noop!(
+			MMRAllNodes::note_finalized_root(
+				RuntimeOrigin::signed(sp_core::sr25519::Public::from_raw([1; 32])),
+				4
+			),
+			sp_runtime::DispatchError::BadOrigin
+		);
+		// and only the roots of the recent blocks can be noted
+		assert_noop!(note(2), crate::pallet::Error::<Test, Instance1>::UnknownBlockRoot);
+		assert_ok!(note(4));
+		assert_noop!(note(3), crate::pallet::Error::<Test, Instance1>::StaleFinalizedBlock);
+		assert_ok!(note(5));
+		assert_eq!(MMRAllNodes::finalized_root(5), Some(root_5));
+		assert_noop!(
+			MMR::note_finalized_root(RuntimeOrigin::root(), 5),
+			crate::pallet::Error::<Test>::FinalizedRootsDisabled
+		);
+
+		// when more blocks are noted as finalized
+		add_blocks_with_roots(3);
+		let root_8 = MMRAllNodes::mmr_root_hash();
+		assert_ok!(note(7));
+		assert_ok!(note(8));
+
+		// then only the latest 3 finalized roots are kept
+		assert_eq!(MMRAllNodes::finalized_root(4), None);
+		assert_eq!(MMRAllNodes::finalized_root(5), Some(root_5));
+		assert_eq!(MMRAllNodes::finalized_root(8), Some(root_8));
+		assert_eq!(
+			crate::FinalizedRootNumbers::<Test, Instance1>::get().into_inner(),
+			vec![5, 7, 8]
+		);
+	});
+}
+
+#[test]
+fn should_time_out_proof_generation() {
+	use sp_core::offchain::Duration;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when the deadline is too tight
+		let proof = crate::Pallet::<Test>::generate_proof_with_deadline(
+			vec![5],
+			None,
+			Duration::from_millis(0),
+		);
+
+		// then nothing is read
+		assert_eq!(proof, Err(Error::ProofGenerationTimeout { offchain_reads: 0 }));
+
+		// when there is enough time
+		let proof = crate::Pallet::<Test>::generate_proof_with_deadline(
+			vec![5],
+			None,
+			Duration::from_millis(1_000),
+		);
+
+		// then the proof is generated as usual
+		assert_eq!(proof, crate::Pallet::<Test>::generate_proof(vec![5], None));
+		assert!(proof.is_ok());
+	});
+}
+
+#[test]
+fn should_estimate_proof_encoded_size() {
+	use codec::Encode;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		for block_num in 1..=7 {
+			// when
+			let (_, proof) = crate::Pallet::<Test>::generate_proof(vec![block_num], None).unwrap();
+			let leaf_index = TypedLeafIndex(proof.leaf_indices[0]);
+			let estimate = MMR::estimate_proof_encoded_bytes(leaf_index);
+
+			// then
+			assert_eq!(estimate, proof.encode().len() as u64, "block {}", block_num);
+		}
+		// the proof of the last leaf, being a peak, only consists of the left-hand peaks
+		assert!(
+			MMR::estimate_proof_encoded_bytes(TypedLeafIndex(6)) <
+				MMR::estimate_proof_encoded_bytes(TypedLeafIndex(0))
+		);
+	});
+}
+
+#[test]
+fn should_notify_about_appends() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		LastAppend::set(None);
+
+		// when
+		add_blocks(1);
+
+		// then
+		assert_eq!(LastAppend::get(), Some((1, vec![0])));
+
+		// when
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6   7
+		add_blocks(6);
+
+		// then the hook is given the state after the latest append
+		assert_eq!(LastAppend::get(), Some((7, vec![6, 9, 10])));
+		assert_eq!(crate::NumberOfLeaves::<Test>::get(), 7);
+		for peak in [6, 9, 10] {
+			assert!(crate::Nodes::<Test>::contains_key(peak));
+		}
+	});
+}
+
+#[test]
+fn should_verify_proofs_with_hasher_supplied_at_runtime() {
+	use sp_runtime::traits::{BlakeTwo256, Hash, Keccak256};
+
+	// given a proof of the first leaf of a 3-leaf MMR, for two different hashings
+	fn leaves_proof_and_root<H: Hash<Output = H256>>() -> (Vec<H256>, Proof<H256>, H256) {
+		let leaf = |i: u64| mmr::Node::<H, u64>::Data(i);
+		let proof = Proof {
+			leaf_indices: vec![0],
+			leaf_count: 3,
+			items: vec![leaf(1).hash(), leaf(2).hash()],
+		};
+		let root = crate::reconstruct_root::<H, u64>(vec![leaf(0)], proof.clone()).unwrap();
+		(vec![leaf(0).hash()], proof, root)
+	}
+	let (keccak_leaves, keccak_proof, keccak_root) = leaves_proof_and_root::<Keccak256>();
+	let (blake2_leaves, blake2_proof, blake2_root) = leaves_proof_and_root::<BlakeTwo256>();
+	assert_ne!(keccak_root, blake2_root);
+
+	// when
+	let keccak = |data: &[u8]| Keccak256::hash(data);
+	let blake2 = |data: &[u8]| BlakeTwo256::hash(data);
+	let verify = |hasher: &dyn Fn(&[u8]) -> H256, root, leaves, proof| {
+		crate::verify_leaves_proof_with_hasher(hasher, root, leaves, proof)
+	};
+
+	// then the same verifier handles both, given the matching hasher
+	assert_eq!(verify(&keccak, keccak_root, keccak_leaves.clone(), keccak_proof.clone()), Ok(()));
+	assert_eq!(verify(&blake2, blake2_root, blake2_leaves, blake2_proof), Ok(()));
+	// but not the other one
+	assert_eq!(verify(&blake2, keccak_root, keccak_leaves, keccak_proof), Err(Error::Verify));
+}
+
+#[test]
+fn should_verify_proofs_against_peaks() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	//       D
+	//     /   \
+	//    /     \
+	//   A       B       C
+	//  / \     / \     / \
+	// 1   2   3   4   5   6   7
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		type Hashing = <Test as Config>::Hashing;
+		let peaks: Vec<_> = [6, 9, 10]
+			.into_iter()
+			.map(|pos| (pos, crate::Nodes::<Test>::get(pos).unwrap()))
+			.collect();
+
+		for leaf_index in 0..7 {
+			// when
+			let (leaves, proof) =
+				crate::Pallet::<Test>::generate_proof(vec![leaf_index + 1], None).unwrap();
+			let node = mmr::Node::Data(leaves[0].clone());
+
+			// then
+			assert_eq!(
+				crate::verify_against_peaks::<Hashing, _>(
+					&peaks,
+					leaf_index,
+					node.clone(),
+					proof.clone()
+				),
+				Ok(())
+			);
+
+			// but not as the proof of a different leaf
+			let other_leaf = (leaf_index + 1) % 7;
+			assert_eq!(
+				crate::verify_against_peaks::<Hashing, _>(&peaks, other_leaf, node, proof.clone()),
+				Err(Error::Verify)
+			);
+
+			// nor against peaks missing the leaf's peak
+			let leaf_peak = match leaf_index {
+				0..=3 => 6,
+				4 | 5 => 9,
+				_ => 10,
+			};
+			let other_peaks: Vec<_> =
+				peaks.iter().filter(|(pos, _)| *pos != leaf_peak).cloned().collect();
+			assert_eq!(
+				crate::verify_against_peaks::<Hashing, _>(
+					&other_peaks,
+					leaf_index,
+					mmr::Node::Data(leaves[0].clone()),
+					proof
+				),
+				Err(Error::Verify)
+			);
+		}
+	});
+}
+
+#[test]
+fn should_generate_and_verify_range_proofs() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given a perfect MMR of 32 leaves
+	ext.execute_with(|| add_blocks(32));
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		type Hashing = <Test as Config>::Hashing;
+		let root = crate::RootHash::<Test>::get();
+		let verify = |leaves: Vec<_>, proof| {
+			let leaves = leaves.into_iter().map(mmr::Node::Data).collect();
+			crate::verify_range_proof::<Hashing, _>(root, leaves, proof)
+		};
+
+		// when proving the 16 leaves in the middle
+		let (leaves, proof) = crate::Pallet::<Test>::generate_range_proof(8, 23).unwrap();
+
+		// then only the roots of the subtrees of the first and the last 8 leaves are needed
+		assert_eq!(leaves.len(), 16);
+		assert_eq!(proof.leaf_indices(), (8..=23).collect::<Vec<_>>());
+		assert_eq!(proof.items.len(), 2);
+		assert_eq!(verify(leaves.clone(), proof.clone()), Ok(()));
+
+		// while the separate proofs of the leaves are 5 items each
+		let (_, leaf_proof) = crate::Pallet::<Test>::generate_proof(vec![9], None).unwrap();
+		assert_eq!(leaf_proof.items.len(), 5);
+
+		// and unaligned ranges are proven as well
+		let (unaligned_leaves, unaligned_proof) =
+			crate::Pallet::<Test>::generate_range_proof(3, 18).unwrap();
+		assert!(unaligned_proof.items.len() < 16);
+		assert_eq!(verify(unaligned_leaves, unaligned_proof), Ok(()));
+
+		// but the proof doesn't verify with a leaf missing
+		assert_eq!(verify(leaves[1..].to_vec(), proof.clone()), Err(Error::Verify));
+
+		// nor with the leaves out of order
+		let mut reordered = leaves;
+		reordered.swap(0, 1);
+		assert_eq!(verify(reordered, proof), Err(Error::Verify));
+
+		// and ranges beyond the MMR are rejected before being expanded
+		let unbounded = primitives::RangeProof {
+			first_leaf_index: 0,
+			last_leaf_index: u64::MAX,
+			leaf_count: 32,
+			items: vec![],
+		};
+		assert_eq!(verify(vec![], unbounded.clone()), Err(Error::Verify));
+		// as are ranges not matching the leaves, even the largest ones
+		let largest = primitives::RangeProof {
+			last_leaf_index: u64::MAX - 1,
+			leaf_count: u64::MAX,
+			..unbounded.clone()
+		};
+		assert_eq!(verify(vec![], largest), Err(Error::Verify));
+		assert_eq!(
+			verify(
+				vec![],
+				primitives::RangeProof { first_leaf_index: 1, last_leaf_index: 0, ..unbounded }
+			),
+			Err(Error::Verify)
+		);
+
+		// and only existing, non-empty ranges can be proven
+		assert_eq!(
+			crate::Pallet::<Test>::generate_range_proof(8, 32).map(|_| ()),
+			Err(Error::LeafIndexOutOfRange { requested: 32, count: 32 })
+		);
+		assert_eq!(
+			crate::Pallet::<Test>::generate_range_proof(9, 8).map(|_| ()),
+			Err(Error::InvalidLeafIndex)
+		);
+	});
+}
+
+#[test]
+fn should_generate_proofs_with_subtree() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		let root = crate::Pallet::<Test>::mmr_root_hash();
+		let verify = |leaves: Vec<crate::LeafOf<Test, ()>>, proof| {
+			let leaves = leaves.into_iter().map(mmr::Node::Data).collect();
+			crate::verify_leaves_proof::<<Test as Config>::Hashing, _>(root, leaves, proof)
+		};
+
+		// when
+		let (leaves, proof) = crate::Pallet::<Test>::generate_proof_with_subtree(1, 2).unwrap();
+
+		// then all the leaves of the height-2 subtree are proven
+		assert_eq!(proof.leaf_indices, vec![0, 1, 2, 3]);
+		let (range_leaves, range_proof) =
+			crate::Pallet::<Test>::generate_range_proof(0, 3).unwrap();
+		assert_eq!(leaves, range_leaves);
+		assert_eq!(proof.items, range_proof.items);
+		assert_eq!(verify(leaves, proof), Ok(()));
+
+		// and only the existing leaves of an incomplete subtree are
+		let (leaves, proof) = crate::Pallet::<Test>::generate_proof_with_subtree(5, 2).unwrap();
+		assert_eq!(proof.leaf_indices, vec![4, 5, 6]);
+		assert_eq!(verify(leaves, proof), Ok(()));
+
+		// and subtrees of height 0 are just the leaf
+		let (leaves, proof) = crate::Pallet::<Test>::generate_proof_with_subtree(5, 0).unwrap();
+		assert_eq!(proof.leaf_indices, vec![5]);
+		assert_eq!(leaves.len(), 1);
+
+		// and huge subtrees are the whole MMR
+		let (_, proof) = crate::Pallet::<Test>::generate_proof_with_subtree(5, 64).unwrap();
+		assert_eq!(proof.leaf_indices, (0..7).collect::<Vec<_>>());
+
+		// but leaves beyond the MMR are rejected
+		assert_eq!(
+			crate::Pallet::<Test>::generate_proof_with_subtree(7, 2).map(|_| ()),
+			Err(Error::LeafIndexOutOfRange { requested: 7, count: 7 })
+		);
+	});
+}
+
+#[test]
+fn should_export_and_import_portable_mmr() {
+	use codec::{Decode, Encode};
+	use sp_runtime::traits::BlakeTwo256;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		type Hashing = <Test as Config>::Hashing;
+		let import =
+			|portable| crate::import_portable::<Hashing, crate::LeafOf<Test, ()>>(portable);
+
+		// when exporting some of the leaves
+		let portable = crate::Pallet::<Test>::export_portable(2, 5).unwrap();
+		assert_eq!(portable.version, 0);
+		assert_eq!(portable.leaf_count, 7);
+		assert_eq!(portable.root, crate::Pallet::<Test>::mmr_root_hash());
+
+		// then they are imported back from the encoded export
+		let decoded = Decode::decode(&mut &*portable.encode()).unwrap();
+		let (leaves, _) = crate::Pallet::<Test>::generate_range_proof(2, 5).unwrap();
+		assert_eq!(import(decoded), Ok(leaves));
+
+		// and so are all of them
+		let full = crate::Pallet::<Test>::export_portable(0, 6).unwrap();
+		assert_eq!(import(full).map(|leaves| leaves.len()), Ok(7));
+
+		// but not with another hashing
+		assert_eq!(
+			crate::import_portable::<BlakeTwo256, crate::LeafOf<Test, ()>>(portable.clone()),
+			Err(Error::Verify)
+		);
+		// nor with another root
+		let mut tampered = portable.clone();
+		tampered.root = H256::repeat_byte(1);
+		assert_eq!(import(tampered), Err(Error::Verify));
+		// nor in an unknown version
+		let mut unknown = portable;
+		unknown.version = 1;
+		assert_eq!(import(unknown), Err(Error::UnsupportedProofVersion { version: 1 }));
+	});
+}
+
+#[test]
+fn should_predict_roots_after_candidate_leaves() {
+	use frame_support::storage::{with_transaction, TransactionOutcome};
+	use sp_mmr_primitives::LeafDataProvider;
+	use sp_runtime::DispatchError;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks, and the next one initialized
+		add_blocks(7);
+		frame_system::Pallet::<Test>::initialize(&8, &H256::repeat_byte(8), &Default::default());
+		let set_candidate = |b: u8| LeafDataTestValue::set(LeafData { a: 8, b: vec![b] });
+		let candidates = (1..=3)
+			.map(|b| {
+				set_candidate(b);
+				<<Test as Config>::LeafData as LeafDataProvider>::leaf_data()
+			})
+			.collect::<Vec<_>>();
+		let root = MMR::mmr_root_hash();
+
+		// when
+		let roots = MMR::roots_after_candidates(candidates).unwrap();
+
+		// then the MMR is untouched
+		assert_eq!(MMR::mmr_leaves(), 7);
+		assert_eq!(MMR::mmr_root_hash(), root);
+
+		// and every candidate leads to a different root
+		assert_eq!(roots.len(), 3);
+		assert!(roots[0] != roots[1] && roots[1] != roots[2] && roots[0] != roots[2]);
+
+		// which is the root after actually appending it
+		for (b, predicted) in (1..=3).zip(roots) {
+			let appended = with_transaction(|| {
+				set_candidate(b);
+				MMR::on_initialize(8);
+				TransactionOutcome::Rollback(Ok::<_, DispatchError>(MMR::mmr_root_hash()))
+			});
+			assert_eq!(appended, Ok(predicted));
+			assert_eq!(MMR::mmr_leaves(), 7);
+		}
+	});
+}
+
+#[test]
+fn should_return_root_bagged_from_onchain_peaks() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		type Hashing = <Test as Config>::Hashing;
+
+		for leaves in 1..=7 {
+			// when
+			add_blocks(1);
+
+			// then the root served by the runtime API is the one of the on-chain peaks
+			let peaks = helper::get_peaks(utils::NodesUtils::new(leaves).size());
+			let last_peak = *peaks.last().unwrap();
+			let expected = crate::root_from_peak::<Hashing>(
+				crate::Nodes::<Test>::get(last_peak).unwrap(),
+				peaks.len() - 1,
+				peaks.len(),
+				MMR::peak_bagging_proof(peaks.len() - 1).unwrap(),
+			)
+			.unwrap();
+			assert_eq!(MMR::mmr_root(), expected);
+			assert_eq!(MMR::mmr_root(), crate::RootHash::<Test>::get());
+		}
+	});
+}
+
+#[test]
+fn should_keep_history_of_pruned_peaks() {
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given 7 blocks, keeping the roots along the way
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6   7
+		let mut roots = vec![];
+		for _ in 0..7 {
+			new_block();
+			MMRBlake2::on_initialize(frame_system::Pallet::<Test>::block_number());
+			roots.push(MMRBlake2::mmr_root_hash());
+		}
+
+		// then the pruned peaks are recorded with the block which pruned them, leaf `1` (pruned
+		// by block 2) being evicted by the later ones
+		assert_eq!(
+			crate::HistoricalPeakPositions::<Test, Instance2>::get().into_inner(),
+			vec![2, 3, 7]
+		);
+		let pruned_at =
+			|pos| crate::HistoricalPeaks::<Test, Instance2>::get(pos).map(|(block, _)| block);
+		assert_eq!(pruned_at(0), None);
+		assert_eq!(pruned_at(2), Some(4));
+		assert_eq!(pruned_at(3), Some(4));
+		assert_eq!(pruned_at(7), Some(6));
+
+		// and the past roots can be rebuilt from them
+		for leaves in 2..=7 {
+			assert_eq!(MMRBlake2::historical_root(leaves), Some(roots[leaves as usize - 1]));
+		}
+		assert_eq!(MMRBlake2::historical_root(1), None);
+		assert_eq!(MMRBlake2::historical_root(8), None);
+
+		// while without the history only the roots of the current peaks are available
+		assert_eq!(crate::HistoricalPeakPositions::<Test>::get().len(), 0);
+		assert_eq!(MMR::historical_root(3), None);
+		assert_eq!(MMR::historical_root(7), Some(MMR::mmr_root_hash()));
+	});
+}
+
+#[test]
+fn should_verify_proofs_incrementally() {
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given
+	ext.execute_with(|| add_blocks(7));
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		type Hashing = <Test as Config>::Hashing;
+		let root = crate::RootHash::<Test>::get();
+		let verifier =
+			|leaf, leaf_index| crate::ProofVerifier::<Hashing>::new(leaf, leaf_index, 7).unwrap();
+
+		for leaf_index in 0..7 {
+			let (leaf, items) =
+				crate::Pallet::<Test>::generate_proof_with_positions(leaf_index).unwrap();
+
+			// when feeding the nodes in order
+			let mut in_order = verifier(leaf.clone(), leaf_index);
+			for (pos, hash) in items.iter() {
+				assert_eq!(in_order.feed_node(*pos, *hash), Ok(()));
+			}
+
+			// then
+			assert_eq!(in_order.finalize(root), Ok(()));
+
+			// but a missing node is detected
+			let mut missing = verifier(leaf.clone(), leaf_index);
+			for (pos, hash) in items.iter().take(items.len() - 1) {
+				assert_eq!(missing.feed_node(*pos, *hash), Ok(()));
+			}
+			assert_eq!(missing.finalize(root), Err(Error::Verify));
+		}
+
+		// when feeding the nodes out of order
+		let (leaf, mut items) = crate::Pallet::<Test>::generate_proof_with_positions(0).unwrap();
+		items.swap(0, 1);
+		let mut out_of_order = verifier(leaf.clone(), 0);
+
+		// then
+		assert_eq!(out_of_order.feed_node(items[0].0, items[0].1), Err(Error::Verify));
+		assert_eq!(out_of_order.feed_node(items[1].0, items[1].1), Err(Error::Verify));
+		assert_eq!(out_of_order.finalize(root), Err(Error::Verify));
+
+		// and the proof doesn't verify against another root
+		let mut in_order = verifier(leaf, 0);
+		items.swap(0, 1);
+		for (pos, hash) in items {
+			assert_eq!(in_order.feed_node(pos, hash), Ok(()));
+		}
+		assert_eq!(in_order.finalize(H256::repeat_byte(1)), Err(Error::Verify));
+	});
+}
+
+#[test]
+fn should_handle_single_leaf_mmr() {
+	use codec::Encode;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+	// given a single leaf
+	ext.execute_with(|| add_blocks(1));
+	ext.persist_offchain_overlay();
+
+	register_offchain_ext(&mut ext);
+	ext.execute_with(|| {
+		type Hashing = <Test as Config>::Hashing;
+
+		// then the only peak is the leaf
+		assert_eq!(MMR::current_peaks_bounded().unwrap().into_inner(), vec![0]);
+		let leaf_hash = crate::Nodes::<Test>::get(0).unwrap();
+		assert_eq!(MMR::peak_bagging_proof(0), Ok(vec![]));
+		assert_eq!(crate::root_from_peak::<Hashing>(leaf_hash, 0, 1, vec![]), Ok(leaf_hash));
+
+		// and the root is the hash of the leaf
+		let root = MMR::mmr_root();
+		assert_eq!(root, leaf_hash);
+		assert_eq!(MMR::historical_root(1), Some(root));
+
+		// and the proof of the leaf is empty
+		let (leaves, proof) = crate::Pallet::<Test>::generate_proof(vec![1], None).unwrap();
+		assert_eq!(proof, Proof { leaf_indices: vec![0], leaf_count: 1, items: vec![] });
+		assert_eq!(mmr::Node::<Hashing, _>::Data(leaves[0].clone()).hash(), root);
+		assert_eq!(
+			crate::Pallet::<Test>::generate_latest_leaf_proof(),
+			Ok((leaves[0].clone(), proof.clone()))
+		);
+		let (_, items) = crate::Pallet::<Test>::generate_proof_with_positions(0).unwrap();
+		assert_eq!(items, vec![]);
+		let estimate = MMR::estimate_proof_encoded_bytes(TypedLeafIndex(0));
+		assert_eq!(estimate, proof.encode().len() as u64);
+
+		// and it verifies
+		let node = mmr::Node::Data(leaves[0].clone());
+		assert_eq!(MMR::verify_leaves(leaves.clone(), proof.clone()), Ok(()));
+		assert_eq!(
+			crate::verify_leaves_proof::<Hashing, _>(root, vec![node.clone()], proof.clone()),
+			Ok(())
+		);
+		assert_eq!(crate::verify_against_peaks::<Hashing, _>(&[(0, root)], 0, node, proof), Ok(()));
+		let verifier = crate::ProofVerifier::<Hashing>::new(leaves[0].clone(), 0, 1).unwrap();
+		assert_eq!(verifier.finalize(root), Ok(()));
+		let (range_leaves, range_proof) =
+			crate::Pallet::<Test>::generate_range_proof(0, 0).unwrap();
+		assert_eq!(range_leaves, leaves);
+		assert_eq!(
+			crate::verify_range_proof::<Hashing, _>(
+				root,
+				range_leaves.into_iter().map(mmr::Node::Data).collect(),
+				range_proof
+			),
+			Ok(())
+		);
+
+		// but not against another root
+		let other_root = H256::repeat_byte(1);
+		let verifier = crate::ProofVerifier::<Hashing>::new(leaves[0].clone(), 0, 1).unwrap();
+		assert_eq!(verifier.finalize(other_root), Err(Error::Verify));
+	});
+}
+
+#[test]
+fn should_list_keys_of_root_state_proof() {
+	use frame_support::storage::unhashed;
+
+	let _ = env_logger::try_init();
+	new_test_ext().execute_with(|| {
+		// given
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6   7
+		add_blocks(7);
+
+		// when
+		let keys = MMR::root_state_proof_keys();
+
+		// then the keys are the leaf count and the peaks
+		assert_eq!(keys.len(), 4);
+		assert_eq!(unhashed::get::<LeafIndex>(&keys[0]), Some(7));
+		let peaks: Vec<H256> =
+			keys[1..].iter().map(|key| unhashed::get::<H256>(key).unwrap()).collect();
+		for (pos, peak) in [6, 9, 10].into_iter().zip(&peaks) {
+			assert_eq!(crate::Nodes::<Test>::get(pos).as_ref(), Some(peak));
+		}
+
+		// and the root can be recomputed from them
+		type Hashing = <Test as Config>::Hashing;
+		assert_eq!(
+			crate::root_from_peak::<Hashing>(peaks[2], 2, 3, vec![peaks[1], peaks[0]]),
+			Ok(MMR::mmr_root())
+		);
+	});
+}
+
+#[test]
+fn should_commit_to_leaves_with_the_cross_chain_commitment() {
+	use sp_runtime::traits::{Hash, Keccak256};
+	type AbiLeaf = primitives::CommittedLeaf<(u64, H256), AbiCommitment>;
+
+	let _ = env_logger::try_init();
+	let mut ext = new_test_ext();
+
+	// given an MMR committing to its leaves ABI-encoded
+	let root = ext.execute_with(|| {
+		new_block();
+		MMRCrossChain::on_initialize(1);
+
+		// then the root of the single leaf MMR is
+		// `keccak256(abi.encode(uint256(0), bytes32(0x0101..01)))`
+		assert_eq!(
+			MMRCrossChain::mmr_root_hash(),
+			hex("d5f4f7e1d989848480236fb0a5f808d5877abf778364ae50845234dd6c1e80fc")
+		);
+		// rather than the hash of the leaf's SCALE encoding
+		let leaf = (0u64, H256::repeat_byte(1));
+		assert_ne!(MMRCrossChain::mmr_root_hash(), Keccak256::hash_of(&leaf));
+
+		// when 6 more blocks add their leaves
+		for _ in 1..7 {
+			new_block();
+			MMRCrossChain::on_initialize(frame_system::Pallet::<Test>::block_number());
+		}
+		MMRCrossChain::mmr_root_hash()
+	});
+	ext.persist_offchain_overlay();
+	register_offchain_ext(&mut ext);
+
+	ext.execute_with(|| {
+		// when
+		let (leaves, proof) =
+			crate::Pallet::<Test, Instance5>::generate_proof(vec![3, 5], None).unwrap();
+
+		// then the leaves are returned as is
+		assert_eq!(leaves, vec![(2, H256::repeat_byte(3)), (4, H256::repeat_byte(5))]);
+		// and the proof verifies with the pallet
+		assert_eq!(MMRCrossChain::verify_leaves(leaves.clone(), proof.clone()), Ok(()));
+		// and with the stateless verifier, given the leaves committed to the same way
+		let nodes =
+			leaves.iter().cloned().map(|leaf| mmr::Node::Data(AbiLeaf::new(leaf))).collect();
+		assert_eq!(crate::verify_leaves_proof::<Keccak256, _>(root, nodes, proof.clone()), Ok(()));
+		// but not with the hashes of their SCALE encoding
+		let nodes = leaves.into_iter().map(mmr::Node::Data).collect();
+		assert_eq!(
+			crate::verify_leaves_proof::<Keccak256, _>(root, nodes, proof),
+			Err(Error::Verify)
+		);
+	});
+}
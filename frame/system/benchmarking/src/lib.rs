@@ -45,6 +45,12 @@ benchmarks! {
 		let caller = whitelisted_caller();
 	}: _(RawOrigin::Signed(caller), remark_message)
 
+	remark_with_topic {
+		let b in 0 .. *T::BlockLength::get().max.get(DispatchClass::Normal) as u32;
+		let remark_message = vec![1; b as usize];
+		let caller = whitelisted_caller();
+	}: _(RawOrigin::Signed(caller), [1; 32], remark_message)
+
 	set_heap_pages {
 	}: _(RawOrigin::Root, Default::default())
 
@@ -62,11 +68,13 @@ benchmarks! {
 		assert_eq!(current_code.len(), 4_000_000 as usize);
 	}
 
+	// Inserting a new key is the worst case, as it creates trie nodes instead of only replacing
+	// a value. This is what `WeightInfo::set_storage` is based on.
 	#[skip_meta]
 	set_storage {
 		let i in 0 .. 1000;
 
-		// Set up i items to add
+		// Set up i items to add, none of which exist yet.
 		let mut items = Vec::new();
 		for j in 0 .. i {
 			let hash = (i, j).using_encoded(T::Hashing::hash).as_ref().to_vec();
@@ -83,6 +91,44 @@ benchmarks! {
 		}
 	}
 
+	// Same as `set_storage`, but every key already exists. Only used to compare against the
+	// new-key case, the call is always charged for the latter.
+	#[extra]
+	#[skip_meta]
+	set_storage_overwrite {
+		let i in 0 .. 1000;
+
+		// Set up i items which are already in storage and get a new value.
+		let mut items = Vec::new();
+		for j in 0 .. i {
+			let hash = (i, j).using_encoded(T::Hashing::hash).as_ref().to_vec();
+			storage::unhashed::put_raw(&hash, &hash);
+			items.push((hash.clone(), hash.encode()));
+		}
+
+		let items_to_verify = items.clone();
+	}: set_storage(RawOrigin::Root, items)
+	verify {
+		for (item, value) in items_to_verify {
+			let stored = storage::unhashed::get_raw(&item).ok_or("No value stored")?;
+			assert_eq!(stored, value);
+		}
+	}
+
+	set_well_known_storage {
+		let i in 0 .. 1000;
+
+		// Set up i items to add, all of them setting the heap pages.
+		let items = vec![(well_known_keys::HEAP_PAGES.to_vec(), 8u64.encode()); i as usize];
+	}: _(RawOrigin::Root, items)
+	verify {
+		if i > 0 {
+			let value = storage::unhashed::get_raw(well_known_keys::HEAP_PAGES)
+				.ok_or("No value stored")?;
+			assert_eq!(value, 8u64.encode());
+		}
+	}
+
 	#[skip_meta]
 	kill_storage {
 		let i in 0 .. 1000;
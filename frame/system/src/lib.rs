@@ -421,6 +421,9 @@ pub mod pallet {
 		}
 
 		/// Set some items of storage.
+		///
+		/// The weight assumes every item inserts a new key, which is more expensive than
+		/// overwriting the value of an existing one.
 		#[pallet::call_index(4)]
 		#[pallet::weight((
 			T::SystemWeightInfo::set_storage(items.len() as u32),
@@ -482,6 +485,59 @@ pub mod pallet {
 			Self::deposit_event(Event::Remarked { sender: who, hash });
 			Ok(().into())
 		}
+
+		/// Make some on-chain remark and emit an event indexed by `topic`.
+		///
+		/// The `topic` is attached to the event as its topic (truncated or zero-padded to the
+		/// length of `T::Hash`), so that subscribers can cheaply filter the remarks by it.
+		#[pallet::call_index(8)]
+		#[pallet::weight(T::SystemWeightInfo::remark_with_topic(data.len() as u32))]
+		pub fn remark_with_topic(
+			origin: OriginFor<T>,
+			topic: [u8; 32],
+			data: Vec<u8>,
+		) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			let hash = T::Hashing::hash(&data[..]);
+			let mut event_topic = T::Hash::default();
+			let len = event_topic.as_ref().len().min(topic.len());
+			event_topic.as_mut()[..len].copy_from_slice(&topic[..len]);
+			Self::deposit_event_indexed(
+				&[event_topic],
+				Event::RemarkedWithTopic { sender: who, topic, hash }.into(),
+			);
+			Ok(().into())
+		}
+
+		/// Set some well-known storage items (like `:heappages`) at once.
+		///
+		/// Unlike [`Pallet::set_storage`], every key is checked against
+		/// [`SETTABLE_WELL_KNOWN_KEYS`] first, and nothing is written if any of them is not
+		/// recognized. This prevents a typo from writing a garbage key.
+		///
+		/// Note the runtime code can't be set this way, use [`Pallet::set_code`] instead.
+		#[pallet::call_index(9)]
+		#[pallet::weight((
+			T::SystemWeightInfo::set_well_known_storage(items.len() as u32),
+			DispatchClass::Operational,
+		))]
+		pub fn set_well_known_storage(
+			origin: OriginFor<T>,
+			items: Vec<KeyValue>,
+		) -> DispatchResultWithPostInfo {
+			ensure_root(origin)?;
+			ensure!(
+				items.iter().all(|(key, _)| SETTABLE_WELL_KNOWN_KEYS.contains(&&key[..])),
+				Error::<T>::UnknownWellKnownKey
+			);
+			for (key, value) in &items {
+				storage::unhashed::put_raw(key, value);
+			}
+			if !items.is_empty() {
+				Self::deposit_log(generic::DigestItem::RuntimeEnvironmentUpdated);
+			}
+			Ok(().into())
+		}
 	}
 
 	/// Event for the System pallet.
@@ -499,6 +555,8 @@ pub mod pallet {
 		KilledAccount { account: T::AccountId },
 		/// On on-chain remark happened.
 		Remarked { sender: T::AccountId, hash: T::Hash },
+		/// On on-chain remark with a topic happened.
+		RemarkedWithTopic { sender: T::AccountId, topic: [u8; 32], hash: T::Hash },
 	}
 
 	/// Error for the System pallet
@@ -520,6 +578,8 @@ pub mod pallet {
 		NonZeroRefCount,
 		/// The origin filter prevent the call to be dispatched.
 		CallFiltered,
+		/// The key is not one of the well-known keys which can be set.
+		UnknownWellKnownKey,
 	}
 
 	/// Exposed trait-generic origin type.
@@ -680,6 +740,12 @@ impl GenesisConfig {
 pub type Key = Vec<u8>;
 pub type KeyValue = (Vec<u8>, Vec<u8>);
 
+/// The well-known storage keys which can be set with [`Pallet::set_well_known_storage`].
+///
+/// `:code` is not part of it, since the runtime upgrades have to be checked by
+/// [`Pallet::set_code`].
+pub const SETTABLE_WELL_KNOWN_KEYS: &[&[u8]] = &[well_known_keys::HEAP_PAGES];
+
 /// A phase of a block's execution.
 #[derive(Encode, Decode, RuntimeDebug, TypeInfo, MaxEncodedLen)]
 #[cfg_attr(feature = "std", derive(Serialize, PartialEq, Eq, Clone))]
@@ -666,6 +666,44 @@ fn runtime_updated_digest_emitted_when_heap_pages_changed() {
 	});
 }
 
+#[test]
+fn set_well_known_storage_rejects_unknown_keys() {
+	new_test_ext().execute_with(|| {
+		System::reset_events();
+		System::initialize(&1, &[0u8; 32].into(), &Default::default());
+
+		// A typo in one of the keys is rejected, and nothing is written.
+		assert_noop!(
+			System::set_well_known_storage(
+				RawOrigin::Root.into(),
+				vec![
+					(well_known_keys::HEAP_PAGES.to_vec(), 5u64.encode()),
+					(b":heappage".to_vec(), 5u64.encode()),
+				],
+			),
+			Error::<Test>::UnknownWellKnownKey
+		);
+
+		// The code can only be set through `set_code`.
+		assert_noop!(
+			System::set_well_known_storage(
+				RawOrigin::Root.into(),
+				vec![(well_known_keys::CODE.to_vec(), vec![1, 2, 3])],
+			),
+			Error::<Test>::UnknownWellKnownKey
+		);
+
+		// Recognized keys are all set at once.
+		assert_ok!(System::set_well_known_storage(
+			RawOrigin::Root.into(),
+			vec![(well_known_keys::HEAP_PAGES.to_vec(), 5u64.encode())],
+		));
+		assert_eq!(storage::unhashed::get_raw(well_known_keys::HEAP_PAGES), Some(5u64.encode()));
+		assert_eq!(storage::unhashed::get_raw(well_known_keys::CODE), None);
+		assert_runtime_updated_digest(1);
+	});
+}
+
 #[test]
 fn ensure_signed_stuff_works() {
 	struct Members;
@@ -691,3 +729,57 @@ fn ensure_signed_stuff_works() {
 		assert_ok!(EnsureSignedBy::<Members, _>::try_origin(successful_origin));
 	}
 }
+
+#[test]
+fn empty_remark_has_non_zero_weight() {
+	use crate::weights::SubstrateWeight;
+
+	// The base cost of `remark` is measured separately from its per-byte cost.
+	assert!(SubstrateWeight::<Test>::remark(0).ref_time() > 0);
+	assert!(<() as WeightInfo>::remark(0).ref_time() > 0);
+	assert!(SubstrateWeight::<Test>::remark(1).any_gt(SubstrateWeight::<Test>::remark(0)));
+}
+
+#[test]
+fn set_storage_weight_assumes_new_keys() {
+	use crate::weights::SubstrateWeight;
+	use frame_support::weights::constants::RocksDbWeight;
+
+	// Every item is charged a full write on top of the per-item cost measured for new-key
+	// insertion, which also covers overwriting existing keys.
+	for i in [1, 10, 1000] {
+		assert!(SubstrateWeight::<Test>::set_storage(i)
+			.all_gte(<Test as Config>::DbWeight::get().writes(i as u64)));
+		assert!(<() as WeightInfo>::set_storage(i).all_gte(RocksDbWeight::get().writes(i as u64)));
+		assert!(<() as WeightInfo>::set_storage(i).any_gt(<() as WeightInfo>::set_storage(i - 1)));
+	}
+}
+
+#[test]
+fn remark_with_topic_is_filterable_by_topic() {
+	new_test_ext().execute_with(|| {
+		const BLOCK_NUMBER: u64 = 1;
+
+		System::reset_events();
+		System::initialize(&BLOCK_NUMBER, &[0u8; 32].into(), &Default::default());
+		System::note_finished_extrinsics();
+
+		let topic = [7u8; 32];
+		assert_ok!(System::remark_with_topic(RuntimeOrigin::signed(1), topic, b"hello".to_vec()));
+		assert_ok!(System::remark_with_event(RuntimeOrigin::signed(2), b"world".to_vec()));
+
+		// The topic is attached to the event as its topic.
+		let event =
+			SysEvent::RemarkedWithTopic { sender: 1, topic, hash: BlakeTwo256::hash(b"hello") };
+		assert_eq!(
+			System::events()[0],
+			EventRecord {
+				phase: Phase::Finalization,
+				event: event.into(),
+				topics: vec![H256::from(topic)]
+			}
+		);
+		// So only the remark with the topic is found by it.
+		assert_eq!(System::event_topics(&H256::from(topic)), vec![(BLOCK_NUMBER, 0)]);
+	});
+}
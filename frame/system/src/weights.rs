@@ -53,8 +53,10 @@ use sp_std::marker::PhantomData;
 pub trait WeightInfo {
 	fn remark(b: u32, ) -> Weight;
 	fn remark_with_event(b: u32, ) -> Weight;
+	fn remark_with_topic(b: u32, ) -> Weight;
 	fn set_heap_pages() -> Weight;
 	fn set_storage(i: u32, ) -> Weight;
+	fn set_well_known_storage(i: u32, ) -> Weight;
 	fn kill_storage(i: u32, ) -> Weight;
 	fn kill_prefix(p: u32, ) -> Weight;
 }
@@ -82,6 +84,16 @@ impl<T: crate::Config> WeightInfo for SubstrateWeight<T> {
 			// Standard Error: 0
 			.saturating_add(Weight::from_parts(1_121, 0).saturating_mul(b.into()))
 	}
+	/// Storage: System EventTopics (r:1 w:1)
+	/// The range of component `b` is `[0, 3932160]`.
+	///
+	/// NOTE: Composed of the benchmarked `remark_with_event` and the `EventTopics` entry of the
+	/// topic, until the `remark_with_topic` benchmark output replaces it.
+	fn remark_with_topic(b: u32, ) -> Weight {
+		Self::remark_with_event(b)
+			.saturating_add(T::DbWeight::get().reads(1_u64))
+			.saturating_add(T::DbWeight::get().writes(1_u64))
+	}
 	/// Storage: System Digest (r:1 w:1)
 	/// Proof Skipped: System Digest (max_values: Some(1), max_size: None, mode: Measured)
 	/// Storage: unknown `0x3a686561707061676573` (r:0 w:1)
@@ -108,6 +120,18 @@ impl<T: crate::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(Weight::from_parts(731_217, 0).saturating_mul(i.into()))
 			.saturating_add(T::DbWeight::get().writes((1_u64).saturating_mul(i.into())))
 	}
+	/// Storage: System Digest (r:1 w:1)
+	/// Proof Skipped: System Digest (max_values: Some(1), max_size: None, mode: Measured)
+	/// Storage: unknown `0x3a686561707061676573` (r:0 w:1)
+	/// Proof Skipped: unknown `0x3a686561707061676573` (r:0 w:1)
+	/// The range of component `i` is `[0, 1000]`.
+	///
+	/// NOTE: Composed of the benchmarked `set_storage` of the items and `set_heap_pages` for
+	/// the digest, until the `set_well_known_storage` benchmark output replaces it.
+	fn set_well_known_storage(i: u32, ) -> Weight {
+		Self::set_storage(i)
+			.saturating_add(Self::set_heap_pages())
+	}
 	/// Storage: Skipped Metadata (r:0 w:0)
 	/// Proof Skipped: Skipped Metadata (max_values: None, max_size: None, mode: Measured)
 	/// The range of component `i` is `[0, 1000]`.
@@ -160,6 +184,16 @@ impl WeightInfo for () {
 			// Standard Error: 0
 			.saturating_add(Weight::from_parts(1_121, 0).saturating_mul(b.into()))
 	}
+	/// Storage: System EventTopics (r:1 w:1)
+	/// The range of component `b` is `[0, 3932160]`.
+	///
+	/// NOTE: Composed of the benchmarked `remark_with_event` and the `EventTopics` entry of the
+	/// topic, until the `remark_with_topic` benchmark output replaces it.
+	fn remark_with_topic(b: u32, ) -> Weight {
+		Self::remark_with_event(b)
+			.saturating_add(RocksDbWeight::get().reads(1_u64))
+			.saturating_add(RocksDbWeight::get().writes(1_u64))
+	}
 	/// Storage: System Digest (r:1 w:1)
 	/// Proof Skipped: System Digest (max_values: Some(1), max_size: None, mode: Measured)
 	/// Storage: unknown `0x3a686561707061676573` (r:0 w:1)
@@ -186,6 +220,18 @@ impl WeightInfo for () {
 			.saturating_add(Weight::from_parts(731_217, 0).saturating_mul(i.into()))
 			.saturating_add(RocksDbWeight::get().writes((1_u64).saturating_mul(i.into())))
 	}
+	/// Storage: System Digest (r:1 w:1)
+	/// Proof Skipped: System Digest (max_values: Some(1), max_size: None, mode: Measured)
+	/// Storage: unknown `0x3a686561707061676573` (r:0 w:1)
+	/// Proof Skipped: unknown `0x3a686561707061676573` (r:0 w:1)
+	/// The range of component `i` is `[0, 1000]`.
+	///
+	/// NOTE: Composed of the benchmarked `set_storage` of the items and `set_heap_pages` for
+	/// the digest, until the `set_well_known_storage` benchmark output replaces it.
+	fn set_well_known_storage(i: u32, ) -> Weight {
+		Self::set_storage(i)
+			.saturating_add(Self::set_heap_pages())
+	}
 	/// Storage: Skipped Metadata (r:0 w:0)
 	/// Proof Skipped: Skipped Metadata (max_values: None, max_size: None, mode: Measured)
 	/// The range of component `i` is `[0, 1000]`.
@@ -34,6 +34,12 @@ pub mod utils;
 /// Prefix for elements stored in the Off-chain DB via Indexing API.
 pub const INDEXING_PREFIX: &'static [u8] = b"mmr";
 
+/// The consensus engine id used for MMR root digest items.
+///
+/// A runtime may deposit its MMR root into the block header digest under this id
+/// (see `DigestItem::Consensus`), so that it's available to light clients.
+pub const MMR_ENGINE_ID: sp_runtime::ConsensusEngineId = *b"MMRR";
+
 /// A type to describe node position in the MMR (node index).
 pub type NodeIndex = u64;
 
@@ -44,6 +50,54 @@ pub type NodeIndex = u64;
 /// but might be actually at different positions in the MMR `NodeIndex`.
 pub type LeafIndex = u64;
 
+/// A strongly-typed [`NodeIndex`].
+///
+/// Unlike the bare [`NodeIndex`] alias it can't be accidentally mixed with a [`TypedLeafIndex`].
+/// See [`utils::NodesUtils`] for conversions between the two.
+#[derive(
+	codec::Encode,
+	codec::Decode,
+	RuntimeDebug,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	TypeInfo,
+)]
+pub struct TypedNodeIndex(pub NodeIndex);
+
+/// A strongly-typed [`LeafIndex`].
+///
+/// Unlike the bare [`LeafIndex`] alias it can't be accidentally mixed with a [`TypedNodeIndex`].
+/// See [`utils::NodesUtils`] for conversions between the two.
+#[derive(
+	codec::Encode,
+	codec::Decode,
+	RuntimeDebug,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	TypeInfo,
+)]
+pub struct TypedLeafIndex(pub LeafIndex);
+
+impl fmt::Display for TypedNodeIndex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl fmt::Display for TypedLeafIndex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
 /// A provider of the MMR's leaf data.
 pub trait LeafDataProvider {
 	/// A type that should end up in the leaf of MMR.
@@ -55,6 +109,16 @@ pub trait LeafDataProvider {
 	/// This is being called by the `on_initialize` method of
 	/// this pallet at the very beginning of each block.
 	fn leaf_data() -> Self::LeafData;
+
+	/// Return the leaf data along with its hash (see [FullLeaf::compact_commitment]), if the
+	/// provider knows it already.
+	///
+	/// Providers which compute the hash anyway can return it here, so that the MMR doesn't
+	/// have to hash the leaf again when appending it. The hash is trusted (it's only checked
+	/// in debug builds), so returning anything else than the leaf's hash corrupts the MMR.
+	fn leaf_data_with_hash<H: traits::Hash>() -> (Self::LeafData, Option<H::Output>) {
+		(Self::leaf_data(), None)
+	}
 }
 
 impl LeafDataProvider for () {
@@ -76,12 +140,107 @@ impl<Hash> OnNewRoot<Hash> for () {
 	fn on_new_root(_root: &Hash) {}
 }
 
+/// MMR growth notification hook.
+pub trait OnAppend {
+	/// Function called by the pallet after nodes have been appended to the MMR, with the new
+	/// number of leaves and the positions of the (ascending) peaks stored on-chain.
+	fn on_append(leaf_count: LeafIndex, peaks: &[NodeIndex]);
+}
+
+/// No-op implementation of [OnAppend].
+impl OnAppend for () {
+	fn on_append(_leaf_count: LeafIndex, _peaks: &[NodeIndex]) {}
+}
+
+/// A post-processing step of the MMR root.
+///
+/// Allows committing to the MMR root together with some extra data (e.g. the next BEEFY
+/// authority set), the result of which is then passed to [OnNewRoot].
+pub trait RootWrapper<Hash> {
+	/// Compute the commitment to the (raw) MMR `root`.
+	fn wrap_root(root: &Hash) -> Hash;
+}
+
+/// Identity implementation of [RootWrapper], committing to the raw MMR root.
+impl<Hash: Clone> RootWrapper<Hash> for () {
+	fn wrap_root(root: &Hash) -> Hash {
+		root.clone()
+	}
+}
+
+/// A transformation of the leaf data (see [LeafDataProvider]) before it's appended to the MMR.
+///
+/// Allows storing (and committing to) something derived from the provided leaf data, e.g.
+/// the leaf data tagged with some extra context.
+pub trait LeafTransform<L> {
+	/// The leaf type actually stored in the MMR.
+	type Leaf: FullLeaf + codec::Decode;
+
+	/// Transform the provided `leaf` data into the stored leaf.
+	fn transform(leaf: L) -> Self::Leaf;
+
+	/// Transform the provided `leaf` data along with its (optional) precomputed hash.
+	///
+	/// The hash of the provided data generally doesn't match the transformed leaf, so it's
+	/// discarded by default. Transformations preserving the leaf commitment may keep it.
+	fn transform_with_hash<H: traits::Hash>(
+		leaf: L,
+		_hash: Option<H::Output>,
+	) -> (Self::Leaf, Option<H::Output>) {
+		(Self::transform(leaf), None)
+	}
+}
+
+/// Identity implementation of [LeafTransform], storing the provided leaf data as is.
+impl<L: FullLeaf + codec::Decode> LeafTransform<L> for () {
+	type Leaf = L;
+
+	fn transform(leaf: L) -> Self::Leaf {
+		leaf
+	}
+
+	fn transform_with_hash<H: traits::Hash>(
+		leaf: L,
+		hash: Option<H::Output>,
+	) -> (Self::Leaf, Option<H::Output>) {
+		(leaf, hash)
+	}
+}
+
+/// A policy deciding whether a leaf can be appended to the MMR.
+///
+/// Allows runtimes to veto the inclusion of leaves based on their content, e.g. their size or
+/// some validity predicate.
+pub trait LeafValidator<L> {
+	/// Check whether `leaf` can be appended to the MMR, returning the reason if it can't.
+	fn validate(leaf: &L) -> Result<(), sp_runtime::DispatchError>;
+}
+
+/// Accept all the leaves.
+impl<L> LeafValidator<L> for () {
+	fn validate(_leaf: &L) -> Result<(), sp_runtime::DispatchError> {
+		Ok(())
+	}
+}
+
 /// A full leaf content stored in the offchain-db.
 pub trait FullLeaf: Clone + PartialEq + fmt::Debug {
 	/// Encode the leaf either in its full or compact form.
 	///
 	/// NOTE the encoding returned here MUST be `Decode`able into `FullLeaf`.
 	fn using_encoded<R, F: FnOnce(&[u8]) -> R>(&self, f: F, compact: bool) -> R;
+
+	/// Compute the commitment to the leaf, i.e. its hash in the MMR.
+	///
+	/// By default this is the hash of the compact encoding of the leaf. Note SCALE-encodable
+	/// types get the default through the blanket implementation, so their commitment can only
+	/// be changed by wrapping them in a [CommittedLeaf]. Leaf types defining their own
+	/// commitment implement [CompactCommitment] instead, and are wrapped with [OwnCommitment].
+	///
+	/// NOTE the commitment of leaves passed as [OpaqueLeaf] is always the hash of their bytes.
+	fn compact_commitment<H: traits::Hash>(&self) -> H::Output {
+		self.using_encoded(<H as traits::Hash>::hash, true)
+	}
 }
 
 impl<T: codec::Encode + codec::Decode + Clone + PartialEq + fmt::Debug> FullLeaf for T {
@@ -90,6 +249,100 @@ impl<T: codec::Encode + codec::Decode + Clone + PartialEq + fmt::Debug> FullLeaf
 	}
 }
 
+/// A way of committing to the leaves of type `L`, i.e. of computing their hash in the MMR.
+///
+/// Leaves meant to be verified outside of Substrate (e.g. by an EVM contract) can be committed
+/// to by hashing a different preimage than their SCALE encoding (like an ABI-encoded one), that's
+/// cheaper to reconstruct for such verifiers.
+pub trait LeafCommitment<L> {
+	/// Compute the commitment to the `leaf`.
+	fn commit<H: traits::Hash>(leaf: &L) -> H::Output;
+}
+
+/// Commit to the leaves with their own commitment (see [FullLeaf::compact_commitment]).
+impl<L: FullLeaf> LeafCommitment<L> for () {
+	fn commit<H: traits::Hash>(leaf: &L) -> H::Output {
+		leaf.compact_commitment::<H>()
+	}
+}
+
+/// A leaf type defining its own commitment, e.g. to emit a verifier-friendly preimage rather
+/// than its SCALE encoding.
+///
+/// Unlike [FullLeaf], this trait is not implemented for all the SCALE-encodable types, so
+/// every leaf type can override [CompactCommitment::compact_commitment]. The leaves are
+/// committed to with it if [OwnCommitment] is their [LeafCommitment].
+pub trait CompactCommitment: FullLeaf {
+	/// Compute the commitment to the leaf, i.e. its hash in the MMR.
+	///
+	/// Defaults to the hash of the compact encoding of the leaf.
+	fn compact_commitment<H: traits::Hash>(&self) -> H::Output {
+		self.using_encoded(<H as traits::Hash>::hash, true)
+	}
+}
+
+/// Commit to the leaves with the commitment they define (see [CompactCommitment]).
+pub struct OwnCommitment;
+
+impl<L: CompactCommitment> LeafCommitment<L> for OwnCommitment {
+	fn commit<H: traits::Hash>(leaf: &L) -> H::Output {
+		CompactCommitment::compact_commitment::<H>(leaf)
+	}
+}
+
+/// A leaf committed to with the [LeafCommitment] `C`, rather than with its own commitment.
+///
+/// The wrapped leaf is encoded (and decoded) as is, so the wrapper only changes the hash of the
+/// leaf in the MMR. Stateless verifiers can be given the leaves wrapped the same way to verify
+/// proofs of an MMR committing to them with `C`.
+pub struct CommittedLeaf<L, C>(pub L, sp_std::marker::PhantomData<C>);
+
+impl<L, C> CommittedLeaf<L, C> {
+	/// Wrap the `leaf`.
+	pub fn new(leaf: L) -> Self {
+		Self(leaf, Default::default())
+	}
+
+	/// Return the wrapped leaf.
+	pub fn into_inner(self) -> L {
+		self.0
+	}
+}
+
+impl<L: Clone, C> Clone for CommittedLeaf<L, C> {
+	fn clone(&self) -> Self {
+		Self::new(self.0.clone())
+	}
+}
+
+impl<L: PartialEq, C> PartialEq for CommittedLeaf<L, C> {
+	fn eq(&self, other: &Self) -> bool {
+		self.0 == other.0
+	}
+}
+
+impl<L: fmt::Debug, C> fmt::Debug for CommittedLeaf<L, C> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl<L: codec::Decode, C> codec::Decode for CommittedLeaf<L, C> {
+	fn decode<In: codec::Input>(input: &mut In) -> Result<Self, codec::Error> {
+		L::decode(input).map(Self::new)
+	}
+}
+
+impl<L: FullLeaf, C: LeafCommitment<L>> FullLeaf for CommittedLeaf<L, C> {
+	fn using_encoded<R, F: FnOnce(&[u8]) -> R>(&self, f: F, compact: bool) -> R {
+		self.0.using_encoded(f, compact)
+	}
+
+	fn compact_commitment<H: traits::Hash>(&self) -> H::Output {
+		C::commit::<H>(&self.0)
+	}
+}
+
 /// A helper type to allow using arbitrary SCALE-encoded leaf data in the RuntimeApi.
 ///
 /// The point is to be able to verify MMR proofs from external MMRs, where we don't
@@ -174,6 +427,14 @@ impl EncodableOpaqueLeaf {
 /// [DataOrHash::hash] method calculates the hash of this element in its compact form,
 /// so should be used instead of hashing the encoded form (which will always be non-compact).
 #[derive(RuntimeDebug, Clone, PartialEq)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+	feature = "std",
+	serde(bound(
+		serialize = "L: serde::Serialize, H::Output: serde::Serialize",
+		deserialize = "L: serde::Deserialize<'de>, H::Output: serde::Deserialize<'de>",
+	))
+)]
 pub enum DataOrHash<H: traits::Hash, L> {
 	/// Arbitrary data in its full form.
 	Data(L),
@@ -224,13 +485,42 @@ impl<H: traits::Hash, L: FullLeaf> DataOrHash<H, L> {
 	/// Retrieve a hash of this item.
 	///
 	/// Depending on the node type it's going to either be a contained value for [DataOrHash::Hash]
-	/// node, or a hash of SCALE-encoded [DataOrHash::Data] data.
+	/// node, or the commitment to [DataOrHash::Data] data (see [FullLeaf::compact_commitment]).
 	pub fn hash(&self) -> H::Output {
 		match *self {
-			Self::Data(ref leaf) => leaf.using_encoded(<H as traits::Hash>::hash, true),
+			Self::Data(ref leaf) => leaf.compact_commitment::<H>(),
 			Self::Hash(ref hash) => *hash,
 		}
 	}
+
+	/// Replace the data with its hash, keeping it hidden.
+	///
+	/// The commitment of a [Compact] leaf doesn't change when some of its elements are
+	/// concealed, so its proof can still be verified with the remaining elements revealed.
+	pub fn conceal(&self) -> Self {
+		Self::Hash(self.hash())
+	}
+}
+
+/// A leaf element committed to together with a random `salt`.
+///
+/// Meant to be used as an element of a [Compact] leaf, which can then be revealed only
+/// partially (see [DataOrHash::conceal]). Without the salt, the hash of a concealed
+/// low-entropy element (like an amount or an account) could be easily brute-forced.
+/// The salt has to be kept secret until the element is revealed.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, PartialEq, Eq, TypeInfo)]
+pub struct Salted<T> {
+	/// The random salt.
+	pub salt: [u8; 32],
+	/// The committed value.
+	pub value: T,
+}
+
+impl<T> Salted<T> {
+	/// Commit to the `value` with given `salt`.
+	pub fn new(salt: [u8; 32], value: T) -> Self {
+		Self { salt, value }
+	}
 }
 
 /// A composition of multiple leaf elements with compact form representation.
@@ -244,10 +534,21 @@ impl<H: traits::Hash, L: FullLeaf> DataOrHash<H, L> {
 /// into [DataOrHash] and each tuple element is hashed first before constructing
 /// the final hash of the entire tuple. This allows you to replace tuple elements
 /// you don't care about with their hashes.
+///
+/// With `std`, it's (de)serialized with `serde` as the bare tuple.
 #[derive(RuntimeDebug, Clone, PartialEq)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+	feature = "std",
+	serde(
+		transparent,
+		bound(serialize = "T: serde::Serialize", deserialize = "T: serde::Deserialize<'de>")
+	)
+)]
 pub struct Compact<H, T> {
 	/// Internal tuple representation.
 	pub tuple: T,
+	#[cfg_attr(feature = "std", serde(skip))]
 	_hash: sp_std::marker::PhantomData<H>,
 }
 
@@ -349,7 +650,13 @@ impl_leaf_data_for_tuple!(A:0, B:1, C:2, D:3);
 impl_leaf_data_for_tuple!(A:0, B:1, C:2, D:3, E:4);
 
 /// An MMR proof data for a group of leaves.
+///
+/// With `std`, the proof can also be (de)serialized with `serde`, e.g. to be sent over JSON-RPC.
+/// The leaf indices and count are represented as numbers, and the items in their `serde`
+/// representation (which is `0x`-prefixed hex for the usual hash types).
 #[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, PartialEq, Eq, TypeInfo)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
 pub struct Proof<Hash> {
 	/// The indices of the leaves the proof is for.
 	pub leaf_indices: Vec<LeafIndex>,
@@ -359,6 +666,260 @@ pub struct Proof<Hash> {
 	pub items: Vec<Hash>,
 }
 
+/// An MMR [Proof] tagged with the version of its format.
+///
+/// The version is encoded as the first byte, so that verifiers can keep accepting proofs
+/// produced by older runtimes after the proof format evolves.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, PartialEq, Eq, TypeInfo)]
+pub enum VersionedProof<Hash> {
+	/// The initial proof format.
+	#[codec(index = 0)]
+	V0(Proof<Hash>),
+}
+
+impl<Hash> VersionedProof<Hash> {
+	/// The latest version of the proof format.
+	pub const LATEST_VERSION: u8 = 0;
+
+	/// Return the version of the proof format.
+	pub fn version(&self) -> u8 {
+		match self {
+			Self::V0(_) => 0,
+		}
+	}
+}
+
+impl<Hash> From<Proof<Hash>> for VersionedProof<Hash> {
+	fn from(proof: Proof<Hash>) -> Self {
+		Self::V0(proof)
+	}
+}
+
+/// The size of an MMR, expressed either as the number of its leaves or of all its nodes.
+///
+/// Proofs carry the number of leaves, but some verifiers only know the number of nodes (the
+/// `mmr_size`). See [utils::NodesUtils] for conversions between the two.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, Copy, PartialEq, Eq, TypeInfo)]
+pub enum MmrSize {
+	/// Number of leaves in the MMR.
+	LeafCount(LeafIndex),
+	/// Number of nodes (leaves and inner nodes) in the MMR.
+	NodeCount(NodeIndex),
+}
+
+impl MmrSize {
+	/// Return the number of leaves in the MMR of this size.
+	///
+	/// Returns `None` if the number of nodes is not a valid MMR size.
+	pub fn leaf_count(&self) -> Option<LeafIndex> {
+		match *self {
+			Self::LeafCount(leaf_count) => Some(leaf_count),
+			Self::NodeCount(size) =>
+				utils::NodesUtils::from_size(size).map(|utils| utils.number_of_leaves()),
+		}
+	}
+}
+
+/// An MMR proof that a prior MMR is a prefix of the current one.
+///
+/// The current MMR has been obtained by only appending leaves to the prior one, if its peaks can
+/// be reconstructed from the peaks of the prior MMR and the proof `items`.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, PartialEq, Eq, TypeInfo)]
+pub struct AncestryProof<Hash> {
+	/// Peaks of the prior MMR.
+	pub prev_peaks: Vec<Hash>,
+	/// Number of leaves in the prior MMR.
+	pub prev_leaf_count: LeafIndex,
+	/// Number of leaves in the current MMR, when the proof was generated.
+	pub leaf_count: LeafIndex,
+	/// Positions and hashes of the roots of the subtrees only containing leaves appended since
+	/// the prior MMR.
+	pub items: Vec<(NodeIndex, Hash)>,
+}
+
+/// An MMR proof for a contiguous range of leaves.
+///
+/// Only the nodes anchoring the whole range to the root are included, the nodes derivable from
+/// the leaves of the range are recomputed by the verifier. The leaf indices are implied by the
+/// bounds of the range, see [Self::leaf_indices].
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, PartialEq, Eq, TypeInfo)]
+pub struct RangeProof<Hash> {
+	/// The index of the first leaf of the range.
+	pub first_leaf_index: LeafIndex,
+	/// The index of the last leaf of the range (inclusive).
+	pub last_leaf_index: LeafIndex,
+	/// Number of leaves in MMR, when the proof was generated.
+	pub leaf_count: NodeIndex,
+	/// Proof elements (hashes of the nodes anchoring the range to the root).
+	pub items: Vec<Hash>,
+}
+
+impl<Hash> RangeProof<Hash> {
+	/// The indices of the leaves the proof is for.
+	pub fn leaf_indices(&self) -> Vec<LeafIndex> {
+		(self.first_leaf_index..=self.last_leaf_index).collect()
+	}
+}
+
+impl<Hash> From<RangeProof<Hash>> for Proof<Hash> {
+	fn from(proof: RangeProof<Hash>) -> Self {
+		Proof {
+			leaf_indices: proof.leaf_indices(),
+			leaf_count: proof.leaf_count,
+			items: proof.items,
+		}
+	}
+}
+
+/// The encoding of the leaves written to the Off-chain DB.
+///
+/// The default [`LeafEncoding::Scale`] stores the SCALE-encoded leaf as is, which is the most
+/// compact. [`LeafEncoding::FixedWidth`] zero-pads every leaf to the same width, so that an
+/// external DB ingesting the leaves can lay them out as fixed-size records and append them (or
+/// seek to a given leaf) without decoding anything. The padding wastes space for leaves shorter
+/// than the width, and leaves longer than it can't be stored at all, so the width should be the
+/// maximum encoded size of the leaf type. Inner nodes are never padded.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, Copy, PartialEq, Eq, TypeInfo)]
+pub enum LeafEncoding {
+	/// The SCALE-encoded leaf, as is.
+	Scale,
+	/// The SCALE-encoded leaf, zero-padded to the given number of bytes.
+	FixedWidth(u32),
+}
+
+impl Default for LeafEncoding {
+	fn default() -> Self {
+		LeafEncoding::Scale
+	}
+}
+
+impl LeafEncoding {
+	/// Check that a leaf encoded into `len` bytes can be stored with this encoding.
+	pub fn check(&self, len: usize) -> Result<(), Error> {
+		match *self {
+			LeafEncoding::FixedWidth(width) if len > width as usize =>
+				Err(Error::LeafTooLarge { size: len as u32, width }),
+			_ => Ok(()),
+		}
+	}
+
+	/// Pad the `encoded` leaf to the width of this encoding.
+	///
+	/// Leaves exceeding the width are left untouched, they should be rejected with
+	/// [`Self::check`] beforehand.
+	pub fn pad(&self, encoded: &mut Vec<u8>) {
+		if let LeafEncoding::FixedWidth(width) = *self {
+			if encoded.len() < width as usize {
+				encoded.resize(width as usize, 0);
+			}
+		}
+	}
+}
+
+/// A fingerprint of the schema of a leaf type, see [leaf_schema_fingerprint].
+pub type LeafSchemaFingerprint = [u8; 8];
+
+/// Return the fingerprint of the schema of the leaf type `L`.
+///
+/// The fingerprint covers the whole type layout of `L`, e.g. the order of the leaf data of
+/// composed [LeafDataProvider]s, so any change of the leaf format (including reordering the
+/// providers) changes the fingerprint.
+pub fn leaf_schema_fingerprint<L: TypeInfo + 'static>() -> LeafSchemaFingerprint {
+	let mut registry = scale_info::Registry::new();
+	registry.register_type(&scale_info::meta_type::<L>());
+	let registry: scale_info::PortableRegistry = registry.into();
+	sp_core::hashing::twox_64(&codec::Encode::encode(&registry))
+}
+
+/// Check that the leaf type `L` matches the declared `schema`, i.e. a version of the leaf
+/// schema along with the fingerprint of the leaf type at that version.
+///
+/// Changing the leaf format silently changes the hashes of all the future leaves, so the check
+/// fails with [Error::LeafSchemaMismatch] until the declared schema is updated (and its version
+/// bumped) explicitly.
+pub fn check_leaf_schema<L: TypeInfo + 'static>(
+	schema: (u32, LeafSchemaFingerprint),
+) -> Result<(), Error> {
+	let (version, fingerprint) = schema;
+	if leaf_schema_fingerprint::<L>() != fingerprint {
+		return Err(Error::LeafSchemaMismatch { version })
+	}
+	Ok(())
+}
+
+/// The version of the [PortableMmr] format.
+pub const PORTABLE_MMR_VERSION: u8 = 0;
+
+/// A self-describing export of a range of the leaves of an MMR, to move them between MMR
+/// implementations.
+///
+/// Only plain data is used, so the SCALE encoding of this structure is stable regardless of the
+/// leaf type: the leaves are kept SCALE-encoded as opaque bytes, and the nodes anchoring them to
+/// the `root` are the items of a [RangeProof]. The hashing of the MMR is identified by the hash
+/// of the empty input.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, PartialEq, Eq, TypeInfo)]
+pub struct PortableMmr<Hash> {
+	/// The version of the format, see [PORTABLE_MMR_VERSION].
+	pub version: u8,
+	/// The hash of the empty input, identifying the hashing of the MMR.
+	pub hashing: Hash,
+	/// Number of leaves in MMR, when the export was made.
+	pub leaf_count: LeafIndex,
+	/// The index of the first exported leaf.
+	pub first_leaf_index: LeafIndex,
+	/// The SCALE-encoded leaves, in order.
+	pub leaves: Vec<Vec<u8>>,
+	/// The hashes of the nodes anchoring the leaves to the root (see [RangeProof::items]).
+	pub items: Vec<Hash>,
+	/// The expected root of the MMR.
+	pub root: Hash,
+}
+
+/// Statistics of the Off-chain DB accesses performed while generating proofs.
+#[derive(RuntimeDebug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProofGenStats {
+	/// Number of values read from the Off-chain DB.
+	pub offchain_reads: u32,
+	/// Number of nodes served from the cache, instead of the Off-chain DB.
+	pub cache_hits: u32,
+	/// Number of hashes of the blocks that added the nodes looked up.
+	pub block_hash_reads: u32,
+}
+
+/// A view of a specific fork of the chain, used to read the Off-chain DB nodes of that fork.
+///
+/// Maps leaf indices to the parent hash of the block which added the leaf on that fork.
+/// The nodes added by leaves missing from the view are looked up on the canonical chain.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, PartialEq, Eq, TypeInfo)]
+pub struct ForkView<Hash> {
+	/// Parent hashes of the blocks which added the leaves, by leaf index.
+	pub parent_hashes: sp_std::collections::btree_map::BTreeMap<LeafIndex, Hash>,
+}
+
+impl<Hash> Default for ForkView<Hash> {
+	fn default() -> Self {
+		Self { parent_hashes: Default::default() }
+	}
+}
+
+impl<Hash: Copy> ForkView<Hash> {
+	/// Return the parent hash of the block which added `leaf_index` on this fork, if known.
+	pub fn parent_hash(&self, leaf_index: LeafIndex) -> Option<Hash> {
+		self.parent_hashes.get(&leaf_index).copied()
+	}
+}
+
+/// A hint describing the leaves an MMR proof verifier already knows.
+///
+/// Authentication nodes which can be derived from these leaves are omitted from the generated
+/// proof; the verifier is expected to supply the known leaves during verification instead, so
+/// that these nodes can be reconstructed.
+#[derive(codec::Encode, codec::Decode, RuntimeDebug, Clone, Default, PartialEq, Eq, TypeInfo)]
+pub struct CompressionHint {
+	/// The indices of the leaves known to the verifier.
+	pub known_leaf_indices: Vec<LeafIndex>,
+}
+
 /// Merkle Mountain Range operation error.
 #[cfg_attr(feature = "std", derive(thiserror::Error))]
 #[derive(RuntimeDebug, codec::Encode, codec::Decode, PartialEq, Eq, TypeInfo)]
@@ -393,6 +954,87 @@ pub enum Error {
 	/// The provided best know block number is invalid.
 	#[cfg_attr(feature = "std", error("Provided best known block number invalid"))]
 	InvalidBestKnownBlock,
+	/// The header does not contain an MMR root digest item.
+	#[cfg_attr(feature = "std", error("MMR root digest not found in header"))]
+	MissingRootDigest,
+	/// The hash of the block that added the requested node is not known (e.g. pruned).
+	#[cfg_attr(feature = "std", error("Hash of block {block_num} is unknown"))]
+	UnknownAncestorHash {
+		/// The number of the block whose hash is unknown.
+		block_num: u64,
+	},
+	/// A node required for the proof was added by a leaf which was never indexed off-chain.
+	#[cfg_attr(feature = "std", error("Leaf {leaf_index} was not indexed in the Off-chain DB"))]
+	LeafNotIndexed {
+		/// The index of the leaf which was not indexed.
+		leaf_index: LeafIndex,
+	},
+	/// A node read from the Off-chain DB does not match its checksum (i.e. it's corrupted).
+	#[cfg_attr(feature = "std", error("Checksum mismatch of a node read from the Off-chain DB"))]
+	OffchainChecksumMismatch,
+	/// The proof format version is not supported by the verifier.
+	#[cfg_attr(feature = "std", error("Proof version {version} is not supported"))]
+	UnsupportedProofVersion {
+		/// The version of the proof format.
+		version: u8,
+	},
+	/// A leaf read from the Off-chain DB has a different type than expected.
+	#[cfg_attr(feature = "std", error("Leaf read from the Off-chain DB has unexpected type"))]
+	LeafTypeMismatch,
+	/// The parent hash of the current block is not known, so the new nodes can't be indexed
+	/// off-chain under a meaningful key.
+	#[cfg_attr(feature = "std", error("Parent hash of the current block is unknown"))]
+	UnknownParentHash,
+	/// The requested leaf index is not part of the MMR.
+	#[cfg_attr(feature = "std", error("Leaf index {requested} out of range of {count} leaves"))]
+	LeafIndexOutOfRange {
+		/// The requested leaf index.
+		requested: LeafIndex,
+		/// The number of leaves in the MMR.
+		count: LeafIndex,
+	},
+	/// Generating the proof would read more nodes than a proof can possibly contain (e.g.
+	/// because the number of leaves is corrupted).
+	#[cfg_attr(feature = "std", error("Proof generation would read too many nodes"))]
+	ReadLimitExceeded,
+	/// Generating the proof took longer than the given deadline.
+	#[cfg_attr(
+		feature = "std",
+		error("Proof generation timed out after {offchain_reads} Off-chain DB reads")
+	)]
+	ProofGenerationTimeout {
+		/// The number of values read from the Off-chain DB before timing out.
+		offchain_reads: u32,
+	},
+	/// A leaf doesn't fit the fixed width of the Off-chain DB values (see [`LeafEncoding`]).
+	#[cfg_attr(feature = "std", error("Leaf of {size} bytes exceeds the width of {width} bytes"))]
+	LeafTooLarge {
+		/// The size of the encoded leaf.
+		size: u32,
+		/// The fixed width of the leaves.
+		width: u32,
+	},
+	/// More leaves would be appended in a single block than allowed.
+	#[cfg_attr(feature = "std", error("Too many leaves appended in a single block"))]
+	TooManyLeavesInBlock,
+	/// The leaf type doesn't match the declared leaf schema (see [check_leaf_schema]).
+	#[cfg_attr(feature = "std", error("Leaf type doesn't match the schema version {version}"))]
+	LeafSchemaMismatch {
+		/// The declared version of the leaf schema.
+		version: u32,
+	},
+	/// The proof is for more leaves than the verifier trusts (i.e. for a state it hasn't
+	/// verified).
+	#[cfg_attr(
+		feature = "std",
+		error("Proof for {leaf_count} leaves exceeds the trusted count of {trusted}")
+	)]
+	UntrustedLeafCount {
+		/// The number of leaves the proof was generated for.
+		leaf_count: LeafIndex,
+		/// The number of leaves trusted by the verifier.
+		trusted: LeafIndex,
+	},
 }
 
 impl Error {
@@ -542,6 +1184,91 @@ mod tests {
 		assert_eq!(b, hex("c3e7ba6b511162fead58f2c8b5764ce869ed1118011ac37392522ed16720bbcd"));
 	}
 
+	#[test]
+	fn should_use_custom_leaf_commitment() {
+		/// Commits to `(block_number, parent_hash)` as
+		/// `keccak256(abi.encode(uint256(block_number), parent_hash))`.
+		struct AbiCommitment;
+
+		impl LeafCommitment<(u32, H256)> for AbiCommitment {
+			fn commit<H: traits::Hash>((block_number, parent_hash): &(u32, H256)) -> H::Output {
+				let mut preimage = [0u8; 64];
+				preimage[28..32].copy_from_slice(&block_number.to_be_bytes());
+				preimage[32..].copy_from_slice(parent_hash.as_bytes());
+				<H as traits::Hash>::hash(&preimage)
+			}
+		}
+
+		// given
+		let leaf = (5u32, H256::repeat_byte(0x11));
+		let committed = CommittedLeaf::<_, AbiCommitment>::new(leaf);
+
+		// when
+		let commitment = DataOrHash::<Keccak256, _>::Data(committed.clone()).hash();
+
+		// then the ABI preimage is hashed instead of the SCALE encoding
+		assert_eq!(
+			commitment,
+			hex("f3308d64dd30200a981fbb01272a1f05b4cfb7ca1b636aed03d9bc7474b03f9e")
+		);
+		assert_eq!(committed.using_encoded(|e| e.to_vec(), true), codec::Encode::encode(&leaf));
+		// while the leaf itself is still committed to by its encoding
+		let scale_commitment =
+			hex("b91ba8b2334c45357003dc961afe9b73a5c70d4c979de89315a65851d4256a0c");
+		assert_eq!(DataOrHash::<Keccak256, _>::Data(leaf).hash(), scale_commitment);
+		assert_eq!(
+			DataOrHash::<Keccak256, _>::Data(CommittedLeaf::<_, ()>::new(leaf)).hash(),
+			scale_commitment
+		);
+	}
+
+	#[test]
+	fn should_use_commitment_defined_by_leaf() {
+		/// Commits to itself as `keccak256(abi.encode(uint256(block_number), parent_hash))`.
+		#[derive(codec::Encode, codec::Decode, Clone, PartialEq, Debug)]
+		struct AbiLeaf {
+			block_number: u32,
+			parent_hash: H256,
+		}
+
+		impl CompactCommitment for AbiLeaf {
+			fn compact_commitment<H: traits::Hash>(&self) -> H::Output {
+				let mut preimage = [0u8; 64];
+				preimage[28..32].copy_from_slice(&self.block_number.to_be_bytes());
+				preimage[32..].copy_from_slice(self.parent_hash.as_bytes());
+				<H as traits::Hash>::hash(&preimage)
+			}
+		}
+
+		/// Keeps the default commitment.
+		#[derive(codec::Encode, codec::Decode, Clone, PartialEq, Debug)]
+		struct ScaleLeaf(u32, H256);
+
+		impl CompactCommitment for ScaleLeaf {}
+
+		// given
+		let leaf = AbiLeaf { block_number: 5, parent_hash: H256::repeat_byte(0x11) };
+		let committed = CommittedLeaf::<_, OwnCommitment>::new(leaf.clone());
+
+		// when
+		let commitment = DataOrHash::<Keccak256, _>::Data(committed).hash();
+
+		// then the ABI preimage is hashed, same as with a `LeafCommitment` of the MMR
+		assert_eq!(
+			commitment,
+			hex("f3308d64dd30200a981fbb01272a1f05b4cfb7ca1b636aed03d9bc7474b03f9e")
+		);
+		// while the leaf itself is still committed to by its encoding
+		assert_ne!(DataOrHash::<Keccak256, _>::Data(leaf).hash(), commitment);
+		// and the default commitment is the one of the encoding
+		let leaf = ScaleLeaf(5, H256::repeat_byte(0x11));
+		assert_eq!(
+			DataOrHash::<Keccak256, _>::Data(CommittedLeaf::<_, OwnCommitment>::new(leaf.clone()))
+				.hash(),
+			DataOrHash::<Keccak256, _>::Data(leaf).hash()
+		);
+	}
+
 	#[test]
 	fn compact_should_work() {
 		// given
@@ -639,4 +1366,111 @@ mod tests {
 		assert_eq!(decoded[0], Ok(case2));
 		assert_eq!(decoded[1], Ok(case1));
 	}
+
+	#[test]
+	fn proof_should_be_serde_compatible() {
+		// given
+		let proof = Proof {
+			leaf_indices: vec![0, 5],
+			leaf_count: 7,
+			items: vec![
+				hex("c3e7ba6b1b5a5e8bbb4e7a5bba1e9f4d8e3c1b8d2a1e6f3d4c5b6a7980e1f2a3"),
+				hex("0000000000000000000000000000000000000000000000000000000000000001"),
+			],
+		};
+
+		// when
+		let json = serde_json::to_string(&proof).unwrap();
+
+		// then the format is stable
+		assert_eq!(
+			json,
+			concat!(
+				r#"{"leafIndices":[0,5],"leafCount":7,"items":["#,
+				r#""0xc3e7ba6b1b5a5e8bbb4e7a5bba1e9f4d8e3c1b8d2a1e6f3d4c5b6a7980e1f2a3","#,
+				r#""0x0000000000000000000000000000000000000000000000000000000000000001"]}"#,
+			)
+		);
+		// and the proof round-trips
+		assert_eq!(serde_json::from_str::<Proof<H256>>(&json).unwrap(), proof);
+	}
+
+	#[test]
+	fn compact_leaf_should_be_serde_compatible() {
+		// given
+		let a = Test::Data("Hello World!".into());
+		let b = Test::Hash(H256::repeat_byte(1));
+		let c: TestCompact = Compact::new((a, b));
+
+		// when
+		let json = serde_json::to_string(&c).unwrap();
+
+		// then the leaf is represented as the bare tuple
+		assert_eq!(
+			json,
+			concat!(
+				r#"[{"Data":"Hello World!"},"#,
+				r#"{"Hash":"0x0101010101010101010101010101010101010101"#,
+				r#"010101010101010101010101"}]"#,
+			)
+		);
+		// and round-trips
+		assert_eq!(serde_json::from_str::<TestCompact>(&json).unwrap(), c);
+	}
+
+	#[test]
+	fn reordering_leaf_data_providers_should_fail_the_schema_check() {
+		struct Number;
+		impl LeafDataProvider for Number {
+			type LeafData = u64;
+			fn leaf_data() -> Self::LeafData {
+				1
+			}
+		}
+		struct Tag;
+		impl LeafDataProvider for Tag {
+			type LeafData = u32;
+			fn leaf_data() -> Self::LeafData {
+				2
+			}
+		}
+		type LeafOf<P> = <P as LeafDataProvider>::LeafData;
+
+		// given the schema declared for the providers in a given order
+		let schema = (1, leaf_schema_fingerprint::<LeafOf<(Number, Tag)>>());
+		assert_eq!(check_leaf_schema::<LeafOf<(Number, Tag)>>(schema), Ok(()));
+
+		// when the providers are reordered without updating the schema
+		let result = check_leaf_schema::<LeafOf<(Tag, Number)>>(schema);
+
+		// then the check fails
+		assert_eq!(result, Err(Error::LeafSchemaMismatch { version: 1 }));
+		// until a new schema is declared
+		let schema = (2, leaf_schema_fingerprint::<LeafOf<(Tag, Number)>>());
+		assert_eq!(check_leaf_schema::<LeafOf<(Tag, Number)>>(schema), Ok(()));
+	}
+
+	#[test]
+	fn fixed_width_leaf_encoding_should_pad_and_reject_oversized_leaves() {
+		let encoding = LeafEncoding::FixedWidth(8);
+
+		let mut leaf = vec![1, 2, 3];
+		assert_eq!(encoding.check(leaf.len()), Ok(()));
+		encoding.pad(&mut leaf);
+		assert_eq!(leaf, vec![1, 2, 3, 0, 0, 0, 0, 0]);
+
+		// a leaf exactly the width is kept as is
+		let mut leaf = vec![7; 8];
+		assert_eq!(encoding.check(leaf.len()), Ok(()));
+		encoding.pad(&mut leaf);
+		assert_eq!(leaf, vec![7; 8]);
+
+		assert_eq!(encoding.check(9), Err(Error::LeafTooLarge { size: 9, width: 8 }));
+
+		// the SCALE encoding stores leaves as is
+		let mut leaf = vec![1, 2, 3];
+		assert_eq!(LeafEncoding::Scale.check(usize::MAX), Ok(()));
+		LeafEncoding::Scale.pad(&mut leaf);
+		assert_eq!(leaf, vec![1, 2, 3]);
+	}
 }
@@ -24,7 +24,7 @@ use sp_runtime::traits::{CheckedAdd, CheckedSub, Header, One};
 #[cfg(not(feature = "std"))]
 use sp_std::prelude::Vec;
 
-use crate::{Error, LeafIndex, NodeIndex};
+use crate::{Error, LeafIndex, NodeIndex, TypedLeafIndex, TypedNodeIndex};
 
 /// Get the first block with MMR.
 pub fn first_mmr_block_num<H: Header>(
@@ -70,11 +70,64 @@ impl NodesUtils {
 		Self { no_of_leaves }
 	}
 
+	/// Create new instance of MMR nodes utilities for the MMR with given `size` (number of nodes).
+	///
+	/// Returns `None` if `size` is not a valid MMR size.
+	pub fn from_size(size: NodeIndex) -> Option<Self> {
+		// The size is `2 * leaves - peaks` and there can't be more than 64 peaks.
+		let min_leaves = size / 2;
+		(min_leaves..=min_leaves.saturating_add(32))
+			.find(|leaves| {
+				leaves.checked_mul(2).map(|double| double - leaves.count_ones() as NodeIndex) ==
+					Some(size)
+			})
+			.map(Self::new)
+	}
+
+	/// Return the number of leaves of the MMR with given `size` (number of nodes).
+	///
+	/// This is the inverse of [`Self::size`], useful to validate externally supplied MMR sizes.
+	/// Returns `None` if `size` is not a valid MMR size.
+	pub fn leaf_count_from_size(size: NodeIndex) -> Option<LeafIndex> {
+		Self::from_size(size).map(|utils| utils.number_of_leaves())
+	}
+
 	/// Calculate number of peaks in the MMR.
 	pub fn number_of_peaks(&self) -> NodeIndex {
 		self.number_of_leaves().count_ones() as NodeIndex
 	}
 
+	/// Return `true` if the leaf appended to an MMR with `leaf_count` leaves stays a standalone
+	/// peak, i.e. a new mountain is created instead of merging the leaf into existing ones.
+	///
+	/// Appending a leaf merges it with as many peaks as there are trailing ones in the binary
+	/// representation of `leaf_count`, so new mountains are created at even leaf counts.
+	pub fn is_peak_creation_leaf(leaf_count: LeafIndex) -> bool {
+		leaf_count % 2 == 0
+	}
+
+	/// Return `true` if node `pos` is a peak of the MMR of given (valid) `size`.
+	///
+	/// Equivalent to looking `pos` up in [`helper::get_peaks`], without collecting all the
+	/// peaks: the peaks are the roots of the largest perfect trees fitting the remaining size,
+	/// from left to right.
+	pub fn is_peak(pos: NodeIndex, size: NodeIndex) -> bool {
+		let mut tree_start = 0;
+		let mut remaining = size;
+		while remaining > 0 {
+			// The largest perfect tree with at most `remaining` nodes has `2^height - 1` nodes.
+			let height = NodeIndex::BITS - 1 - remaining.saturating_add(1).leading_zeros();
+			let tree_size = (1 << height) - 1;
+			let peak = tree_start + tree_size - 1;
+			if pos <= peak {
+				return pos == peak
+			}
+			tree_start += tree_size;
+			remaining -= tree_size;
+		}
+		false
+	}
+
 	/// Return the number of leaves in the MMR.
 	pub fn number_of_leaves(&self) -> LeafIndex {
 		self.no_of_leaves
@@ -85,10 +138,53 @@ impl NodesUtils {
 		2 * self.no_of_leaves - self.number_of_peaks()
 	}
 
+	/// Return the number of all the nodes (leaves and inner nodes) ever created for the MMR.
+	///
+	/// Nodes are never removed from the MMR, so this is the same as [`Self::size`].
+	pub fn total_nodes_created(&self) -> NodeIndex {
+		self.size()
+	}
+
+	/// Return the number of leaf nodes of the MMR, i.e. [`Self::number_of_leaves`].
+	pub fn leaves_only(&self) -> NodeIndex {
+		self.number_of_leaves()
+	}
+
+	/// Return the number of inner nodes of the MMR, i.e. the nodes created by merging others.
+	pub fn inner_nodes(&self) -> NodeIndex {
+		self.no_of_leaves - self.number_of_peaks()
+	}
+
+	/// Iterate over all the nodes of the MMR, in the order of their positions.
+	///
+	/// Every node is described by its position, whether it's a leaf and its height in the tree
+	/// (`0` for the leaves). This is pure index math, no storage is involved.
+	pub fn iter_nodes(&self) -> impl Iterator<Item = (NodeIndex, bool, u32)> {
+		(0..self.size()).map(|pos| {
+			let height = helper::pos_height_in_tree(pos);
+			(pos, height == 0, height)
+		})
+	}
+
 	/// Calculate `LeafIndex` for the leaf that added `node_index` to the MMR.
-	pub fn leaf_index_that_added_node(node_index: NodeIndex) -> LeafIndex {
-		let rightmost_leaf_pos = Self::rightmost_leaf_node_index_from_pos(node_index);
-		Self::leaf_node_index_to_leaf_index(rightmost_leaf_pos)
+	pub fn leaf_index_that_added_node(node_index: TypedNodeIndex) -> TypedLeafIndex {
+		let rightmost_leaf_pos = Self::rightmost_leaf_node_index_from_pos(node_index.0);
+		TypedLeafIndex(Self::leaf_node_index_to_leaf_index(rightmost_leaf_pos))
+	}
+
+	/// Translate a `leaf_index` into the `NodeIndex` of that leaf.
+	pub fn leaf_to_node_index(leaf_index: TypedLeafIndex) -> TypedNodeIndex {
+		TypedNodeIndex(helper::leaf_index_to_pos(leaf_index.0))
+	}
+
+	/// Translate a `node_index` into its `LeafIndex`.
+	///
+	/// Returns `None` if `node_index` is an inner node, i.e. not a leaf.
+	pub fn node_to_leaf_index(node_index: TypedNodeIndex) -> Option<TypedLeafIndex> {
+		if helper::pos_height_in_tree(node_index.0) != 0 {
+			return None
+		}
+		Some(TypedLeafIndex(Self::leaf_node_index_to_leaf_index(node_index.0)))
 	}
 
 	// Translate a _leaf_ `NodeIndex` to its `LeafIndex`.
@@ -109,10 +205,80 @@ impl NodesUtils {
 	/// Starting from any leaf index, get the sequence of positions of the nodes added
 	/// to the mmr when this leaf was added (inclusive of the leaf's position itself).
 	/// That is, all of these nodes are right children of their respective parents.
-	pub fn right_branch_ending_in_leaf(leaf_index: LeafIndex) -> Vec<NodeIndex> {
-		let pos = helper::leaf_index_to_pos(leaf_index);
-		let num_parents = leaf_index.trailing_ones() as u64;
-		return (pos..=pos + num_parents).collect()
+	pub fn right_branch_ending_in_leaf(leaf_index: TypedLeafIndex) -> Vec<TypedNodeIndex> {
+		let pos = Self::leaf_to_node_index(leaf_index).0;
+		let num_parents = leaf_index.0.trailing_ones() as u64;
+		return (pos..=pos + num_parents).map(TypedNodeIndex).collect()
+	}
+
+	/// Return positions of all the nodes required to generate a proof for `leaf_index`.
+	///
+	/// These are: the peaks on the left of the leaf's peak, the siblings of the nodes on the
+	/// path from the leaf up to its peak (bottom-up), and the peaks on the right of the leaf's
+	/// peak. This is the order in which these nodes end up in the proof items, except that
+	/// multiple right-hand peaks get bagged into a single proof item.
+	///
+	/// Returns an empty collection if `leaf_index` is not part of the MMR.
+	pub fn proof_node_indices(&self, leaf_index: TypedLeafIndex) -> Vec<TypedNodeIndex> {
+		if leaf_index.0 >= self.no_of_leaves {
+			return Vec::new()
+		}
+
+		let peaks = helper::get_peaks(self.size());
+		let mut pos = Self::leaf_to_node_index(leaf_index).0;
+		let mut height = 0;
+		let mut path = Vec::new();
+		while !peaks.contains(&pos) {
+			let sibling_offset = (2 << height) - 1;
+			if helper::pos_height_in_tree(pos + 1) > height {
+				// `pos` is the right child, parent is right next to it.
+				path.push(pos - sibling_offset);
+				pos += 1;
+			} else {
+				// `pos` is the left child, parent follows the right sibling's subtree.
+				path.push(pos + sibling_offset);
+				pos += 2 << height;
+			}
+			height += 1;
+		}
+
+		let leaf_peak = pos;
+		peaks
+			.iter()
+			.filter(|peak| **peak < leaf_peak)
+			.copied()
+			.chain(path)
+			.chain(peaks.iter().filter(|peak| **peak > leaf_peak).copied())
+			.map(TypedNodeIndex)
+			.collect()
+	}
+
+	/// Return positions of the nodes required by the proofs of both `leaf_a` and `leaf_b`.
+	///
+	/// These nodes only need to be included once in a proof for both leaves (see
+	/// [Self::proof_node_indices]). The positions are returned in ascending order.
+	pub fn shared_auth_nodes(
+		&self,
+		leaf_a: TypedLeafIndex,
+		leaf_b: TypedLeafIndex,
+	) -> Vec<TypedNodeIndex> {
+		let nodes_b = self.proof_node_indices(leaf_b);
+		let mut shared = self
+			.proof_node_indices(leaf_a)
+			.into_iter()
+			.filter(|pos| nodes_b.contains(pos))
+			.collect::<Vec<_>>();
+		shared.sort();
+		shared
+	}
+
+	/// Build the common prefix of all the offchain keys of MMR nodes (temporary and canonical,
+	/// as well as the mirrored peaks).
+	///
+	/// Note this is the SCALE-encoded `prefix`, i.e. it includes the length of the `prefix`.
+	/// It can be used to scan the Off-chain DB for MMR nodes.
+	pub fn node_offchain_key_prefix(prefix: &[u8]) -> Vec<u8> {
+		prefix.encode()
 	}
 
 	/// Build offchain key from `parent_hash` of block that originally added node `pos` to MMR.
@@ -120,10 +286,10 @@ impl NodesUtils {
 	/// This combination makes the offchain (key,value) entry resilient to chain forks.
 	pub fn node_temp_offchain_key<H: Header>(
 		prefix: &[u8],
-		pos: NodeIndex,
+		pos: TypedNodeIndex,
 		parent_hash: H::Hash,
 	) -> Vec<u8> {
-		(prefix, pos, parent_hash).encode()
+		(prefix, pos.0, parent_hash).encode()
 	}
 
 	/// Build canonical offchain key for node `pos` in MMR.
@@ -131,8 +297,28 @@ impl NodesUtils {
 	/// Used for nodes added by now finalized blocks.
 	/// Never read keys using `node_canon_offchain_key` unless you sure that
 	/// there's no `node_offchain_key` key in the storage.
-	pub fn node_canon_offchain_key(prefix: &[u8], pos: NodeIndex) -> sp_std::prelude::Vec<u8> {
-		(prefix, pos).encode()
+	pub fn node_canon_offchain_key(prefix: &[u8], pos: TypedNodeIndex) -> sp_std::prelude::Vec<u8> {
+		(prefix, pos.0).encode()
+	}
+
+	/// Build offchain key for the mirror of peak `pos` of the MMR.
+	///
+	/// Unlike the keys of the nodes, it's not fork-aware, the peaks of the latest imported block
+	/// are stored under it.
+	pub fn peak_offchain_key(prefix: &[u8], pos: TypedNodeIndex) -> Vec<u8> {
+		(prefix, b"peaks", pos.0).encode()
+	}
+
+	/// Build offchain key for the cursor of the pruning done by the offchain worker, i.e. the
+	/// number of the next block whose nodes are going to be canonicalized.
+	pub fn prune_cursor_offchain_key(prefix: &[u8]) -> Vec<u8> {
+		(prefix, b"prune-cursor").encode()
+	}
+
+	/// Build offchain key for the leaves added by the blocks number `block_num` the offchain
+	/// worker has seen, along with the parent hashes they were indexed under.
+	pub fn fork_leaves_offchain_key<H: Header>(prefix: &[u8], block_num: H::Number) -> Vec<u8> {
+		(prefix, b"fork-leaves", block_num).encode()
 	}
 }
 
@@ -163,8 +349,13 @@ mod tests {
 		}
 
 		for leaf_index in 0..100000 {
-			let pos = mmr_lib::helper::leaf_index_to_pos(leaf_index);
-			assert_eq!(NodesUtils::right_branch_ending_in_leaf(pos), left_jump_sequence(pos));
+			assert_eq!(
+				NodesUtils::right_branch_ending_in_leaf(TypedLeafIndex(leaf_index)),
+				left_jump_sequence(leaf_index)
+					.into_iter()
+					.map(TypedNodeIndex)
+					.collect::<Vec<_>>()
+			);
 		}
 	}
 
@@ -172,8 +363,10 @@ mod tests {
 	fn should_calculate_rightmost_leaf_node_index_from_pos() {
 		for pos in 0..100000 {
 			let leaf_pos = NodesUtils::rightmost_leaf_node_index_from_pos(pos);
-			let leaf_index = NodesUtils::leaf_node_index_to_leaf_index(leaf_pos);
-			assert!(NodesUtils::right_branch_ending_in_leaf(leaf_index).contains(&pos));
+			let leaf_index = TypedLeafIndex(NodesUtils::leaf_node_index_to_leaf_index(leaf_pos));
+			assert!(
+				NodesUtils::right_branch_ending_in_leaf(leaf_index).contains(&TypedNodeIndex(pos))
+			);
 		}
 	}
 
@@ -199,6 +392,66 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn should_detect_leaves_creating_new_peaks() {
+		assert_eq!(
+			(0..12).map(NodesUtils::is_peak_creation_leaf).collect::<Vec<_>>(),
+			vec![true, false, true, false, true, false, true, false, true, false, true, false]
+		);
+		// a new peak is created exactly when the number of peaks grows
+		for n in 0..64 {
+			let grows =
+				NodesUtils::new(n + 1).number_of_peaks() > NodesUtils::new(n).number_of_peaks();
+			assert_eq!(NodesUtils::is_peak_creation_leaf(n), grows);
+		}
+	}
+
+	#[test]
+	fn should_detect_peaks() {
+		// the 7-leaf MMR has its peaks at positions 6, 9 and 10
+		let size = NodesUtils::new(7).size();
+		assert_eq!(
+			(0..12).filter(|pos| NodesUtils::is_peak(*pos, size)).collect::<Vec<_>>(),
+			vec![6, 9, 10]
+		);
+		assert!(!NodesUtils::is_peak(0, 0));
+
+		for leaves in 1..100 {
+			let size = NodesUtils::new(leaves).size();
+			let peaks = helper::get_peaks(size);
+			for pos in 0..size + 2 {
+				assert_eq!(NodesUtils::is_peak(pos, size), peaks.contains(&pos));
+			}
+		}
+	}
+
+	#[test]
+	fn should_iterate_over_nodes() {
+		// the 4-leaf MMR is a single perfect tree of height 2
+		assert_eq!(
+			NodesUtils::new(4).iter_nodes().collect::<Vec<_>>(),
+			vec![
+				(0, true, 0),
+				(1, true, 0),
+				(2, false, 1),
+				(3, true, 0),
+				(4, true, 0),
+				(5, false, 1),
+				(6, false, 2),
+			]
+		);
+		assert_eq!(NodesUtils::new(0).iter_nodes().count(), 0);
+
+		for leaves in 1..100 {
+			let utils = NodesUtils::new(leaves);
+			let nodes = utils.iter_nodes().collect::<Vec<_>>();
+			assert_eq!(nodes.len() as NodeIndex, utils.size());
+			let leaf_positions =
+				nodes.iter().filter(|(_, is_leaf, _)| *is_leaf).map(|(pos, ..)| *pos);
+			assert!(leaf_positions.eq((0..leaves).map(leaf_index_to_pos)));
+		}
+	}
+
 	#[test]
 	fn should_calculate_the_size_correctly() {
 		let leaves = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 21];
@@ -212,4 +465,122 @@ mod tests {
 			sizes.clone()
 		);
 	}
+
+	#[test]
+	fn should_count_leaves_and_inner_nodes() {
+		let leaves = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 21];
+		let inner_nodes = vec![0, 0, 1, 1, 3, 3, 4, 4, 7, 7, 8, 8, 10, 10, 11, 11, 18];
+		for (n, inner) in leaves.into_iter().zip(inner_nodes) {
+			let utils = NodesUtils::new(n);
+			assert_eq!(utils.inner_nodes(), inner);
+			assert_eq!(utils.leaves_only(), n);
+			assert_eq!(utils.inner_nodes() + utils.leaves_only(), utils.size());
+			assert_eq!(utils.total_nodes_created(), utils.size());
+		}
+	}
+
+	#[test]
+	fn should_calculate_the_number_of_leaves_from_size() {
+		let leaves = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 21];
+		for n in leaves {
+			let size = NodesUtils::new(n).size();
+			assert_eq!(NodesUtils::from_size(size).map(|utils| utils.number_of_leaves()), Some(n));
+		}
+		for invalid_size in [2, 5, 6, 9, 12, 13, 14, 17] {
+			assert!(NodesUtils::from_size(invalid_size).is_none());
+		}
+		assert!(NodesUtils::from_size(u64::MAX).is_none());
+	}
+
+	#[test]
+	fn should_convert_between_size_and_leaf_count() {
+		for (size, leaves) in [(0, 0), (1, 1), (3, 2), (4, 3), (7, 4)] {
+			assert_eq!(NodesUtils::leaf_count_from_size(size), Some(leaves));
+			assert_eq!(NodesUtils::new(leaves).size(), size);
+		}
+		assert_eq!(NodesUtils::leaf_count_from_size(5), None);
+	}
+
+	#[test]
+	fn should_calculate_proof_node_indices_correctly() {
+		//       D
+		//     /   \
+		//    /     \
+		//   A       B       C
+		//  / \     / \     / \
+		// 1   2   3   4   5   6  7
+		let utils = NodesUtils::new(7);
+		let proof_node_indices = |leaf_index| {
+			utils
+				.proof_node_indices(TypedLeafIndex(leaf_index))
+				.into_iter()
+				.map(|node_index| node_index.0)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(proof_node_indices(0), vec![1, 5, 9, 10]);
+		assert_eq!(proof_node_indices(2), vec![4, 2, 9, 10]);
+		assert_eq!(proof_node_indices(4), vec![6, 8, 10]);
+		assert_eq!(proof_node_indices(6), vec![6, 9]);
+		// leaves outside of the MMR don't have proofs.
+		assert_eq!(proof_node_indices(7), Vec::<NodeIndex>::new());
+		// single-leaf MMR has an empty proof.
+		assert_eq!(NodesUtils::new(1).proof_node_indices(TypedLeafIndex(0)), vec![]);
+	}
+
+	#[test]
+	fn should_find_shared_auth_nodes() {
+		let shared_auth_nodes = |leaves_count, leaf_a, leaf_b| {
+			NodesUtils::new(leaves_count)
+				.shared_auth_nodes(TypedLeafIndex(leaf_a), TypedLeafIndex(leaf_b))
+				.into_iter()
+				.map(|node_index| node_index.0)
+				.collect::<Vec<_>>()
+		};
+
+		//               14
+		//            /      \
+		//          6          13
+		//        /   \      /    \
+		//       2     5     9     12
+		//      / \   / \   / \   /  \
+		//     0   1 3   4 7   8 10  11
+		// leaves in the same mountain share the nodes above their lowest common ancestor.
+		assert_eq!(shared_auth_nodes(8, 0, 1), vec![5, 13]);
+		assert_eq!(shared_auth_nodes(8, 0, 3), vec![13]);
+		assert_eq!(shared_auth_nodes(8, 0, 7), vec![]);
+
+		//          6
+		//        /   \
+		//       2     5     9
+		//      / \   / \   / \
+		//     0   1 3   4 7   8 10
+		// leaves in different mountains only share the peaks of the other mountains.
+		assert_eq!(shared_auth_nodes(7, 0, 4), vec![10]);
+		assert_eq!(shared_auth_nodes(7, 0, 6), vec![9]);
+		assert_eq!(shared_auth_nodes(7, 4, 6), vec![6]);
+		// the shared nodes are symmetric.
+		assert_eq!(shared_auth_nodes(7, 4, 0), vec![10]);
+		// leaves outside of the MMR don't share any nodes.
+		assert_eq!(shared_auth_nodes(7, 0, 7), vec![]);
+	}
+
+	#[test]
+	fn should_round_trip_typed_indices() {
+		for leaf_index in (0..100000).map(TypedLeafIndex) {
+			let node_index = NodesUtils::leaf_to_node_index(leaf_index);
+			assert_eq!(node_index.0, leaf_index_to_pos(leaf_index.0));
+			assert_eq!(NodesUtils::node_to_leaf_index(node_index), Some(leaf_index));
+			assert_eq!(NodesUtils::leaf_index_that_added_node(node_index), leaf_index);
+		}
+	}
+
+	#[test]
+	fn should_not_convert_inner_nodes_to_leaf_indices() {
+		//   2
+		//  / \
+		// 0   1   3
+		assert_eq!(NodesUtils::node_to_leaf_index(TypedNodeIndex(2)), None);
+		assert_eq!(NodesUtils::leaf_index_that_added_node(TypedNodeIndex(2)), TypedLeafIndex(1));
+		assert_eq!(NodesUtils::node_to_leaf_index(TypedNodeIndex(3)), Some(TypedLeafIndex(2)));
+	}
 }